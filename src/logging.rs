@@ -1,7 +1,10 @@
 use std::fmt::Display;
 
 use colored::{control::set_override, Colorize};
-use log::Level;
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level,
+};
 
 /// External crates only need to log warnings and errors
 const ALLOWED_EXTERNAL_LEVELS: [Level; 2] = [Level::Warn, Level::Error];
@@ -18,11 +21,12 @@ pub fn init_logger() {
             let now = chrono::Local::now();
 
             out.finish(format_args!(
-                "{:^5} {} {} {}",
+                "{:^5} {} {} {}{}",
                 level_to_string(&record.level()),
                 now.format("%H:%M:%S").to_string().bright_black(),
                 target,
-                message
+                message,
+                KeyValues(record.key_values())
             ))
         })
         .filter(|meta| {
@@ -76,6 +80,32 @@ impl Display for Target {
     }
 }
 
+/// Displays the structured context of a record after the message, like ` room=lounge player_id=1`
+struct KeyValues<'a>(&'a dyn kv::Source);
+
+impl Display for KeyValues<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut visitor = KeyValueVisitor {
+            pairs: String::new(),
+        };
+
+        // Visiting a string never fails
+        let _ = self.0.visit(&mut visitor);
+        Display::fmt(&visitor.pairs.bright_black(), f)
+    }
+}
+
+struct KeyValueVisitor {
+    pairs: String,
+}
+
+impl<'kvs> VisitSource<'kvs> for KeyValueVisitor {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.pairs.push_str(&format!(" {}={}", key, value));
+        Ok(())
+    }
+}
+
 fn level_to_string(level: &Level) -> String {
     match level {
         Level::Error => " ERR ".black().on_red().bold().to_string(),
@@ -1,8 +1,8 @@
-use std::{env, sync::Arc};
+use std::{env, sync::Arc, time::Duration};
 
-use turntable_collab::Collab;
-use turntable_core::Config;
-use turntable_server::run_server;
+use turntable_collab::{Collab, CollabConfig, MissedSchedulePolicy};
+use turntable_core::{ResamplerQuality, SeekBehavior};
+use turntable_server::{run_server, ServerConfig};
 
 mod logging;
 
@@ -15,11 +15,138 @@ async fn main() {
     logging::init_logger();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let collab = Arc::new(Collab::new(Config::default(), &database_url).await);
+    let mut collab_config = CollabConfig::default();
+
+    if let Ok(secs) = env::var("TURNTABLE_DECODED_CACHE_SECS") {
+        let secs = secs
+            .parse::<f32>()
+            .expect("Decoded cache size must be a number");
+        collab_config.pipeline.decoded_cache_size_in_seconds = secs;
+    }
+
+    if let Ok(threads) = env::var("TURNTABLE_DECODE_THREADS") {
+        let threads = threads
+            .parse::<usize>()
+            .expect("Decode thread count must be a number");
+        collab_config.pipeline.decode_threads = threads;
+    }
+
+    if let Ok(seek_across_tracks) = env::var("TURNTABLE_SEEK_ACROSS_TRACKS") {
+        if seek_across_tracks == "true" {
+            collab_config.pipeline.seek_behavior = SeekBehavior::CrossBoundary;
+        }
+    }
+
+    if let Ok(quality) = env::var("TURNTABLE_RESAMPLER_QUALITY") {
+        collab_config.pipeline.resampler_quality = match quality.as_str() {
+            "low" => ResamplerQuality::Low,
+            "medium" => ResamplerQuality::Medium,
+            "high" => ResamplerQuality::High,
+            _ => panic!("Resampler quality must be low, medium or high"),
+        };
+    }
+
+    if let Ok(ceiling) = env::var("TURNTABLE_LIMITER_CEILING_DB") {
+        collab_config.pipeline.limiter_ceiling_in_db = match ceiling.as_str() {
+            "off" => None,
+            db => Some(
+                db.parse::<f32>()
+                    .expect("Limiter ceiling must be a number or off"),
+            ),
+        };
+    }
+
+    if let Ok(max) = env::var("TURNTABLE_MAX_SINKS") {
+        let max = max.parse::<usize>().expect("Sink limit must be a number");
+        collab_config.pipeline.max_activated_sinks = Some(max);
+    }
+
+    if let Ok(max) = env::var("TURNTABLE_MAX_SINK_MEMORY_SECONDS") {
+        let max = max
+            .parse::<f32>()
+            .expect("Sink memory limit must be a number");
+        collab_config.pipeline.max_sink_memory_in_seconds = Some(max);
+    }
+
+    if let Ok(dir) = env::var("TURNTABLE_RECORDINGS_DIR") {
+        collab_config.recordings_dir = dir.into();
+    }
+
+    if let Ok(max) = env::var("TURNTABLE_MAX_CONNECTIONS_PER_USER") {
+        let max = max
+            .parse::<usize>()
+            .expect("Connection limit must be a number");
+        collab_config.max_connections_per_user = Some(max);
+    }
+
+    if let Ok(max) = env::var("TURNTABLE_MAX_CONNECTIONS_PER_ROOM") {
+        let max = max
+            .parse::<usize>()
+            .expect("Connection limit must be a number");
+        collab_config.max_connections_per_room = Some(max);
+    }
+
+    if let Ok(latency) = env::var("TURNTABLE_DEFAULT_LATENCY_MS") {
+        let latency = latency
+            .parse::<u32>()
+            .expect("Default latency must be a number");
+        collab_config.default_latency_in_ms = Some(latency);
+    }
+
+    // Like "youtube=2,wavedistrict=4"
+    if let Ok(limits) = env::var("TURNTABLE_SOURCE_CONCURRENCY") {
+        collab_config.source_concurrency = limits
+            .split(',')
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                let (source, max) = l
+                    .split_once('=')
+                    .expect("Source concurrency must be source=max");
+                let max = max
+                    .trim()
+                    .parse::<usize>()
+                    .expect("Source concurrency must be a number");
+
+                (source.trim().to_string(), max)
+            })
+            .collect();
+    }
+
+    if let Ok(policy) = env::var("TURNTABLE_MISSED_SCHEDULES") {
+        collab_config.missed_schedule_policy = match policy.as_str() {
+            "skip" => MissedSchedulePolicy::Skip,
+            "fire" => MissedSchedulePolicy::Fire,
+            _ => panic!("Missed schedule policy must be skip or fire"),
+        };
+    }
+
+    // Like "keyword,Some Channel"
+    if let Ok(blocklist) = env::var("TURNTABLE_EXPLICIT_BLOCKLIST") {
+        collab_config.explicit_blocklist = blocklist
+            .split(',')
+            .map(|entry| entry.trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+    }
+
+    let collab = Arc::new(Collab::new(collab_config, &database_url).await);
 
     let port = env::var("TURNTABLE_SERVER_PORT")
         .map(|x| x.parse::<u16>().expect("Port must be a number"))
         .unwrap_or(DEFAULT_PORT);
 
-    run_server(&collab, port).await
+    let mut server_config = ServerConfig::default();
+
+    if let Ok(secs) = env::var("TURNTABLE_SSE_KEEPALIVE_SECS") {
+        let secs = secs
+            .parse::<u64>()
+            .expect("Keepalive interval must be a number");
+        server_config.sse_keep_alive_interval = Duration::from_secs(secs);
+    }
+
+    if let Ok(proxy_artwork) = env::var("TURNTABLE_PROXY_ARTWORK") {
+        server_config.proxy_artwork = proxy_artwork == "true";
+    }
+
+    run_server(&collab, port, server_config).await
 }
@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE rooms
+  ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public'
+  CHECK (visibility IN ('public', 'unlisted', 'private'));
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+CREATE TABLE track_plays (
+  id SERIAL PRIMARY KEY,
+  canonical TEXT NOT NULL,
+  title TEXT NOT NULL,
+  artist TEXT,
+  source TEXT NOT NULL,
+  room_id INT NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  time_played REAL NOT NULL,
+  skipped BOOLEAN NOT NULL,
+  played_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX track_plays_played_at ON track_plays (played_at);
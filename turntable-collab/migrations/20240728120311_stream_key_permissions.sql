@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE stream_keys ADD COLUMN can_queue BOOLEAN NOT NULL DEFAULT false;
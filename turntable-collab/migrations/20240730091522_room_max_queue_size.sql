@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE rooms ADD COLUMN max_queue_size INTEGER;
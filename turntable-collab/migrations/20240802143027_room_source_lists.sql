@@ -0,0 +1,4 @@
+-- Add migration script here
+
+ALTER TABLE rooms ADD COLUMN allowed_sources TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE rooms ADD COLUMN denied_sources TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE rooms
+  ADD COLUMN disconnect_policy TEXT NOT NULL DEFAULT 'keep_queued'
+  CHECK (disconnect_policy IN ('keep_queued', 'remove_queued'));
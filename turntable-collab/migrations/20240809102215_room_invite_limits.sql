@@ -0,0 +1,6 @@
+-- Add migration script here
+
+ALTER TABLE room_invites
+  ADD COLUMN expires_at TIMESTAMPTZ,
+  ADD COLUMN max_uses INTEGER,
+  ADD COLUMN uses INTEGER NOT NULL DEFAULT 0;
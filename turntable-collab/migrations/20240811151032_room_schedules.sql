@@ -0,0 +1,12 @@
+-- Add migration script here
+
+CREATE TABLE room_schedules (
+  id SERIAL PRIMARY KEY,
+  room_id INT NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+  user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  action TEXT NOT NULL CHECK (action IN ('play', 'queue_playlist')),
+  urls TEXT[] NOT NULL DEFAULT '{}',
+  fire_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX room_schedules_fire_at ON room_schedules (fire_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE rooms ADD COLUMN track_gap_in_ms INTEGER NOT NULL DEFAULT 0 CHECK (track_gap_in_ms >= 0);
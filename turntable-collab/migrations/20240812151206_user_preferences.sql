@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TABLE user_preferences (
+  user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  value TEXT NOT NULL,
+  PRIMARY KEY (user_id, key)
+);
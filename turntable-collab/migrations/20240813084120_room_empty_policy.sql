@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE rooms
+  ADD COLUMN empty_room_policy TEXT NOT NULL DEFAULT 'advance_when_empty'
+  CHECK (empty_room_policy IN ('advance_when_empty', 'pause_when_empty'));
@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE rooms ADD COLUMN default_latency_in_ms INTEGER CHECK (default_latency_in_ms >= 0);
@@ -0,0 +1 @@
+ALTER TABLE rooms ADD COLUMN trim_leading_silence BOOLEAN NOT NULL DEFAULT FALSE;
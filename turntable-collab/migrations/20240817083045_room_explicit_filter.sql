@@ -0,0 +1 @@
+ALTER TABLE rooms ADD COLUMN explicit_filter BOOLEAN NOT NULL DEFAULT FALSE;
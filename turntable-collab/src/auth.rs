@@ -12,7 +12,7 @@ use crate::{
     UpdatedUser, UserData,
 };
 
-pub struct Auth<Db> {
+pub struct Auth<Db: ?Sized> {
     db: Arc<Db>,
     argon: Argon2<'static>,
 }
@@ -33,7 +33,7 @@ pub enum AuthError {
 
 impl<Db> Auth<Db>
 where
-    Db: Database,
+    Db: Database + ?Sized,
 {
     const SESSION_DURATION_IN_DAYS: usize = 7;
 
@@ -0,0 +1,61 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use turntable_core::Config;
+
+/// The configuration of the collab system
+#[derive(Debug, Clone)]
+pub struct CollabConfig {
+    /// The configuration of the audio pipeline
+    pub pipeline: Config,
+    /// The fraction of a track that has to be heard for it to count as a play.
+    /// Tracks that end before this are recorded as skips.
+    pub play_threshold: f32,
+    /// The directory recordings of rooms are written to
+    pub recordings_dir: PathBuf,
+    /// How many stream connections a user can have at once, across all rooms, if limited
+    pub max_connections_per_user: Option<usize>,
+    /// How many stream connections a room can have at once, if limited
+    pub max_connections_per_room: Option<usize>,
+    /// How many milliseconds of latency streams have if they don't ask for any, unless the room has its own default.
+    /// If [None], they get as much as the stream preload cache holds.
+    pub default_latency_in_ms: Option<u32>,
+    /// How many fetches and activations of a source can run at once, by source like `youtube`.
+    /// Sources that aren't listed aren't limited.
+    pub source_concurrency: HashMap<String, usize>,
+    /// What happens to scheduled actions that were due while turntable wasn't running
+    pub missed_schedule_policy: MissedSchedulePolicy,
+    /// Keywords and channel names that rooms with the explicit content filter reject,
+    /// for sources that don't flag tracks as explicit themselves
+    pub explicit_blocklist: Vec<String>,
+    /// How long members of a room have to wait before moving their tracks to the front again.
+    /// Owners of the room aren't limited.
+    pub move_to_front_cooldown: Duration,
+}
+
+/// Determines what happens to scheduled actions that were due while turntable wasn't running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissedSchedulePolicy {
+    /// The actions are dropped, since they are likely irrelevant by now
+    #[default]
+    Skip,
+    /// The actions happen as soon as turntable starts
+    Fire,
+}
+
+impl Default for CollabConfig {
+    fn default() -> Self {
+        Self {
+            pipeline: Config::default(),
+            play_threshold: 0.5,
+            recordings_dir: PathBuf::from("recordings"),
+            max_connections_per_user: None,
+            max_connections_per_room: None,
+            default_latency_in_ms: None,
+            // yt-dlp gets rate limited when many videos are resolved at once
+            source_concurrency: HashMap::from([("youtube".to_string(), 2)]),
+            missed_schedule_policy: Default::default(),
+            explicit_blocklist: vec![],
+            move_to_front_cooldown: Duration::from_secs(10 * 60),
+        }
+    }
+}
@@ -13,6 +13,14 @@ pub struct UserData {
     pub superuser: bool,
 }
 
+/// A preference of a user, like the format they prefer to listen in
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserPreferenceData {
+    pub user_id: PrimaryKey,
+    pub key: String,
+    pub value: String,
+}
+
 /// Login session data for authentication
 #[derive(Debug, Clone)]
 pub struct SessionData {
@@ -33,9 +41,124 @@ pub struct RoomData {
     pub slug: String,
     pub title: String,
     pub description: Option<String>,
+    /// Who can see and resolve the room
+    pub visibility: RoomVisibility,
+    /// How many items the queue can contain at most, if limited
+    pub max_queue_size: Option<i32>,
+    /// How many seconds a track can play for at most, if limited
+    pub max_track_duration: Option<i32>,
+    /// How many milliseconds of silence are played between tracks
+    pub track_gap_in_ms: i32,
+    /// Sources that can be queued. Any source can be queued if empty.
+    pub allowed_sources: Vec<String>,
+    /// Sources that can't be queued, even if they're allowed
+    pub denied_sources: Vec<String>,
+    /// What happens to a member's queued items when they disconnect
+    pub disconnect_policy: DisconnectPolicy,
+    /// Whether the room keeps playing when nobody is listening
+    pub empty_room_policy: EmptyRoomPolicy,
+    /// How many milliseconds of latency streams have if they don't ask for any, if not the server's default
+    pub default_latency_in_ms: Option<i32>,
+    /// Whether silence at the start of tracks is skipped, up to a few seconds
+    pub trim_leading_silence: bool,
+    /// The input a jingle played between tracks is queried from, like a URL or file path, if any
+    pub jingle: Option<String>,
+    /// Whether tracks flagged as explicit or matching the blocklist are rejected when added
+    pub explicit_filter: bool,
     pub members: Vec<RoomMemberData>,
 }
 
+/// Determines who can see a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoomVisibility {
+    /// The room is listed and can be resolved by anyone
+    #[default]
+    Public,
+    /// The room is omitted from listings, but can be resolved by anyone with a link
+    Unlisted,
+    /// The room can only be resolved by its members
+    Private,
+}
+
+impl RoomVisibility {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Unlisted => "unlisted",
+            Self::Private => "private",
+        }
+    }
+
+    /// Parses a value stored in the database.
+    /// Unknown values are treated as private, to avoid exposing rooms by accident.
+    pub fn from_db_value(value: &str) -> Self {
+        match value {
+            "public" => Self::Public,
+            "unlisted" => Self::Unlisted,
+            _ => Self::Private,
+        }
+    }
+}
+
+/// Determines what happens to the queued items of a member when they disconnect from a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisconnectPolicy {
+    /// The items stay in the queue
+    #[default]
+    KeepQueued,
+    /// The items that haven't played yet are removed once the member has no connections left
+    RemoveQueued,
+}
+
+impl DisconnectPolicy {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::KeepQueued => "keep_queued",
+            Self::RemoveQueued => "remove_queued",
+        }
+    }
+
+    /// Parses a value stored in the database.
+    /// Unknown values keep the items, since removing them can't be undone.
+    pub fn from_db_value(value: &str) -> Self {
+        match value {
+            "remove_queued" => Self::RemoveQueued,
+            _ => Self::KeepQueued,
+        }
+    }
+}
+
+/// Determines what a room does when its last listener disconnects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyRoomPolicy {
+    /// The room keeps playing, so the queue advances and plays are counted like before
+    #[default]
+    AdvanceWhenEmpty,
+    /// The room pauses until someone connects again, which saves processing
+    PauseWhenEmpty,
+}
+
+impl EmptyRoomPolicy {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AdvanceWhenEmpty => "advance_when_empty",
+            Self::PauseWhenEmpty => "pause_when_empty",
+        }
+    }
+
+    /// Parses a value stored in the database.
+    /// Unknown values keep the room playing, which is how rooms behaved before the policy existed.
+    pub fn from_db_value(value: &str) -> Self {
+        match value {
+            "pause_when_empty" => Self::PauseWhenEmpty,
+            _ => Self::AdvanceWhenEmpty,
+        }
+    }
+}
+
 /// A member of a room
 #[derive(Debug, Clone)]
 pub struct RoomMemberData {
@@ -53,6 +176,79 @@ pub struct RoomInviteData {
     pub token: String,
     pub room: RoomData,
     pub inviter: UserData,
+    /// When the invite stops working. Never if not set.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// How many times the invite can be used. Unlimited if not set.
+    pub max_uses: Option<i32>,
+    /// How many times the invite has been used
+    pub uses: i32,
+}
+
+impl RoomInviteData {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|e| e <= Utc::now())
+    }
+
+    /// Returns true if the invite was used as many times as it can be
+    pub fn is_exhausted(&self) -> bool {
+        self.max_uses.is_some_and(|max| self.uses >= max)
+    }
+}
+
+/// An action that happens in a room at a given time, like the start of an event
+#[derive(Debug, Clone)]
+pub struct RoomScheduleData {
+    pub id: PrimaryKey,
+    pub room_id: PrimaryKey,
+    /// The user that scheduled the action, which tracks are queued on behalf of
+    pub user_id: PrimaryKey,
+    pub action: ScheduledAction,
+    /// When the action happens
+    pub fire_at: DateTime<Utc>,
+}
+
+impl RoomScheduleData {
+    /// Returns true if the action should have happened already
+    pub fn is_due(&self) -> bool {
+        self.fire_at <= Utc::now()
+    }
+}
+
+/// Something that can be scheduled to happen in a room
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduledAction {
+    /// Starts playback, activating the room if needed
+    Play,
+    /// Adds the tracks of each url to the queue, in order
+    QueuePlaylist(Vec<String>),
+}
+
+impl ScheduledAction {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Play => "play",
+            Self::QueuePlaylist(_) => "queue_playlist",
+        }
+    }
+
+    /// Returns the urls stored in the database, which only playlists have
+    pub fn urls(&self) -> &[String] {
+        match self {
+            Self::Play => &[],
+            Self::QueuePlaylist(urls) => urls,
+        }
+    }
+
+    /// Parses a value stored in the database.
+    /// Unknown actions are [None], so they never happen.
+    pub fn from_db_value(value: &str, urls: Vec<String>) -> Option<Self> {
+        match value {
+            "play" => Some(Self::Play),
+            "queue_playlist" => Some(Self::QueuePlaylist(urls)),
+            _ => None,
+        }
+    }
 }
 
 /// A stream key is used to access the audio stream of a room
@@ -69,4 +265,27 @@ pub struct StreamKeyData {
     pub room_id: PrimaryKey,
     /// The user this stream key belongs to
     pub user_id: PrimaryKey,
+    /// If the holder of this stream key can add tracks to the queue.
+    /// Otherwise, the stream key is listen-only.
+    pub can_queue: bool,
+}
+
+/// How many times a track was played
+#[derive(Debug, Clone)]
+pub struct TrackStatsData {
+    /// The canonical url of the track, used to identify it
+    pub canonical: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub source: String,
+    /// Amount of plays, excluding skips
+    pub plays: i64,
+}
+
+/// How many times tracks submitted by a user were played
+#[derive(Debug, Clone)]
+pub struct SubmitterStatsData {
+    pub user: UserData,
+    /// Amount of plays, excluding skips
+    pub plays: i64,
 }
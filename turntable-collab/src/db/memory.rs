@@ -0,0 +1,788 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+
+use crate::{
+    Database, DatabaseError, DisconnectPolicy, EmptyRoomPolicy, NewRoom, NewRoomInvite,
+    NewRoomMember, NewRoomSchedule, NewSession, NewStreamKey, NewTrackPlay, NewUser, PrimaryKey,
+    Result, RoomData, RoomInviteData, RoomMemberData, RoomScheduleData, RoomVisibility,
+    SessionData, StreamKeyData, SubmitterStatsData, TrackStatsData, UpdatedRoom, UpdatedUser,
+    UserData, UserPreferenceData,
+};
+
+/// An in-memory database, for testing code that depends on a [Database] without Postgres.
+///
+/// It mirrors the Postgres implementation: lookups and conflicts return the same errors,
+/// deletes cascade, and violated constraints are internal errors.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    /// Ids are shared between tables, so they are unique but not sequential per table
+    sequence: AtomicI32,
+
+    users: DashMap<PrimaryKey, UserData>,
+    sessions: DashMap<PrimaryKey, SessionRow>,
+    rooms: DashMap<PrimaryKey, RoomRow>,
+    members: DashMap<PrimaryKey, MemberRow>,
+    invites: DashMap<PrimaryKey, InviteRow>,
+    stream_keys: DashMap<PrimaryKey, StreamKeyData>,
+    track_plays: DashMap<PrimaryKey, TrackPlayRow>,
+    schedules: DashMap<PrimaryKey, RoomScheduleData>,
+    preferences: DashMap<(PrimaryKey, String), String>,
+}
+
+#[derive(Clone)]
+struct SessionRow {
+    id: PrimaryKey,
+    token: String,
+    user_id: PrimaryKey,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+struct RoomRow {
+    id: PrimaryKey,
+    slug: String,
+    title: String,
+    description: Option<String>,
+    visibility: RoomVisibility,
+    max_queue_size: Option<i32>,
+    max_track_duration: Option<i32>,
+    track_gap_in_ms: i32,
+    allowed_sources: Vec<String>,
+    denied_sources: Vec<String>,
+    disconnect_policy: DisconnectPolicy,
+    empty_room_policy: EmptyRoomPolicy,
+    default_latency_in_ms: Option<i32>,
+    trim_leading_silence: bool,
+    jingle: Option<String>,
+    explicit_filter: bool,
+}
+
+#[derive(Clone)]
+struct MemberRow {
+    id: PrimaryKey,
+    user_id: PrimaryKey,
+    room_id: PrimaryKey,
+    owner: bool,
+}
+
+#[derive(Clone)]
+struct InviteRow {
+    id: PrimaryKey,
+    token: String,
+    room_id: PrimaryKey,
+    inviter_id: PrimaryKey,
+    expires_at: Option<DateTime<Utc>>,
+    max_uses: Option<i32>,
+    uses: i32,
+}
+
+#[derive(Clone)]
+struct TrackPlayRow {
+    canonical: String,
+    title: String,
+    artist: Option<String>,
+    source: String,
+    room_id: PrimaryKey,
+    user_id: PrimaryKey,
+    skipped: bool,
+    played_at: DateTime<Utc>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next_id(&self) -> PrimaryKey {
+        self.sequence.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn room_members(&self, room_id: PrimaryKey) -> Vec<RoomMemberData> {
+        let mut rows: Vec<_> = self
+            .members
+            .iter()
+            .filter(|m| m.room_id == room_id)
+            .map(|m| m.clone())
+            .collect();
+
+        rows.sort_by_key(|m| m.id);
+        rows.into_iter()
+            .filter_map(|m| {
+                self.users.get(&m.user_id).map(|user| RoomMemberData {
+                    id: m.id,
+                    owner: m.owner,
+                    user: user.clone(),
+                })
+            })
+            .collect()
+    }
+
+    fn room_data(&self, row: RoomRow) -> RoomData {
+        RoomData {
+            members: self.room_members(row.id),
+            id: row.id,
+            slug: row.slug,
+            title: row.title,
+            description: row.description,
+            visibility: row.visibility,
+            max_queue_size: row.max_queue_size,
+            max_track_duration: row.max_track_duration,
+            track_gap_in_ms: row.track_gap_in_ms,
+            allowed_sources: row.allowed_sources,
+            denied_sources: row.denied_sources,
+            disconnect_policy: row.disconnect_policy,
+            empty_room_policy: row.empty_room_policy,
+            default_latency_in_ms: row.default_latency_in_ms,
+            trim_leading_silence: row.trim_leading_silence,
+            jingle: row.jingle.clone(),
+            explicit_filter: row.explicit_filter,
+        }
+    }
+
+    fn session_data(&self, row: SessionRow) -> Result<SessionData> {
+        Ok(SessionData {
+            user: self.user(row.user_id)?,
+            id: row.id,
+            token: row.token,
+            expires_at: row.expires_at,
+        })
+    }
+
+    fn invite_data(&self, row: InviteRow) -> Result<RoomInviteData> {
+        let room = self.rooms.get(&row.room_id).map(|r| r.clone());
+
+        Ok(RoomInviteData {
+            room: self.room_data(room.ok_or_else(|| missing_reference("room"))?),
+            inviter: self.user(row.inviter_id)?,
+            id: row.id,
+            token: row.token,
+            expires_at: row.expires_at,
+            max_uses: row.max_uses,
+            uses: row.uses,
+        })
+    }
+
+    fn user(&self, user_id: PrimaryKey) -> Result<UserData> {
+        self.users
+            .get(&user_id)
+            .map(|u| u.clone())
+            .ok_or_else(|| not_found("user", "id"))
+    }
+
+    /// Fails like a foreign key constraint would, if the rows don't exist
+    fn ensure_references(
+        &self,
+        user_id: Option<PrimaryKey>,
+        room_id: Option<PrimaryKey>,
+    ) -> Result<()> {
+        if user_id.is_some_and(|id| !self.users.contains_key(&id)) {
+            return Err(missing_reference("user"));
+        }
+
+        if room_id.is_some_and(|id| !self.rooms.contains_key(&id)) {
+            return Err(missing_reference("room"));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Database for MemoryDatabase {
+    async fn check_for_superuser(&self) -> Result<bool> {
+        Ok(self.users.iter().any(|u| u.superuser))
+    }
+
+    async fn user_by_id(&self, user_id: PrimaryKey) -> Result<UserData> {
+        self.user(user_id)
+    }
+
+    async fn user_by_username(&self, username: &str) -> Result<UserData> {
+        self.users
+            .iter()
+            .find(|u| u.username == username)
+            .map(|u| u.clone())
+            .ok_or_else(|| not_found("user", "username"))
+    }
+
+    async fn create_user(&self, new_user: NewUser) -> Result<UserData> {
+        if self.user_by_username(&new_user.username).await.is_ok() {
+            return Err(conflict("user", "username", &new_user.username));
+        }
+
+        let user = UserData {
+            id: self.next_id(),
+            username: new_user.username,
+            password: new_user.password,
+            display_name: new_user.display_name,
+            superuser: new_user.superuser,
+        };
+
+        self.users.insert(user.id, user.clone());
+        Ok(user)
+    }
+
+    async fn update_user(&self, updated_user: UpdatedUser) -> Result<UserData> {
+        let mut user = self
+            .users
+            .get_mut(&updated_user.id)
+            .ok_or_else(|| not_found("user", "id"))?;
+
+        if let Some(display_name) = updated_user.display_name {
+            user.display_name = display_name;
+        }
+
+        Ok(user.clone())
+    }
+
+    async fn delete_user(&self, user_id: PrimaryKey) -> Result<()> {
+        self.users
+            .remove(&user_id)
+            .ok_or_else(|| not_found("user", "id"))?;
+
+        self.sessions.retain(|_, s| s.user_id != user_id);
+        self.members.retain(|_, m| m.user_id != user_id);
+        self.invites.retain(|_, i| i.inviter_id != user_id);
+        self.stream_keys.retain(|_, k| k.user_id != user_id);
+        self.track_plays.retain(|_, p| p.user_id != user_id);
+        self.schedules.retain(|_, s| s.user_id != user_id);
+        self.preferences.retain(|(id, _), _| *id != user_id);
+
+        Ok(())
+    }
+
+    async fn list_user_preferences(&self, user_id: PrimaryKey) -> Result<Vec<UserPreferenceData>> {
+        let mut preferences: Vec<_> = self
+            .preferences
+            .iter()
+            .filter(|p| p.key().0 == user_id)
+            .map(|p| UserPreferenceData {
+                user_id,
+                key: p.key().1.clone(),
+                value: p.value().clone(),
+            })
+            .collect();
+
+        preferences.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(preferences)
+    }
+
+    async fn set_user_preference(&self, preference: UserPreferenceData) -> Result<()> {
+        self.ensure_references(Some(preference.user_id), None)?;
+
+        self.preferences
+            .insert((preference.user_id, preference.key), preference.value);
+
+        Ok(())
+    }
+
+    async fn delete_user_preference(&self, user_id: PrimaryKey, key: &str) -> Result<()> {
+        self.preferences
+            .remove(&(user_id, key.to_string()))
+            .ok_or_else(|| not_found("user preference", "key"))?;
+
+        Ok(())
+    }
+
+    async fn session_by_token(&self, token: &str) -> Result<SessionData> {
+        let row = self
+            .sessions
+            .iter()
+            .find(|s| s.token == token)
+            .map(|s| s.clone())
+            .ok_or_else(|| not_found("session", "token"))?;
+
+        self.session_data(row)
+    }
+
+    async fn create_session(&self, new_session: NewSession) -> Result<SessionData> {
+        if self.session_by_token(&new_session.token).await.is_ok() {
+            return Err(conflict("session", "token", &new_session.token));
+        }
+
+        self.ensure_references(Some(new_session.user_id), None)?;
+
+        let row = SessionRow {
+            id: self.next_id(),
+            token: new_session.token,
+            user_id: new_session.user_id,
+            expires_at: new_session.expires_at,
+        };
+
+        self.sessions.insert(row.id, row.clone());
+        self.session_data(row)
+    }
+
+    async fn delete_session_by_token(&self, token: &str) -> Result<()> {
+        let session = self.session_by_token(token).await?;
+        self.sessions.remove(&session.id);
+
+        Ok(())
+    }
+
+    async fn clear_expired_sessions(&self) -> Result<()> {
+        let now = Utc::now();
+        self.sessions.retain(|_, s| s.expires_at >= now);
+
+        Ok(())
+    }
+
+    async fn room_by_id(&self, room_id: PrimaryKey) -> Result<RoomData> {
+        let row = self
+            .rooms
+            .get(&room_id)
+            .map(|r| r.clone())
+            .ok_or_else(|| not_found("room", "id"))?;
+
+        Ok(self.room_data(row))
+    }
+
+    async fn room_by_slug(&self, slug: &str) -> Result<RoomData> {
+        let row = self
+            .rooms
+            .iter()
+            .find(|r| r.slug == slug)
+            .map(|r| r.clone())
+            .ok_or_else(|| not_found("room", "slug"))?;
+
+        Ok(self.room_data(row))
+    }
+
+    async fn room_invite_by_token(&self, token: &str) -> Result<RoomInviteData> {
+        let row = self
+            .invites
+            .iter()
+            .find(|i| i.token == token)
+            .map(|i| i.clone())
+            .ok_or_else(|| not_found("room invite", "token"))?;
+
+        self.invite_data(row)
+    }
+
+    async fn list_rooms(&self) -> Result<Vec<RoomData>> {
+        let mut rows: Vec<_> = self.rooms.iter().map(|r| r.clone()).collect();
+        rows.sort_by_key(|r| r.id);
+
+        Ok(rows.into_iter().map(|r| self.room_data(r)).collect())
+    }
+
+    async fn create_room(&self, new_room: NewRoom) -> Result<RoomData> {
+        if self.room_by_slug(&new_room.slug).await.is_ok() {
+            return Err(conflict("room", "slug", &new_room.slug));
+        }
+
+        let user = self.user(new_room.user_id)?;
+        let row = RoomRow {
+            id: self.next_id(),
+            slug: new_room.slug,
+            title: new_room.title,
+            description: new_room.description,
+            visibility: new_room.visibility,
+            max_queue_size: new_room.max_queue_size,
+            max_track_duration: new_room.max_track_duration,
+            track_gap_in_ms: new_room.track_gap_in_ms,
+            allowed_sources: new_room.allowed_sources,
+            denied_sources: new_room.denied_sources,
+            disconnect_policy: new_room.disconnect_policy,
+            empty_room_policy: new_room.empty_room_policy,
+            default_latency_in_ms: new_room.default_latency_in_ms,
+            trim_leading_silence: new_room.trim_leading_silence,
+            jingle: new_room.jingle,
+            explicit_filter: new_room.explicit_filter,
+        };
+
+        self.rooms.insert(row.id, row.clone());
+
+        // Add owner as a member to the room
+        self.create_room_member(NewRoomMember {
+            user_id: user.id,
+            room_id: row.id,
+            owner: true,
+        })
+        .await?;
+
+        Ok(self.room_data(row))
+    }
+
+    async fn create_room_member(&self, new_member: NewRoomMember) -> Result<RoomMemberData> {
+        let exists = self
+            .members
+            .iter()
+            .any(|m| m.user_id == new_member.user_id && m.room_id == new_member.room_id);
+
+        if exists {
+            let value = format!("{}:{}", new_member.user_id, new_member.room_id);
+            return Err(conflict("room member", "user:room", &value));
+        }
+
+        self.ensure_references(Some(new_member.user_id), Some(new_member.room_id))?;
+
+        let row = MemberRow {
+            id: self.next_id(),
+            user_id: new_member.user_id,
+            room_id: new_member.room_id,
+            owner: new_member.owner,
+        };
+
+        self.members.insert(row.id, row.clone());
+
+        Ok(RoomMemberData {
+            id: row.id,
+            owner: row.owner,
+            user: self.user(row.user_id)?,
+        })
+    }
+
+    async fn update_room(&self, updated_room: UpdatedRoom) -> Result<RoomData> {
+        let row = {
+            let mut row = self
+                .rooms
+                .get_mut(&updated_room.id)
+                .ok_or_else(|| not_found("room", "id"))?;
+
+            let UpdatedRoom {
+                title,
+                description,
+                visibility,
+                max_queue_size,
+                max_track_duration,
+                track_gap_in_ms,
+                allowed_sources,
+                denied_sources,
+                disconnect_policy,
+                empty_room_policy,
+                default_latency_in_ms,
+                trim_leading_silence,
+                jingle,
+                explicit_filter,
+                ..
+            } = updated_room;
+
+            row.title = title.unwrap_or(row.title.clone());
+            row.description = description.or(row.description.clone());
+            row.visibility = visibility.unwrap_or(row.visibility);
+            row.max_queue_size = max_queue_size.or(row.max_queue_size);
+            row.max_track_duration = max_track_duration.or(row.max_track_duration);
+            row.track_gap_in_ms = track_gap_in_ms.unwrap_or(row.track_gap_in_ms);
+            row.allowed_sources = allowed_sources.unwrap_or(row.allowed_sources.clone());
+            row.denied_sources = denied_sources.unwrap_or(row.denied_sources.clone());
+            row.disconnect_policy = disconnect_policy.unwrap_or(row.disconnect_policy);
+            row.empty_room_policy = empty_room_policy.unwrap_or(row.empty_room_policy);
+            row.default_latency_in_ms = default_latency_in_ms.or(row.default_latency_in_ms);
+            row.trim_leading_silence = trim_leading_silence.unwrap_or(row.trim_leading_silence);
+            row.jingle = jingle.or(row.jingle.take());
+            row.explicit_filter = explicit_filter.unwrap_or(row.explicit_filter);
+
+            row.clone()
+        };
+
+        Ok(self.room_data(row))
+    }
+
+    async fn delete_room(&self, room_id: PrimaryKey) -> Result<()> {
+        self.rooms
+            .remove(&room_id)
+            .ok_or_else(|| not_found("room", "id"))?;
+
+        self.members.retain(|_, m| m.room_id != room_id);
+        self.invites.retain(|_, i| i.room_id != room_id);
+        self.stream_keys.retain(|_, k| k.room_id != room_id);
+        self.track_plays.retain(|_, p| p.room_id != room_id);
+        self.schedules.retain(|_, s| s.room_id != room_id);
+
+        Ok(())
+    }
+
+    async fn delete_room_member(&self, room_id: PrimaryKey, user_id: PrimaryKey) -> Result<()> {
+        let member_id = self
+            .members
+            .iter()
+            .find(|m| m.room_id == room_id && m.user_id == user_id)
+            .map(|m| m.id)
+            .ok_or_else(|| not_found("room member", "room_id:user_id"))?;
+
+        self.members.remove(&member_id);
+        Ok(())
+    }
+
+    async fn create_room_invite(&self, new_room_invite: NewRoomInvite) -> Result<RoomInviteData> {
+        if self
+            .room_invite_by_token(&new_room_invite.token)
+            .await
+            .is_ok()
+        {
+            return Err(conflict("room invite", "token", &new_room_invite.token));
+        }
+
+        self.ensure_references(Some(new_room_invite.user_id), Some(new_room_invite.room_id))?;
+
+        let row = InviteRow {
+            id: self.next_id(),
+            token: new_room_invite.token,
+            room_id: new_room_invite.room_id,
+            inviter_id: new_room_invite.user_id,
+            expires_at: new_room_invite.expires_at,
+            max_uses: new_room_invite.max_uses,
+            uses: 0,
+        };
+
+        self.invites.insert(row.id, row.clone());
+        self.invite_data(row)
+    }
+
+    async fn create_room_member_with_invite(
+        &self,
+        invite_id: PrimaryKey,
+        new_member: NewRoomMember,
+    ) -> Result<Option<RoomMemberData>> {
+        let has_uses_left = self
+            .invites
+            .get(&invite_id)
+            .is_some_and(|i| i.max_uses.is_none_or(|max| i.uses < max));
+
+        if !has_uses_left {
+            return Ok(None);
+        }
+
+        // The member is created first, so the invite is left as it was if that fails
+        let member = self.create_room_member(new_member).await?;
+
+        let is_exhausted = self.invites.get_mut(&invite_id).is_some_and(|mut i| {
+            i.uses += 1;
+            i.max_uses.is_some_and(|max| i.uses >= max)
+        });
+
+        if is_exhausted {
+            self.invites.remove(&invite_id);
+        }
+
+        Ok(Some(member))
+    }
+
+    async fn delete_room_invite(&self, invite_id: PrimaryKey) -> Result<()> {
+        self.invites
+            .remove(&invite_id)
+            .ok_or_else(|| not_found("room invite", "id"))?;
+
+        Ok(())
+    }
+
+    async fn stream_key_by_token(&self, token: &str) -> Result<StreamKeyData> {
+        self.stream_keys
+            .iter()
+            .find(|k| k.token == token)
+            .map(|k| k.clone())
+            .ok_or_else(|| not_found("stream key", "token"))
+    }
+
+    async fn create_stream_key(&self, new_key: NewStreamKey) -> Result<StreamKeyData> {
+        let exists = self.stream_keys.iter().any(|k| {
+            k.token == new_key.token && k.room_id == new_key.room_id && k.user_id == new_key.user_id
+        });
+
+        let value = format!(
+            "{} or {}:{}",
+            &new_key.token, new_key.room_id, new_key.user_id
+        );
+
+        if exists {
+            return Err(conflict("stream key", "token or room:user", &value));
+        }
+
+        // Other duplicates violate a unique constraint, like they do in Postgres
+        let duplicate = self.stream_keys.iter().any(|k| {
+            k.token == new_key.token
+                || (k.source == new_key.source
+                    && k.room_id == new_key.room_id
+                    && k.user_id == new_key.user_id)
+        });
+
+        if duplicate {
+            return Err(DatabaseError::Internal(
+                format!("duplicate stream key {}", value).into(),
+            ));
+        }
+
+        self.ensure_references(Some(new_key.user_id), Some(new_key.room_id))?;
+
+        let key = StreamKeyData {
+            id: self.next_id(),
+            token: new_key.token,
+            source: new_key.source,
+            room_id: new_key.room_id,
+            user_id: new_key.user_id,
+            can_queue: new_key.can_queue,
+        };
+
+        self.stream_keys.insert(key.id, key.clone());
+        Ok(key)
+    }
+
+    async fn list_stream_keys(
+        &self,
+        room_id: PrimaryKey,
+        user_id: PrimaryKey,
+    ) -> Result<Vec<StreamKeyData>> {
+        let mut keys: Vec<_> = self
+            .stream_keys
+            .iter()
+            .filter(|k| k.room_id == room_id && k.user_id == user_id)
+            .map(|k| k.clone())
+            .collect();
+
+        keys.sort_by_key(|k| k.id);
+        Ok(keys)
+    }
+
+    async fn delete_stream_key(&self, key_id: PrimaryKey) -> Result<()> {
+        self.stream_keys
+            .remove(&key_id)
+            .ok_or_else(|| not_found("stream key", "id"))?;
+
+        Ok(())
+    }
+
+    async fn list_room_schedules(&self) -> Result<Vec<RoomScheduleData>> {
+        let mut schedules: Vec<_> = self.schedules.iter().map(|s| s.clone()).collect();
+
+        schedules.sort_by_key(|s| (s.fire_at, s.id));
+        Ok(schedules)
+    }
+
+    async fn create_room_schedule(
+        &self,
+        new_schedule: NewRoomSchedule,
+    ) -> Result<RoomScheduleData> {
+        self.ensure_references(Some(new_schedule.user_id), Some(new_schedule.room_id))?;
+
+        let schedule = RoomScheduleData {
+            id: self.next_id(),
+            room_id: new_schedule.room_id,
+            user_id: new_schedule.user_id,
+            action: new_schedule.action,
+            fire_at: new_schedule.fire_at,
+        };
+
+        self.schedules.insert(schedule.id, schedule.clone());
+        Ok(schedule)
+    }
+
+    async fn delete_room_schedule(&self, schedule_id: PrimaryKey) -> Result<()> {
+        self.schedules
+            .remove(&schedule_id)
+            .ok_or_else(|| not_found("room schedule", "id"))?;
+
+        Ok(())
+    }
+
+    async fn create_track_play(&self, new_play: NewTrackPlay) -> Result<()> {
+        self.ensure_references(Some(new_play.user_id), Some(new_play.room_id))?;
+
+        let row = TrackPlayRow {
+            canonical: new_play.canonical,
+            title: new_play.title,
+            artist: new_play.artist,
+            source: new_play.source,
+            room_id: new_play.room_id,
+            user_id: new_play.user_id,
+            skipped: new_play.skipped,
+            played_at: Utc::now(),
+        };
+
+        self.track_plays.insert(self.next_id(), row);
+        Ok(())
+    }
+
+    async fn top_tracks(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TrackStatsData>> {
+        let mut stats: HashMap<String, TrackStatsData> = HashMap::new();
+
+        for play in self.counted_plays(since) {
+            let entry = stats
+                .entry(play.canonical.clone())
+                .or_insert_with(|| TrackStatsData {
+                    canonical: play.canonical.clone(),
+                    title: play.title.clone(),
+                    artist: None,
+                    source: play.source.clone(),
+                    plays: 0,
+                });
+
+            // Mirrors MAX() in Postgres, which ignores nulls
+            entry.title = entry.title.clone().max(play.title.clone());
+            entry.artist = entry.artist.clone().max(play.artist.clone());
+            entry.source = entry.source.clone().max(play.source.clone());
+            entry.plays += 1;
+        }
+
+        let mut stats: Vec<_> = stats.into_values().collect();
+        stats.sort_by(|a, b| {
+            (Reverse(a.plays), &a.canonical).cmp(&(Reverse(b.plays), &b.canonical))
+        });
+        stats.truncate(limit.max(0) as usize);
+
+        Ok(stats)
+    }
+
+    async fn top_submitters(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SubmitterStatsData>> {
+        let mut plays: HashMap<PrimaryKey, i64> = HashMap::new();
+
+        for play in self.counted_plays(since) {
+            *plays.entry(play.user_id).or_default() += 1;
+        }
+
+        let mut stats: Vec<_> = plays
+            .into_iter()
+            .filter_map(|(user_id, plays)| {
+                let user = self.user(user_id).ok()?;
+                Some(SubmitterStatsData { user, plays })
+            })
+            .collect();
+
+        stats.sort_by_key(|s| (Reverse(s.plays), s.user.id));
+        stats.truncate(limit.max(0) as usize);
+
+        Ok(stats)
+    }
+}
+
+impl MemoryDatabase {
+    /// Returns the plays since the given time that weren't skipped
+    fn counted_plays(&self, since: DateTime<Utc>) -> Vec<TrackPlayRow> {
+        self.track_plays
+            .iter()
+            .filter(|p| !p.skipped && p.played_at >= since)
+            .map(|p| p.clone())
+            .collect()
+    }
+}
+
+fn not_found(resource: &'static str, identifier: &'static str) -> DatabaseError {
+    DatabaseError::NotFound {
+        resource,
+        identifier,
+    }
+}
+
+fn conflict(resource: &'static str, field: &'static str, value: &str) -> DatabaseError {
+    DatabaseError::Conflict {
+        resource,
+        field,
+        value: value.to_string(),
+    }
+}
+
+/// The error Postgres gives when a foreign key constraint is violated, as an internal error
+fn missing_reference(table: &str) -> DatabaseError {
+    DatabaseError::Internal(format!("referenced {} does not exist", table).into())
+}
@@ -8,6 +8,11 @@ pub use data::*;
 mod pg;
 pub use pg::*;
 
+#[cfg(any(test, feature = "testing"))]
+mod memory;
+#[cfg(any(test, feature = "testing"))]
+pub use memory::*;
+
 pub type Result<T> = std::result::Result<T, DatabaseError>;
 pub type BoxedDatabase = Box<dyn Database>;
 
@@ -73,7 +78,7 @@ impl<T> DatabaseResult for Result<T> {
 
 /// Represents a type that can fetch turntable data from a database
 #[async_trait]
-pub trait Database {
+pub trait Database: Send + Sync {
     async fn check_for_superuser(&self) -> Result<bool>;
     async fn user_by_id(&self, user_id: PrimaryKey) -> Result<UserData>;
     async fn user_by_username(&self, username: &str) -> Result<UserData>;
@@ -81,6 +86,12 @@ pub trait Database {
     async fn update_user(&self, updated_user: UpdatedUser) -> Result<UserData>;
     async fn delete_user(&self, user_id: PrimaryKey) -> Result<()>;
 
+    /// Lists the preferences of a user, ordered by key
+    async fn list_user_preferences(&self, user_id: PrimaryKey) -> Result<Vec<UserPreferenceData>>;
+    /// Sets a preference of a user, replacing the value if it was set already
+    async fn set_user_preference(&self, preference: UserPreferenceData) -> Result<()>;
+    async fn delete_user_preference(&self, user_id: PrimaryKey, key: &str) -> Result<()>;
+
     async fn session_by_token(&self, token: &str) -> Result<SessionData>;
     async fn create_session(&self, new_session: NewSession) -> Result<SessionData>;
     async fn delete_session_by_token(&self, token: &str) -> Result<()>;
@@ -96,6 +107,13 @@ pub trait Database {
     async fn delete_room(&self, room_id: PrimaryKey) -> Result<()>;
     async fn delete_room_member(&self, room_id: PrimaryKey, user_id: PrimaryKey) -> Result<()>;
     async fn create_room_invite(&self, new_room_invite: NewRoomInvite) -> Result<RoomInviteData>;
+    /// Adds a member with an invite, counting the use and deleting the invite once it has no uses left.
+    /// Nothing changes if any of these fail. Returns [None] if the invite has no uses left.
+    async fn create_room_member_with_invite(
+        &self,
+        invite_id: PrimaryKey,
+        new_member: NewRoomMember,
+    ) -> Result<Option<RoomMemberData>>;
     async fn delete_room_invite(&self, invite_id: PrimaryKey) -> Result<()>;
 
     async fn stream_key_by_token(&self, token: &str) -> Result<StreamKeyData>;
@@ -106,6 +124,20 @@ pub trait Database {
         user_id: PrimaryKey,
     ) -> Result<Vec<StreamKeyData>>;
     async fn delete_stream_key(&self, key_id: PrimaryKey) -> Result<()>;
+
+    /// Lists the scheduled actions of all rooms, the earliest first
+    async fn list_room_schedules(&self) -> Result<Vec<RoomScheduleData>>;
+    async fn create_room_schedule(&self, new_schedule: NewRoomSchedule)
+        -> Result<RoomScheduleData>;
+    async fn delete_room_schedule(&self, schedule_id: PrimaryKey) -> Result<()>;
+
+    async fn create_track_play(&self, new_play: NewTrackPlay) -> Result<()>;
+    async fn top_tracks(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TrackStatsData>>;
+    async fn top_submitters(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SubmitterStatsData>>;
 }
 
 #[derive(Debug)]
@@ -129,20 +161,56 @@ pub struct NewSession {
     pub expires_at: DateTime<Utc>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct NewRoom {
+    /// Must be a valid slug. When creating a room through the room manager, it is derived from the title if empty.
     pub slug: String,
     pub title: String,
     pub description: Option<String>,
+    pub visibility: RoomVisibility,
+    /// How many items the queue can contain at most, if limited
+    pub max_queue_size: Option<i32>,
+    /// How many seconds a track can play for at most, if limited
+    pub max_track_duration: Option<i32>,
+    /// How many milliseconds of silence are played between tracks
+    pub track_gap_in_ms: i32,
+    /// Sources that can be queued. Any source can be queued if empty.
+    pub allowed_sources: Vec<String>,
+    /// Sources that can't be queued, even if they're allowed
+    pub denied_sources: Vec<String>,
+    /// What happens to a member's queued items when they disconnect
+    pub disconnect_policy: DisconnectPolicy,
+    /// Whether the room keeps playing when nobody is listening
+    pub empty_room_policy: EmptyRoomPolicy,
+    /// How many milliseconds of latency streams have if they don't ask for any, if not the server's default
+    pub default_latency_in_ms: Option<i32>,
+    /// Whether silence at the start of tracks is skipped, up to a few seconds
+    pub trim_leading_silence: bool,
+    /// The input a jingle played between tracks is queried from, like a URL or file path, if any
+    pub jingle: Option<String>,
+    /// Whether tracks flagged as explicit or matching the blocklist are rejected when added
+    pub explicit_filter: bool,
     /// The owner of the new room
     pub user_id: PrimaryKey,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct UpdatedRoom {
     pub id: PrimaryKey,
     pub title: Option<String>,
     pub description: Option<String>,
+    pub visibility: Option<RoomVisibility>,
+    pub max_queue_size: Option<i32>,
+    pub max_track_duration: Option<i32>,
+    pub track_gap_in_ms: Option<i32>,
+    pub allowed_sources: Option<Vec<String>>,
+    pub denied_sources: Option<Vec<String>>,
+    pub disconnect_policy: Option<DisconnectPolicy>,
+    pub empty_room_policy: Option<EmptyRoomPolicy>,
+    pub default_latency_in_ms: Option<i32>,
+    pub trim_leading_silence: Option<bool>,
+    pub jingle: Option<String>,
+    pub explicit_filter: Option<bool>,
 }
 
 #[derive(Debug)]
@@ -158,6 +226,10 @@ pub struct NewRoomInvite {
     pub room_id: PrimaryKey,
     /// The inviter of the new room invite
     pub user_id: PrimaryKey,
+    /// When the invite stops working. Never if not set.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// How many times the invite can be used. Unlimited if not set.
+    pub max_uses: Option<i32>,
 }
 
 #[derive(Debug)]
@@ -166,4 +238,29 @@ pub struct NewStreamKey {
     pub room_id: PrimaryKey,
     pub user_id: PrimaryKey,
     pub source: String,
+    pub can_queue: bool,
+}
+
+#[derive(Debug)]
+pub struct NewRoomSchedule {
+    pub room_id: PrimaryKey,
+    /// The user that schedules the action
+    pub user_id: PrimaryKey,
+    pub action: ScheduledAction,
+    pub fire_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewTrackPlay {
+    pub canonical: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub source: String,
+    pub room_id: PrimaryKey,
+    /// The user that submitted the track
+    pub user_id: PrimaryKey,
+    /// How long the track was heard for, in seconds
+    pub time_played: f32,
+    /// If the track ended before enough of it was heard
+    pub skipped: bool,
 }
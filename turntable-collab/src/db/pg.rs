@@ -1,10 +1,14 @@
 use async_trait::async_trait;
-use sqlx::{postgres::PgPoolOptions, query, query_as, Error as SqlxError, PgPool};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use sqlx::{postgres::PgPoolOptions, query, query_as, Error as SqlxError, PgConnection, PgPool};
 
 use crate::{
-    Database, DatabaseError, DatabaseResult, IntoDatabaseError, NewRoom, NewRoomInvite,
-    NewRoomMember, NewSession, NewStreamKey, NewUser, PrimaryKey, Result, RoomData, RoomInviteData,
-    RoomMemberData, SessionData, StreamKeyData, UpdatedRoom, UpdatedUser, UserData,
+    Database, DatabaseError, DatabaseResult, DisconnectPolicy, EmptyRoomPolicy, IntoDatabaseError,
+    NewRoom, NewRoomInvite, NewRoomMember, NewRoomSchedule, NewSession, NewStreamKey, NewTrackPlay,
+    NewUser, PrimaryKey, Result, RoomData, RoomInviteData, RoomMemberData, RoomScheduleData,
+    RoomVisibility, ScheduledAction, SessionData, StreamKeyData, SubmitterStatsData,
+    TrackStatsData, UpdatedRoom, UpdatedUser, UserData, UserPreferenceData,
 };
 
 /// A postgres database implementation for turntable
@@ -23,6 +27,98 @@ impl PgDatabase {
         Ok(Self { pool })
     }
 
+    /// Creates a database from an existing connection pool
+    pub fn from_pool(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a database connecting to the same database as a test pool.
+    ///
+    /// A collab keeps running after a test ends, so connections are closed as soon
+    /// as they're released, instead of holding on to the test database.
+    pub fn from_test_pool(pool: &PgPool) -> Self {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .after_release(|_, _| Box::pin(async { Ok(false) }))
+            .connect_lazy_with((*pool.connect_options()).clone());
+
+        Self { pool }
+    }
+
+    /// Runs an operation in a transaction, which is committed if it succeeds and rolled back otherwise.
+    /// This way, an operation made of multiple statements doesn't leave anything half done.
+    pub async fn with_transaction<T, F>(&self, operation: F) -> Result<T>
+    where
+        T: Send,
+        F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.any())?;
+
+        // Dropping the transaction without committing it rolls it back
+        let value = operation(&mut transaction).await?;
+        transaction.commit().await.map_err(|e| e.any())?;
+
+        Ok(value)
+    }
+
+    async fn insert_room_member(
+        connection: &mut PgConnection,
+        new_member: NewRoomMember,
+    ) -> Result<RoomMemberData> {
+        // Ensure the user isn't a member of this room already
+        query!(
+            "SELECT id FROM room_members WHERE user_id = $1 AND room_id = $2",
+            new_member.user_id,
+            new_member.room_id
+        )
+        .fetch_one(&mut *connection)
+        .await
+        .map_err(|e| e.not_found_or("", ""))
+        .conflict_or_ok(
+            "room member",
+            "user:room",
+            format!("{}:{}", new_member.user_id, new_member.room_id).as_str(),
+        )?;
+
+        // Insert and read back the member with its user in one round trip
+        let row = query!(
+            "
+            WITH inserted AS (
+                INSERT INTO room_members (user_id, room_id, owner)
+                VALUES ($1, $2, $3)
+                RETURNING id, user_id, owner
+            )
+            SELECT
+                inserted.id,
+                inserted.user_id,
+                inserted.owner,
+                users.username,
+                users.password,
+                users.display_name,
+                users.superuser
+            FROM inserted
+                INNER JOIN users ON inserted.user_id = users.id",
+            new_member.user_id,
+            new_member.room_id,
+            new_member.owner,
+        )
+        .fetch_one(&mut *connection)
+        .await
+        .map_err(|e| e.any())?;
+
+        Ok(RoomMemberData {
+            id: row.id,
+            owner: row.owner,
+            user: UserData {
+                id: row.user_id,
+                username: row.username,
+                password: row.password,
+                display_name: row.display_name,
+                superuser: row.superuser,
+            },
+        })
+    }
+
     async fn room_members(&self, room_id: PrimaryKey) -> Result<Vec<RoomMemberData>> {
         let member_rows = query!(
             "
@@ -133,6 +229,45 @@ impl Database for PgDatabase {
             .map(|_| ())
     }
 
+    async fn list_user_preferences(&self, user_id: PrimaryKey) -> Result<Vec<UserPreferenceData>> {
+        query_as!(
+            UserPreferenceData,
+            "SELECT * FROM user_preferences WHERE user_id = $1 ORDER BY key",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.any())
+    }
+
+    async fn set_user_preference(&self, preference: UserPreferenceData) -> Result<()> {
+        query!(
+            "
+            INSERT INTO user_preferences (user_id, key, value)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, key) DO UPDATE SET value = EXCLUDED.value",
+            preference.user_id,
+            preference.key,
+            preference.value
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.any())
+        .map(|_| ())
+    }
+
+    async fn delete_user_preference(&self, user_id: PrimaryKey, key: &str) -> Result<()> {
+        query!(
+            "DELETE FROM user_preferences WHERE user_id = $1 AND key = $2 RETURNING key",
+            user_id,
+            key
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.not_found_or("user preference", "key"))
+        .map(|_| ())
+    }
+
     async fn session_by_token(&self, token: &str) -> Result<SessionData> {
         let row = query!(
             "SELECT
@@ -217,6 +352,18 @@ impl Database for PgDatabase {
             slug: room_row.slug,
             title: room_row.title,
             description: room_row.description,
+            visibility: RoomVisibility::from_db_value(&room_row.visibility),
+            max_queue_size: room_row.max_queue_size,
+            max_track_duration: room_row.max_track_duration,
+            track_gap_in_ms: room_row.track_gap_in_ms,
+            allowed_sources: room_row.allowed_sources,
+            denied_sources: room_row.denied_sources,
+            disconnect_policy: DisconnectPolicy::from_db_value(&room_row.disconnect_policy),
+            empty_room_policy: EmptyRoomPolicy::from_db_value(&room_row.empty_room_policy),
+            default_latency_in_ms: room_row.default_latency_in_ms,
+            trim_leading_silence: room_row.trim_leading_silence,
+            jingle: room_row.jingle,
+            explicit_filter: room_row.explicit_filter,
             members,
         })
     }
@@ -240,7 +387,19 @@ impl Database for PgDatabase {
                 users.superuser,
                 rooms.slug,
                 rooms.title,
-                rooms.description
+                rooms.description,
+                rooms.visibility,
+                rooms.max_queue_size,
+                rooms.max_track_duration,
+                rooms.track_gap_in_ms,
+                rooms.allowed_sources,
+                rooms.denied_sources,
+                rooms.disconnect_policy,
+                rooms.empty_room_policy,
+                rooms.default_latency_in_ms,
+                rooms.trim_leading_silence,
+                rooms.jingle,
+                rooms.explicit_filter
             FROM room_invites AS invites
                 INNER JOIN users ON invites.inviter_id = users.id
                 INNER JOIN rooms ON invites.room_id = rooms.id
@@ -262,6 +421,18 @@ impl Database for PgDatabase {
                 slug: row.slug,
                 title: row.title,
                 description: row.description,
+                visibility: RoomVisibility::from_db_value(&row.visibility),
+                max_queue_size: row.max_queue_size,
+                max_track_duration: row.max_track_duration,
+                track_gap_in_ms: row.track_gap_in_ms,
+                allowed_sources: row.allowed_sources,
+                denied_sources: row.denied_sources,
+                disconnect_policy: DisconnectPolicy::from_db_value(&row.disconnect_policy),
+                empty_room_policy: EmptyRoomPolicy::from_db_value(&row.empty_room_policy),
+                default_latency_in_ms: row.default_latency_in_ms,
+                trim_leading_silence: row.trim_leading_silence,
+                jingle: row.jingle,
+                explicit_filter: row.explicit_filter,
                 members,
             },
             inviter: UserData {
@@ -271,6 +442,9 @@ impl Database for PgDatabase {
                 display_name: row.display_name,
                 superuser: row.superuser,
             },
+            expires_at: row.expires_at,
+            max_uses: row.max_uses,
+            uses: row.uses,
         })
     }
 
@@ -285,6 +459,18 @@ impl Database for PgDatabase {
                 slug: row.slug,
                 title: row.title,
                 description: row.description,
+                visibility: RoomVisibility::from_db_value(&row.visibility),
+                max_queue_size: row.max_queue_size,
+                max_track_duration: row.max_track_duration,
+                track_gap_in_ms: row.track_gap_in_ms,
+                allowed_sources: row.allowed_sources,
+                denied_sources: row.denied_sources,
+                disconnect_policy: DisconnectPolicy::from_db_value(&row.disconnect_policy),
+                empty_room_policy: EmptyRoomPolicy::from_db_value(&row.empty_room_policy),
+                default_latency_in_ms: row.default_latency_in_ms,
+                trim_leading_silence: row.trim_leading_silence,
+                jingle: row.jingle,
+                explicit_filter: row.explicit_filter,
                 members: vec![],
             })
             .collect();
@@ -302,87 +488,58 @@ impl Database for PgDatabase {
             .conflict_or_ok("room", "slug", &new_room.slug)?;
 
         let user = self.user_by_id(new_room.user_id).await?;
-        let room = query!(
-            "
-            INSERT INTO rooms (slug, title, description)
-            VALUES ($1, $2, $3)
-            RETURNING id
-        ",
-            new_room.slug,
-            new_room.title,
-            new_room.description
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.any())?;
 
-        // Add owner as a member to the room
-        self.create_room_member(NewRoomMember {
-            user_id: user.id,
-            room_id: room.id,
-            owner: true,
-        })
-        .await?;
+        let room_id = self
+            .with_transaction(|connection| {
+                Box::pin(async move {
+                    let room = query!(
+                    "
+                    INSERT INTO rooms (slug, title, description, visibility, max_queue_size, max_track_duration, track_gap_in_ms, allowed_sources, denied_sources, disconnect_policy, empty_room_policy, default_latency_in_ms, trim_leading_silence, jingle, explicit_filter)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                    RETURNING id
+                ",
+                    new_room.slug,
+                    new_room.title,
+                    new_room.description,
+                    new_room.visibility.as_str(),
+                    new_room.max_queue_size,
+                    new_room.max_track_duration,
+                    new_room.track_gap_in_ms,
+                    &new_room.allowed_sources,
+                    &new_room.denied_sources,
+                    new_room.disconnect_policy.as_str(),
+                    new_room.empty_room_policy.as_str(),
+                    new_room.default_latency_in_ms,
+                    new_room.trim_leading_silence,
+                    new_room.jingle,
+                    new_room.explicit_filter
+                )
+                    .fetch_one(&mut *connection)
+                    .await
+                    .map_err(|e| e.any())?;
+
+                    // Add owner as a member to the room
+                    Self::insert_room_member(
+                        connection,
+                        NewRoomMember {
+                            user_id: user.id,
+                            room_id: room.id,
+                            owner: true,
+                        },
+                    )
+                    .await?;
+
+                    Ok(room.id)
+                })
+            })
+            .await?;
 
-        self.room_by_id(room.id).await
+        self.room_by_id(room_id).await
     }
 
     async fn create_room_member(&self, new_member: NewRoomMember) -> Result<RoomMemberData> {
-        // Ensure the user isn't a member of this room already
-        query!(
-            "SELECT id FROM room_members WHERE user_id = $1 AND room_id = $2",
-            new_member.user_id,
-            new_member.room_id
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.not_found_or("", ""))
-        .conflict_or_ok(
-            "room member",
-            "user:room",
-            format!("{}:{}", new_member.user_id, new_member.room_id).as_str(),
-        )?;
-
-        let row = query!(
-            "
-            INSERT INTO room_members (user_id, room_id, owner)
-            VALUES ($1, $2, $3) RETURNING id",
-            new_member.user_id,
-            new_member.room_id,
-            new_member.owner,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.any())?;
-
-        let row = query!(
-            "
-            SELECT
-                room_members.*,
-                users.username,
-                users.password,
-                users.display_name,
-                users.superuser
-            FROM room_members
-                INNER JOIN users ON room_members.user_id = users.id
-            WHERE room_members.id = $1",
-            row.id
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.any())?;
-
-        Ok(RoomMemberData {
-            id: row.id,
-            owner: row.owner,
-            user: UserData {
-                id: row.user_id,
-                username: row.username,
-                password: row.password,
-                display_name: row.display_name,
-                superuser: row.superuser,
-            },
-        })
+        let mut connection = self.pool.acquire().await.map_err(|e| e.any())?;
+        Self::insert_room_member(&mut connection, new_member).await
     }
 
     async fn update_room(&self, updated_room: UpdatedRoom) -> Result<RoomData> {
@@ -391,10 +548,44 @@ impl Database for PgDatabase {
         query!(
             "UPDATE rooms SET
                 title = $1,
-                description = $2
-            WHERE id = $3",
+                description = $2,
+                visibility = $3,
+                max_queue_size = $4,
+                max_track_duration = $5,
+                track_gap_in_ms = $6,
+                allowed_sources = $7,
+                denied_sources = $8,
+                disconnect_policy = $9,
+                empty_room_policy = $10,
+                default_latency_in_ms = $11,
+                trim_leading_silence = $12,
+                jingle = $13,
+                explicit_filter = $14
+            WHERE id = $15",
             updated_room.title.unwrap_or(room.title),
             updated_room.description.or(room.description),
+            updated_room.visibility.unwrap_or(room.visibility).as_str(),
+            updated_room.max_queue_size.or(room.max_queue_size),
+            updated_room.max_track_duration.or(room.max_track_duration),
+            updated_room.track_gap_in_ms.unwrap_or(room.track_gap_in_ms),
+            &updated_room.allowed_sources.unwrap_or(room.allowed_sources),
+            &updated_room.denied_sources.unwrap_or(room.denied_sources),
+            updated_room
+                .disconnect_policy
+                .unwrap_or(room.disconnect_policy)
+                .as_str(),
+            updated_room
+                .empty_room_policy
+                .unwrap_or(room.empty_room_policy)
+                .as_str(),
+            updated_room
+                .default_latency_in_ms
+                .or(room.default_latency_in_ms),
+            updated_room
+                .trim_leading_silence
+                .unwrap_or(room.trim_leading_silence),
+            updated_room.jingle.or(room.jingle),
+            updated_room.explicit_filter.unwrap_or(room.explicit_filter),
             updated_room.id
         )
         .execute(&self.pool)
@@ -438,15 +629,55 @@ impl Database for PgDatabase {
             .conflict_or_ok("room invite", "token", &new_room_invite.token)?;
 
         let invite = query!(
-            "INSERT INTO room_invites (token, room_id, inviter_id) VALUES ($1, $2, $3) RETURNING token",
+            "INSERT INTO room_invites (token, room_id, inviter_id, expires_at, max_uses) VALUES ($1, $2, $3, $4, $5) RETURNING token",
             new_room_invite.token,
             new_room_invite.room_id,
-            new_room_invite.user_id
+            new_room_invite.user_id,
+            new_room_invite.expires_at,
+            new_room_invite.max_uses
         ).fetch_one(&self.pool).await.map_err(|e| e.any())?;
 
         self.room_invite_by_token(&invite.token).await
     }
 
+    async fn create_room_member_with_invite(
+        &self,
+        invite_id: PrimaryKey,
+        new_member: NewRoomMember,
+    ) -> Result<Option<RoomMemberData>> {
+        self.with_transaction(|connection| {
+            Box::pin(async move {
+                // Only counts the use if the invite has uses left, even if it's used at the same time
+                let invite = query!(
+                    "
+                    UPDATE room_invites SET uses = uses + 1
+                    WHERE id = $1 AND (max_uses IS NULL OR uses < max_uses)
+                    RETURNING uses, max_uses",
+                    invite_id
+                )
+                .fetch_optional(&mut *connection)
+                .await
+                .map_err(|e| e.any())?;
+
+                let Some(invite) = invite else {
+                    return Ok(None);
+                };
+
+                let member = Self::insert_room_member(connection, new_member).await?;
+
+                if invite.max_uses.is_some_and(|max| invite.uses >= max) {
+                    query!("DELETE FROM room_invites WHERE id = $1", invite_id)
+                        .execute(&mut *connection)
+                        .await
+                        .map_err(|e| e.any())?;
+                }
+
+                Ok(Some(member))
+            })
+        })
+        .await
+    }
+
     async fn delete_room_invite(&self, invite_id: PrimaryKey) -> Result<()> {
         // Ensure invite exists
         query!("SELECT id FROM room_invites WHERE id = $1", invite_id)
@@ -494,11 +725,12 @@ impl Database for PgDatabase {
             .as_str(),
         )?;
 
-        query_as!(StreamKeyData, "INSERT INTO stream_keys (token, source, room_id, user_id) VALUES ($1, $2, $3, $4) RETURNING *",
+        query_as!(StreamKeyData, "INSERT INTO stream_keys (token, source, room_id, user_id, can_queue) VALUES ($1, $2, $3, $4, $5) RETURNING *",
             new_key.token,
             new_key.source,
             new_key.room_id,
-            new_key.user_id
+            new_key.user_id,
+            new_key.can_queue
         ).fetch_one(&self.pool).await.map_err(|e| e.any())
     }
 
@@ -531,6 +763,161 @@ impl Database for PgDatabase {
             .map_err(|e| e.any())
             .map(|_| ())
     }
+
+    async fn list_room_schedules(&self) -> Result<Vec<RoomScheduleData>> {
+        let rows = query!("SELECT * FROM room_schedules ORDER BY fire_at, id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.any())?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(RoomScheduleData {
+                    action: ScheduledAction::from_db_value(&row.action, row.urls)?,
+                    id: row.id,
+                    room_id: row.room_id,
+                    user_id: row.user_id,
+                    fire_at: row.fire_at,
+                })
+            })
+            .collect())
+    }
+
+    async fn create_room_schedule(
+        &self,
+        new_schedule: NewRoomSchedule,
+    ) -> Result<RoomScheduleData> {
+        let row = query!(
+            "
+            INSERT INTO room_schedules (room_id, user_id, action, urls, fire_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id",
+            new_schedule.room_id,
+            new_schedule.user_id,
+            new_schedule.action.as_str(),
+            new_schedule.action.urls(),
+            new_schedule.fire_at,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.any())?;
+
+        Ok(RoomScheduleData {
+            id: row.id,
+            room_id: new_schedule.room_id,
+            user_id: new_schedule.user_id,
+            action: new_schedule.action,
+            fire_at: new_schedule.fire_at,
+        })
+    }
+
+    async fn delete_room_schedule(&self, schedule_id: PrimaryKey) -> Result<()> {
+        // Deleting in one statement means only one caller can succeed, so it also claims the schedule
+        query!(
+            "DELETE FROM room_schedules WHERE id = $1 RETURNING id",
+            schedule_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.not_found_or("room schedule", "id"))
+        .map(|_| ())
+    }
+
+    async fn create_track_play(&self, new_play: NewTrackPlay) -> Result<()> {
+        query!(
+            "
+            INSERT INTO track_plays (canonical, title, artist, source, room_id, user_id, time_played, skipped)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            new_play.canonical,
+            new_play.title,
+            new_play.artist,
+            new_play.source,
+            new_play.room_id,
+            new_play.user_id,
+            new_play.time_played,
+            new_play.skipped
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.any())
+        .map(|_| ())
+    }
+
+    async fn top_tracks(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TrackStatsData>> {
+        let rows = query!(
+            r#"
+            SELECT
+                canonical,
+                MAX(title) AS "title!",
+                MAX(artist) AS artist,
+                MAX(source) AS "source!",
+                COUNT(*) AS "plays!"
+            FROM track_plays
+            WHERE skipped = false AND played_at >= $1
+            GROUP BY canonical
+            ORDER BY COUNT(*) DESC, canonical
+            LIMIT $2"#,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.any())?;
+
+        let stats = rows
+            .into_iter()
+            .map(|r| TrackStatsData {
+                canonical: r.canonical,
+                title: r.title,
+                artist: r.artist,
+                source: r.source,
+                plays: r.plays,
+            })
+            .collect();
+
+        Ok(stats)
+    }
+
+    async fn top_submitters(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SubmitterStatsData>> {
+        let rows = query!(
+            r#"
+            SELECT
+                users.*,
+                COUNT(*) AS "plays!"
+            FROM track_plays
+                INNER JOIN users ON track_plays.user_id = users.id
+            WHERE skipped = false AND played_at >= $1
+            GROUP BY users.id
+            ORDER BY COUNT(*) DESC, users.id
+            LIMIT $2"#,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.any())?;
+
+        let stats = rows
+            .into_iter()
+            .map(|r| SubmitterStatsData {
+                user: UserData {
+                    id: r.id,
+                    username: r.username,
+                    password: r.password,
+                    display_name: r.display_name,
+                    superuser: r.superuser,
+                },
+                plays: r.plays,
+            })
+            .collect();
+
+        Ok(stats)
+    }
 }
 
 impl IntoDatabaseError for SqlxError {
@@ -548,3 +935,67 @@ impl IntoDatabaseError for SqlxError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use sqlx::PgPool;
+
+    use crate::{Database, NewRoom, NewTrackPlay, NewUser, PgDatabase, RoomVisibility};
+
+    #[sqlx::test]
+    async fn test_track_plays(pool: PgPool) {
+        let database = PgDatabase::from_pool(pool);
+
+        let user = database
+            .create_user(NewUser {
+                username: "listener".to_string(),
+                password: "password".to_string(),
+                display_name: "Listener".to_string(),
+                superuser: false,
+            })
+            .await
+            .unwrap();
+
+        let room = database
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let new_play = |skipped| NewTrackPlay {
+            canonical: "https://example.com/track".to_string(),
+            title: "Track".to_string(),
+            artist: None,
+            source: "example".to_string(),
+            room_id: room.id,
+            user_id: user.id,
+            time_played: 120.,
+            skipped,
+        };
+
+        database.create_track_play(new_play(false)).await.unwrap();
+        database.create_track_play(new_play(false)).await.unwrap();
+        database.create_track_play(new_play(true)).await.unwrap();
+
+        let since = Utc::now() - Duration::days(1);
+
+        let tracks = database.top_tracks(since, 10).await.unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].canonical, "https://example.com/track");
+        assert_eq!(tracks[0].plays, 2);
+
+        let submitters = database.top_submitters(since, 10).await.unwrap();
+        assert_eq!(submitters.len(), 1);
+        assert_eq!(submitters[0].user.id, user.id);
+        assert_eq!(submitters[0].plays, 2);
+
+        let tracks = database.top_tracks(Utc::now(), 10).await.unwrap();
+        assert!(tracks.is_empty());
+    }
+}
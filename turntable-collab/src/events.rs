@@ -1,7 +1,12 @@
+use std::time::Duration;
+
 use crossbeam::channel::{Receiver, Sender};
-use turntable_core::{PipelineEvent, PlayerState};
+use turntable_core::{IngestionError, PipelineEvent, PlayerState, SinkId};
 
-use crate::{CollabContext, LinearQueueItem, PrimaryKey, RoomMemberData, TrackId};
+use crate::{
+    CollabContext, LinearQueueItem, PendingAddId, PrimaryKey, RoomData, RoomMemberData,
+    RoomVisibility, TrackId,
+};
 
 pub type EventSender = Sender<CollabEvent>;
 pub type EventReceiver = Receiver<CollabEvent>;
@@ -9,6 +14,18 @@ pub type EventReceiver = Receiver<CollabEvent>;
 /// Events emitted by
 #[derive(Debug)]
 pub enum CollabEvent {
+    /// A room was created
+    RoomCreated { room: RoomData },
+    /// The details of a room changed
+    RoomUpdated {
+        room: RoomData,
+        /// Who could see the room before it changed
+        previous_visibility: RoomVisibility,
+    },
+    /// A room was deleted, with its details from before it was
+    RoomDeleted { room: RoomData },
+    /// An inactive room was activated, so it has a player and queue again
+    RoomResumed { room: RoomData },
     /// A player's state has changed.
     PlayerStateUpdate {
         room_id: PrimaryKey,
@@ -22,6 +39,24 @@ pub enum CollabEvent {
         /// The total position of the player, in seconds.
         total_position: f32,
     },
+    /// A player's playback speed has changed.
+    PlayerSpeedUpdate { room_id: PrimaryKey, speed: f32 },
+    /// A player was rebuilt from its queue to recover it, so the current track started over.
+    PlayerReseated { room_id: PrimaryKey },
+    /// The levels of what a room played recently, only emitted while subscribed to.
+    /// Mono audio has the same levels on both sides.
+    Levels {
+        room_id: PrimaryKey,
+        peak_l: f32,
+        peak_r: f32,
+        rms_l: f32,
+        rms_r: f32,
+    },
+    /// Samples were cleared from memory. This is not specific to a room.
+    SinksCleared {
+        ids: Vec<SinkId>,
+        samples_freed: usize,
+    },
     /// A track as a queue item has been ingested
     TrackActivated {
         /// The id of the player the queue item's queue belongs to.
@@ -36,9 +71,26 @@ pub enum CollabEvent {
         /// The id of the new sink created for the queue item.
         track_id: TrackId,
         /// The error that happened while activating the queue item.
-        error: String,
+        error: IngestionError,
+    },
+    /// A source refused to resolve inputs because too many requests were made.
+    /// This is not specific to a room, and is emitted at most once per [crate::RATE_LIMIT_REPORT_INTERVAL] for each source.
+    SourceRateLimited {
+        /// The name of the source, like `youtube`
+        source: String,
+        /// How long the source asked to wait before trying again, if it said
+        retry_after: Option<Duration>,
     },
-    /// The currently playing track of a room updated
+    /// A track stopped playing, either because it finished or because it was skipped
+    TrackEnded {
+        room_id: PrimaryKey,
+        item: LinearQueueItem,
+        /// How long the track was heard for, in seconds
+        time_played: f32,
+        /// If enough of the track was heard for it to count as a play
+        counted_as_play: bool,
+    },
+    /// The item at the front of a room's queue changed, which is the one playing or about to play
     RoomQueueItemUpdate {
         room_id: PrimaryKey,
         new_item: Option<LinearQueueItem>,
@@ -49,6 +101,26 @@ pub enum CollabEvent {
         history: Vec<LinearQueueItem>,
         items: Vec<LinearQueueItem>,
     },
+    /// An entry of an add to a room's queue resolved
+    QueueAddProgress {
+        room_id: PrimaryKey,
+        add_id: PendingAddId,
+        user_id: PrimaryKey,
+        /// How many entries have resolved so far, including failed ones
+        resolved: usize,
+        /// How many entries failed to resolve or be added
+        failed: usize,
+        /// How many entries the add consists of
+        total: usize,
+        /// Why the entry that just resolved could not be added, if it couldn't
+        error: Option<String>,
+    },
+    /// An add to a room's queue finished, either because all entries resolved or it was cancelled
+    QueueAddFinished {
+        room_id: PrimaryKey,
+        add_id: PendingAddId,
+        cancelled: bool,
+    },
     /// User become a member of a room
     UserJoined {
         room_id: PrimaryKey,
@@ -74,6 +146,35 @@ pub enum CollabEvent {
 }
 
 impl CollabEvent {
+    /// Returns the id of the room an event happening in a room is for.
+    /// Events about the room itself, like it being created, are not included.
+    pub fn room_id(&self) -> Option<PrimaryKey> {
+        match self {
+            Self::PlayerStateUpdate { room_id, .. }
+            | Self::PlayerTimeUpdate { room_id, .. }
+            | Self::PlayerSpeedUpdate { room_id, .. }
+            | Self::PlayerReseated { room_id }
+            | Self::Levels { room_id, .. }
+            | Self::TrackActivated { room_id, .. }
+            | Self::TrackActivationError { room_id, .. }
+            | Self::TrackEnded { room_id, .. }
+            | Self::RoomQueueItemUpdate { room_id, .. }
+            | Self::RoomQueueUpdate { room_id, .. }
+            | Self::QueueAddProgress { room_id, .. }
+            | Self::QueueAddFinished { room_id, .. }
+            | Self::UserJoined { room_id, .. }
+            | Self::UserLeft { room_id, .. }
+            | Self::UserConnected { room_id, .. }
+            | Self::UserDisconnected { room_id, .. } => Some(*room_id),
+            Self::RoomCreated { .. }
+            | Self::RoomUpdated { .. }
+            | Self::RoomDeleted { .. }
+            | Self::RoomResumed { .. }
+            | Self::SinksCleared { .. }
+            | Self::SourceRateLimited { .. } => None,
+        }
+    }
+
     /// Convert a pipeline event to a friendly collab event
     pub fn from_pipeline_event(
         context: &CollabContext,
@@ -100,12 +201,58 @@ impl CollabEvent {
                     position,
                     total_position,
                 }),
-            PipelineEvent::PlayerAdvanced { player_id } => context
+            PipelineEvent::PlayerSpeedUpdate { player_id, speed } => context
                 .room_by_player_id(player_id)
-                .map(|room| Self::RoomQueueItemUpdate {
+                .map(|room| Self::PlayerSpeedUpdate {
                     room_id: room.id(),
-                    new_item: room.current_item(),
+                    speed,
                 }),
+            PipelineEvent::PlayerReseated { player_id } => context
+                .room_by_player_id(player_id)
+                .map(|room| Self::PlayerReseated { room_id: room.id() }),
+            PipelineEvent::PlayerLevels { player_id, levels } => {
+                let room = context.room_by_player_id(player_id)?;
+                let (peak_l, rms_l) = (levels.peak[0], levels.rms[0]);
+
+                Some(Self::Levels {
+                    room_id: room.id(),
+                    peak_l,
+                    peak_r: levels.peak.get(1).copied().unwrap_or(peak_l),
+                    rms_l,
+                    rms_r: levels.rms.get(1).copied().unwrap_or(rms_l),
+                })
+            }
+            PipelineEvent::SinksCleared { ids, samples_freed } => {
+                Some(Self::SinksCleared { ids, samples_freed })
+            }
+            PipelineEvent::QueueItemActivated {
+                player_id, item_id, ..
+            } => {
+                let room = context.room_by_player_id(player_id)?;
+                let track = room.find_track(|t| t.id.to_string() == item_id)?;
+
+                // Activating can fill in details that were missing, which is emitted as a queue update
+                room.refresh_metadata(track.id);
+
+                Some(Self::TrackActivated {
+                    room_id: room.id(),
+                    track_id: track.id,
+                })
+            }
+            PipelineEvent::QueueItemActivationError {
+                player_id,
+                item_id,
+                error,
+            } => {
+                let room = context.room_by_player_id(player_id)?;
+                let track = room.find_track(|t| t.id.to_string() == item_id)?;
+
+                Some(Self::TrackActivationError {
+                    room_id: room.id(),
+                    track_id: track.id,
+                    error,
+                })
+            }
             _ => None,
         }
     }
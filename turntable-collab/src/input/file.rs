@@ -21,6 +21,8 @@ pub struct FileInput {
 
 #[async_trait]
 impl Inputable for FileInput {
+    const SOURCE: &'static str = "file";
+
     fn test(query: &str) -> bool {
         REGEX.is_match(query)
     }
@@ -51,7 +53,7 @@ impl Inputable for FileInput {
 
     fn loadable(&self) -> BoxedLoadable {
         let file = self.file.lock().take().expect("file is taken");
-        LoadableFile::new(file).boxed()
+        LoadableFile::new(file).with_path(&self.path).boxed()
     }
 
     fn metadata(&self) -> Metadata {
@@ -59,9 +61,10 @@ impl Inputable for FileInput {
             title: self.path.clone(),
             artist: None,
             canonical: self.path.clone(),
-            source: "file".to_string(),
+            source: Self::SOURCE.to_string(),
             duration: 0.,
             artwork: None,
+            explicit: false,
         }
     }
 }
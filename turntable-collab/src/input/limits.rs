@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    io::SeekFrom,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::warn;
+use parking_lot::Mutex;
+use tokio::sync::Semaphore;
+use turntable_core::{
+    BoxedLoadable, CacheKey, Loadable, LoaderLength, QueueItem, ReadResult, SinkId,
+};
+
+use crate::{events::EventSender, CollabEvent, Input, InputError, Track};
+
+/// How often a source being rate limited is reported at most.
+/// Sources refuse every request for a while once they rate limit, which would otherwise flood the events.
+pub const RATE_LIMIT_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Fetches inputs and activates them within the limits of their sources,
+/// reporting to the collab it belongs to when a source rate limits.
+pub struct Inputs {
+    limits: SourceLimits,
+    rate_limits: RateLimitReporter,
+}
+
+/// Caps how many fetches and activations of each source run at once, by the source name of its inputs.
+/// A source that rate limits concurrent access can then be throttled without holding up the others.
+#[derive(Default)]
+pub struct SourceLimits {
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl SourceLimits {
+    pub fn new(limits: &HashMap<String, usize>) -> Self {
+        let me = Self::default();
+        me.set(limits);
+        me
+    }
+
+    /// Replaces the limits. Sources that aren't listed aren't limited.
+    /// What is already running keeps counting towards the old limit until it finishes.
+    pub fn set(&self, limits: &HashMap<String, usize>) {
+        *self.semaphores.lock() = limits
+            .iter()
+            // A limit of 0 would never let anything run
+            .map(|(source, max)| (source.clone(), Arc::new(Semaphore::new((*max).max(1)))))
+            .collect();
+    }
+
+    /// Runs the future once fewer than the limit of the source are running
+    pub async fn run<T>(&self, source: &str, fut: impl Future<Output = T>) -> T {
+        let semaphore = self.semaphores.lock().get(source).cloned();
+
+        let _permit = match &semaphore {
+            Some(semaphore) => Some(semaphore.acquire().await.expect("semaphore is not closed")),
+            None => None,
+        };
+
+        fut.await
+    }
+}
+
+impl Inputs {
+    /// Creates the inputs of a collab, with how many fetches and activations of each source can run at once
+    pub(crate) fn new(source_concurrency: &HashMap<String, usize>, sender: &EventSender) -> Self {
+        Self {
+            limits: SourceLimits::new(source_concurrency),
+            rate_limits: RateLimitReporter::new(RATE_LIMIT_REPORT_INTERVAL, sender.clone()),
+        }
+    }
+
+    /// Fetches the results of a query within the limit of its source, see [Input::query]
+    pub async fn query(&self, input: &str) -> Result<Vec<Input>, InputError> {
+        let source = Input::source_of(input).ok_or(InputError::NoMatch)?;
+        self.limited(source, Input::query(input)).await
+    }
+
+    /// Returns a queue item of the track, whose input is activated within the limit of its source
+    pub(crate) fn limited_item(self: &Arc<Self>, track: Track) -> LimitedItem {
+        LimitedItem {
+            inputs: self.clone(),
+            track,
+        }
+    }
+
+    /// Runs the future within the limit of the source, reporting it if it was rate limited
+    pub(crate) async fn limited<T>(
+        &self,
+        source: &str,
+        fut: impl Future<Output = Result<T, InputError>>,
+    ) -> Result<T, InputError> {
+        let result = self.limits.run(source, fut).await;
+
+        if let Err(error) = &result {
+            self.rate_limits.report(source, error);
+        }
+
+        result
+    }
+}
+
+/// Emits [CollabEvent::SourceRateLimited] when an input is rate limited, at most once per interval for each source.
+pub(crate) struct RateLimitReporter {
+    interval: Duration,
+    sender: EventSender,
+    last_reported: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimitReporter {
+    pub fn new(interval: Duration, sender: EventSender) -> Self {
+        Self {
+            interval,
+            sender,
+            last_reported: Default::default(),
+        }
+    }
+
+    /// Reports the error if it is a rate limit, returning true if an event was emitted
+    pub fn report(&self, source: &str, error: &InputError) -> bool {
+        let InputError::RateLimited { retry_after } = error else {
+            return false;
+        };
+
+        let now = Instant::now();
+        let mut last_reported = self.last_reported.lock();
+
+        if last_reported
+            .get(source)
+            .is_some_and(|last| now.duration_since(*last) < self.interval)
+        {
+            return false;
+        }
+
+        last_reported.insert(source.to_string(), now);
+        warn!(
+            "Source {} is rate limited, retry after {:?}",
+            source, retry_after
+        );
+
+        // Nobody is listening anymore if the collab was dropped
+        let _ = self.sender.send(CollabEvent::SourceRateLimited {
+            source: source.to_string(),
+            retry_after: *retry_after,
+        });
+
+        true
+    }
+}
+
+/// A track as the pipeline plays it, whose loadable is activated within the limit of its source
+pub(crate) struct LimitedItem {
+    inputs: Arc<Inputs>,
+    track: Track,
+}
+
+impl QueueItem for LimitedItem {
+    fn length(&self) -> Option<f32> {
+        self.track.length()
+    }
+
+    fn register_sink(&self, sink_id: SinkId) {
+        self.track.register_sink(sink_id)
+    }
+
+    fn sink_id(&self) -> Option<SinkId> {
+        self.track.sink_id()
+    }
+
+    fn item_id(&self) -> String {
+        self.track.item_id()
+    }
+
+    fn loadable(&self) -> BoxedLoadable {
+        LimitedLoadable {
+            inputs: self.inputs.clone(),
+            source: self.track.source(),
+            inner: self.track.loadable(),
+        }
+        .boxed()
+    }
+
+    fn start(&self) -> Option<f32> {
+        self.track.start()
+    }
+
+    fn end(&self) -> Option<f32> {
+        self.track.end()
+    }
+
+    fn gain(&self) -> Option<f32> {
+        self.track.gain()
+    }
+}
+
+/// Activates a loadable within the limit of its source.
+/// Only activating is limited, since that is where sources resolve streams.
+struct LimitedLoadable {
+    inputs: Arc<Inputs>,
+    source: &'static str,
+    inner: BoxedLoadable,
+}
+
+#[async_trait]
+impl Loadable for LimitedLoadable {
+    async fn activate(&self) -> Result<(), Box<dyn Error>> {
+        let result = self
+            .inputs
+            .limits
+            .run(self.source, self.inner.activate())
+            .await;
+
+        if let Some(error) = result.as_ref().err().and_then(|e| e.downcast_ref()) {
+            self.inputs.rate_limits.report(self.source, error);
+        }
+
+        result
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+        self.inner.read(buf).await
+    }
+
+    async fn length(&self) -> Option<LoaderLength> {
+        self.inner.length().await
+    }
+
+    async fn seekable(&self) -> bool {
+        self.inner.seekable().await
+    }
+
+    async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+        self.inner.seek(seek).await
+    }
+
+    async fn cache_key(&self) -> Option<CacheKey> {
+        self.inner.cache_key().await
+    }
+
+    fn description(&self) -> Option<String> {
+        self.inner.description()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crossbeam::channel::unbounded;
+    use futures_util::future::join_all;
+
+    use super::{RateLimitReporter, SourceLimits};
+    use crate::{Collab, CollabConfig, CollabEvent, InputError, MemoryDatabase};
+
+    /// Tracks how many are running at once, and the most that ever were
+    #[derive(Default)]
+    struct Concurrency {
+        running: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl Concurrency {
+        async fn run(&self) {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(running, Ordering::SeqCst);
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_limits() {
+        let limits = SourceLimits::new(&HashMap::from([("youtube".to_string(), 2)]));
+        let youtube = Arc::new(Concurrency::default());
+        let other = Arc::new(Concurrency::default());
+
+        let youtube_runs = (0..6).map(|_| limits.run("youtube", youtube.run()));
+        let other_runs = (0..6).map(|_| limits.run("wavedistrict", other.run()));
+
+        join_all(youtube_runs.chain(other_runs).collect::<Vec<_>>()).await;
+
+        assert_eq!(youtube.max.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            other.max.load(Ordering::SeqCst),
+            6,
+            "other sources are not throttled"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_reporting() {
+        let (sender, receiver) = unbounded();
+        let reporter = RateLimitReporter::new(Duration::from_secs(60), sender);
+
+        let rate_limited = InputError::RateLimited {
+            retry_after: Some(Duration::from_secs(10)),
+        };
+
+        assert!(!reporter.report("youtube", &InputError::NotFound));
+        assert!(reporter.report("youtube", &rate_limited));
+
+        match receiver.try_recv() {
+            Ok(CollabEvent::SourceRateLimited {
+                source,
+                retry_after,
+            }) => {
+                assert_eq!(source, "youtube");
+                assert_eq!(retry_after, Some(Duration::from_secs(10)));
+            }
+            event => panic!("expected a rate limit event, got {:?}", event),
+        }
+
+        assert!(
+            !reporter.report("youtube", &rate_limited),
+            "reports of the same source are throttled"
+        );
+        assert!(reporter.report("wavedistrict", &rate_limited));
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_per_collab() {
+        let config = CollabConfig {
+            source_concurrency: HashMap::from([("youtube".to_string(), 1)]),
+            ..Default::default()
+        };
+
+        let unlimited_config = CollabConfig {
+            source_concurrency: HashMap::new(),
+            ..Default::default()
+        };
+
+        let limited = Collab::with_database(config, MemoryDatabase::new()).await;
+        let unlimited = Collab::with_database(unlimited_config, MemoryDatabase::new()).await;
+
+        for (collab, expected) in [(&limited, 1), (&unlimited, 3)] {
+            let youtube = Arc::new(Concurrency::default());
+
+            let runs = (0..3).map(|_| {
+                collab.inputs.limited("youtube", async {
+                    youtube.run().await;
+                    Ok(())
+                })
+            });
+
+            join_all(runs.collect::<Vec<_>>()).await;
+            assert_eq!(youtube.max.load(Ordering::SeqCst), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_reach_their_collab() {
+        let collab = Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await;
+        let other = Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await;
+
+        let result = collab
+            .inputs
+            .limited("youtube", async {
+                Err::<(), _>(InputError::RateLimited { retry_after: None })
+            })
+            .await;
+        assert!(matches!(result, Err(InputError::RateLimited { .. })));
+
+        let reported = std::iter::from_fn(|| collab.try_wait_for_event().ok()).any(
+            |e| matches!(e, CollabEvent::SourceRateLimited { source, .. } if source == "youtube"),
+        );
+        assert!(reported);
+
+        let reported_elsewhere = std::iter::from_fn(|| other.try_wait_for_event().ok())
+            .any(|e| matches!(e, CollabEvent::SourceRateLimited { .. }));
+        assert!(!reported_elsewhere, "other collabs aren't told");
+    }
+}
@@ -1,13 +1,22 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
 use thiserror::Error;
 use turntable_core::BoxedLoadable;
+use url::Url;
 use wavedistrict::WaveDistrictTrackInput;
 use youtube::YouTubeVideoInput;
 
+use crate::util::URL_SCHEME_REGEX;
+
 mod file;
+mod limits;
 mod wavedistrict;
 mod youtube;
 
+pub use limits::{Inputs, SourceLimits, RATE_LIMIT_REPORT_INTERVAL};
+
 #[derive(Debug, Error)]
 pub enum InputError {
     #[error("Input did not match")]
@@ -31,12 +40,19 @@ pub enum InputError {
     #[error("Failed to parse resource: {0}")]
     ParseError(String),
 
+    /// The source refused the request because too many were made.
+    #[error("Rate limited by the source")]
+    RateLimited {
+        /// How long the source asked to wait before trying again, if it said
+        retry_after: Option<Duration>,
+    },
+
     #[error("{0}")]
     Other(String),
 }
 
 /// Represents metadata of the input
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Metadata {
     pub title: String,
     pub artist: Option<String>,
@@ -46,6 +62,9 @@ pub struct Metadata {
 
     pub duration: f32,
     pub artwork: Option<String>,
+    /// Whether the source flags the track as explicit, like an age restriction.
+    /// False if it isn't known yet, like for playlist entries, which rooms check again once activated.
+    pub explicit: bool,
 }
 
 /// Represents any resource that can be used as an input for turntable
@@ -56,8 +75,40 @@ pub enum Input {
     File(file::FileInput),
 }
 
+/// The relevance of a result that is exactly what the query points to
+pub const EXACT_MATCH: f32 = 1.;
+/// The relevance of a result that is part of what a URL points to, like an entry of a playlist
+pub const RESOURCE_MATCH: f32 = 0.5;
+/// The relevance of a result matching every word of a text query
+pub const TEXT_MATCH: f32 = 0.4;
+
 impl Input {
+    /// Fetches the results of a query, with the most relevant ones first.
+    /// This isn't limited by the source, see [Inputs::query] for that.
     pub async fn query(input: &str) -> Result<Vec<Self>, InputError> {
+        let results = Self::fetch(input).await?;
+        Ok(rank(results, |r| r.relevance(input)))
+    }
+
+    /// Returns true if the query points to a resource of a supported input, without fetching it.
+    pub fn is_supported(input: &str) -> bool {
+        Self::source_of(input).is_some()
+    }
+
+    /// Returns the name of the source a query points to, if it is supported
+    pub fn source_of(input: &str) -> Option<&'static str> {
+        if YouTubeVideoInput::test(input) {
+            Some(YouTubeVideoInput::SOURCE)
+        } else if WaveDistrictTrackInput::test(input) {
+            Some(WaveDistrictTrackInput::SOURCE)
+        } else if file::FileInput::test(input) {
+            Some(file::FileInput::SOURCE)
+        } else {
+            None
+        }
+    }
+
+    async fn fetch(input: &str) -> Result<Vec<Self>, InputError> {
         if YouTubeVideoInput::test(input) {
             let results = YouTubeVideoInput::fetch(input).await?;
             return Ok(results.into_iter().map(Input::YouTube).collect());
@@ -76,6 +127,8 @@ impl Input {
         Err(InputError::NoMatch)
     }
 
+    /// Returns the loadable of the input.
+    /// This isn't limited by the source, which the queue of a room takes care of.
     pub fn loadable(&self) -> BoxedLoadable {
         match self {
             Input::WaveDistrict(input) => input.loadable(),
@@ -84,6 +137,15 @@ impl Input {
         }
     }
 
+    /// Returns the name of the source of the input, like `youtube`
+    pub fn source(&self) -> &'static str {
+        match self {
+            Input::WaveDistrict(_) => WaveDistrictTrackInput::SOURCE,
+            Input::YouTube(_) => YouTubeVideoInput::SOURCE,
+            Input::File(_) => file::FileInput::SOURCE,
+        }
+    }
+
     pub fn length(&self) -> Option<f32> {
         match self {
             Input::WaveDistrict(input) => input.length(),
@@ -99,11 +161,102 @@ impl Input {
             Input::File(input) => input.metadata(),
         }
     }
+
+    pub fn relevance(&self, query: &str) -> f32 {
+        match self {
+            Input::WaveDistrict(input) => input.relevance(query),
+            Input::YouTube(input) => input.relevance(query),
+            Input::File(input) => input.relevance(query),
+        }
+    }
+}
+
+/// Creates the error of a request the source refused for being rate limited.
+/// How long to wait is only known if the response has a `Retry-After` in seconds.
+pub(crate) fn rate_limited(headers: &HeaderMap) -> InputError {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_secs);
+
+    InputError::RateLimited { retry_after }
+}
+
+/// Sorts items by their relevance, most relevant first.
+/// Items of equal relevance keep their order, so a playlist stays in order.
+pub fn rank<T>(items: Vec<T>, relevance: impl Fn(&T) -> f32) -> Vec<T> {
+    let mut scored: Vec<_> = items.into_iter().map(|i| (relevance(&i), i)).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Returns how relevant a result with the given metadata is to a query, from 0 to 1.
+///
+/// A result whose canonical URL is the query is an exact match. Any other result of a URL
+/// is part of what it points to, and results of text queries are scored by how many words match.
+pub fn metadata_relevance(query: &str, metadata: &Metadata) -> f32 {
+    if normalize_url(query) == normalize_url(&metadata.canonical) {
+        return EXACT_MATCH;
+    }
+
+    if is_url(query) {
+        return RESOURCE_MATCH;
+    }
+
+    let query_words = words(query);
+
+    if query_words.is_empty() {
+        return 0.;
+    }
+
+    let text = format!(
+        "{} {}",
+        metadata.title,
+        metadata.artist.as_deref().unwrap_or_default()
+    );
+    let text_words = words(&text);
+
+    let matching = query_words
+        .iter()
+        .filter(|w| text_words.contains(w))
+        .count();
+
+    TEXT_MATCH * matching as f32 / query_words.len() as f32
+}
+
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = URL_SCHEME_REGEX.replace(&url, "");
+    let url = url.strip_prefix("www.").unwrap_or(&url);
+
+    url.trim_end_matches('/').to_string()
+}
+
+fn is_url(query: &str) -> bool {
+    let query = URL_SCHEME_REGEX.replace(query.trim(), "https://");
+
+    query.starts_with("file://")
+        || Url::parse(&query)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.contains('.')))
+            .unwrap_or_default()
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
 }
 
 /// Represents a type that can be used as an input to turntable
 #[async_trait]
 pub trait Inputable {
+    /// The name of the source the inputs come from, like `youtube`
+    const SOURCE: &'static str;
+
     /// Returns true if the given query matches the pattern of this inputable
     fn test(query: &str) -> bool;
 
@@ -119,6 +272,77 @@ pub trait Inputable {
     /// "Activates" the resource, returning a loadable that can be used to play it.
     fn loadable(&self) -> BoxedLoadable;
 
-    /// Returns the metadata of the input
+    /// Returns the metadata of the input.
+    /// This can change once the loadable is activated, if the input was missing details until then.
     fn metadata(&self) -> Metadata;
+
+    /// Returns how relevant this result is to the query it was fetched with, from 0 to 1.
+    /// A result that is exactly what the query points to should return [EXACT_MATCH].
+    fn relevance(&self, query: &str) -> f32 {
+        metadata_relevance(query, &self.metadata())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{metadata_relevance, rank, Metadata, EXACT_MATCH, RESOURCE_MATCH, TEXT_MATCH};
+
+    fn metadata(title: &str, artist: &str, canonical: &str) -> Metadata {
+        Metadata {
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            canonical: canonical.to_string(),
+            source: "wavedistrict".to_string(),
+            duration: 0.,
+            artwork: None,
+            explicit: false,
+        }
+    }
+
+    #[test]
+    fn test_relevance() {
+        let track = metadata(
+            "Trance",
+            "Maple",
+            "https://wavedistrict.com/@maple/tracks/trance",
+        );
+
+        let url = "wavedistrict.com/@maple/tracks/trance/";
+        assert_eq!(metadata_relevance(url, &track), EXACT_MATCH);
+
+        let playlist = "https://wavedistrict.com/@maple/collections/all";
+        assert_eq!(metadata_relevance(playlist, &track), RESOURCE_MATCH);
+
+        assert_eq!(metadata_relevance("maple trance", &track), TEXT_MATCH);
+        assert_eq!(metadata_relevance("trance remix", &track), TEXT_MATCH / 2.);
+        assert_eq!(metadata_relevance("house", &track), 0.);
+    }
+
+    #[test]
+    fn test_direct_url_ranks_above_text_match() {
+        let query = "https://wavedistrict.com/@maple/tracks/trance";
+
+        let results = vec![
+            // Shares every word of the query, but isn't what it points to
+            metadata(
+                "https wavedistrict com maple tracks trance",
+                "Someone",
+                "https://wavedistrict.com/@someone/tracks/copy",
+            ),
+            metadata("Trance", "Maple", query),
+        ];
+
+        let ranked = rank(results, |m| metadata_relevance(query, m));
+        assert_eq!(ranked[0].canonical, query, "the direct match is first");
+
+        let text = metadata("Trance", "Maple", "https://example.com/trance");
+        let exact = metadata("Trance", "Maple", query);
+        assert!(metadata_relevance(query, &exact) > metadata_relevance("maple trance", &text));
+    }
+
+    #[test]
+    fn test_rank_keeps_order_of_equal_relevance() {
+        let ranked = rank(vec![3, 1, 2, 5, 4], |n| (n % 2) as f32);
+        assert_eq!(ranked, vec![3, 1, 5, 2, 4]);
+    }
 }
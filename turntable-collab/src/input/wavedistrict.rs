@@ -5,6 +5,7 @@ use turntable_core::{BoxedLoadable, Loadable};
 use turntable_impls::LoadableNetworkStream;
 use url::Url;
 
+use super::rate_limited;
 use crate::{util::URL_SCHEME_REGEX, InputError, Inputable, Metadata};
 
 const API_BASE: &str = "https://api.wavedistrict.com";
@@ -75,7 +76,7 @@ impl MediaRef<ImageSource> {
             .collect();
 
         // Sort to get the biggest image at the end
-        sources.sort_by(|a, b| a.width.cmp(&b.width));
+        sources.sort_by_key(|a| a.width);
 
         let best_source = sources.pop()?;
 
@@ -117,6 +118,8 @@ impl UrlExtraction {
 
 #[async_trait]
 impl Inputable for WaveDistrictTrackInput {
+    const SOURCE: &'static str = "wavedistrict";
+
     fn test(query: &str) -> bool {
         extract_from_url(query).is_some()
     }
@@ -147,11 +150,13 @@ impl Inputable for WaveDistrictTrackInput {
 
         Metadata {
             artist: Some(self.0.user.display_name.clone()),
-            source: "wavedistrict".to_string(),
+            source: Self::SOURCE.to_string(),
             title: self.0.title.clone(),
             duration: self.0.duration,
             canonical,
             artwork,
+            // WaveDistrict doesn't flag tracks as explicit
+            explicit: false,
         }
     }
 }
@@ -258,6 +263,10 @@ async fn handle_unsuccessful_request(response: Response, status: StatusCode) ->
         return InputError::NotFound;
     }
 
+    if status.as_u16() == 429 {
+        return rate_limited(response.headers());
+    }
+
     let result = response.text().await;
 
     match result {
@@ -2,8 +2,12 @@
 // There are many fields we wanna use here, but we're not using them yet. The warnings are annoying, so they're disabled for now.
 
 use async_trait::async_trait;
+use log::warn;
 use parking_lot::Mutex;
+use reqwest::{Client, StatusCode};
 use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::env;
 use std::io::SeekFrom;
 use std::process::Stdio;
@@ -16,13 +20,19 @@ use url::Url;
 
 use crate::{util::URL_SCHEME_REGEX, Metadata};
 
-use super::{InputError, Inputable};
+use super::{metadata_relevance, rate_limited, InputError, Inputable, EXACT_MATCH};
 
 const YT_UNAVAILABLE: &str = "Video unavailable. This video is not available";
 const YT_TOO_MANY_REQUESTS: &str = "Too Many Requests";
 const YT_NOT_FOUND: &str = "Video unavailable";
 const YT_ID_ERROR: &str = "Incomplete YouTube ID";
 
+/// The format selector passed to yt-dlp, unless overridden with `YOUTUBE_FORMAT`
+const DEFAULT_FORMAT: &str = "bestaudio[ext=m4a]/bestaudio/best";
+
+/// The age limit of videos that are age restricted, which counts as explicit
+const ADULT_AGE_LIMIT: u32 = 18;
+
 /// A YouTube video that can be played by turntable.
 #[derive(Clone)]
 pub struct YouTubeVideoInput {
@@ -31,6 +41,11 @@ pub struct YouTubeVideoInput {
     duration: f32,
     thumbnail: String,
     channel: String,
+    /// The minimum age to watch the video, where 18 means it's age restricted.
+    /// Playlist entries can be missing it, so it's only known once the video is resolved.
+    age_limit: Option<u32>,
+    /// Filled in once the video is fully resolved to be played, since playlist entries can be incomplete
+    resolved: Arc<Mutex<Option<ResolvedYouTubeVideo>>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,8 +55,14 @@ struct Format {
     format: String,
     container: Option<String>,
     quality: Option<f32>,
+    /// The average audio bitrate, in kbps
+    abr: Option<f32>,
     acodec: Option<String>,
+    vcodec: Option<String>,
     audio_ext: Option<String>,
+    /// Headers that have to be sent alongside the signed url
+    #[serde(default)]
+    http_headers: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,9 +72,10 @@ struct FlatYouTubeVideo {
     channel: String,
     thumbnails: Vec<Thumbnail>,
     duration: f32,
+    age_limit: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct Thumbnail {
     url: String,
     width: Option<u32>,
@@ -61,8 +83,21 @@ struct Thumbnail {
 
 #[derive(Debug, Deserialize)]
 struct PlayableYouTubeVideo {
-    format_id: String,
+    /// The format yt-dlp selected, which isn't always one of `formats`
+    format_id: Option<String>,
     formats: Vec<Format>,
+    #[serde(flatten)]
+    details: ResolvedYouTubeVideo,
+}
+
+/// The details of a fully resolved video, which take precedence over the ones of a playlist entry
+#[derive(Debug, Default, Clone, Deserialize)]
+struct ResolvedYouTubeVideo {
+    title: Option<String>,
+    channel: Option<String>,
+    duration: Option<f32>,
+    thumbnails: Option<Vec<Thumbnail>>,
+    age_limit: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -94,10 +129,13 @@ enum YouTubeResource {
 pub struct LoadableYouTubeVideo {
     id: String,
     stream: Mutex<Option<Arc<LoadableNetworkStream>>>,
+    resolved: Arc<Mutex<Option<ResolvedYouTubeVideo>>>,
 }
 
 #[async_trait]
 impl Inputable for YouTubeVideoInput {
+    const SOURCE: &'static str = "youtube";
+
     fn test(query: &str) -> bool {
         let query = URL_SCHEME_REGEX.replace(query, "https://");
         let url = Url::parse(&query);
@@ -168,29 +206,74 @@ impl Inputable for YouTubeVideoInput {
     }
 
     fn length(&self) -> Option<f32> {
-        Some(self.duration)
+        Some(self.metadata().duration)
     }
 
     fn loadable(&self) -> BoxedLoadable {
         LoadableYouTubeVideo {
             id: self.id.clone(),
             stream: Default::default(),
+            resolved: self.resolved.clone(),
         }
         .boxed()
     }
 
+    fn relevance(&self, query: &str) -> f32 {
+        // A link to a video in a playlist points to the video first
+        if video_id(query).is_some_and(|id| id == self.id) {
+            return EXACT_MATCH;
+        }
+
+        metadata_relevance(query, &self.metadata())
+    }
+
     fn metadata(&self) -> Metadata {
+        let resolved = self.resolved.lock().clone().unwrap_or_default();
+
+        // Playlist entries without a duration have it set to 0
+        let duration = resolved
+            .duration
+            .filter(|d| *d > 0.)
+            .unwrap_or(self.duration);
+        let thumbnail = resolved
+            .thumbnails
+            .filter(|t| !t.is_empty())
+            .map(determine_thumbnail)
+            .unwrap_or_else(|| self.thumbnail.clone());
+
         Metadata {
-            title: self.title.clone(),
-            artist: Some(self.channel.clone()),
-            duration: self.duration,
-            artwork: Some(self.thumbnail.clone()),
+            title: resolved.title.unwrap_or_else(|| self.title.clone()),
+            artist: Some(resolved.channel.unwrap_or_else(|| self.channel.clone())),
+            duration,
+            artwork: Some(thumbnail),
             canonical: format!("https://youtube.com/v/{}", self.id),
-            source: "youtube".to_string(),
+            source: Self::SOURCE.to_string(),
+            explicit: resolved
+                .age_limit
+                .or(self.age_limit)
+                .is_some_and(|a| a >= ADULT_AGE_LIMIT),
         }
     }
 }
 
+/// Returns the id of the video a URL links to, if any
+fn video_id(url: &str) -> Option<String> {
+    let url = URL_SCHEME_REGEX.replace(url, "https://");
+    let url = Url::parse(&url).ok()?;
+
+    if url.host_str() == Some("youtu.be") {
+        return url.path_segments()?.next().map(str::to_string);
+    }
+
+    if let Some(id) = url.path().strip_prefix("/v/") {
+        return Some(id.to_string());
+    }
+
+    url.query_pairs()
+        .find(|(k, _)| k == "v")
+        .map(|(_, v)| v.to_string())
+}
+
 impl YouTubeResource {
     /// Attempts to fetch a video or several videos from the given URL using yt-dlp.
     pub async fn fetch(url: &str) -> Result<Self, InputError> {
@@ -253,7 +336,152 @@ impl YouTubeResource {
 
 impl LoadableYouTubeVideo {
     async fn setup(&self) -> Result<(), Box<dyn Error>> {
-        let url = format!("https://youtube.com/watch?v={}", self.id);
+        let stream = resolve(&resolvers(), &self.id).await?;
+
+        *self.resolved.lock() = Some(stream.details);
+
+        let stream = LoadableNetworkStream::new(stream.url).with_headers(stream.headers);
+        *self.stream.lock() = Some(Arc::new(stream));
+        Ok(())
+    }
+
+    fn stream(&self) -> Arc<LoadableNetworkStream> {
+        self.stream
+            .lock()
+            .as_ref()
+            .expect("stream exists")
+            .to_owned()
+    }
+}
+
+#[async_trait]
+impl Loadable for LoadableYouTubeVideo {
+    async fn activate(&self) -> Result<(), Box<dyn Error>> {
+        self.setup().await?;
+        self.stream().activate().await?;
+
+        Ok(())
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+        self.stream().read(buf).await
+    }
+
+    async fn length(&self) -> Option<LoaderLength> {
+        self.stream().length().await
+    }
+
+    async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+        self.stream().seek(seek).await
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("YouTube video {}", self.id))
+    }
+}
+
+/// A way of getting a stream of a YouTube video, like yt-dlp.
+/// Videos are resolved with the first resolver that succeeds, see [resolve].
+#[async_trait]
+trait YouTubeResolver: Send + Sync {
+    /// The name shown when the resolver fails
+    fn name(&self) -> &str;
+
+    async fn resolve(&self, id: &str) -> Result<YouTubeStream, InputError>;
+}
+
+/// A stream of a YouTube video, and the details of the video
+struct YouTubeStream {
+    url: String,
+    /// Headers that have to be sent alongside the url
+    headers: HashMap<String, String>,
+    details: ResolvedYouTubeVideo,
+}
+
+/// Resolves videos with yt-dlp
+struct YtDlpResolver;
+
+/// Resolves videos with the API of a Piped instance, set with `YOUTUBE_PIPED_INSTANCE`.
+/// Piped extracts streams differently than yt-dlp, so it often works when yt-dlp is rate limited or broken.
+struct PipedResolver {
+    instance: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipedVideo {
+    title: String,
+    uploader: String,
+    duration: f32,
+    thumbnail_url: String,
+    audio_streams: Vec<PipedAudioStream>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipedAudioStream {
+    url: String,
+    /// The bitrate in bits per second
+    bitrate: u32,
+}
+
+/// Returns the resolvers to try in order, which are yt-dlp and any configured fallbacks
+fn resolvers() -> Vec<Box<dyn YouTubeResolver>> {
+    let mut resolvers: Vec<Box<dyn YouTubeResolver>> = vec![Box::new(YtDlpResolver)];
+
+    if let Ok(instance) = env::var("YOUTUBE_PIPED_INSTANCE") {
+        resolvers.push(Box::new(PipedResolver {
+            instance: instance.trim_end_matches('/').to_string(),
+        }));
+    }
+
+    resolvers
+}
+
+/// Resolves a video with the first resolver that succeeds.
+///
+/// The next resolver is only tried if the error may not happen with another one, like being rate limited.
+/// Errors that mean the video can't be played, like it being deleted, are returned right away.
+async fn resolve(
+    resolvers: &[Box<dyn YouTubeResolver>],
+    id: &str,
+) -> Result<YouTubeStream, InputError> {
+    let mut last_error = InputError::Other("No YouTube resolvers".to_string());
+
+    for resolver in resolvers {
+        match resolver.resolve(id).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) if is_transient(&err) => {
+                warn!(
+                    "Resolving YouTube video {} with {} failed: {}",
+                    id,
+                    resolver.name(),
+                    err
+                );
+                last_error = err;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Returns true if resolving might work with another resolver
+fn is_transient(error: &InputError) -> bool {
+    !matches!(
+        error,
+        InputError::NotFound | InputError::Unavailable | InputError::Invalid(_)
+    )
+}
+
+#[async_trait]
+impl YouTubeResolver for YtDlpResolver {
+    fn name(&self) -> &str {
+        "yt-dlp"
+    }
+
+    async fn resolve(&self, id: &str) -> Result<YouTubeStream, InputError> {
+        let url = format!("https://youtube.com/watch?v={}", id);
 
         let mut command = Command::new("yt-dlp");
 
@@ -262,16 +490,20 @@ impl LoadableYouTubeVideo {
             command.args(["--cookies", &cookie_path]);
         }
 
+        let format = env::var("YOUTUBE_FORMAT").unwrap_or(DEFAULT_FORMAT.to_string());
+
         command
             .arg("-f")
-            .arg("bestaudio[ext=mp3]/best")
+            .arg(format)
             .arg("-j")
             .arg("--")
             .arg(url)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
 
-        let mut child = command.spawn()?;
+        let mut child = command
+            .spawn()
+            .map_err(|e| InputError::Other(e.to_string()))?;
 
         let mut output = String::new();
         let mut error_output = String::new();
@@ -281,7 +513,8 @@ impl LoadableYouTubeVideo {
             .take()
             .unwrap()
             .read_to_string(&mut output)
-            .await?;
+            .await
+            .map_err(|e| InputError::Other(e.to_string()))?;
 
         child
             .stderr
@@ -291,57 +524,123 @@ impl LoadableYouTubeVideo {
             .await
             .ok();
 
-        let exit = child.wait().await?;
+        let exit = child
+            .wait()
+            .await
+            .map_err(|e| InputError::Other(e.to_string()))?;
 
         if !exit.success() {
-            return Err(Box::new(handle_error(error_output)));
+            return Err(handle_error(error_output));
         }
 
         let entry: PlayableYouTubeVideo =
             serde_json::from_str(&output).map_err(|e| InputError::ParseError(e.to_string()))?;
 
-        let stream_url = entry
-            .formats
-            .iter()
-            .find(|f| f.format_id == entry.format_id)
-            .map(|f| f.url.to_owned())
+        let details = entry.details.clone();
+        let format = entry
+            .select_format()
             .ok_or(InputError::Other("No supported format found".to_string()))?;
 
-        *self.stream.lock() = Some(Arc::new(LoadableNetworkStream::new(stream_url)));
-        Ok(())
-    }
-
-    fn stream(&self) -> Arc<LoadableNetworkStream> {
-        self.stream
-            .lock()
-            .as_ref()
-            .expect("stream exists")
-            .to_owned()
+        Ok(YouTubeStream {
+            url: format.url,
+            headers: format.http_headers,
+            details,
+        })
     }
 }
 
 #[async_trait]
-impl Loadable for LoadableYouTubeVideo {
-    async fn activate(&self) -> Result<(), Box<dyn Error>> {
-        self.setup().await?;
-        self.stream().activate().await?;
+impl YouTubeResolver for PipedResolver {
+    fn name(&self) -> &str {
+        "Piped"
+    }
 
-        Ok(())
+    async fn resolve(&self, id: &str) -> Result<YouTubeStream, InputError> {
+        let url = format!("{}/streams/{}", self.instance, id);
+
+        let response = Client::new()
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| InputError::FetchError(e.to_string()))?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => return Err(InputError::NotFound),
+            StatusCode::TOO_MANY_REQUESTS => return Err(rate_limited(response.headers())),
+            status if !status.is_success() => {
+                return Err(InputError::FetchError(status.to_string()))
+            }
+            _ => {}
+        }
+
+        let video: PipedVideo = response
+            .json()
+            .await
+            .map_err(|e| InputError::ParseError(e.to_string()))?;
+
+        let stream = video
+            .audio_streams
+            .into_iter()
+            .max_by_key(|s| s.bitrate)
+            .ok_or(InputError::Unavailable)?;
+
+        Ok(YouTubeStream {
+            url: stream.url,
+            headers: HashMap::new(),
+            details: ResolvedYouTubeVideo {
+                title: Some(video.title),
+                channel: Some(video.uploader),
+                duration: Some(video.duration),
+                thumbnails: Some(vec![Thumbnail {
+                    url: video.thumbnail_url,
+                    width: None,
+                }]),
+                age_limit: None,
+            },
+        })
     }
+}
 
-    async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
-        self.stream().read(buf).await
+impl PlayableYouTubeVideo {
+    /// Returns the format selected by yt-dlp if it has audio.
+    /// Otherwise, the best audio-only format is picked, or the best format with audio if there are none.
+    fn select_format(self) -> Option<Format> {
+        let (selected, others): (Vec<_>, Vec<_>) = self
+            .formats
+            .into_iter()
+            .filter(Format::has_audio)
+            .partition(|f| Some(&f.format_id) == self.format_id.as_ref());
+
+        if let Some(format) = selected.into_iter().next() {
+            return Some(format);
+        }
+
+        let (audio_only, with_video): (Vec<_>, Vec<_>) =
+            others.into_iter().partition(Format::is_audio_only);
+
+        best_format(audio_only).or_else(|| best_format(with_video))
     }
+}
 
-    async fn length(&self) -> Option<LoaderLength> {
-        self.stream().length().await
+impl Format {
+    fn has_audio(&self) -> bool {
+        self.acodec.as_deref().is_some_and(|c| c != "none")
     }
 
-    async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
-        self.stream().seek(seek).await
+    fn is_audio_only(&self) -> bool {
+        self.has_audio() && self.vcodec.as_deref().is_none_or(|c| c == "none")
     }
 }
 
+/// Returns the format with the highest audio bitrate, using yt-dlp's quality to break ties
+fn best_format(formats: Vec<Format>) -> Option<Format> {
+    let rank = |f: &Format| (f.abr.unwrap_or(0.), f.quality.unwrap_or(0.));
+
+    formats
+        .into_iter()
+        .max_by(|a, b| rank(a).partial_cmp(&rank(b)).unwrap_or(Ordering::Equal))
+}
+
 impl From<FlatYouTubeVideo> for YouTubeVideoInput {
     fn from(video: FlatYouTubeVideo) -> Self {
         YouTubeVideoInput {
@@ -350,6 +649,8 @@ impl From<FlatYouTubeVideo> for YouTubeVideoInput {
             duration: video.duration,
             channel: video.channel,
             thumbnail: determine_thumbnail(video.thumbnails),
+            age_limit: video.age_limit,
+            resolved: Default::default(),
         }
     }
 }
@@ -362,7 +663,7 @@ impl Debug for YouTubeVideoInput {
 
 fn determine_thumbnail(mut thumbnails: Vec<Thumbnail>) -> String {
     // Sort to get the largest at end
-    thumbnails.sort_by(|a, b| a.width.cmp(&b.width));
+    thumbnails.sort_by_key(|a| a.width);
 
     thumbnails
         .pop()
@@ -384,7 +685,8 @@ fn handle_error(error_output: String) -> InputError {
     }
 
     if error_output.contains(YT_TOO_MANY_REQUESTS) {
-        return InputError::FetchError("Too Many Requests".to_string());
+        // yt-dlp doesn't say how long to wait
+        return InputError::RateLimited { retry_after: None };
     }
 
     InputError::Other(error_output)
@@ -392,7 +694,13 @@ fn handle_error(error_output: String) -> InputError {
 
 #[cfg(test)]
 mod test {
+    use turntable_core::{PipelineEvent, QueueItem, SinkId};
+
     use super::*;
+    use crate::{
+        Collab, CollabConfig, CollabEvent, Input, MemoryDatabase, NewRoom, NewUser, Room,
+        RoomError, RoomVisibility, Submitter, Track,
+    };
 
     #[test]
     fn test_url_testing() {
@@ -418,4 +726,314 @@ mod test {
         assert!(!YouTubeVideoInput::test("https://www.youtube.com/@Ayrun"));
         assert!(!YouTubeVideoInput::test("youtube.com/"));
     }
+
+    #[test]
+    fn test_linked_video_is_exact_match() {
+        let video = |id: &str| YouTubeVideoInput {
+            id: id.to_string(),
+            title: "Title".to_string(),
+            duration: 0.,
+            thumbnail: String::new(),
+            channel: "Channel".to_string(),
+            age_limit: None,
+            resolved: Default::default(),
+        };
+
+        let query = "https://www.youtube.com/watch?v=JwRWf3ho4B8&list=PL23A657E4BD523733";
+        assert_eq!(video("JwRWf3ho4B8").relevance(query), EXACT_MATCH);
+        assert!(video("z09GolEktUw").relevance(query) < EXACT_MATCH);
+
+        assert_eq!(
+            video_id("youtu.be/z09GolEktUw").as_deref(),
+            Some("z09GolEktUw")
+        );
+        assert_eq!(
+            video_id("https://youtube.com/v/z09GolEktUw").as_deref(),
+            Some("z09GolEktUw")
+        );
+        assert_eq!(video_id("https://www.youtube.com/playlist?list=PL"), None);
+    }
+
+    fn fixture() -> PlayableYouTubeVideo {
+        serde_json::from_str(include_str!("../../tests/assets/youtube_video.json"))
+            .expect("fixture is parsed")
+    }
+
+    /// Creates a collab with a room in memory, limited to tracks of the given duration
+    async fn setup_room(
+        max_track_duration: Option<i32>,
+        explicit_filter: bool,
+    ) -> (Collab, Arc<Room>) {
+        let collab = Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await;
+        let owner = collab
+            .rooms
+            .context
+            .database
+            .create_user(NewUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+                superuser: false,
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "room".to_string(),
+                visibility: RoomVisibility::Public,
+                max_track_duration,
+                explicit_filter,
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        (collab, room)
+    }
+
+    /// A video that isn't on YouTube, so activating it fails
+    fn video(duration: f32) -> YouTubeVideoInput {
+        YouTubeVideoInput {
+            id: "not-a-video".to_string(),
+            title: "Example video".to_string(),
+            duration,
+            thumbnail: String::new(),
+            channel: "Example channel".to_string(),
+            age_limit: None,
+            resolved: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_track_duration() {
+        let (_collab, room) = setup_room(Some(60), false).await;
+
+        let long = Track::from(Input::YouTube(video(600.)));
+        let result = room.add_to_queue(vec![long.clone()], Submitter::System);
+        assert!(matches!(
+            result,
+            Err(RoomError::TrackTooLong {
+                length: 600.,
+                max: 60
+            })
+        ));
+
+        // Only the part that plays counts
+        let trimmed = long.with_trim(Some(10.), Some(40.));
+        assert_eq!(trimmed.played_length(), Some(30.));
+
+        let short = Track::from(Input::YouTube(video(30.)));
+        let added = room
+            .add_to_queue(vec![short, trimmed], Submitter::System)
+            .expect("tracks within the limit are queued");
+        assert_eq!(added, 2);
+
+        let (items, _) = room.queue().unwrap().tracks();
+        assert_eq!(items[0].track.end, None, "known lengths aren't cut off");
+
+        // Playlist entries can be missing their duration
+        let flat = Track::from(Input::YouTube(video(0.)));
+        assert_eq!(flat.played_length(), None);
+
+        room.add_to_queue(vec![flat], Submitter::System)
+            .expect("track with unknown length is queued");
+
+        let (items, _) = room.queue().unwrap().tracks();
+        assert_eq!(items[2].track.end, Some(60.), "unknown lengths are cut off");
+    }
+
+    #[tokio::test]
+    async fn test_resolved_details_update_queue() {
+        let (collab, room) = setup_room(None, false).await;
+
+        // A playlist entry that is missing its duration
+        let video = video(0.);
+        let resolved = video.resolved.clone();
+
+        let track = Track::from(Input::YouTube(video));
+        let track_id = track.id;
+        room.queue()
+            .unwrap()
+            .push(track, Submitter::System)
+            .unwrap();
+
+        // What resolving the video does when it is activated
+        *resolved.lock() = Some(fixture().details);
+
+        let event = CollabEvent::from_pipeline_event(
+            &collab.rooms.context,
+            PipelineEvent::QueueItemActivated {
+                player_id: room.player().unwrap().id,
+                new_sink_id: SinkId::new(),
+                item_id: track_id.to_string(),
+            },
+        );
+        assert!(matches!(event, Some(CollabEvent::TrackActivated { .. })));
+
+        let (items, _) = room.queue().unwrap().tracks();
+        assert_eq!(items[0].track.metadata.duration, 213.);
+        assert_eq!(items[0].track.length(), Some(213.));
+        assert!(items[0].track.updated_metadata().is_none());
+
+        let mut updated = vec![];
+
+        while let Ok(event) = collab.try_wait_for_event() {
+            match event {
+                CollabEvent::RoomQueueUpdate { items, .. } => {
+                    updated.push(items[0].track.metadata.duration)
+                }
+                CollabEvent::RoomQueueItemUpdate {
+                    new_item: Some(item),
+                    ..
+                } => updated.push(item.track.metadata.duration),
+                _ => continue,
+            }
+        }
+
+        assert_eq!(updated, vec![0., 0., 213., 213.], "the update is emitted");
+    }
+
+    #[tokio::test]
+    async fn test_explicit_filter_after_resolve() {
+        let (collab, room) = setup_room(None, true).await;
+        let queue = room.queue().unwrap();
+
+        // Playlist entries don't say whether they're age restricted
+        let videos = [video(30.), video(30.), video(30.)];
+        let resolved: Vec<_> = videos.iter().map(|v| v.resolved.clone()).collect();
+        let tracks: Vec<_> = videos
+            .into_iter()
+            .map(|v| Track::from(Input::YouTube(v)))
+            .collect();
+        let ids: Vec<_> = tracks.iter().map(|t| t.id).collect();
+
+        room.add_to_queue(tracks, Submitter::System)
+            .expect("entries with an unknown age limit are queued");
+
+        let activate = |index: usize, age_limit: u32| {
+            *resolved[index].lock() = Some(ResolvedYouTubeVideo {
+                age_limit: Some(age_limit),
+                ..Default::default()
+            });
+
+            CollabEvent::from_pipeline_event(
+                &collab.rooms.context,
+                PipelineEvent::QueueItemActivated {
+                    player_id: room.player().unwrap().id,
+                    new_sink_id: SinkId::new(),
+                    item_id: ids[index].to_string(),
+                },
+            );
+        };
+
+        activate(1, 0);
+        activate(2, ADULT_AGE_LIMIT);
+        activate(0, ADULT_AGE_LIMIT);
+
+        let (items, _) = queue.tracks();
+        let remaining: Vec<_> = items.iter().map(|i| i.track.id).collect();
+        assert_eq!(
+            remaining,
+            [ids[1]],
+            "age restricted tracks are removed once resolved, even the current one"
+        );
+    }
+
+    /// A resolver that fails with the given error, or succeeds if there is none
+    struct FakeResolver {
+        error: Option<fn() -> InputError>,
+    }
+
+    impl FakeResolver {
+        fn boxed(error: Option<fn() -> InputError>) -> Box<dyn YouTubeResolver> {
+            Box::new(Self { error })
+        }
+    }
+
+    #[async_trait]
+    impl YouTubeResolver for FakeResolver {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn resolve(&self, id: &str) -> Result<YouTubeStream, InputError> {
+            match self.error {
+                Some(error) => Err(error()),
+                None => Ok(YouTubeStream {
+                    url: format!("https://example.com/{}", id),
+                    headers: HashMap::new(),
+                    details: Default::default(),
+                }),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolver_fallback() {
+        let rate_limited = || InputError::RateLimited { retry_after: None };
+        let deleted = || InputError::NotFound;
+
+        let resolvers = [
+            FakeResolver::boxed(Some(rate_limited)),
+            FakeResolver::boxed(None),
+        ];
+        let stream = resolve(&resolvers, "id").await.expect("fallback resolves");
+        assert_eq!(stream.url, "https://example.com/id");
+
+        let resolvers = [
+            FakeResolver::boxed(Some(deleted)),
+            FakeResolver::boxed(None),
+        ];
+        assert!(
+            matches!(resolve(&resolvers, "id").await, Err(InputError::NotFound)),
+            "permanent errors don't fall back"
+        );
+
+        let resolvers = [
+            FakeResolver::boxed(Some(rate_limited)),
+            FakeResolver::boxed(Some(rate_limited)),
+        ];
+        assert!(matches!(
+            resolve(&resolvers, "id").await,
+            Err(InputError::RateLimited { .. })
+        ));
+
+        assert!(is_transient(&handle_error(format!(
+            "ERROR: HTTP Error 429: {}",
+            YT_TOO_MANY_REQUESTS
+        ))));
+        assert!(!is_transient(&handle_error(YT_UNAVAILABLE.to_string())));
+    }
+
+    #[test]
+    fn test_format_selection() {
+        let mut video = fixture();
+        video.format_id = Some("140".to_string());
+
+        let format = video.select_format().expect("format is selected");
+        assert_eq!(format.format_id, "140");
+        assert_eq!(format.http_headers["User-Agent"], "Mozilla/5.0");
+
+        // The selected format isn't in the list, so the best audio-only one is picked
+        let format = fixture().select_format().expect("format is selected");
+        assert_eq!(format.format_id, "251");
+
+        // A selected format without audio is never used
+        let mut video = fixture();
+        video.format_id = Some("160".to_string());
+        assert_eq!(video.select_format().unwrap().format_id, "251");
+
+        // Without audio-only formats, a format with video is used
+        let mut video = fixture();
+        video.formats.retain(|f| !f.is_audio_only());
+        assert_eq!(video.select_format().unwrap().format_id, "18");
+
+        let mut video = fixture();
+        video.formats.retain(|f| !f.has_audio());
+        assert!(video.select_format().is_none());
+    }
 }
@@ -1,39 +1,60 @@
 mod auth;
+mod config;
 mod db;
 mod events;
 mod input;
+mod playlist;
+mod preferences;
 mod queues;
 mod rooms;
+mod schedule;
+mod stats;
 mod track;
 mod util;
 
 use auth::Auth;
-use crossbeam::channel::unbounded;
+use crossbeam::channel::{unbounded, RecvError, RecvTimeoutError, TryRecvError};
 use events::{EventReceiver, EventSender};
 use log::info;
+use parking_lot::Mutex;
 use rooms::{RoomId, RoomManager};
-use std::{sync::Arc, thread};
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
 
 pub use auth::{AuthError, Credentials, NewPlainUser};
+pub use config::*;
 pub use db::*;
 pub use events::CollabEvent;
 pub use input::*;
+pub use playlist::*;
+pub use preferences::*;
 pub use queues::*;
-pub use rooms::{Room, RoomConnection, RoomConnectionHandle, RoomError, RoomState};
+pub use rooms::{
+    normalize_slug, slug_from_title, AddEntry, PendingAdd, PendingAddId, RecordedFile, Recording,
+    RecordingId, RecordingMetadata, RecordingOptions, Room, RoomConnection, RoomConnectionHandle,
+    RoomError, RoomState, SlugError, TrackMarker,
+};
+pub use schedule::Scheduler;
+pub use stats::Stats;
 pub use track::*;
 
-use turntable_core::{ArcedStore, Config, Pipeline, PlayerId};
+use turntable_core::{ArcedStore, Pipeline, PlayerId};
 use turntable_impls::SymphoniaIngestion;
 
 pub type CollabPipeline = Pipeline<SymphoniaIngestion>;
-pub type CollabDatabase = PgDatabase;
+pub type CollabDatabase = dyn Database;
 
 /// The turntable collab system, facilitating room management, authentication, and more.
 pub struct Collab {
     event_receiver: EventReceiver,
 
+    pub config: CollabConfig,
+    pub pipeline: Arc<CollabPipeline>,
+    pub inputs: Arc<Inputs>,
     pub auth: Auth<CollabDatabase>,
     pub rooms: RoomManager,
+    pub schedules: Scheduler,
+    pub stats: Stats,
+    pub preferences: Preferences,
 }
 
 /// A type passed to various components of the collab system, to access state, emit events, and dispatch actions.
@@ -41,36 +62,58 @@ pub struct Collab {
 pub struct CollabContext {
     event_sender: EventSender,
 
+    pub config: CollabConfig,
     pub pipeline: Arc<CollabPipeline>,
+    pub inputs: Arc<Inputs>,
     pub database: Arc<CollabDatabase>,
     pub rooms: ArcedStore<RoomId, Room>,
+    /// How many stream connections each user has, across all rooms
+    pub(crate) user_connections: Arc<Mutex<HashMap<PrimaryKey, usize>>>,
 }
 
 impl Collab {
-    pub async fn new(config: Config, database_url: &str) -> Self {
+    pub async fn new(config: CollabConfig, database_url: &str) -> Self {
         info!("Connecting to database...");
 
-        let database = Arc::new(
-            CollabDatabase::new(database_url)
-                .await
-                .expect("database is created"),
-        );
+        let database = PgDatabase::new(database_url)
+            .await
+            .expect("database is created");
+
+        Self::with_database(config, database).await
+    }
 
-        let pipeline = Arc::new(CollabPipeline::new(config));
+    /// Creates the collab with an already connected database
+    pub async fn with_database(config: CollabConfig, database: impl Database + 'static) -> Self {
+        let database: Arc<CollabDatabase> = Arc::new(database);
+
+        let pipeline = Arc::new(CollabPipeline::new(config.pipeline.clone()));
         let (event_sender, event_receiver) = unbounded();
+        let inputs = Arc::new(Inputs::new(&config.source_concurrency, &event_sender));
 
         let context = CollabContext {
+            config: config.clone(),
             database: database.clone(),
             pipeline: pipeline.clone(),
+            inputs: inputs.clone(),
             event_sender: event_sender.clone(),
             rooms: Default::default(),
+            user_connections: Default::default(),
         };
 
         let room_manager = RoomManager::new(&context);
         let auth = Auth::new(&database);
+        let stats = Stats::new(&context);
+        let schedules = Scheduler::new(&context);
+        let preferences = Preferences::new(&context);
 
         let new = Self {
+            config,
+            pipeline,
+            inputs,
             auth,
+            stats,
+            schedules,
+            preferences,
             event_receiver,
             rooms: room_manager,
         };
@@ -86,19 +129,36 @@ impl Collab {
     /// Must be called after creation
     async fn init(&self) {
         self.rooms.restore().await.expect("rooms are restored");
+        self.schedules
+            .restore()
+            .await
+            .expect("schedules are restored");
     }
 
-    /// Receive events from the collab.
-    pub fn wait_for_event(&self) -> CollabEvent {
-        self.event_receiver
-            .recv()
-            .expect("event is received without error")
+    /// Receive events from the collab, blocking until one is available.
+    /// Returns an error if the collab has shut down.
+    pub fn wait_for_event(&self) -> std::result::Result<CollabEvent, RecvError> {
+        self.event_receiver.recv()
+    }
+
+    /// Receive an event from the collab if one is available, without blocking.
+    pub fn try_wait_for_event(&self) -> std::result::Result<CollabEvent, TryRecvError> {
+        self.event_receiver.try_recv()
+    }
+
+    /// Receive events from the collab, blocking until one is available or the timeout passes.
+    pub fn wait_for_event_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<CollabEvent, RecvTimeoutError> {
+        self.event_receiver.recv_timeout(timeout)
     }
 }
 
 impl CollabContext {
     pub fn emit(&self, event: CollabEvent) {
-        self.event_sender.send(event).expect("event is sent");
+        // Nobody is listening anymore if the collab was dropped
+        let _ = self.event_sender.send(event);
     }
 
     /// Gets a room by its player id if it exists and is active
@@ -114,12 +174,18 @@ fn spawn_pipeline_event_conversion_thread(context: &CollabContext, sender: &Even
     let context = context.to_owned();
     let sender = sender.to_owned();
 
-    let run = move || loop {
-        let event = context.pipeline.wait_for_event();
+    let run = move || {
+        while let Ok(event) = context.pipeline.wait_for_event() {
+            let Some(converted_event) = CollabEvent::from_pipeline_event(&context, event) else {
+                continue;
+            };
 
-        if let Some(converted_event) = CollabEvent::from_pipeline_event(&context, event) {
-            sender.send(converted_event).expect("event is sent")
+            if sender.send(converted_event).is_err() {
+                break;
+            }
         }
+
+        info!("Pipeline events stopped, exiting event conversion");
     };
 
     thread::Builder::new()
@@ -0,0 +1,146 @@
+use url::Url;
+
+use crate::Metadata;
+
+/// A portable list of tracks, identified by their canonical URLs.
+/// It can be written as an M3U playlist, and read back to add the same tracks again.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+    /// The titles of the tracks that were left out, since they have no URL that works elsewhere
+    pub omitted: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub title: String,
+    pub artist: Option<String>,
+    /// The length of the track in seconds, if known
+    pub duration: Option<f32>,
+}
+
+impl Playlist {
+    pub const CONTENT_TYPE: &'static str = "audio/x-mpegurl";
+
+    /// Creates a playlist of the tracks with the given metadata, in order.
+    /// Tracks without a shareable URL, like local files, are omitted.
+    pub fn from_metadata(metadata: impl IntoIterator<Item = Metadata>) -> Self {
+        let mut playlist = Self::default();
+
+        for metadata in metadata {
+            if !is_shareable(&metadata.canonical) {
+                playlist.omitted.push(metadata.title);
+                continue;
+            }
+
+            playlist.entries.push(PlaylistEntry {
+                url: metadata.canonical,
+                title: metadata.title,
+                artist: metadata.artist,
+                duration: Some(metadata.duration).filter(|d| *d > 0.),
+            });
+        }
+
+        playlist
+    }
+
+    /// Writes the playlist in the extended M3U format.
+    /// Omitted tracks are noted in comments, so it's clear the playlist is incomplete.
+    pub fn to_m3u(&self) -> String {
+        let mut m3u = "#EXTM3U\n".to_string();
+
+        for title in &self.omitted {
+            m3u.push_str(&format!(
+                "# Omitted \"{}\", since it has no shareable URL\n",
+                single_line(title)
+            ));
+        }
+
+        for entry in &self.entries {
+            let duration = entry.duration.map_or(-1, |d| d.round() as i64);
+            let name = match &entry.artist {
+                Some(artist) => format!("{} - {}", artist, entry.title),
+                None => entry.title.clone(),
+            };
+
+            m3u.push_str(&format!("#EXTINF:{},{}\n", duration, single_line(&name)));
+            m3u.push_str(&entry.url);
+            m3u.push('\n');
+        }
+
+        m3u
+    }
+
+    /// Reads the URLs of an M3U playlist, or any list of URLs with one on each line.
+    /// These can be added to a queue like any other query.
+    pub fn parse_m3u(m3u: &str) -> Vec<String> {
+        m3u.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Returns true if the URL can be played by others, which excludes local files
+fn is_shareable(canonical: &str) -> bool {
+    Url::parse(canonical).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+/// Titles are written on a single line in M3U, so line breaks would split an entry
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Input, Metadata};
+
+    use super::Playlist;
+
+    fn metadata(title: &str, canonical: &str, source: &str) -> Metadata {
+        Metadata {
+            title: title.to_string(),
+            artist: Some("Artist".to_string()),
+            canonical: canonical.to_string(),
+            source: source.to_string(),
+            duration: 120.,
+            artwork: None,
+            explicit: false,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let playlist = Playlist::from_metadata([
+            metadata("Video", "https://youtube.com/v/dQw4w9WgXcQ", "youtube"),
+            metadata("Local", "/home/user/music/song.mp3", "file"),
+            metadata(
+                "Track",
+                "https://wavedistrict.com/@artist/tracks/track",
+                "wavedistrict",
+            ),
+        ]);
+
+        assert_eq!(playlist.omitted, ["Local"], "files are omitted");
+
+        let m3u = playlist.to_m3u();
+        assert!(m3u.starts_with("#EXTM3U\n"));
+        assert!(m3u.contains("#EXTINF:120,Artist - Video\n"));
+        assert!(m3u.contains("# Omitted \"Local\""));
+
+        let urls = Playlist::parse_m3u(&m3u);
+        assert_eq!(
+            urls,
+            [
+                "https://youtube.com/v/dQw4w9WgXcQ",
+                "https://wavedistrict.com/@artist/tracks/track"
+            ]
+        );
+        assert!(
+            urls.iter().all(|u| Input::is_supported(u)),
+            "urls can be added again"
+        );
+    }
+}
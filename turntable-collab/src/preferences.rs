@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+use tokio::sync::Mutex;
+use turntable_impls::EncoderKind;
+
+use crate::{CollabContext, DatabaseError, PrimaryKey, UserPreferenceData};
+
+/// The content type of the format a user prefers to listen in
+pub const ENCODER_PREFERENCE: &str = "encoder";
+/// The volume a user listens at, from 0 to 1
+pub const VOLUME_PREFERENCE: &str = "volume";
+
+/// How many preferences a user can have at most
+pub const MAX_PREFERENCES: usize = 32;
+/// How long a key can be at most
+const MAX_KEY_LENGTH: usize = 64;
+/// How long a value can be at most, in bytes
+const MAX_VALUE_LENGTH: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum PreferenceError {
+    /// Keys are short and made of lowercase letters, digits, and `.`, `-` or `_`
+    #[error("Invalid preference key: {0}")]
+    InvalidKey(String),
+    #[error("Invalid value for preference {key}: {reason}")]
+    InvalidValue { key: String, reason: String },
+    #[error("User has too many preferences, at most {max} can be set")]
+    TooMany { max: usize },
+    #[error(transparent)]
+    Database(DatabaseError),
+}
+
+/// Stores preferences of users, like the format they listen in, so clients don't have to send them again.
+///
+/// Known preferences are validated, and any other key can be used for preferences only clients know about.
+pub struct Preferences {
+    context: CollabContext,
+    /// Held while a preference is set, so concurrent sets can't exceed [MAX_PREFERENCES]
+    set_lock: Mutex<()>,
+}
+
+impl Preferences {
+    pub fn new(context: &CollabContext) -> Self {
+        Self {
+            context: context.clone(),
+            set_lock: Default::default(),
+        }
+    }
+
+    /// Returns the preferences of a user by key
+    pub async fn get(&self, user_id: PrimaryKey) -> Result<HashMap<String, String>, DatabaseError> {
+        let preferences = self.context.database.list_user_preferences(user_id).await?;
+
+        Ok(preferences.into_iter().map(|p| (p.key, p.value)).collect())
+    }
+
+    /// Sets a preference of a user, replacing its value if it was set already
+    pub async fn set(
+        &self,
+        user_id: PrimaryKey,
+        key: &str,
+        value: &str,
+    ) -> Result<(), PreferenceError> {
+        validate(key, value)?;
+
+        let _guard = self.set_lock.lock().await;
+        let existing = self.get(user_id).await.map_err(PreferenceError::Database)?;
+
+        if !existing.contains_key(key) && existing.len() >= MAX_PREFERENCES {
+            return Err(PreferenceError::TooMany {
+                max: MAX_PREFERENCES,
+            });
+        }
+
+        self.context
+            .database
+            .set_user_preference(UserPreferenceData {
+                user_id,
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .await
+            .map_err(PreferenceError::Database)
+    }
+
+    /// Removes a preference of a user
+    pub async fn remove(&self, user_id: PrimaryKey, key: &str) -> Result<(), DatabaseError> {
+        self.context
+            .database
+            .delete_user_preference(user_id, key)
+            .await
+    }
+}
+
+fn validate(key: &str, value: &str) -> Result<(), PreferenceError> {
+    let is_valid_key = !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_'));
+
+    if !is_valid_key {
+        return Err(PreferenceError::InvalidKey(key.to_string()));
+    }
+
+    let invalid = |reason: &str| PreferenceError::InvalidValue {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    };
+
+    if value.len() > MAX_VALUE_LENGTH {
+        return Err(invalid("value is too long"));
+    }
+
+    match key {
+        ENCODER_PREFERENCE if EncoderKind::from_mime(value).is_none() => {
+            Err(invalid("not a supported format"))
+        }
+        VOLUME_PREFERENCE if !value.parse::<f32>().is_ok_and(|v| (0. ..=1.).contains(&v)) => {
+            Err(invalid("must be a number from 0 to 1"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::future::join_all;
+    use sqlx::PgPool;
+
+    use crate::{Collab, CollabConfig, MemoryDatabase, NewUser, PgDatabase};
+
+    use super::{PreferenceError, MAX_PREFERENCES};
+
+    #[tokio::test]
+    async fn test_preferences() {
+        let collab = Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await;
+        let user = collab
+            .rooms
+            .context
+            .database
+            .create_user(NewUser {
+                username: "listener".to_string(),
+                password: "password".to_string(),
+                display_name: "listener".to_string(),
+                superuser: false,
+            })
+            .await
+            .expect("user is created");
+
+        let preferences = &collab.preferences;
+
+        preferences
+            .set(user.id, "encoder", "audio/pcm")
+            .await
+            .unwrap();
+        preferences.set(user.id, "volume", "0.5").await.unwrap();
+        preferences.set(user.id, "theme", "dark").await.unwrap();
+        preferences.set(user.id, "theme", "light").await.unwrap();
+
+        let stored = preferences.get(user.id).await.unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored["encoder"], "audio/pcm");
+        assert_eq!(stored["theme"], "light", "setting again replaces");
+
+        assert!(matches!(
+            preferences.set(user.id, "encoder", "audio/ogg").await,
+            Err(PreferenceError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            preferences.set(user.id, "volume", "11").await,
+            Err(PreferenceError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            preferences.set(user.id, "Not A Key", "value").await,
+            Err(PreferenceError::InvalidKey(_))
+        ));
+
+        for i in stored.len()..MAX_PREFERENCES {
+            let key = format!("custom.{}", i);
+            preferences.set(user.id, &key, "value").await.unwrap();
+        }
+
+        assert!(matches!(
+            preferences.set(user.id, "one-more", "value").await,
+            Err(PreferenceError::TooMany { .. })
+        ));
+        assert!(
+            preferences.set(user.id, "theme", "dark").await.is_ok(),
+            "existing preferences can still be changed"
+        );
+
+        preferences.remove(user.id, "theme").await.unwrap();
+        assert!(!preferences
+            .get(user.id)
+            .await
+            .unwrap()
+            .contains_key("theme"));
+    }
+
+    #[sqlx::test]
+    async fn test_concurrent_sets_are_limited(pool: PgPool) {
+        let collab =
+            Collab::with_database(CollabConfig::default(), PgDatabase::from_test_pool(&pool)).await;
+        let user = collab
+            .rooms
+            .context
+            .database
+            .create_user(NewUser {
+                username: "listener".to_string(),
+                password: "password".to_string(),
+                display_name: "listener".to_string(),
+                superuser: false,
+            })
+            .await
+            .expect("user is created");
+
+        let preferences = &collab.preferences;
+        let keys: Vec<_> = (0..MAX_PREFERENCES + 8)
+            .map(|i| format!("custom.{}", i))
+            .collect();
+
+        let results = join_all(keys.iter().map(|k| preferences.set(user.id, k, "value"))).await;
+        let rejected = results
+            .iter()
+            .filter(|r| matches!(r, Err(PreferenceError::TooMany { .. })))
+            .count();
+
+        assert_eq!(rejected, 8);
+        assert_eq!(
+            preferences.get(user.id).await.unwrap().len(),
+            MAX_PREFERENCES
+        );
+    }
+}
@@ -1,16 +1,73 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
+use crossbeam::atomic::AtomicCell;
 use parking_lot::Mutex;
-use turntable_core::{BoxedQueueItem, Queue, QueueItem, QueueNotifier, SinkId};
+use thiserror::Error;
+use turntable_core::{
+    BoxedQueueItem, Queue, QueueItem, QueueNotifier, SinkId, MAX_PREWARMED_ITEMS,
+};
 
-use crate::{events::CollabEvent, CollabContext, PrimaryKey, Track};
+use crate::{events::CollabEvent, CollabContext, Metadata, PrimaryKey, Track, TrackId, UserData};
+
+/// How many played items the history of a queue keeps.
+/// Older items are dropped, so they can't be gone back to.
+pub const MAX_HISTORY_LENGTH: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("Queue is full, it can contain at most {max} items")]
+    Full { max: usize },
+    #[error("Track {0} is not in the queue or its history")]
+    TrackNotFound(TrackId),
+}
 
 #[derive(Debug, Clone)]
 pub struct LinearQueueItem {
-    pub user_id: PrimaryKey,
+    pub submitter: Submitter,
     pub track: Track,
 }
 
+/// Who added an item to a queue
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submitter {
+    /// A user, with their display name at the time the item was added
+    User {
+        id: PrimaryKey,
+        display_name: String,
+    },
+    /// turntable itself, for items no user added
+    System,
+}
+
+impl Submitter {
+    /// The name shown for items added by turntable
+    pub const SYSTEM_NAME: &'static str = "turntable";
+
+    /// Returns the id of the user, if added by one
+    pub fn user_id(&self) -> Option<PrimaryKey> {
+        match self {
+            Self::User { id, .. } => Some(*id),
+            Self::System => None,
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::User { display_name, .. } => display_name,
+            Self::System => Self::SYSTEM_NAME,
+        }
+    }
+}
+
+impl From<&UserData> for Submitter {
+    fn from(user: &UserData) -> Self {
+        Self::User {
+            id: user.id,
+            display_name: user.display_name.clone(),
+        }
+    }
+}
+
 /// Wraps the [QueueNotifier] to also emit a collab event when the queue updates
 pub struct WrappedQueueNotifier {
     pub room_id: PrimaryKey,
@@ -22,109 +79,534 @@ pub struct WrappedQueueNotifier {
 pub struct LinearQueue {
     notifier: WrappedQueueNotifier,
 
-    history: Mutex<Vec<LinearQueueItem>>,
-    items: Mutex<VecDeque<LinearQueueItem>>,
+    /// The items and history are behind one lock, so an item is never in both or neither.
+    state: Mutex<LinearQueueState>,
+    /// How many items the queue can contain at most, if limited
+    max_size: AtomicCell<Option<usize>>,
+}
+
+#[derive(Default)]
+struct LinearQueueState {
+    history: Vec<LinearQueueItem>,
+    items: VecDeque<LinearQueueItem>,
+    /// The track at the front when last notified, to know when the current item changes
+    current: Option<TrackId>,
+    /// Upcoming tracks to activate early, the most recently hinted first
+    prewarmed: VecDeque<TrackId>,
+    /// The users whose items are skipped over until they are unmuted, without being removed
+    muted: HashSet<PrimaryKey>,
+    /// The jingle played between items, if any
+    jingle: Option<Jingle>,
+}
+
+/// A short track played between items, like a station ident.
+/// It isn't an item of the queue, so it never ends up in the history or stats.
+struct Jingle {
+    track: Track,
+    /// The copy of the track that plays next, which is renewed each time it plays
+    upcoming: Track,
+    /// Whether the jingle is playing, or is about to since an item ended
+    is_current: bool,
+}
+
+impl Jingle {
+    fn new(track: Track) -> Self {
+        Self {
+            upcoming: track.requeued(),
+            track,
+            is_current: false,
+        }
+    }
+
+    /// Prepares a new copy to play after the next item, since this one played or was interrupted
+    fn renew(&mut self) {
+        self.upcoming = self.track.requeued();
+        self.is_current = false;
+    }
+
+    /// Renews the jingle if it's playing, so it plays from the start after the next item
+    fn interrupt(&mut self) {
+        if self.is_current {
+            self.renew();
+        }
+    }
 }
 
 impl LinearQueue {
     pub fn new(notifier: WrappedQueueNotifier) -> Self {
         Self {
             notifier,
-            history: Default::default(),
-            items: Default::default(),
+            state: Default::default(),
+            max_size: Default::default(),
         }
     }
 
-    pub fn push(&self, item: Track, user_id: PrimaryKey) {
-        let item = LinearQueueItem {
-            user_id,
-            track: item,
+    /// Limits how many items the queue can contain. Items already in the queue are kept.
+    pub fn set_max_size(&self, max_size: Option<usize>) {
+        self.max_size.store(max_size);
+    }
+
+    /// Adds an item to the end of the queue, unless the queue is full.
+    pub fn push(&self, item: Track, submitter: Submitter) -> Result<(), QueueError> {
+        self.push_many(vec![item], submitter).map(|_| ())
+    }
+
+    /// Adds items to the end of the queue, leaving out the ones that don't fit.
+    /// Returns how many items were added, or an error if none could be.
+    pub fn push_many(&self, items: Vec<Track>, submitter: Submitter) -> Result<usize, QueueError> {
+        let mut state = self.state.lock();
+        let queue_items = &mut state.items;
+
+        let available = match self.max_size.load() {
+            Some(max) if queue_items.len() + items.len() > max => {
+                let available = max.saturating_sub(queue_items.len());
+
+                if available == 0 && !items.is_empty() {
+                    return Err(QueueError::Full { max });
+                }
+
+                available
+            }
+            _ => items.len(),
         };
 
-        {
-            self.items.lock().push_back(item);
+        queue_items.extend(
+            items
+                .into_iter()
+                .take(available)
+                .map(|track| LinearQueueItem {
+                    submitter: submitter.clone(),
+                    track,
+                }),
+        );
+
+        self.notify(&mut state);
+        Ok(available)
+    }
+
+    /// Removes the tracks with the given ids, returning how many were removed.
+    /// The current item is kept, since it may be playing.
+    pub fn remove(&self, track_ids: &[TrackId]) -> usize {
+        self.remove_where(|item| track_ids.contains(&item.track.id))
+    }
+
+    /// Removes the items submitted by the user, returning how many were removed.
+    /// Like [LinearQueue::remove], the current item is kept.
+    pub fn remove_submitted_by(&self, user_id: PrimaryKey) -> usize {
+        self.remove_where(|item| item.submitter.user_id() == Some(user_id))
+    }
+
+    fn remove_where(&self, f: impl Fn(&LinearQueueItem) -> bool) -> usize {
+        let mut state = self.state.lock();
+        let len = state.items.len();
+        let current = state.current_item().map(|i| i.track.id);
+
+        state
+            .items
+            .retain(|item| Some(item.track.id) == current || !f(item));
+
+        let removed = len - state.items.len();
+
+        if removed > 0 {
+            self.notify(&mut state);
+        }
+
+        removed
+    }
+
+    /// Moves the items submitted by the user right after the current item, keeping their order.
+    /// Returns how many items were moved.
+    ///
+    /// This is one-shot, so items the user adds afterwards are queued at the end like usual.
+    /// The current item is kept in place, since it may be playing.
+    pub fn move_submitted_to_front(&self, user_id: PrimaryKey) -> usize {
+        let mut state = self.state.lock();
+        let current = state.current_item().map(|i| i.track.id);
+
+        // Muted items before the current one stay where they are, like the current item
+        let start = current
+            .and_then(|id| state.items.iter().position(|i| i.track.id == id))
+            .map_or(0, |index| index + 1);
+
+        let (moved, rest): (Vec<_>, Vec<_>) = state
+            .items
+            .drain(start..)
+            .partition(|i| i.submitter.user_id() == Some(user_id));
+
+        let count = moved.len();
+        state.items.extend(moved.into_iter().chain(rest));
+
+        if count > 0 {
+            self.notify(&mut state);
+        }
+
+        count
+    }
+
+    /// Replaces the metadata of the track with the given id, in the queue or history.
+    /// Returns false if the track isn't in either.
+    pub fn update_metadata(&self, track_id: TrackId, metadata: Metadata) -> bool {
+        let mut state = self.state.lock();
+        let LinearQueueState { history, items, .. } = &mut *state;
+
+        let Some(item) = items
+            .iter_mut()
+            .chain(history.iter_mut())
+            .find(|i| i.track.id == track_id)
+        else {
+            return false;
+        };
+
+        item.track.metadata = metadata;
+        self.notify(&mut state);
+
+        // The current item didn't change, but what is shown for it did
+        if state.current == Some(track_id) {
+            self.notifier
+                .current_item_changed(state.current_item().cloned());
+        }
+
+        true
+    }
+
+    /// Adjusts the gain of a queued track in dB, or resets it with [None], see [Track::set_gain].
+    /// It applies right away, even if the track is playing.
+    pub fn set_gain(&self, track_id: TrackId, gain: Option<f32>) -> Result<(), QueueError> {
+        let mut state = self.state.lock();
+
+        let item = state
+            .items
+            .iter_mut()
+            .find(|i| i.track.id == track_id)
+            .ok_or(QueueError::TrackNotFound(track_id))?;
+
+        item.track.set_gain(gain);
+        self.notify(&mut state);
+
+        Ok(())
+    }
+
+    /// Makes the track with the given id the current item, so playback continues from it.
+    ///
+    /// Jumping ahead moves the items before it to the history, ending the current item.
+    /// Jumping back moves the items after it in the history back into the queue.
+    /// Items of muted users that are jumped over stay in the queue.
+    pub fn set_current(&self, track_id: TrackId) -> Result<(), QueueError> {
+        let ended_item = {
+            let mut state = self.state.lock();
+            let LinearQueueState {
+                history,
+                items,
+                muted,
+                jingle,
+                ..
+            } = &mut *state;
+
+            // Jumping skips a jingle that is playing
+            if let Some(jingle) = jingle {
+                jingle.interrupt();
+            }
+
+            let ended_item = if let Some(index) = items.iter().position(|i| i.track.id == track_id)
+            {
+                let (skipped, kept): (Vec<_>, Vec<_>) =
+                    items.drain(..index).partition(|i| is_playable(muted, i));
+                let ended_item = skipped.first().cloned();
+
+                for item in kept.into_iter().rev() {
+                    items.push_front(item);
+                }
+
+                push_history(history, skipped);
+                ended_item
+            } else if let Some(index) = history.iter().position(|i| i.track.id == track_id) {
+                for item in history.drain(index..).rev() {
+                    items.push_front(item);
+                }
+
+                None
+            } else {
+                return Err(QueueError::TrackNotFound(track_id));
+            };
+
+            self.notify(&mut state);
+            ended_item
+        };
+
+        // Like in [Queue::next], recording the end happens outside the lock
+        if let Some(item) = ended_item {
+            self.notifier.track_ended(item);
         }
 
-        self.notify();
+        Ok(())
+    }
+
+    /// Hints that the track with the given id is likely to play soon, like when it is about to be skipped to.
+    /// It is then activated and loaded before the items ahead of it.
+    ///
+    /// Only the last [MAX_PREWARMED_ITEMS] hinted tracks are kept.
+    pub fn prewarm(&self, track_id: TrackId) -> Result<(), QueueError> {
+        let mut state = self.state.lock();
+
+        if !state.items.iter().any(|i| i.track.id == track_id) {
+            return Err(QueueError::TrackNotFound(track_id));
+        }
+
+        state.prewarmed.retain(|id| *id != track_id);
+        state.prewarmed.push_front(track_id);
+        state.prewarmed.truncate(MAX_PREWARMED_ITEMS);
+
+        // Nothing about the items changed, so only the pipeline is notified
+        self.notifier.notifier.notify();
+        Ok(())
     }
 
     /// Get a track by sink id, if it exists
     pub fn get_by_sink_id(&self, sink_id: SinkId) -> Option<LinearQueueItem> {
-        self.items
+        self.state
             .lock()
+            .items
             .iter()
             .find(|q| q.track.sink_id() == Some(sink_id))
             .cloned()
     }
 
-    /// Gets all the tracks + history
+    /// Plays a jingle between items, or stops playing one with [None].
+    /// A jingle only plays between two items, so not before the first one or after the last one.
+    pub fn set_jingle(&self, track: Option<Track>) {
+        let mut state = self.state.lock();
+        state.jingle = track.map(Jingle::new);
+
+        // Nothing about the items changed, so only the pipeline is notified
+        self.notifier.notifier.notify();
+    }
+
+    /// Returns the first item that isn't muted, which is the one playing or about to play
+    pub fn current(&self) -> Option<LinearQueueItem> {
+        self.state.lock().current_item().cloned()
+    }
+
+    /// Mutes or unmutes the items of a user. Returns false if nothing changed.
+    ///
+    /// Items of muted users are skipped over, but stay in the queue so they play once unmuted.
+    /// If a muted item is playing, playback moves on to the next item that isn't muted.
+    pub fn set_muted(&self, user_id: PrimaryKey, muted: bool) -> bool {
+        let mut state = self.state.lock();
+
+        let changed = if muted {
+            state.muted.insert(user_id)
+        } else {
+            state.muted.remove(&user_id)
+        };
+
+        if changed {
+            self.notify(&mut state);
+        }
+
+        changed
+    }
+
+    /// Returns the users whose items are muted
+    pub fn muted(&self) -> Vec<PrimaryKey> {
+        self.state.lock().muted.iter().copied().collect()
+    }
+
+    /// Returns up to `limit` of the items that played most recently, the most recent first
+    pub fn history(&self, limit: usize) -> Vec<LinearQueueItem> {
+        let state = self.state.lock();
+        state.history.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Gets the tracks in the order they play + history.
+    /// Items of muted users are left out, since they don't play until unmuted.
     pub fn tracks(&self) -> (Vec<LinearQueueItem>, Vec<LinearQueueItem>) {
-        let items: Vec<_> = self.items.lock().iter().cloned().collect();
-        let history: Vec<_> = self.history.lock().iter().cloned().collect();
+        let state = self.state.lock();
+        state.tracks()
+    }
+
+    /// Notifies about the given state.
+    /// This must be called while the state is locked, so updates are emitted in the order they happened.
+    fn notify(&self, state: &mut LinearQueueState) {
+        let (items, history) = state.tracks();
+        let current = items.first().map(|i| i.track.id);
+
+        self.notifier.notify(items, history);
+
+        if state.current != current {
+            state.current = current;
+            self.notifier
+                .current_item_changed(state.current_item().cloned());
+        }
+    }
+
+    /// Returns the track as the pipeline plays it, activated within the limit of its source
+    fn playable_item(&self, track: Track) -> BoxedQueueItem {
+        BoxedQueueItem::new(self.notifier.context.inputs.limited_item(track))
+    }
+}
 
-        (items, history)
+impl LinearQueueState {
+    fn tracks(&self) -> (Vec<LinearQueueItem>, Vec<LinearQueueItem>) {
+        (self.playable().cloned().collect(), self.history.clone())
     }
 
-    fn notify(&self) {
-        let tracks = self.tracks();
-        self.notifier.notify(tracks.0, tracks.1);
+    /// Returns the items that play, skipping over the ones of muted users
+    fn playable(&self) -> impl Iterator<Item = &LinearQueueItem> {
+        self.items.iter().filter(|i| is_playable(&self.muted, i))
     }
+
+    fn current_item(&self) -> Option<&LinearQueueItem> {
+        self.playable().next()
+    }
+}
+
+/// Adds played items to the end of the history, dropping the oldest ones past [MAX_HISTORY_LENGTH]
+fn push_history(
+    history: &mut Vec<LinearQueueItem>,
+    items: impl IntoIterator<Item = LinearQueueItem>,
+) {
+    history.extend(items);
+
+    let excess = history.len().saturating_sub(MAX_HISTORY_LENGTH);
+    history.drain(..excess);
+}
+
+fn is_playable(muted: &HashSet<PrimaryKey>, item: &LinearQueueItem) -> bool {
+    item.submitter
+        .user_id()
+        .is_none_or(|id| !muted.contains(&id))
 }
 
 impl Queue for LinearQueue {
     fn peek(&self) -> Vec<BoxedQueueItem> {
-        self.items
-            .lock()
-            .iter()
-            .map(|q| BoxedQueueItem::new(q.track.clone()))
-            .collect()
+        let state = self.state.lock();
+        let mut items: Vec<_> = state
+            .playable()
+            .map(|q| self.playable_item(q.track.clone()))
+            .collect();
+
+        // The jingle goes between the current and the next item, so it loads ahead of time like they do
+        if let Some(jingle) = &state.jingle {
+            let jingle_item = self.playable_item(jingle.upcoming.clone());
+
+            match jingle.is_current {
+                true if !items.is_empty() => items.insert(0, jingle_item),
+                false if items.len() > 1 => items.insert(1, jingle_item),
+                _ => {}
+            }
+        }
+
+        items
     }
 
     fn next(&self) {
-        {
-            let mut items = self.items.lock();
+        let ended_item = {
+            let mut state = self.state.lock();
 
-            if let Some(item) = items.pop_front() {
-                self.history.lock().push(item);
+            // The jingle ended, which doesn't change the items
+            if let Some(jingle) = state.jingle.as_mut().filter(|j| j.is_current) {
+                jingle.renew();
+                self.notifier.notifier.notify();
+                return;
             }
-        }
 
-        self.notify();
+            let index = state
+                .items
+                .iter()
+                .position(|i| is_playable(&state.muted, i));
+            let item = index.and_then(|i| state.items.remove(i));
+
+            if let Some(item) = &item {
+                push_history(&mut state.history, [item.clone()]);
+            }
+
+            // The jingle plays before the next item, if there is one
+            let has_next = state.current_item().is_some();
+            if let Some(jingle) = state.jingle.as_mut().filter(|_| item.is_some() && has_next) {
+                jingle.is_current = true;
+            }
+
+            self.notify(&mut state);
+            item
+        };
+
+        // Recording the end can take a while, so it happens outside the lock
+        if let Some(item) = ended_item {
+            self.notifier.track_ended(item);
+        }
     }
 
     fn previous(&self) {
-        {
-            let mut items = self.items.lock();
-            let mut history = self.history.lock();
+        let mut state = self.state.lock();
 
-            if let Some(item) = history.pop() {
-                items.push_front(item);
-            }
+        if let Some(item) = state.history.pop() {
+            state.items.push_front(item);
+        }
+
+        if let Some(jingle) = &mut state.jingle {
+            jingle.interrupt();
         }
 
-        self.notify();
+        self.notify(&mut state);
     }
 
     fn reset(&self) {
-        {
-            let mut items = self.items.lock();
-            let mut history = self.history.lock();
+        let mut state = self.state.lock();
+        let LinearQueueState { history, items, .. } = &mut *state;
 
-            for item in history.drain(..) {
-                items.push_front(item);
-            }
+        for item in history.drain(..) {
+            items.push_front(item);
         }
 
-        self.notify();
+        if let Some(jingle) = &mut state.jingle {
+            jingle.interrupt();
+        }
+
+        self.notify(&mut state);
     }
 
     fn skip(&self, id: &str) {
-        let mut items = self.items.lock();
-        items.retain(|item| item.track.item_id() != id);
+        let mut state = self.state.lock();
+        state.items.retain(|item| item.track.item_id() != id);
+
+        // A jingle that can't be ingested is left out until it's set again
+        if state
+            .jingle
+            .as_ref()
+            .is_some_and(|j| j.upcoming.item_id() == id)
+        {
+            state.jingle = None;
+        }
+    }
+
+    fn prewarmed(&self) -> Vec<String> {
+        let state = self.state.lock();
+
+        state
+            .prewarmed
+            .iter()
+            .filter_map(|id| state.playable().find(|i| i.track.id == *id))
+            .map(|i| i.track.item_id())
+            .collect()
     }
 }
 
 impl WrappedQueueNotifier {
+    fn track_ended(&self, item: LinearQueueItem) {
+        let room = self.context.rooms.get(&self.room_id).map(|r| r.clone());
+
+        if let Some(room) = room {
+            room.track_ended(item);
+        }
+    }
+
+    fn current_item_changed(&self, new_item: Option<LinearQueueItem>) {
+        self.context.emit(CollabEvent::RoomQueueItemUpdate {
+            room_id: self.room_id,
+            new_item,
+        });
+    }
+
     fn notify(&self, items: Vec<LinearQueueItem>, history: Vec<LinearQueueItem>) {
         self.context.emit(CollabEvent::RoomQueueUpdate {
             room_id: self.room_id,
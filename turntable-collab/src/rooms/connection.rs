@@ -8,7 +8,7 @@ use std::{
 use futures_util::{FutureExt, Stream};
 use parking_lot::Mutex;
 use tokio::task::{spawn_blocking, JoinHandle};
-use turntable_core::{Consumer, ConsumerId, Id};
+use turntable_core::{Consumer, ConsumerId, ConsumerStart, Id};
 
 use crate::{CollabContext, PrimaryKey};
 
@@ -68,6 +68,11 @@ impl RoomConnectionHandle {
     pub fn content_type(&self) -> String {
         self.stream.content_type()
     }
+
+    /// Decides what happens if the room has no audio yet when the stream starts, see [Consumer::start_with].
+    pub fn start_with(&self, start: ConsumerStart) {
+        self.stream.start_with(start)
+    }
 }
 
 impl Drop for RoomConnectionHandle {
@@ -1,21 +1,33 @@
 mod connection;
+mod pending_add;
+mod recording;
 mod room;
+mod slug;
+mod sources;
 
-use std::sync::Arc;
+use std::{future::Future, sync::Arc, time::Duration};
 
 use crate::{
-    util::random_string, CollabContext, Database, DatabaseError, NewRoom, NewRoomInvite,
-    NewRoomMember, NewStreamKey, PrimaryKey, RoomInviteData, StreamKeyData,
+    util::random_string, CollabContext, CollabEvent, DatabaseError, InputError, NewRoom,
+    NewRoomInvite, NewRoomMember, NewStreamKey, PrimaryKey, QueueError, RoomInviteData,
+    RoomMemberData, StreamKeyData, Submitter, Track, UpdatedRoom,
 };
 
+use chrono::{DateTime, Utc};
 pub use connection::*;
 use futures_util::TryFutureExt;
 use log::info;
+pub use pending_add::*;
+pub use recording::*;
 pub use room::*;
+pub use slug::*;
+pub use sources::*;
 use thiserror::Error;
+use turntable_core::Consumer;
+use turntable_impls::EncoderKind;
 
 pub struct RoomManager {
-    context: CollabContext,
+    pub(crate) context: CollabContext,
 }
 
 #[derive(Debug, Error)]
@@ -26,10 +38,48 @@ pub enum RoomError {
     RoomNotActive,
     #[error("User is not a member of this room")]
     UserNotInRoom,
+    #[error("Only owners of this room can do this")]
+    OwnerRequired,
     #[error("User does not own this stream key")]
     StreamKeyNotOwn,
     #[error("Stream key does not exist")]
     StreamKeyNotFound,
+    #[error("Stream key does not allow adding to the queue")]
+    StreamKeyCannotQueue,
+    #[error("The current item has no known length")]
+    UnknownLength,
+    #[error("Source {0} is not allowed in this room")]
+    SourceNotAllowed(String),
+    #[error("Track plays for {length} seconds, but this room allows at most {max} seconds")]
+    TrackTooLong { length: f32, max: i32 },
+    #[error("Track is blocked by the explicit content filter of this room, since {0}")]
+    ExplicitContent(String),
+    #[error("Room has too many connections, it allows at most {max}")]
+    TooManyConnections { max: usize },
+    #[error("User has too many stream connections, at most {max} are allowed across all rooms")]
+    TooManyUserConnections { max: usize },
+    #[error("Tracks were moved to the front recently, try again in {} seconds", retry_after.as_secs())]
+    MoveToFrontCooldown { retry_after: Duration },
+    #[error("Invite has expired")]
+    InviteExpired,
+    #[error("Invite has been used as many times as it can be")]
+    InviteExhausted,
+    #[error("Add {0} does not exist or has already finished")]
+    AddNotFound(PendingAddId),
+    #[error("Room is already being recorded")]
+    AlreadyRecording,
+    #[error("Room is not being recorded")]
+    NotRecording,
+    #[error("Recording failed: {0}")]
+    Recording(std::io::Error),
+    #[error("Recorded file {0} does not exist, or is still being written")]
+    RecordedFileNotFound(String),
+    #[error(transparent)]
+    InvalidSlug(SlugError),
+    #[error(transparent)]
+    Input(InputError),
+    #[error(transparent)]
+    Queue(QueueError),
     #[error(transparent)]
     Database(DatabaseError),
 }
@@ -55,23 +105,83 @@ impl RoomManager {
             .collect();
 
         for (id, room) in rooms {
-            info!("Restored room \"{}\"", room.data().title);
+            info!(
+                room_id = id, room:% = room.data().slug;
+                "Restored room \"{}\"", room.data().title
+            );
             self.context.rooms.insert(id, room.into());
         }
 
         Ok(())
     }
 
-    /// Creates a new room
-    pub async fn create_room(&self, new_room: NewRoom) -> Result<Arc<Room>, DatabaseError> {
-        let room_data = self.context.database.create_room(new_room).await?;
+    /// Creates a new room, deriving the slug from the title if it is empty
+    pub async fn create_room(&self, mut new_room: NewRoom) -> Result<Arc<Room>, RoomError> {
+        if new_room.slug.trim().is_empty() {
+            new_room.slug = slug_from_title(&new_room.title);
+        }
+
+        new_room.slug = normalize_slug(&new_room.slug).map_err(RoomError::InvalidSlug)?;
+
+        let room_data = self
+            .context
+            .database
+            .create_room(new_room)
+            .await
+            .map_err(RoomError::Database)?;
         let room = Arc::new(Room::new(&self.context, room_data));
 
         self.context.rooms.insert(room.id(), room.clone());
+        self.context
+            .emit(CollabEvent::RoomCreated { room: room.data() });
+
+        Ok(room)
+    }
+
+    /// Updates the details of a room, leaving the ones that aren't set as they are
+    pub async fn update_room(&self, updated_room: UpdatedRoom) -> Result<Arc<Room>, RoomError> {
+        let room = self.room_by_id(updated_room.id)?;
+        let previous_visibility = room.data().visibility;
+
+        let room_data = self
+            .context
+            .database
+            .update_room(updated_room)
+            .await
+            .map_err(RoomError::Database)?;
+
+        room.set_data(room_data);
+        self.context.emit(CollabEvent::RoomUpdated {
+            room: room.data(),
+            previous_visibility,
+        });
 
         Ok(room)
     }
 
+    /// Deletes a room. Its player is paused, since players can't be removed from the pipeline.
+    pub async fn delete_room(&self, room_id: PrimaryKey) -> Result<(), RoomError> {
+        let room = self.room_by_id(room_id)?;
+
+        self.context
+            .database
+            .delete_room(room_id)
+            .await
+            .map_err(RoomError::Database)?;
+
+        self.context.rooms.remove(&room_id);
+
+        if let Ok(player) = room.player() {
+            player.pause();
+        }
+
+        info!(room_id = room_id, room:% = room.data().slug; "Deleted room \"{}\"", room.data().title);
+        self.context
+            .emit(CollabEvent::RoomDeleted { room: room.data() });
+
+        Ok(())
+    }
+
     /// Returns a room by id if it exists
     pub fn room_by_id(&self, room_id: PrimaryKey) -> Result<Arc<Room>, RoomError> {
         self.context
@@ -81,19 +191,47 @@ impl RoomManager {
             .ok_or(RoomError::RoomNotFound(room_id.to_string()))
     }
 
-    /// Returns a room by slug if it exists
-    pub fn room_by_slug(&self, slug: &str) -> Result<Arc<Room>, RoomError> {
+    /// Returns a room by id if it exists and is visible to the user
+    pub fn room_by_id_for(
+        &self,
+        room_id: PrimaryKey,
+        user_id: PrimaryKey,
+    ) -> Result<Arc<Room>, RoomError> {
+        self.room_by_id(room_id)
+            .ok()
+            .filter(|r| r.is_visible_to(user_id))
+            .ok_or(RoomError::RoomNotFound(room_id.to_string()))
+    }
+
+    /// Returns a room by slug if it exists and is visible to the user
+    pub fn room_by_slug(&self, slug: &str, user_id: PrimaryKey) -> Result<Arc<Room>, RoomError> {
         self.context
             .rooms
             .iter()
-            .find(|r| r.data().slug == slug)
+            .find(|r| r.data().slug == slug && r.is_visible_to(user_id))
             .map(|r| r.clone())
             .ok_or(RoomError::RoomNotFound(slug.to_string()))
     }
 
-    /// Get all rooms in memory
-    pub fn list_all(&self) -> Vec<Arc<Room>> {
-        self.context.rooms.iter().map(|r| r.clone()).collect()
+    /// Get all rooms in memory that are listed for the user
+    pub fn list_all(&self, user_id: PrimaryKey) -> Vec<Arc<Room>> {
+        self.context
+            .rooms
+            .iter()
+            .filter(|r| r.is_listed_for(user_id))
+            .map(|r| r.clone())
+            .collect()
+    }
+
+    /// Returns a track in any active room matching the predicate
+    pub fn find_track<F>(&self, predicate: F) -> Option<Track>
+    where
+        F: Fn(&Track) -> bool,
+    {
+        self.context
+            .rooms
+            .iter()
+            .find_map(|r| r.find_track(&predicate))
     }
 
     /// Get a list of stream keys by `user_id` and `room_id`
@@ -114,6 +252,7 @@ impl RoomManager {
         room_id: PrimaryKey,
         user_id: PrimaryKey,
         source: String,
+        can_queue: bool,
     ) -> Result<StreamKeyData, RoomError> {
         // Ensure room exists
         let room = self.room_by_id(room_id)?;
@@ -129,21 +268,17 @@ impl RoomManager {
                 room_id,
                 user_id,
                 source,
+                can_queue,
             })
             .map_err(RoomError::Database)
             .await
     }
 
-    /// Connects to a room and returns a connection handle using a stream key token
-    pub async fn connect(
-        &self,
-        token: String,
-        with_latency: Option<u32>,
-    ) -> Result<RoomConnectionHandle, RoomError> {
-        let stream_key = self
-            .context
+    /// Returns a stream key by its token
+    async fn stream_key_by_token(&self, token: &str) -> Result<StreamKeyData, RoomError> {
+        self.context
             .database
-            .stream_key_by_token(&token)
+            .stream_key_by_token(token)
             .await
             .map_err(|e| match e {
                 DatabaseError::NotFound {
@@ -151,24 +286,97 @@ impl RoomManager {
                     identifier: _,
                 } => RoomError::StreamKeyNotFound,
                 e => RoomError::Database(e),
-            })?;
+            })
+    }
+
+    /// Returns the room a stream key token belongs to
+    pub async fn room_by_stream_token(&self, token: &str) -> Result<Arc<Room>, RoomError> {
+        let stream_key = self.stream_key_by_token(token).await?;
+        self.room_by_id(stream_key.room_id)
+    }
+
+    /// Connects to a room and returns a connection handle using a stream key token
+    pub async fn connect(
+        &self,
+        token: String,
+        with_latency: Option<u32>,
+        encoder: EncoderKind,
+    ) -> Result<RoomConnectionHandle, RoomError> {
+        let stream_key = self.stream_key_by_token(&token).await?;
 
         let room = self.room_by_id(stream_key.room_id)?;
-        let handle = room.connect(stream_key.user_id, stream_key.source, with_latency)?;
+        let handle = room.connect(
+            stream_key.user_id,
+            stream_key.source,
+            with_latency,
+            encoder,
+            Some(token),
+        )?;
 
         Ok(handle)
     }
 
+    /// Creates an anonymous preview stream of a public room by its slug
+    pub fn preview(&self, slug: &str, encoder: EncoderKind) -> Result<Consumer, RoomError> {
+        let room = self
+            .context
+            .rooms
+            .iter()
+            .find(|r| r.data().slug == slug)
+            .map(|r| r.clone())
+            .ok_or(RoomError::RoomNotFound(slug.to_string()))?;
+
+        room.preview(encoder)
+    }
+
+    /// Adds tracks to the queue of a room using a stream key token.
+    /// The tracks are attributed to the user of the stream key, if the key allows queueing.
+    ///
+    /// The tracks are only resolved once the key is known to allow queueing, so a key that doesn't can't make turntable fetch anything.
+    pub async fn add_to_queue_with_stream_key(
+        &self,
+        token: String,
+        tracks: impl Future<Output = Result<Vec<Track>, InputError>>,
+    ) -> Result<(), RoomError> {
+        let stream_key = self.stream_key_by_token(&token).await?;
+
+        if !stream_key.can_queue {
+            return Err(RoomError::StreamKeyCannotQueue);
+        }
+
+        let room = self.room_by_id(stream_key.room_id)?;
+        // Ensure the user is still a member of the room
+        let member = room.member_by_user_id(stream_key.user_id)?;
+
+        let tracks = tracks.await.map_err(RoomError::Input)?;
+        room.add_to_queue(tracks, Submitter::from(&member.user))?;
+
+        Ok(())
+    }
+
     /// Deletes a stream key
     pub async fn delete_stream_key(&self, key_id: PrimaryKey) -> Result<(), DatabaseError> {
         self.context.database.delete_stream_key(key_id).await
     }
 
-    /// Creates an invite for a room
+    /// Creates an invite for a room, which can be used once
     pub async fn create_invite(
         &self,
         inviter_id: PrimaryKey,
         for_room: PrimaryKey,
+    ) -> Result<RoomInviteData, RoomError> {
+        self.create_invite_with_limits(inviter_id, for_room, None, Some(1))
+            .await
+    }
+
+    /// Creates an invite for a room, which works until `expires_at` and for `max_uses` members if set.
+    /// Without `max_uses`, it can be used any amount of times.
+    pub async fn create_invite_with_limits(
+        &self,
+        inviter_id: PrimaryKey,
+        for_room: PrimaryKey,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<i32>,
     ) -> Result<RoomInviteData, RoomError> {
         // Ensure room exists
         let room = self.room_by_id(for_room)?;
@@ -183,6 +391,8 @@ impl RoomManager {
                 room_id: for_room,
                 user_id: inviter_id,
                 token,
+                expires_at,
+                max_uses,
             })
             .await
             .map_err(RoomError::Database)
@@ -193,12 +403,13 @@ impl RoomManager {
         self.context.database.room_invite_by_token(&token).await
     }
 
-    /// Adds a user as a member to a room by consuming an invite
+    /// Adds a user as a member to a room by using an invite, returning the new member.
+    /// The invite is deleted once it can't be used anymore.
     pub async fn add_member_with_invite(
         &self,
         user_id: PrimaryKey,
         token: String,
-    ) -> Result<(), RoomError> {
+    ) -> Result<RoomMemberData, RoomError> {
         let invite = self
             .context
             .database
@@ -206,27 +417,1830 @@ impl RoomManager {
             .await
             .map_err(RoomError::Database)?;
 
+        if invite.is_expired() {
+            return Err(RoomError::InviteExpired);
+        }
+
+        if invite.is_exhausted() {
+            return Err(RoomError::InviteExhausted);
+        }
+
         let room = self.room_by_id(invite.room.id)?;
 
+        // The invite may have been used up at the same time, so it's checked again
         let member = self
             .context
             .database
-            .create_room_member(NewRoomMember {
-                owner: false,
-                room_id: room.id(),
-                user_id,
-            })
+            .create_room_member_with_invite(
+                invite.id,
+                NewRoomMember {
+                    owner: false,
+                    room_id: room.id(),
+                    user_id,
+                },
+            )
             .map_err(RoomError::Database)
-            .await?;
+            .await?
+            .ok_or(RoomError::InviteExhausted)?;
 
-        room.add_member(member);
+        room.add_member(member.clone());
 
-        self.context
+        Ok(member)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use futures_util::{FutureExt, StreamExt};
+    use sqlx::PgPool;
+    use tokio::{sync::oneshot, task::spawn_blocking};
+    use turntable_core::{Queue, QueueItem};
+    use turntable_impls::EncoderKind;
+
+    use crate::{
+        AddEntry, Collab, CollabConfig, CollabEvent, DatabaseError, DisconnectPolicy,
+        EmptyRoomPolicy, Input, InputError, LinearQueueItem, MemoryDatabase, NewRoom,
+        NewRoomMember, NewUser, PgDatabase, PrimaryKey, QueueError, RecordingOptions, Room,
+        RoomError, RoomVisibility, Submitter, Track, TrackId, UpdatedRoom, UserData,
+    };
+
+    async fn create_user(collab: &Collab, username: &str) -> UserData {
+        collab
+            .rooms
+            .context
             .database
-            .delete_room_invite(invite.id)
-            .map_err(RoomError::Database)
-            .await?;
+            .create_user(NewUser {
+                username: username.to_string(),
+                password: "password".to_string(),
+                display_name: username.to_string(),
+                superuser: false,
+            })
+            .await
+            .expect("user is created")
+    }
 
-        Ok(())
+    async fn create_room(
+        collab: &Collab,
+        owner: &UserData,
+        slug: &str,
+        visibility: RoomVisibility,
+    ) -> i32 {
+        collab
+            .rooms
+            .create_room(NewRoom {
+                slug: slug.to_string(),
+                title: slug.to_string(),
+                visibility,
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await
+            .expect("room is created")
+            .id()
+    }
+
+    async fn setup(pool: PgPool) -> Collab {
+        Collab::with_database(CollabConfig::default(), PgDatabase::from_test_pool(&pool)).await
+    }
+
+    async fn setup_in_memory() -> Collab {
+        Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await
+    }
+
+    /// Returns the room lifecycle events emitted so far, by name and room id
+    fn lifecycle_events(collab: &Collab) -> Vec<(&'static str, PrimaryKey)> {
+        let mut events = vec![];
+
+        while let Ok(event) = collab.try_wait_for_event() {
+            let event = match event {
+                CollabEvent::RoomCreated { room } => ("created", room.id),
+                CollabEvent::RoomUpdated { room, .. } => ("updated", room.id),
+                CollabEvent::RoomDeleted { room } => ("deleted", room.id),
+                CollabEvent::RoomResumed { room } => ("resumed", room.id),
+                _ => continue,
+            };
+
+            events.push(event);
+        }
+
+        events
+    }
+
+    #[tokio::test]
+    async fn test_room_lifecycle_events() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "lounge", RoomVisibility::Public).await;
+        assert_eq!(lifecycle_events(&collab), [("created", room_id)]);
+
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        room.queue().unwrap();
+        assert_eq!(lifecycle_events(&collab), [("resumed", room_id)]);
+
+        let room = collab
+            .rooms
+            .update_room(UpdatedRoom {
+                id: room_id,
+                title: Some("Renamed".to_string()),
+                max_queue_size: Some(2),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(room.data().title, "Renamed");
+        assert_eq!(lifecycle_events(&collab), [("updated", room_id)]);
+
+        collab.rooms.delete_room(room_id).await.unwrap();
+
+        assert_eq!(lifecycle_events(&collab), [("deleted", room_id)]);
+        assert!(collab.rooms.room_by_id(room_id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_slug_conflict() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        create_room(&collab, &owner, "lounge", RoomVisibility::Public).await;
+
+        let result = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "lounge".to_string(),
+                title: "Another lounge".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RoomError::Database(DatabaseError::Conflict {
+                resource: "room",
+                field: "slug",
+                ..
+            }))
+        ));
+        assert_eq!(collab.rooms.list_all(owner.id).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_invite() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let invite = collab.rooms.create_invite(owner.id, room_id).await.unwrap();
+
+        assert!(collab.rooms.room_by_slug("private", guest.id).is_err());
+
+        let member = collab
+            .rooms
+            .add_member_with_invite(guest.id, invite.token.clone())
+            .await
+            .expect("guest joins with the invite");
+
+        assert_eq!(member.user.id, guest.id);
+        assert!(collab.rooms.room_by_slug("private", guest.id).is_ok());
+
+        // The invite is consumed
+        let result = collab
+            .rooms
+            .add_member_with_invite(guest.id, invite.token)
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RoomError::Database(DatabaseError::NotFound {
+                resource: "room invite",
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_invite() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let expires_at = Utc::now() - chrono::Duration::seconds(1);
+        let invite = collab
+            .rooms
+            .create_invite_with_limits(owner.id, room_id, Some(expires_at), None)
+            .await
+            .unwrap();
+
+        let result = collab
+            .rooms
+            .add_member_with_invite(guest.id, invite.token)
+            .await;
+
+        assert!(matches!(result, Err(RoomError::InviteExpired)));
+        assert!(collab.rooms.room_by_slug("private", guest.id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_invite() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let first = create_user(&collab, "first").await;
+        let second = create_user(&collab, "second").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let invite = collab
+            .rooms
+            .create_invite_with_limits(owner.id, room_id, None, Some(1))
+            .await
+            .unwrap();
+
+        let database = &collab.rooms.context.database;
+        let new_member = |user: &UserData| NewRoomMember {
+            owner: false,
+            room_id,
+            user_id: user.id,
+        };
+
+        let member = database
+            .create_room_member_with_invite(invite.id, new_member(&first))
+            .await
+            .unwrap();
+        assert!(member.is_some(), "first use is allowed");
+
+        // Looked up before the first use at the same time, so the use is one too many
+        let member = database
+            .create_room_member_with_invite(invite.id, new_member(&second))
+            .await
+            .unwrap();
+        assert!(member.is_none());
+
+        let room = database.room_by_id(room_id).await.unwrap();
+        assert!(!room.members.iter().any(|m| m.user.id == second.id));
+    }
+
+    #[sqlx::test]
+    async fn test_failed_invite_use_changes_nothing(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let invite = collab.rooms.create_invite(owner.id, room_id).await.unwrap();
+
+        // The use is counted before the member is added, which fails since the owner is a member already
+        let result = collab
+            .rooms
+            .add_member_with_invite(owner.id, invite.token.clone())
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RoomError::Database(DatabaseError::Conflict { .. }))
+        ));
+
+        let invite = collab
+            .rooms
+            .context
+            .database
+            .room_invite_by_token(&invite.token)
+            .await
+            .expect("invite still exists");
+
+        assert_eq!(invite.uses, 0, "use is rolled back");
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_invite() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let invite = collab
+            .rooms
+            .create_invite_with_limits(owner.id, room_id, None, None)
+            .await
+            .unwrap();
+
+        for username in ["first", "second", "third"] {
+            let guest = create_user(&collab, username).await;
+
+            collab
+                .rooms
+                .add_member_with_invite(guest.id, invite.token.clone())
+                .await
+                .expect("invite can be used any amount of times");
+        }
+
+        let invite = collab.rooms.invite_by_token(invite.token).await.unwrap();
+        assert_eq!(invite.uses, 3);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_stream_keys() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let outsider = create_user(&collab, "outsider").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let key = collab
+            .rooms
+            .create_stream_key(room_id, owner.id, "web".to_string(), false)
+            .await
+            .expect("stream key is created");
+
+        let room = collab.rooms.room_by_stream_token(&key.token).await.unwrap();
+        assert_eq!(room.id(), room_id);
+
+        let result = collab
+            .rooms
+            .create_stream_key(room_id, outsider.id, "web".to_string(), false)
+            .await;
+
+        assert!(matches!(result, Err(RoomError::UserNotInRoom)));
+
+        collab.rooms.delete_stream_key(key.id).await.unwrap();
+
+        let result = collab.rooms.room_by_stream_token(&key.token).await;
+        assert!(matches!(result, Err(RoomError::StreamKeyNotFound)));
+    }
+
+    #[sqlx::test]
+    async fn test_visibility_is_persisted(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let data = collab
+            .rooms
+            .context
+            .database
+            .room_by_id(room_id)
+            .await
+            .unwrap();
+
+        assert_eq!(data.visibility, RoomVisibility::Private);
+    }
+
+    #[sqlx::test]
+    async fn test_visibility_listing(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+        let outsider = create_user(&collab, "outsider").await;
+
+        create_room(&collab, &owner, "public", RoomVisibility::Public).await;
+        create_room(&collab, &owner, "unlisted", RoomVisibility::Unlisted).await;
+        create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+
+        let slugs = |user_id| {
+            let mut slugs: Vec<_> = collab
+                .rooms
+                .list_all(user_id)
+                .into_iter()
+                .map(|r| r.data().slug)
+                .collect();
+
+            slugs.sort();
+            slugs
+        };
+
+        assert_eq!(slugs(outsider.id), vec!["public"]);
+        assert_eq!(slugs(owner.id), vec!["private", "public", "unlisted"]);
+    }
+
+    #[sqlx::test]
+    async fn test_visibility_resolving(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+        let outsider = create_user(&collab, "outsider").await;
+
+        create_room(&collab, &owner, "public", RoomVisibility::Public).await;
+        create_room(&collab, &owner, "unlisted", RoomVisibility::Unlisted).await;
+        create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+
+        assert!(collab.rooms.room_by_slug("public", outsider.id).is_ok());
+        assert!(collab.rooms.room_by_slug("unlisted", outsider.id).is_ok());
+        assert!(collab.rooms.room_by_slug("private", outsider.id).is_err());
+        assert!(collab.rooms.room_by_slug("private", owner.id).is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_private_room_join_with_invite(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+        let invitee = create_user(&collab, "invitee").await;
+
+        let room_id = create_room(&collab, &owner, "private", RoomVisibility::Private).await;
+        let invite = collab
+            .rooms
+            .create_invite(owner.id, room_id)
+            .await
+            .expect("invite is created");
+
+        let member = collab
+            .rooms
+            .add_member_with_invite(invitee.id, invite.token)
+            .await
+            .expect("invitee joins");
+
+        assert!(!member.owner);
+        assert_eq!(member.user.id, invitee.id);
+        assert_eq!(member.user.username, "invitee");
+        assert_eq!(member.user.display_name, invitee.display_name);
+
+        assert!(collab.rooms.room_by_slug("private", invitee.id).is_ok());
+        assert_eq!(collab.rooms.list_all(invitee.id).len(), 1);
+    }
+
+    async fn file_track(name: &str) -> Track {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, []).expect("file is written");
+
+        let query = format!("file://{}", path.display());
+        let mut inputs = Input::query(&query).await.expect("file is found");
+
+        inputs.remove(0).into()
+    }
+
+    /// Creates a track of a 16-bit stereo wave file with the given length
+    async fn wave_track(name: &str, seconds: usize) -> Track {
+        let data: Vec<u8> = (0..seconds * 44100 * 2)
+            .flat_map(|i| ((i % 1000) as i16 + 1).to_le_bytes())
+            .collect();
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).expect("file is written");
+
+        let query = format!("file://{}", path.display());
+        let mut inputs = Input::query(&query).await.expect("file is found");
+
+        inputs.remove(0).into()
+    }
+
+    #[sqlx::test]
+    async fn test_connect_with_stream_key(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Private).await;
+        let key = collab
+            .rooms
+            .create_stream_key(room_id, owner.id, "web".to_string(), false)
+            .await
+            .expect("stream key is created");
+
+        let mut handle = collab
+            .rooms
+            .connect(key.token.clone(), None, EncoderKind::Wave)
+            .await
+            .expect("connects with the key");
+
+        assert_eq!(handle.content_type(), EncoderKind::Wave.content_type());
+
+        let room = collab.rooms.room_by_stream_token(&key.token).await.unwrap();
+        let connections = room.current_connections();
+
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].user_id, owner.id);
+        assert_eq!(connections[0].source, "web");
+
+        let bytes = handle.next().await.expect("stream has audio").unwrap();
+        assert!(!bytes.is_empty());
+
+        drop(handle);
+        assert!(room.current_connections().is_empty());
+
+        let result = collab
+            .rooms
+            .connect("invalid".to_string(), None, EncoderKind::Wave)
+            .await;
+
+        assert!(matches!(result, Err(RoomError::StreamKeyNotFound)));
+    }
+
+    #[sqlx::test]
+    async fn test_connection_limits(pool: PgPool) {
+        let config = CollabConfig {
+            max_connections_per_user: Some(2),
+            max_connections_per_room: Some(3),
+            ..Default::default()
+        };
+        let collab = Collab::with_database(config, PgDatabase::from_test_pool(&pool)).await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+
+        let invite = collab.rooms.create_invite(owner.id, room_id).await.unwrap();
+        collab
+            .rooms
+            .add_member_with_invite(guest.id, invite.token)
+            .await
+            .unwrap();
+
+        let other_id = create_room(&collab, &owner, "other", RoomVisibility::Public).await;
+        let other = collab.rooms.room_by_id(other_id).unwrap();
+
+        let connect = |room: &Room, user_id| {
+            room.connect(user_id, "web".to_string(), None, EncoderKind::Wave, None)
+        };
+
+        let first = connect(&room, owner.id).unwrap();
+        let _second = connect(&other, owner.id).unwrap();
+
+        let result = connect(&room, owner.id);
+        assert!(
+            matches!(result, Err(RoomError::TooManyUserConnections { max: 2 })),
+            "connections to other rooms count too"
+        );
+
+        let _third = connect(&room, guest.id).expect("other users can still connect");
+        let _fourth = connect(&room, guest.id).unwrap();
+
+        let result = connect(&room, guest.id);
+        assert!(matches!(
+            result,
+            Err(RoomError::TooManyConnections { max: 3 })
+        ));
+        assert_eq!(room.current_connections().len(), 3);
+
+        // Disconnecting frees up a slot
+        drop(first);
+        let _fifth = connect(&room, owner.id).expect("connects after disconnecting");
+    }
+
+    #[sqlx::test]
+    async fn test_stream_key_queue_permission(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let listen_key = collab
+            .rooms
+            .create_stream_key(room_id, owner.id, "listen".to_string(), false)
+            .await
+            .expect("listen-only key is created");
+        let queue_key = collab
+            .rooms
+            .create_stream_key(room_id, owner.id, "queue".to_string(), true)
+            .await
+            .expect("queue key is created");
+
+        let track = file_track("turntable_stream_key_queue.wav").await;
+        let result = collab
+            .rooms
+            .add_to_queue_with_stream_key(listen_key.token, async {
+                panic!("tracks are not resolved for a key that can't queue")
+            })
+            .await;
+
+        assert!(matches!(result, Err(RoomError::StreamKeyCannotQueue)));
+
+        let result = collab
+            .rooms
+            .add_to_queue_with_stream_key("invalid".to_string(), async {
+                panic!("tracks are not resolved for an invalid key")
+            })
+            .await;
+
+        assert!(result.is_err());
+
+        collab
+            .rooms
+            .add_to_queue_with_stream_key(queue_key.token, async { Ok(vec![track]) })
+            .await
+            .expect("track is added");
+
+        let queue = collab.rooms.room_by_id(room_id).unwrap().queue().unwrap();
+        let (items, history) = queue.tracks();
+        let submitters: Vec<_> = items
+            .iter()
+            .chain(&history)
+            .map(|i| i.submitter.user_id())
+            .collect();
+
+        assert_eq!(submitters, vec![Some(owner.id)]);
+    }
+
+    #[sqlx::test]
+    async fn test_max_queue_size(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "limited".to_string(),
+                title: "Limited".to_string(),
+                visibility: RoomVisibility::Public,
+                max_queue_size: Some(3),
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await
+            .expect("room is created");
+
+        assert_eq!(room.data().max_queue_size, Some(3), "limit is persisted");
+
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        // Keep the player from consuming the queue while it is asserted
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let track = file_track("turntable_max_queue_size.wav").await;
+
+        let added = queue
+            .push_many(vec![track.clone(), track.clone()], (&owner).into())
+            .expect("tracks fit");
+        assert_eq!(added, 2);
+
+        let added = queue
+            .push_many(vec![track.clone(), track.clone()], (&owner).into())
+            .expect("playlist partially fits");
+        assert_eq!(added, 1, "playlist is truncated to fit");
+
+        let result = queue.push(track, (&owner).into());
+        assert!(matches!(result, Err(QueueError::Full { max: 3 })));
+        assert_eq!(queue.tracks().0.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_add_batch() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let room_id = create_room(&collab, &owner, "batch", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+
+        room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        // Keep the player from consuming the queue while it is asserted
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let path = std::env::temp_dir().join("turntable_add_batch.mp3");
+        std::fs::write(&path, []).expect("file is written");
+
+        let queries = [
+            format!("file://{}", path.display()),
+            "https://example.com/not-a-track".to_string(),
+        ];
+
+        let entries: Vec<AddEntry> = queries
+            .into_iter()
+            .map(|q| {
+                async move {
+                    let inputs = Input::query(&q).await?;
+                    Ok(inputs.into_iter().map(Track::from).collect())
+                }
+                .boxed()
+            })
+            .collect();
+
+        let results = room
+            .add_batch(entries, (&owner).into())
+            .await
+            .expect("batch is added");
+
+        let queued = room.queue().unwrap().tracks().0;
+        let added = results[0].as_ref().expect("valid url is queued");
+
+        assert_eq!(added.len(), 1);
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].track.id, added[0]);
+        assert!(
+            matches!(results[1], Err(RoomError::Input(_))),
+            "invalid url is reported"
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_set_current(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let mut tracks = vec![];
+        for i in 0..4 {
+            tracks.push(wave_track(&format!("turntable_set_current_{}.wav", i), 1).await);
+        }
+
+        let ids: Vec<_> = tracks.iter().map(|t| t.id).collect();
+        queue.push_many(tracks, (&owner).into()).unwrap();
+
+        let track_ids = |items: Vec<LinearQueueItem>| -> Vec<TrackId> {
+            items.into_iter().map(|i| i.track.id).collect()
+        };
+
+        queue.set_current(ids[2]).unwrap();
+
+        let (items, history) = queue.tracks();
+        assert_eq!(track_ids(items), ids[2..]);
+        assert_eq!(track_ids(history), ids[..2]);
+        assert_eq!(queue.current().unwrap().track.id, ids[2]);
+
+        // Jumping back restores the order
+        queue.set_current(ids[1]).unwrap();
+
+        let (items, history) = queue.tracks();
+        assert_eq!(track_ids(items), ids[1..]);
+        assert_eq!(track_ids(history), ids[..1]);
+
+        let unknown = wave_track("turntable_set_current_unknown.wav", 1).await;
+        let result = queue.set_current(unknown.id);
+
+        assert!(matches!(result, Err(QueueError::TrackNotFound(_))));
+        assert_eq!(queue.tracks().0.len(), 3, "queue is unchanged");
+    }
+
+    #[sqlx::test]
+    async fn test_max_track_duration_cutoff(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "short".to_string(),
+                title: "Short".to_string(),
+                visibility: RoomVisibility::Public,
+                max_track_duration: Some(1),
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await
+            .expect("room is created");
+
+        assert_eq!(
+            room.data().max_track_duration,
+            Some(1),
+            "limit is persisted"
+        );
+
+        // The length of a file is only known once it is decoded
+        let track = wave_track("turntable_max_track_duration.wav", 3).await;
+        assert_eq!(track.played_length(), None);
+
+        room.add_to_queue(vec![track], (&owner).into())
+            .expect("track with unknown length is queued");
+
+        let pipeline = &collab.rooms.context.pipeline;
+        let _listener =
+            EncoderKind::Wave.consume_player(pipeline, room.player().unwrap().id, None, None);
+
+        let mut ended = None;
+
+        for _ in 0..5000 {
+            match collab.try_wait_for_event() {
+                Ok(CollabEvent::TrackEnded {
+                    item, time_played, ..
+                }) => {
+                    ended = Some((item, time_played));
+                    break;
+                }
+                Ok(_) => continue,
+                Err(_) => tokio::time::sleep(Duration::from_millis(1)).await,
+            }
+        }
+
+        let (item, time_played) = ended.expect("track ends before its length");
+        assert_eq!(item.track.end, Some(1.));
+        assert!(time_played < 1.5, "played for {}", time_played);
+    }
+
+    #[sqlx::test]
+    async fn test_source_lists(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let create = |slug: &str, allowed: &[&str], denied: &[&str]| {
+            collab.rooms.create_room(NewRoom {
+                slug: slug.to_string(),
+                title: slug.to_string(),
+                visibility: RoomVisibility::Public,
+                allowed_sources: allowed.iter().map(|s| s.to_string()).collect(),
+                denied_sources: denied.iter().map(|s| s.to_string()).collect(),
+                user_id: owner.id,
+                ..Default::default()
+            })
+        };
+
+        let allowing = create("allowing", &["file"], &[]).await.unwrap();
+        let denying = create("denying", &[], &["file"]).await.unwrap();
+
+        let data = collab
+            .rooms
+            .context
+            .database
+            .room_by_id(denying.id())
+            .await
+            .unwrap();
+        assert_eq!(data.denied_sources, vec!["file"], "lists are persisted");
+
+        let track = file_track("turntable_source_lists.wav").await;
+
+        allowing
+            .add_to_queue(vec![track.clone()], (&owner).into())
+            .expect("allowed source is queued");
+
+        let result = denying.add_to_queue(vec![track], (&owner).into());
+        assert!(matches!(result, Err(RoomError::SourceNotAllowed(s)) if s == "file"));
+
+        let (items, history) = denying.queue().unwrap().tracks();
+        assert!(items.is_empty() && history.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_seek_fraction(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let result = room.seek_fraction(owner.id, 0.5);
+        assert!(matches!(result, Err(RoomError::UnknownLength)));
+
+        let track = wave_track("turntable_seek_fraction.wav", 4).await;
+        queue.push(track, (&owner).into()).unwrap();
+
+        let length = wait_for(|| player.current_length()).await;
+        let config = &collab.rooms.context.config.pipeline;
+        assert_eq!(length, config.seconds_to_samples(4.));
+
+        room.seek_fraction(owner.id, 0.5).unwrap();
+
+        let offset =
+            wait_for(|| Some(config.seconds_to_samples(player.current_time())).filter(|o| *o > 0))
+                .await;
+        assert_eq!(offset, length / 2);
+    }
+
+    #[sqlx::test]
+    async fn test_cancel_add(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // Each entry resolves when the test sends its tracks
+        let mut senders = vec![];
+        let mut entries: Vec<AddEntry> = vec![];
+
+        for _ in 0..4 {
+            let (sender, receiver) = oneshot::channel::<Vec<Track>>();
+            senders.push(sender);
+            entries.push(async move { Ok(receiver.await.unwrap_or_default()) }.boxed());
+        }
+
+        let add = room.add_entries(entries, &owner);
+        let mut senders = senders.into_iter();
+
+        for i in 0..2 {
+            let track = file_track(&format!("turntable_cancel_add_{}.wav", i)).await;
+            senders.next().unwrap().send(vec![track]).unwrap();
+        }
+
+        wait_for(|| (queue.tracks().0.len() == 2).then_some(())).await;
+
+        room.cancel_add(owner.id, add.id, true).unwrap();
+        assert_eq!(
+            queue.tracks().0.len(),
+            1,
+            "added items are removed, except the front one"
+        );
+
+        for sender in senders {
+            let track = file_track("turntable_cancel_add_late.wav").await;
+            let _ = sender.send(vec![track]);
+        }
+
+        wait_for(|| {
+            let result = room.cancel_add(owner.id, add.id, false);
+            matches!(result, Err(RoomError::AddNotFound(_))).then_some(())
+        })
+        .await;
+
+        assert_eq!(queue.tracks().0.len(), 1, "no more entries are added");
+    }
+
+    #[tokio::test]
+    async fn test_add_reports_failed_entries() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        room.queue().unwrap();
+
+        let track = file_track("turntable_add_reports_failed_entries.wav").await;
+        let entries: Vec<AddEntry> = vec![
+            async { Err(InputError::NotFound) }.boxed(),
+            async move { Ok(vec![track]) }.boxed(),
+        ];
+
+        let add = room.add_entries(entries, &owner);
+        let mut errors = vec![];
+
+        loop {
+            let Ok(event) = collab.try_wait_for_event() else {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                continue;
+            };
+
+            match event {
+                CollabEvent::QueueAddProgress { add_id, error, .. } if add_id == add.id => {
+                    errors.push(error)
+                }
+                CollabEvent::QueueAddFinished { add_id, .. } if add_id == add.id => break,
+                _ => continue,
+            }
+        }
+
+        assert_eq!(
+            errors,
+            [Some(InputError::NotFound.to_string()), None],
+            "each entry reports why it failed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_room_policy() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        for empty_room_policy in [
+            EmptyRoomPolicy::AdvanceWhenEmpty,
+            EmptyRoomPolicy::PauseWhenEmpty,
+        ] {
+            let slug = empty_room_policy.as_str().replace('_', "-");
+            let room = collab
+                .rooms
+                .create_room(NewRoom {
+                    slug: slug.clone(),
+                    title: slug,
+                    visibility: RoomVisibility::Public,
+                    empty_room_policy,
+                    user_id: owner.id,
+                    ..Default::default()
+                })
+                .await
+                .expect("room is created");
+
+            assert_eq!(room.data().empty_room_policy, empty_room_policy);
+
+            let connect = || {
+                room.connect(owner.id, "web".to_string(), None, EncoderKind::Wave, None)
+                    .unwrap()
+            };
+
+            let connection = connect();
+            let player = room.player().unwrap();
+
+            let name = format!("turntable_{}.wav", empty_room_policy.as_str());
+            let tracks = vec![wave_track(&name, 1).await, wave_track(&name, 1).await];
+            room.add_to_queue(tracks, (&owner).into()).unwrap();
+
+            while player.current_time() == 0. {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            drop(connection);
+
+            if empty_room_policy == EmptyRoomPolicy::AdvanceWhenEmpty {
+                assert!(player.is_playing());
+
+                let queue = room.queue().unwrap();
+                while queue.tracks().1.is_empty() {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+
+                continue;
+            }
+
+            // The room is paused without listeners
+            while player.is_playing() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            let position = player.current_time();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            assert_eq!(player.current_time(), position, "nothing is processed");
+            assert!(room.queue().unwrap().tracks().1.is_empty());
+
+            let _connection = connect();
+
+            // The room resumes when someone connects
+            while player.current_time() == position {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_disconnect_policy(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let policies = [
+            (
+                DisconnectPolicy::KeepQueued,
+                vec!["guest", "owner", "guest"],
+            ),
+            (DisconnectPolicy::RemoveQueued, vec!["guest", "owner"]),
+        ];
+
+        for (disconnect_policy, expected) in policies {
+            let slug = disconnect_policy.as_str().replace('_', "-");
+            let room = collab
+                .rooms
+                .create_room(NewRoom {
+                    slug: slug.clone(),
+                    title: slug,
+                    visibility: RoomVisibility::Public,
+                    disconnect_policy,
+                    user_id: owner.id,
+                    ..Default::default()
+                })
+                .await
+                .expect("room is created");
+
+            assert_eq!(room.data().disconnect_policy, disconnect_policy);
+
+            let invite = collab
+                .rooms
+                .create_invite(owner.id, room.id())
+                .await
+                .unwrap();
+            collab
+                .rooms
+                .add_member_with_invite(guest.id, invite.token)
+                .await
+                .unwrap();
+
+            let queue = room.queue().unwrap();
+            let player = room.player().unwrap();
+
+            // Keep the player from consuming the queue while it is asserted
+            player.pause();
+            while player.is_playing() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            for submitter in [&guest, &owner, &guest] {
+                let track = file_track("turntable_disconnect_policy.wav").await;
+                queue.push(track, submitter.into()).unwrap();
+            }
+
+            let first = room
+                .connect(guest.id, "web".to_string(), None, EncoderKind::Wave, None)
+                .unwrap();
+            let second = room
+                .connect(guest.id, "bot".to_string(), None, EncoderKind::Wave, None)
+                .unwrap();
+
+            // The guest is still connected after the first disconnect
+            drop(first);
+            assert_eq!(queue.tracks().0.len(), 3);
+
+            drop(second);
+
+            let (items, history) = queue.tracks();
+            let names: Vec<_> = items.iter().map(|i| i.submitter.display_name()).collect();
+
+            assert_eq!(names, expected, "{:?}", disconnect_policy);
+            assert!(history.is_empty());
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_submitter_of_current_item(pool: PgPool) {
+        let collab = setup(pool).await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+
+        let first = wave_track("turntable_submitter_first.wav", 1).await;
+        let second = wave_track("turntable_submitter_second.wav", 1).await;
+
+        room.add_to_queue(vec![first], (&owner).into()).unwrap();
+        room.add_to_queue(vec![second], (&guest).into()).unwrap();
+
+        let pipeline = &collab.rooms.context.pipeline;
+        let _listener =
+            EncoderKind::Wave.consume_player(pipeline, room.player().unwrap().id, None, None);
+
+        let mut submitters = vec![];
+
+        // Blocking on events would keep the tracks from loading
+        for _ in 0..5000 {
+            match collab.try_wait_for_event() {
+                Ok(CollabEvent::RoomQueueItemUpdate {
+                    new_item: Some(item),
+                    ..
+                }) => submitters.push(item.submitter),
+                Ok(_) => continue,
+                Err(_) if submitters.len() < 2 => {
+                    tokio::time::sleep(Duration::from_millis(1)).await
+                }
+                Err(_) => break,
+            }
+        }
+
+        assert_eq!(
+            submitters,
+            vec![
+                Submitter::User {
+                    id: owner.id,
+                    display_name: "owner".to_string()
+                },
+                Submitter::User {
+                    id: guest.id,
+                    display_name: "guest".to_string()
+                },
+            ]
+        );
+
+        assert_eq!(Submitter::System.user_id(), None);
+        assert_eq!(Submitter::System.display_name(), Submitter::SYSTEM_NAME);
+    }
+
+    #[tokio::test]
+    async fn test_muted_submitter() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "muted", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+
+        assert!(matches!(
+            room.set_submitter_muted(guest.id, owner.id, true),
+            Err(RoomError::UserNotInRoom)
+        ));
+
+        room.set_submitter_muted(owner.id, guest.id, true).unwrap();
+        assert_eq!(queue.muted(), vec![guest.id]);
+
+        let mut submitted = vec![];
+        for (i, user) in [&owner, &guest, &owner, &guest].into_iter().enumerate() {
+            let track = wave_track(&format!("turntable_muted_{}.wav", i), 1).await;
+            submitted.push(track.id);
+            room.add_to_queue(vec![track], user.into()).unwrap();
+        }
+
+        let ids = |items: Vec<LinearQueueItem>| -> Vec<TrackId> {
+            items.into_iter().map(|i| i.track.id).collect()
+        };
+
+        assert_eq!(
+            ids(queue.tracks().0),
+            vec![submitted[0], submitted[2]],
+            "the playable order leaves out muted tracks"
+        );
+
+        let _connection = room
+            .connect(owner.id, "web".to_string(), None, EncoderKind::Wave, None)
+            .unwrap();
+
+        wait_for(|| queue.tracks().0.is_empty().then_some(())).await;
+        assert_eq!(
+            ids(queue.tracks().1),
+            vec![submitted[0], submitted[2]],
+            "only unmuted tracks played"
+        );
+
+        room.set_submitter_muted(owner.id, guest.id, false).unwrap();
+        assert_eq!(ids(queue.tracks().0), vec![submitted[1], submitted[3]]);
+
+        let player = room.player().unwrap();
+        wait_for(|| {
+            let current = room.current_item()?;
+            (current.track.id == submitted[1]).then_some(())
+        })
+        .await;
+
+        // Muting the submitter of the playing track stops it, since nothing else can play
+        room.set_submitter_muted(owner.id, guest.id, true).unwrap();
+        wait_for(|| player.current_sink().is_none().then_some(())).await;
+
+        assert_eq!(queue.tracks().1.len(), 2, "muted tracks aren't played");
+        assert!(queue.current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_move_tracks_to_front() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "front", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+
+        let invite = collab.rooms.create_invite(owner.id, room_id).await.unwrap();
+        collab
+            .rooms
+            .add_member_with_invite(guest.id, invite.token)
+            .await
+            .unwrap();
+
+        let mut submitted = vec![];
+        for (i, user) in [&owner, &guest, &owner, &guest].into_iter().enumerate() {
+            let track = wave_track(&format!("turntable_front_{}.wav", i), 1).await;
+            submitted.push(track.id);
+            room.add_to_queue(vec![track], user.into()).unwrap();
+        }
+
+        let ids = |items: Vec<LinearQueueItem>| -> Vec<TrackId> {
+            items.into_iter().map(|i| i.track.id).collect()
+        };
+
+        while collab.try_wait_for_event().is_ok() {}
+        assert_eq!(room.move_tracks_to_front(guest.id).unwrap(), 2);
+
+        let expected = vec![submitted[0], submitted[1], submitted[3], submitted[2]];
+        assert_eq!(
+            ids(queue.tracks().0),
+            expected,
+            "the current track stays, and the guest's tracks play next in order"
+        );
+
+        let emitted =
+            std::iter::from_fn(|| collab.try_wait_for_event().ok()).find_map(|e| match e {
+                CollabEvent::RoomQueueUpdate { items, .. } => Some(ids(items)),
+                _ => None,
+            });
+        assert_eq!(emitted, Some(expected), "the new order is emitted");
+
+        // Moving is one-shot, so later tracks are queued at the end
+        let track = wave_track("turntable_front_4.wav", 1).await;
+        let later = track.id;
+        room.add_to_queue(vec![track], (&guest).into()).unwrap();
+        assert_eq!(ids(queue.tracks().0).last(), Some(&later));
+
+        assert!(
+            matches!(
+                room.move_tracks_to_front(guest.id),
+                Err(RoomError::MoveToFrontCooldown { .. })
+            ),
+            "members can't move their tracks again right away"
+        );
+        assert_eq!(queue.tracks().0.last().map(|i| i.track.id), Some(later));
+
+        assert_eq!(room.move_tracks_to_front(owner.id).unwrap(), 1);
+        assert!(
+            room.move_tracks_to_front(owner.id).is_ok(),
+            "owners aren't limited"
+        );
+
+        assert!(matches!(
+            room.move_tracks_to_front(create_user(&collab, "other").await.id),
+            Err(RoomError::UserNotInRoom)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_requeue_from_history() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+        let guest = create_user(&collab, "guest").await;
+
+        let room_id = create_room(&collab, &owner, "history", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        // The player is paused, so only the test moves through the queue
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let mut tracks = vec![];
+        for i in 0..4 {
+            tracks.push(wave_track(&format!("turntable_history_{}.wav", i), 1).await);
+        }
+
+        let ids: Vec<_> = tracks.iter().map(|t| t.id).collect();
+        room.add_to_queue(tracks, (&owner).into()).unwrap();
+
+        let track_ids = |items: Vec<LinearQueueItem>| -> Vec<TrackId> {
+            items.into_iter().map(|i| i.track.id).collect()
+        };
+
+        // Play the first three tracks
+        for _ in 0..3 {
+            queue.next();
+        }
+
+        assert_eq!(track_ids(queue.history(2)), [ids[2], ids[1]]);
+        assert_eq!(track_ids(queue.history(10)), [ids[2], ids[1], ids[0]]);
+
+        let requeued = room.requeue_from_history(ids[0], &guest).unwrap();
+        assert_ne!(requeued, ids[0], "the requeued track is a new item");
+
+        let (items, history) = queue.tracks();
+        assert_eq!(track_ids(items.clone()), [ids[3], requeued]);
+        assert_eq!(items[1].submitter.user_id(), Some(guest.id));
+        assert_eq!(history.len(), 3, "the history is left as is");
+
+        // Only tracks in the history can be requeued
+        assert!(matches!(
+            room.requeue_from_history(ids[3], &guest),
+            Err(RoomError::Queue(QueueError::TrackNotFound(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_jingle_plays_between_tracks() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "jingle", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let jingle = wave_track("turntable_jingle.wav", 1).await;
+        let jingle_path = std::env::temp_dir().join("turntable_jingle.wav");
+
+        collab
+            .rooms
+            .update_room(UpdatedRoom {
+                id: room_id,
+                jingle: Some(format!("file://{}", jingle_path.display())),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut tracks = vec![];
+        for i in 0..2 {
+            tracks.push(wave_track(&format!("turntable_jingle_track_{}.wav", i), 1).await);
+        }
+
+        let ids: Vec<_> = tracks.iter().map(|t| t.id.to_string()).collect();
+        queue.push_many(tracks, (&owner).into()).unwrap();
+
+        let peeked = || -> Vec<String> { queue.peek().iter().map(|i| i.item_id()).collect() };
+
+        // The jingle resolves in the background
+        let started = std::time::Instant::now();
+        while peeked().len() < 3 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "jingle resolves"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let items = peeked();
+        assert_eq!([&items[0], &items[2]], [&ids[0], &ids[1]]);
+
+        let jingle_id = items[1].clone();
+        assert!(!ids.contains(&jingle_id));
+        assert_ne!(jingle_id, jingle.id.to_string(), "the jingle is a copy");
+
+        // The jingle plays once the first track ends, without being an item of the queue
+        queue.next();
+        assert_eq!(peeked(), [jingle_id.clone(), ids[1].clone()]);
+        assert_eq!(queue.tracks().0.len(), 1);
+        assert_eq!(queue.history(10).len(), 1);
+
+        queue.next();
+        assert_eq!(peeked(), [ids[1].clone()]);
+        assert_eq!(
+            queue.history(10).len(),
+            1,
+            "the jingle isn't in the history"
+        );
+
+        // There's nothing after the last track, so no jingle plays
+        queue.next();
+        assert!(peeked().is_empty());
+        assert_eq!(queue.history(10).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_explicit_filter() {
+        let config = CollabConfig {
+            explicit_blocklist: vec!["forbidden".to_string()],
+            ..Default::default()
+        };
+        let collab = Collab::with_database(config, MemoryDatabase::new()).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "family", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+
+        // The filter is off by default
+        let blocked = wave_track("turntable_forbidden_song.wav", 1).await;
+        room.add_to_queue(vec![blocked.clone()], (&owner).into())
+            .unwrap();
+
+        collab
+            .rooms
+            .update_room(UpdatedRoom {
+                id: room_id,
+                explicit_filter: Some(true),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let result = room.add_to_queue(vec![blocked.requeued()], (&owner).into());
+        assert!(
+            matches!(&result, Err(RoomError::ExplicitContent(reason)) if reason.contains("forbidden")),
+            "{:?}",
+            result
+        );
+
+        let allowed = wave_track("turntable_clean_song.wav", 1).await;
+        assert_eq!(
+            room.add_to_queue(vec![allowed], (&owner).into()).unwrap(),
+            1
+        );
+        assert_eq!(room.queue().unwrap().tracks().0.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_default_latency() {
+        let config = CollabConfig {
+            default_latency_in_ms: Some(500),
+            ..Default::default()
+        };
+        let collab = Collab::with_database(config, MemoryDatabase::new()).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let server_default = create_room(&collab, &owner, "server", RoomVisibility::Public).await;
+        let room_default = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+
+        collab
+            .rooms
+            .update_room(UpdatedRoom {
+                id: room_default,
+                default_latency_in_ms: Some(200),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let rooms = [(server_default, 500), (room_default, 200)].map(|(room_id, latency)| {
+            let room = collab.rooms.room_by_id(room_id).unwrap();
+            room.queue().unwrap();
+            (room, latency)
+        });
+
+        // Fills the preload caches, so the latency decides how much a consumer starts with
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+
+        let pipeline_config = &collab.rooms.context.config.pipeline;
+
+        for (room, latency) in rooms {
+            assert_eq!(room.stream_latency(None), latency);
+            assert_eq!(
+                room.stream_latency(Some(50)),
+                50,
+                "requested latency is kept"
+            );
+
+            let mut handle = room
+                .connect(owner.id, "test".to_string(), None, EncoderKind::Pcm, None)
+                .unwrap();
+
+            let bytes = handle.next().await.unwrap().unwrap();
+            let samples = pipeline_config.bytes_to_samples(bytes.len());
+            let millis = pipeline_config.samples_to_seconds(samples) * 1000.;
+
+            // A buffer may have been played between connecting and reading
+            assert!(
+                millis >= latency as f32 && millis <= latency as f32 + 150.,
+                "consumer started {}ms behind instead of {}ms",
+                millis,
+                latency
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_track_gain() {
+        let collab = setup_in_memory().await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "gain", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+
+        let track = wave_track("turntable_gain.wav", 1).await;
+        let track_id = track.id;
+        room.add_to_queue(vec![track], (&owner).into()).unwrap();
+
+        while collab.try_wait_for_event().is_ok() {}
+        room.set_track_gain(track_id, Some(40.)).unwrap();
+
+        let updated_gain =
+            std::iter::from_fn(|| collab.try_wait_for_event().ok()).find_map(|e| match e {
+                CollabEvent::RoomQueueUpdate { items, .. } => Some(items[0].track.gain),
+                _ => None,
+            });
+        assert_eq!(updated_gain, Some(Some(12.)), "gain is clamped and emitted");
+
+        room.set_track_gain(track_id, None).unwrap();
+        assert_eq!(queue.tracks().0[0].track.gain, None);
+
+        assert!(matches!(
+            room.set_track_gain(TrackId::new(), Some(-3.)),
+            Err(RoomError::Queue(QueueError::TrackNotFound(_)))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_recording(pool: PgPool) {
+        let dir = std::env::temp_dir().join("turntable_recording");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let config = CollabConfig {
+            recordings_dir: dir.clone(),
+            ..Default::default()
+        };
+        let collab = Collab::with_database(config, PgDatabase::from_test_pool(&pool)).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+        let pipeline = &collab.rooms.context.pipeline;
+
+        let listener = EncoderKind::Wave.consume_player(pipeline, player.id, None, None);
+
+        let track = wave_track("turntable_recording.wav", 4).await;
+        queue.push(track, (&owner).into()).unwrap();
+
+        let recording = room
+            .start_recording(RecordingOptions {
+                encoder: EncoderKind::Wave,
+                max_file_seconds: Some(0.5),
+                max_file_bytes: None,
+            })
+            .unwrap();
+
+        let result = room.start_recording(RecordingOptions::default());
+        assert!(matches!(result, Err(RoomError::AlreadyRecording)));
+
+        wait_for(|| {
+            let metadata = recording.metadata();
+            (metadata.files.len() >= 3 && !metadata.markers.is_empty()).then_some(())
+        })
+        .await;
+
+        let files = recording.metadata().files;
+        let live_file = &files.last().unwrap().name;
+
+        assert!(room.recorded_file(&files[0].name).is_ok());
+        assert!(
+            matches!(
+                room.recorded_file(live_file),
+                Err(RoomError::RecordedFileNotFound(_))
+            ),
+            "the file being written is not served"
+        );
+        assert!(room.recorded_file("../room-1-metadata.wav").is_err());
+
+        let cloned = room.clone();
+        spawn_blocking(move || cloned.stop_recording().is_ok())
+            .await
+            .unwrap()
+            .then_some(())
+            .expect("recording is stopped");
+
+        assert!(room.recording().is_none());
+        assert!(listener.bytes().is_some(), "listeners are not affected");
+
+        let metadata = recording.metadata();
+        assert_eq!(metadata.markers[0].submitter_id, Some(owner.id));
+
+        let metadata_file = metadata.files[0].name.replace("000.wav", "metadata.json");
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.join(metadata_file)).unwrap()).unwrap();
+        assert_eq!(
+            written["files"].as_array().unwrap().len(),
+            metadata.files.len()
+        );
+
+        for file in &metadata.files {
+            let bytes = std::fs::read(dir.join(&file.name)).unwrap();
+            let data_length = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+
+            assert_eq!(
+                data_length as usize,
+                bytes.len() - 44,
+                "header has the length"
+            );
+        }
+
+        for file in &metadata.files[..metadata.files.len() - 1] {
+            assert!(file.length_seconds >= 0.5);
+        }
+
+        assert!(
+            room.recorded_file(live_file).is_ok(),
+            "finished once stopped"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Polls until the closure returns something, panicking if it takes too long
+    async fn wait_for<T>(f: impl Fn() -> Option<T>) -> T {
+        for _ in 0..5000 {
+            if let Some(value) = f() {
+                return value;
+            }
+
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        panic!("timed out")
+    }
+
+    #[sqlx::test]
+    async fn test_concurrent_queue_operations(pool: PgPool) {
+        const PUSHERS: usize = 4;
+        const PUSHES: usize = 50;
+        const NEXTS: usize = 100;
+
+        let collab = setup(pool.clone()).await;
+        let owner = create_user(&collab, "owner").await;
+
+        let room_id = create_room(&collab, &owner, "room", RoomVisibility::Public).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+        let queue = room.queue().unwrap();
+        let player = room.player().unwrap();
+
+        // Only the test should advance the queue
+        player.pause();
+        while player.is_playing() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // The tracks each pusher adds, in order
+        let mut batches = vec![];
+        for _ in 0..PUSHERS {
+            let mut batch = vec![];
+
+            for _ in 0..PUSHES {
+                batch.push(file_track("turntable_concurrent_queue.wav").await);
+            }
+
+            batches.push(batch);
+        }
+
+        let pushed: Vec<Vec<TrackId>> = batches
+            .iter()
+            .map(|b| b.iter().map(|t| t.id).collect())
+            .collect();
+
+        let mut tasks = vec![];
+
+        for batch in batches {
+            let queue = queue.clone();
+            let submitter = Submitter::from(&owner);
+
+            tasks.push(spawn_blocking(move || {
+                for track in batch {
+                    queue
+                        .push(track, submitter.clone())
+                        .expect("queue is not limited");
+                }
+            }));
+        }
+
+        for _ in 0..2 {
+            let queue = queue.clone();
+
+            tasks.push(spawn_blocking(move || {
+                for _ in 0..NEXTS / 2 {
+                    queue.next();
+                }
+            }));
+        }
+
+        let reader_queue = queue.clone();
+        let reader_pushed = pushed.clone();
+
+        tasks.push(spawn_blocking(move || {
+            for _ in 0..500 {
+                let (_, ended_before) = reader_queue.tracks();
+                let current = reader_queue.current();
+                let (items, history) = reader_queue.tracks();
+
+                let order: Vec<_> = history.iter().chain(&items).map(|i| i.track.id).collect();
+
+                // Every track appears exactly once, in the order it was pushed
+                for pushed in &reader_pushed {
+                    let seen: Vec<_> = order.iter().filter(|id| pushed.contains(id)).collect();
+                    let expected: Vec<_> = pushed.iter().take(seen.len()).collect();
+
+                    assert_eq!(seen, expected);
+                }
+
+                if let Some(current) = current {
+                    let id = current.track.id;
+
+                    assert!(
+                        !ended_before.iter().any(|i| i.track.id == id),
+                        "current item has not ended"
+                    );
+                    assert!(order.contains(&id), "current item was pushed");
+                }
+            }
+        }));
+
+        for task in tasks {
+            task.await.expect("task does not panic");
+        }
+
+        let (items, history) = queue.tracks();
+        // Advancing an empty queue does nothing, so fewer items may have ended
+        assert!(history.len() <= NEXTS);
+        assert_eq!(items.len() + history.len(), PUSHERS * PUSHES);
+
+        // Let the ended items finish recording before the database is dropped
+        loop {
+            let plays: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM track_plays")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+            if plays as usize >= history.len() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
     }
 }
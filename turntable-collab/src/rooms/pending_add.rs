@@ -0,0 +1,54 @@
+use futures_util::future::BoxFuture;
+use parking_lot::{Mutex, MutexGuard};
+use turntable_core::Id;
+
+use crate::{InputError, PrimaryKey, Track, TrackId};
+
+pub type PendingAddId = Id<PendingAdd>;
+
+/// An entry of an add, resolving to the tracks it contains.
+pub type AddEntry = BoxFuture<'static, Result<Vec<Track>, InputError>>;
+
+/// An add to a room's queue whose entries are still resolving.
+pub struct PendingAdd {
+    pub id: PendingAddId,
+    /// The user the entries are added on behalf of
+    pub user_id: PrimaryKey,
+    /// How many entries the add consists of
+    pub total: usize,
+
+    state: Mutex<PendingAddState>,
+}
+
+#[derive(Debug, Default)]
+pub struct PendingAddState {
+    /// If set, no more entries are added
+    pub cancelled: bool,
+    /// The tracks that were added to the queue so far
+    pub added: Vec<TrackId>,
+    /// How many entries have resolved, including failed ones
+    pub resolved: usize,
+    /// How many entries failed to resolve or be added
+    pub failed: usize,
+}
+
+impl PendingAdd {
+    pub fn new(user_id: PrimaryKey, total: usize) -> Self {
+        Self {
+            id: PendingAddId::new(),
+            user_id,
+            total,
+            state: Default::default(),
+        }
+    }
+
+    /// Locks the state of the add.
+    /// Entries are only added while this is held, so nothing is added after cancelling under it.
+    pub fn state(&self) -> MutexGuard<'_, PendingAddState> {
+        self.state.lock()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().cancelled
+    }
+}
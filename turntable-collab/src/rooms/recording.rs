@@ -0,0 +1,304 @@
+use std::{
+    fs::{self, File},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use parking_lot::Mutex;
+use serde::Serialize;
+use turntable_core::{Consumer, Id};
+use turntable_impls::EncoderKind;
+
+use crate::{CollabContext, PrimaryKey, TrackId};
+
+use super::RoomId;
+
+pub type RecordingId = Id<Recording>;
+
+/// Decides how a room is recorded
+#[derive(Debug, Clone, Default)]
+pub struct RecordingOptions {
+    /// The format the audio is written in
+    pub encoder: EncoderKind,
+    /// A new file is started once the current one is this many seconds long
+    pub max_file_seconds: Option<f32>,
+    /// A new file is started once the audio in the current one is this many bytes
+    pub max_file_bytes: Option<usize>,
+}
+
+/// Records the audio of a room to files, rolling over to a new file when a limit is reached.
+///
+/// A metadata file describing the files and which tracks played when is written alongside them.
+pub struct Recording {
+    pub id: RecordingId,
+    pub room_id: RoomId,
+    pub started_at: DateTime<Utc>,
+    pub options: RecordingOptions,
+
+    context: CollabContext,
+    stopped: AtomicBool,
+    metadata: Mutex<RecordingMetadata>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Written alongside the recorded files
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingMetadata {
+    pub room_id: RoomId,
+    pub room_title: String,
+    /// When the recording started, in RFC 3339
+    pub started_at: String,
+    pub content_type: String,
+    pub files: Vec<RecordedFile>,
+    pub markers: Vec<TrackMarker>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedFile {
+    pub name: String,
+    /// Where the file starts in the recording, in seconds
+    pub start_seconds: f32,
+    pub length_seconds: f32,
+}
+
+/// Marks where in the recording a track started playing
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMarker {
+    /// Where the track starts in the recording, in seconds
+    pub offset_seconds: f32,
+    pub title: String,
+    pub artist: Option<String>,
+    pub canonical: String,
+    /// The user that queued the track, if it wasn't added by turntable
+    pub submitter_id: Option<PrimaryKey>,
+    pub submitter_name: String,
+}
+
+/// The file currently being written to
+struct RecordingFile {
+    file: File,
+    index: usize,
+    /// How many bytes of audio have been written, excluding the header
+    length: usize,
+}
+
+impl Recording {
+    /// Starts recording the audio of the consumer in a new thread.
+    /// The first file is created before this returns, so that invalid directories are reported.
+    pub fn start(
+        context: &CollabContext,
+        room_id: RoomId,
+        room_title: String,
+        options: RecordingOptions,
+        consumer: Consumer,
+    ) -> io::Result<Arc<Self>> {
+        fs::create_dir_all(&context.config.recordings_dir)?;
+
+        let started_at = Utc::now();
+
+        let recording = Arc::new(Self {
+            id: RecordingId::new(),
+            room_id,
+            started_at,
+            context: context.clone(),
+            stopped: Default::default(),
+            thread: Default::default(),
+            metadata: Mutex::new(RecordingMetadata {
+                room_id,
+                room_title,
+                started_at: started_at.to_rfc3339(),
+                content_type: options.encoder.content_type().to_string(),
+                files: vec![],
+                markers: vec![],
+            }),
+            options,
+        });
+
+        let file = recording.create_file(0)?;
+        let cloned = recording.clone();
+
+        let handle = thread::Builder::new()
+            .name(format!("recording-{}", room_id))
+            .spawn(move || {
+                if let Err(err) = cloned.run(consumer, file) {
+                    error!(
+                        room_id = cloned.room_id;
+                        "Recording of room {} failed: {}", cloned.room_id, err
+                    );
+                }
+
+                cloned.stopped.store(true, Ordering::Relaxed);
+            })?;
+
+        *recording.thread.lock() = Some(handle);
+        info!(
+            room_id = room_id, recording_id = recording.id.value();
+            "Started recording room {}", room_id
+        );
+
+        Ok(recording)
+    }
+
+    /// Stops the recording, waiting for the files and metadata to be written.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.thread.lock().take() {
+            let _ = handle.join();
+        }
+
+        info!(
+            room_id = self.room_id, recording_id = self.id.value();
+            "Stopped recording room {}", self.room_id
+        );
+    }
+
+    /// Returns true if the recording was stopped, or failed
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    pub fn metadata(&self) -> RecordingMetadata {
+        self.metadata.lock().clone()
+    }
+
+    /// Returns the path of a file of the recording, without extension
+    fn path(&self, name: &str) -> PathBuf {
+        let timestamp = self.started_at.format("%Y%m%d-%H%M%S");
+        let name = format!("room-{}-{}-{}", self.room_id, timestamp, name);
+
+        self.context.config.recordings_dir.join(name)
+    }
+
+    fn create_file(&self, index: usize) -> io::Result<RecordingFile> {
+        let encoder = self.options.encoder;
+        let path = self
+            .path(&format!("{:03}", index))
+            .with_extension(encoder.extension());
+
+        let mut file = File::create(&path)?;
+        file.write_all(&encoder.header(&self.context.config.pipeline, None))?;
+
+        let start_seconds = self.metadata().files.iter().map(|f| f.length_seconds).sum();
+        let name = file_name(&path);
+
+        self.metadata.lock().files.push(RecordedFile {
+            name,
+            start_seconds,
+            length_seconds: 0.,
+        });
+
+        Ok(RecordingFile {
+            file,
+            index,
+            length: 0,
+        })
+    }
+
+    /// Writes the actual length to the header of a file, now that it's known
+    fn finish_file(&self, mut file: RecordingFile) -> io::Result<()> {
+        let header = self
+            .options
+            .encoder
+            .header(&self.context.config.pipeline, Some(file.length));
+
+        file.file.seek(SeekFrom::Start(0))?;
+        file.file.write_all(&header)?;
+        file.file.flush()?;
+
+        self.write_metadata()
+    }
+
+    fn write_metadata(&self) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.metadata()).expect("metadata is serialized");
+        fs::write(self.path("metadata").with_extension("json"), json)
+    }
+
+    fn run(&self, consumer: Consumer, mut file: RecordingFile) -> io::Result<()> {
+        let encoder = self.options.encoder;
+        let config = &self.context.config.pipeline;
+
+        let bytes_per_sec = encoder.bitrate(config) as f32 / 8.;
+
+        // The consumer's output starts with a header, but each file gets its own
+        let mut header_left = encoder.header(config, None).len();
+        let mut current_track: Option<TrackId> = None;
+
+        while !self.is_stopped() {
+            let Some(mut bytes) = consumer.bytes() else {
+                continue;
+            };
+
+            let skipped = header_left.min(bytes.len());
+            bytes.drain(..skipped);
+            header_left -= skipped;
+
+            let file_seconds = file.length as f32 / bytes_per_sec;
+            let item = self
+                .context
+                .rooms
+                .get(&self.room_id)
+                .and_then(|r| r.current_item());
+
+            {
+                let mut metadata = self.metadata.lock();
+                let offset_seconds =
+                    metadata.files.last().map(|f| f.start_seconds).unwrap_or(0.) + file_seconds;
+
+                if let Some(item) = item.filter(|i| Some(i.track.id) != current_track) {
+                    current_track = Some(item.track.id);
+
+                    metadata.markers.push(TrackMarker {
+                        offset_seconds,
+                        title: item.track.metadata.title.clone(),
+                        artist: item.track.metadata.artist.clone(),
+                        canonical: item.track.metadata.canonical.clone(),
+                        submitter_id: item.submitter.user_id(),
+                        submitter_name: item.submitter.display_name().to_string(),
+                    });
+                }
+            }
+
+            file.file.write_all(&bytes)?;
+            file.length += bytes.len();
+            let length_seconds = file.length as f32 / bytes_per_sec;
+
+            if let Some(last) = self.metadata.lock().files.last_mut() {
+                last.length_seconds = length_seconds;
+            }
+
+            let exceeds_seconds = self
+                .options
+                .max_file_seconds
+                .is_some_and(|max| length_seconds >= max);
+
+            let exceeds_bytes = self
+                .options
+                .max_file_bytes
+                .is_some_and(|max| file.length >= max);
+
+            if exceeds_seconds || exceeds_bytes {
+                let index = file.index + 1;
+
+                self.finish_file(file)?;
+                file = self.create_file(index)?;
+            }
+        }
+
+        self.finish_file(file)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
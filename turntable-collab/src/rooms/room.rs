@@ -1,16 +1,27 @@
-use std::sync::Arc;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
 
-use log::info;
+use crossbeam::atomic::AtomicCell;
+use futures_util::{future::join_all, stream::FuturesOrdered, StreamExt};
+use log::{info, warn};
 use parking_lot::Mutex;
-use turntable_core::PlayerContext as Player;
-use turntable_impls::WaveEncoder;
+use tracing::{info_span, Instrument, Span};
+use turntable_core::{
+    get_or_create_handle, Consumer, LevelsSubscription, PlayerContext as Player, Queue, QueueItem,
+};
+use turntable_impls::EncoderKind;
 
 use crate::{
-    events::CollabEvent, CollabContext, LinearQueue, LinearQueueItem, PrimaryKey, RoomData,
-    RoomMemberData, WrappedQueueNotifier,
+    events::CollabEvent, CollabContext, DisconnectPolicy, EmptyRoomPolicy, InputError, LinearQueue,
+    LinearQueueItem, Metadata, Playlist, PrimaryKey, QueueError, RoomData, RoomMemberData,
+    RoomVisibility, Submitter, Track, TrackId, UserData, WrappedQueueNotifier, MAX_HISTORY_LENGTH,
 };
 
-use super::{RoomConnection, RoomConnectionHandle, RoomConnectionId, RoomError};
+use crate::stats::{is_play, record_track_end};
+
+use super::{
+    disallowed_source, explicit_content, AddEntry, PendingAdd, PendingAddId, Recording,
+    RecordingOptions, RoomConnection, RoomConnectionHandle, RoomConnectionId, RoomError,
+};
 
 pub type RoomId = PrimaryKey;
 
@@ -21,6 +32,14 @@ pub struct Room {
     data: Mutex<RoomData>,
     /// The users currently connected and listening in this room
     connections: Mutex<Vec<RoomConnection>>,
+    /// Adds to the queue whose entries are still resolving
+    pending_adds: Mutex<Vec<Arc<PendingAdd>>>,
+    /// The recording of the room's audio, if it's being recorded
+    recording: Mutex<Option<Arc<Recording>>>,
+    /// True if the room paused because its last listener disconnected, so it resumes once someone connects
+    paused_when_empty: AtomicCell<bool>,
+    /// When members last moved their tracks to the front, by user id
+    moved_to_front: Mutex<HashMap<PrimaryKey, Instant>>,
 }
 
 #[derive(Default)]
@@ -39,13 +58,18 @@ impl Room {
             context: context.clone(),
             state: Default::default(),
             connections: Default::default(),
+            pending_adds: Default::default(),
+            recording: Default::default(),
+            paused_when_empty: Default::default(),
+            moved_to_front: Default::default(),
             data: data.into(),
         }
     }
 
     /// Activates the room, which means it has an active player and queue associated.
     pub fn activate(&self) {
-        info!("Activating room...");
+        let _span = self.span().entered();
+        info!(room_id = self.id(), room:% = self.data().slug; "Activating room...");
         let new_player = self.context.pipeline.create_player();
 
         let new_queue = self
@@ -59,14 +83,93 @@ impl Room {
                 })
             });
 
-        info!("Room {} activated", self.data().title);
+        let max_queue_size = self.data().max_queue_size.map(|m| m.max(0) as usize);
+        new_queue.set_max_size(max_queue_size);
+        new_player.set_gap(track_gap_in_seconds(&self.data()));
+        new_player.set_trim_leading_silence(self.data().trim_leading_silence);
+        self.apply_jingle(&new_queue, self.data().jingle);
+
+        info!(
+            room_id = self.id(), room:% = self.data().slug, player_id = new_player.id.value();
+            "Room {} activated", self.data().title
+        );
 
         *self.state.lock() = RoomState::Active {
             player: new_player.into(),
             queue: new_queue,
+        };
+
+        self.context
+            .emit(CollabEvent::RoomResumed { room: self.data() });
+    }
+
+    /// Replaces the details of the room, applying the queue limit, track gap, silence trimming, jingle and empty room policy if active
+    pub(crate) fn set_data(&self, data: RoomData) {
+        let max_queue_size = data.max_queue_size.map(|m| m.max(0) as usize);
+        let track_gap = track_gap_in_seconds(&data);
+        let trim_leading_silence = data.trim_leading_silence;
+        let empty_room_policy = data.empty_room_policy;
+        let jingle = data.jingle.clone();
+        let jingle_changed = jingle != self.data().jingle;
+        *self.data.lock() = data;
+
+        if let RoomState::Active { queue, player } = &*self.state.lock() {
+            queue.set_max_size(max_queue_size);
+            player.set_gap(track_gap);
+            player.set_trim_leading_silence(trim_leading_silence);
+
+            if jingle_changed {
+                self.apply_jingle(queue, jingle);
+            }
+        }
+
+        match empty_room_policy {
+            EmptyRoomPolicy::AdvanceWhenEmpty => self.resume_if_paused_when_empty(),
+            EmptyRoomPolicy::PauseWhenEmpty if self.connections.lock().is_empty() => {
+                self.apply_empty_room_policy()
+            }
+            EmptyRoomPolicy::PauseWhenEmpty => {}
         }
     }
 
+    /// Has the queue play the jingle queried from `source` between tracks, or stop playing one.
+    /// The jingle resolves in the background, and isn't applied if the jingle of the room changed meanwhile.
+    fn apply_jingle(&self, queue: &Arc<LinearQueue>, source: Option<String>) {
+        let Some(source) = source else {
+            queue.set_jingle(None);
+            return;
+        };
+
+        let queue = queue.clone();
+        let context = self.context.clone();
+        let room_id = self.id();
+
+        get_or_create_handle().spawn(async move {
+            let track = context
+                .inputs
+                .query(&source)
+                .await
+                .and_then(|inputs| inputs.into_iter().next().ok_or(InputError::NotFound));
+
+            let is_current = context
+                .rooms
+                .get(&room_id)
+                .is_some_and(|r| r.data().jingle.as_deref() == Some(source.as_str()));
+
+            if !is_current {
+                return;
+            }
+
+            match track {
+                Ok(input) => queue.set_jingle(Some(input.into())),
+                Err(err) => warn!(
+                    room_id = room_id;
+                    "Jingle {} of room {} could not be resolved: {}", source, room_id, err
+                ),
+            }
+        });
+    }
+
     /// Ensure the room is activated
     fn ensure_activation(&self) {
         let is_inactive = {
@@ -91,6 +194,37 @@ impl Room {
         }
     }
 
+    /// Called when a queue item stops playing, to record it and notify about it
+    pub fn track_ended(&self, item: LinearQueueItem) {
+        let time_played = self
+            .player()
+            .ok()
+            .zip(item.track.sink_id())
+            .and_then(|(player, sink_id)| player.time_played(sink_id))
+            .unwrap_or_default();
+
+        let counted_as_play = is_play(
+            time_played,
+            item.track.length(),
+            self.context.config.play_threshold,
+        );
+
+        record_track_end(
+            &self.context,
+            self.id(),
+            &item,
+            time_played,
+            counted_as_play,
+        );
+
+        self.context.emit(CollabEvent::TrackEnded {
+            room_id: self.id(),
+            item,
+            time_played,
+            counted_as_play,
+        });
+    }
+
     /// Gets the associated queue if the room is active
     pub fn queue(&self) -> Result<Arc<LinearQueue>, RoomError> {
         // Activate room if queue is accessed
@@ -104,6 +238,410 @@ impl Room {
         }
     }
 
+    /// Exports the queue as a playlist, starting with the history if `include_history` is true.
+    pub fn export_playlist(&self, include_history: bool) -> Result<Playlist, RoomError> {
+        let (items, history) = self.queue()?.tracks();
+        let history = history.into_iter().filter(|_| include_history);
+
+        Ok(Playlist::from_metadata(
+            history.chain(items).map(|i| i.track.metadata),
+        ))
+    }
+
+    /// Adds tracks to the queue on behalf of a user, returning how many were added.
+    /// Nothing is added if any of the tracks come from a source the room doesn't allow,
+    /// are rejected by its explicit content filter, or play for longer than the room allows.
+    ///
+    /// Tracks with an unknown length are cut off at the maximum duration instead, once they play for that long.
+    pub fn add_to_queue(
+        &self,
+        tracks: Vec<Track>,
+        submitter: Submitter,
+    ) -> Result<usize, RoomError> {
+        let tracks = self.allowed_tracks(tracks)?;
+
+        self.queue()?
+            .push_many(tracks, submitter)
+            .map_err(RoomError::Queue)
+    }
+
+    /// Checks that the room allows the tracks, limiting their duration if the room does
+    fn allowed_tracks(&self, tracks: Vec<Track>) -> Result<Vec<Track>, RoomError> {
+        let data = self.data();
+
+        for track in &tracks {
+            let disallowed =
+                disallowed_source(&data.allowed_sources, &data.denied_sources, &track.metadata);
+
+            if let Some(source) = disallowed {
+                return Err(RoomError::SourceNotAllowed(source));
+            }
+
+            if let Some(reason) = self.explicit_content(&track.metadata) {
+                return Err(RoomError::ExplicitContent(reason));
+            }
+
+            let too_long = data
+                .max_track_duration
+                .zip(track.played_length())
+                .filter(|(max, length)| *length > *max as f32);
+
+            if let Some((max, length)) = too_long {
+                return Err(RoomError::TrackTooLong { length, max });
+            }
+        }
+
+        Ok(match data.max_track_duration {
+            Some(max) => tracks
+                .into_iter()
+                .map(|t| t.with_max_duration(max as f32))
+                .collect(),
+            None => tracks,
+        })
+    }
+
+    /// Resolves all entries at once, then adds their tracks to the queue in order with a single update.
+    /// Returns the ids of the tracks each entry added, or why it added none.
+    ///
+    /// Unlike [Room::add_to_queue], an entry that can't be added doesn't keep the others from being added.
+    /// If the queue fills up, the tracks of the last entries are left out.
+    pub async fn add_batch(
+        &self,
+        entries: Vec<AddEntry>,
+        submitter: Submitter,
+    ) -> Result<Vec<Result<Vec<TrackId>, RoomError>>, RoomError> {
+        let queue = self.queue()?;
+        let mut tracks = vec![];
+
+        let mut results: Vec<_> = join_all(entries)
+            .await
+            .into_iter()
+            .map(|resolved| {
+                let allowed = resolved
+                    .map_err(RoomError::Input)
+                    .and_then(|t| self.allowed_tracks(t))?;
+
+                let ids: Vec<_> = allowed.iter().map(|t| t.id).collect();
+                tracks.extend(allowed);
+
+                Ok(ids)
+            })
+            .collect();
+
+        let max_queue_size = self.data().max_queue_size.map(|m| m.max(0) as usize);
+        let (added, max) = match queue.push_many(tracks, submitter) {
+            Ok(added) => (added, max_queue_size.unwrap_or_default()),
+            Err(QueueError::Full { max }) => (0, max),
+            Err(err) => return Err(RoomError::Queue(err)),
+        };
+
+        // Tracks past the amount added were left out, since the queue is full
+        let mut position = 0;
+
+        for result in &mut results {
+            let Ok(ids) = result else {
+                continue;
+            };
+
+            let fitting = added.saturating_sub(position).min(ids.len());
+            position += ids.len();
+
+            if fitting == 0 && !ids.is_empty() {
+                *result = Err(RoomError::Queue(QueueError::Full { max }));
+            } else {
+                ids.truncate(fitting);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Adds the tracks of each entry to the queue as they resolve, in the order of the entries.
+    /// Returns the pending add right away, which can be used to cancel the remaining entries.
+    /// Why an entry couldn't be added is reported in its [CollabEvent::QueueAddProgress].
+    pub fn add_entries(
+        self: &Arc<Self>,
+        entries: Vec<AddEntry>,
+        user: &UserData,
+    ) -> Arc<PendingAdd> {
+        let (user_id, submitter) = (user.id, Submitter::from(user));
+        let pending = Arc::new(PendingAdd::new(user_id, entries.len()));
+        self.pending_adds.lock().push(pending.clone());
+
+        let room = self.clone();
+        let add = pending.clone();
+
+        let task = async move {
+            // Entries resolve concurrently, but are added in order
+            let mut results: FuturesOrdered<_> = entries.into_iter().collect();
+
+            while let Some(result) = results.next().await {
+                let mut state = add.state();
+
+                if state.cancelled {
+                    break;
+                }
+
+                let added = result.map_err(RoomError::Input).and_then(|tracks| {
+                    let ids: Vec<_> = tracks.iter().map(|t| t.id).collect();
+                    let added = room.add_to_queue(tracks, submitter.clone())?;
+
+                    Ok(ids.into_iter().take(added).collect::<Vec<_>>())
+                });
+
+                let error = match added {
+                    Ok(ids) => {
+                        state.added.extend(ids);
+                        None
+                    }
+                    Err(err) => {
+                        warn!(
+                            room_id = room.id(), user_id = add.user_id;
+                            "Entry of add {} could not be added: {}", add.id, err
+                        );
+                        state.failed += 1;
+                        Some(err.to_string())
+                    }
+                };
+
+                state.resolved += 1;
+
+                room.context.emit(CollabEvent::QueueAddProgress {
+                    room_id: room.id(),
+                    add_id: add.id,
+                    user_id,
+                    resolved: state.resolved,
+                    failed: state.failed,
+                    total: add.total,
+                    error,
+                });
+            }
+
+            room.pending_adds.lock().retain(|p| p.id != add.id);
+
+            room.context.emit(CollabEvent::QueueAddFinished {
+                room_id: room.id(),
+                add_id: add.id,
+                cancelled: add.is_cancelled(),
+            });
+        };
+
+        get_or_create_handle().spawn(task.instrument(self.span()));
+
+        pending
+    }
+
+    /// Cancels an add, so that no more of its entries are added once this returns.
+    /// If `remove_added` is true, the tracks it already added are also removed from the queue,
+    /// except the one at the front, since it may be playing.
+    pub fn cancel_add(
+        &self,
+        user_id: PrimaryKey,
+        add_id: PendingAddId,
+        remove_added: bool,
+    ) -> Result<(), RoomError> {
+        let pending = self
+            .pending_adds
+            .lock()
+            .iter()
+            .find(|p| p.id == add_id && p.user_id == user_id)
+            .cloned()
+            .ok_or(RoomError::AddNotFound(add_id))?;
+
+        let mut state = pending.state();
+        state.cancelled = true;
+
+        if remove_added {
+            self.queue()?.remove(&state.added);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a track in the queue or history matching the predicate, without activating the room
+    pub fn find_track<F>(&self, predicate: F) -> Option<Track>
+    where
+        F: Fn(&Track) -> bool,
+    {
+        let state = self.state.lock();
+
+        match &*state {
+            RoomState::Inactive => None,
+            RoomState::Active { player: _, queue } => {
+                let (items, history) = queue.tracks();
+
+                items
+                    .into_iter()
+                    .chain(history)
+                    .map(|i| i.track)
+                    .find(|t| predicate(t))
+            }
+        }
+    }
+
+    /// Updates the metadata of a track in the queue if its input has more details now, like after activation.
+    /// Returns true if the metadata was updated.
+    ///
+    /// Whether a track is explicit may only be known now, so tracks the explicit content filter
+    /// rejects with their new metadata are skipped and removed from the queue.
+    pub fn refresh_metadata(&self, track_id: TrackId) -> bool {
+        // Finding the track first keeps an inactive room from being activated
+        let updated = self
+            .find_track(|t| t.id == track_id)
+            .and_then(|t| t.updated_metadata());
+
+        let (Some(metadata), Ok(queue)) = (updated, self.queue()) else {
+            return false;
+        };
+
+        let explicit = self.explicit_content(&metadata);
+
+        if !queue.update_metadata(track_id, metadata) {
+            return false;
+        }
+
+        if let Some(reason) = explicit {
+            info!(room_id = self.id(), track_id:% = track_id; "Removed track {} from the queue, since {}", track_id, reason);
+
+            if queue.current().is_some_and(|i| i.track.id == track_id) {
+                queue.next();
+            }
+
+            queue.remove(&[track_id]);
+        }
+
+        true
+    }
+
+    /// Returns why the explicit content filter of the room rejects a track, if it's on and does
+    fn explicit_content(&self, metadata: &Metadata) -> Option<String> {
+        self.data
+            .lock()
+            .explicit_filter
+            .then(|| explicit_content(&self.context.config.explicit_blocklist, metadata))
+            .flatten()
+    }
+
+    /// Seeks to a fraction of the current item's length, where 0.0 is the start and 1.0 is the end.
+    /// Fails if the length of the current item isn't known, like for live streams.
+    pub fn seek_fraction(&self, user_id: PrimaryKey, fraction: f32) -> Result<(), RoomError> {
+        let _ = self.member_by_user_id(user_id)?;
+
+        let player = self.player()?;
+        let length = player.current_length().ok_or(RoomError::UnknownLength)?;
+
+        let offset = (length as f32 * fraction.clamp(0., 1.)) as usize;
+        player.seek(self.context.config.pipeline.samples_to_seconds(offset));
+
+        Ok(())
+    }
+
+    /// Mutes or unmutes the tracks a user queued on behalf of an owner of the room.
+    /// Muted tracks don't play but stay queued, so they play once the user is unmuted.
+    pub fn set_submitter_muted(
+        &self,
+        owner_id: PrimaryKey,
+        user_id: PrimaryKey,
+        muted: bool,
+    ) -> Result<(), RoomError> {
+        if !self.member_by_user_id(owner_id)?.owner {
+            return Err(RoomError::OwnerRequired);
+        }
+
+        if self.queue()?.set_muted(user_id, muted) {
+            info!(
+                room_id = self.id(), user_id = user_id;
+                "{} queued items of user {}", if muted { "Muted" } else { "Unmuted" }, user_id
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Queues a track from the history again on behalf of a user, at the end of the queue.
+    /// Returns the id of the new track.
+    pub fn requeue_from_history(
+        &self,
+        track_id: TrackId,
+        user: &UserData,
+    ) -> Result<TrackId, RoomError> {
+        let track = self
+            .queue()?
+            .history(MAX_HISTORY_LENGTH)
+            .into_iter()
+            .find(|i| i.track.id == track_id)
+            .map(|i| i.track.requeued())
+            .ok_or(RoomError::Queue(QueueError::TrackNotFound(track_id)))?;
+
+        let new_id = track.id;
+        self.add_to_queue(vec![track], user.into())?;
+
+        info!(
+            room_id = self.id(), user_id = user.id;
+            "User {} queued track {} from the history again", user.id, track_id
+        );
+
+        Ok(new_id)
+    }
+
+    /// Plays the tracks a member queued next, see [LinearQueue::move_submitted_to_front].
+    /// Returns how many tracks were moved.
+    ///
+    /// Members that aren't owners can only do this once per [crate::CollabConfig::move_to_front_cooldown],
+    /// so they can't keep their tracks ahead of everyone else's.
+    pub fn move_tracks_to_front(&self, user_id: PrimaryKey) -> Result<usize, RoomError> {
+        let member = self.member_by_user_id(user_id)?;
+        let queue = self.queue()?;
+
+        if !member.owner {
+            let cooldown = self.context.config.move_to_front_cooldown;
+            let mut moved_to_front = self.moved_to_front.lock();
+
+            if let Some(last) = moved_to_front
+                .get(&user_id)
+                .filter(|l| l.elapsed() < cooldown)
+            {
+                return Err(RoomError::MoveToFrontCooldown {
+                    retry_after: cooldown.saturating_sub(last.elapsed()),
+                });
+            }
+
+            moved_to_front.insert(user_id, Instant::now());
+        }
+
+        let moved = queue.move_submitted_to_front(user_id);
+
+        if moved > 0 {
+            info!(
+                room_id = self.id(), user_id = user_id;
+                "Moved {} queued items of user {} to the front", moved, user_id
+            );
+        }
+
+        Ok(moved)
+    }
+
+    /// Adjusts the gain of a queued track in dB, or resets it with [None], see [LinearQueue::set_gain].
+    pub fn set_track_gain(&self, track_id: TrackId, gain: Option<f32>) -> Result<(), RoomError> {
+        self.queue()?
+            .set_gain(track_id, gain)
+            .map_err(RoomError::Queue)
+    }
+
+    /// Rebuilds the player from the queue and resumes playback, to recover a room that is stuck.
+    /// The current track starts over, and other rooms aren't affected.
+    pub fn reseat(&self) -> Result<(), RoomError> {
+        let data = self.data();
+
+        warn!(
+            room_id = self.id(), room:% = data.slug;
+            "Reseating the player of room {}", data.title
+        );
+
+        self.player()?.reseat();
+        Ok(())
+    }
+
     /// Gets the player if the room is active
     pub fn player(&self) -> Result<Arc<Player>, RoomError> {
         let state = self.state.lock();
@@ -114,6 +652,95 @@ impl Room {
         }
     }
 
+    /// Makes the room emit [CollabEvent::Levels] until the subscription is dropped, activating it if needed.
+    pub fn subscribe_levels(&self) -> Result<LevelsSubscription, RoomError> {
+        self.ensure_activation();
+        self.player().map(|p| p.subscribe_levels())
+    }
+
+    /// Starts recording the audio of the room to disk, without affecting listeners.
+    pub fn start_recording(&self, options: RecordingOptions) -> Result<Arc<Recording>, RoomError> {
+        let mut recording = self.recording.lock();
+
+        if recording.as_ref().is_some_and(|r| !r.is_stopped()) {
+            return Err(RoomError::AlreadyRecording);
+        }
+
+        self.ensure_activation();
+        let player = self.player()?;
+
+        // The recording should start from now, so nothing already played is included
+        let consumer =
+            options
+                .encoder
+                .consume_player(&self.context.pipeline, player.id, Some(0), None);
+
+        let new_recording = Recording::start(
+            &self.context,
+            self.id(),
+            self.data().title,
+            options,
+            consumer,
+        )
+        .map_err(RoomError::Recording)?;
+
+        *recording = Some(new_recording.clone());
+        Ok(new_recording)
+    }
+
+    /// Stops recording the room, returning the finished recording.
+    ///
+    /// Note: This blocks until the files are written.
+    pub fn stop_recording(&self) -> Result<Arc<Recording>, RoomError> {
+        let recording = self.recording().ok_or(RoomError::NotRecording)?;
+
+        // The recording is kept until the files are written, so the last one isn't served before that
+        recording.stop();
+
+        let mut current = self.recording.lock();
+
+        if current.as_ref().is_some_and(|r| Arc::ptr_eq(r, &recording)) {
+            *current = None;
+        }
+
+        Ok(recording)
+    }
+
+    /// Returns the path of a finished file recorded from the room, and the encoder it was written with.
+    /// The file that is still being written to is live, so it has no known length and isn't returned.
+    pub fn recorded_file(&self, name: &str) -> Result<(PathBuf, EncoderKind), RoomError> {
+        let not_found = || RoomError::RecordedFileNotFound(name.to_string());
+
+        let is_own = name.starts_with(&format!("room-{}-", self.id()));
+        let is_plain_name = !name.contains(['/', '\\']) && !name.contains("..");
+
+        if !is_own || !is_plain_name {
+            return Err(not_found());
+        }
+
+        let encoder = EncoderKind::ALL
+            .into_iter()
+            .find(|e| name.ends_with(&format!(".{}", e.extension())))
+            .ok_or_else(not_found)?;
+
+        let is_live = self
+            .recording()
+            .is_some_and(|r| r.metadata().files.last().is_some_and(|f| f.name == name));
+
+        let path = self.context.config.recordings_dir.join(name);
+
+        if is_live || !path.is_file() {
+            return Err(not_found());
+        }
+
+        Ok((path, encoder))
+    }
+
+    /// Returns the recording of the room, if it's being recorded
+    pub fn recording(&self) -> Option<Arc<Recording>> {
+        self.recording.lock().clone()
+    }
+
     /// Registers an added member to the room
     pub fn add_member(&self, new_member: RoomMemberData) {
         self.data.lock().members.push(new_member.clone());
@@ -135,13 +762,58 @@ impl Room {
             .ok_or(RoomError::UserNotInRoom)
     }
 
+    /// Returns true if the user is allowed to resolve the room
+    pub fn is_visible_to(&self, user_id: PrimaryKey) -> bool {
+        let data = self.data.lock();
+
+        match data.visibility {
+            RoomVisibility::Public | RoomVisibility::Unlisted => true,
+            RoomVisibility::Private => data.members.iter().any(|m| m.user.id == user_id),
+        }
+    }
+
+    /// Returns true if the room can be resolved without being a member
+    pub fn is_visible_to_anyone(&self) -> bool {
+        self.data.lock().visibility != RoomVisibility::Private
+    }
+
+    /// Returns true if the room should appear in listings for the user
+    pub fn is_listed_for(&self, user_id: PrimaryKey) -> bool {
+        let data = self.data.lock();
+
+        match data.visibility {
+            RoomVisibility::Public => true,
+            RoomVisibility::Unlisted | RoomVisibility::Private => {
+                data.members.iter().any(|m| m.user.id == user_id)
+            }
+        }
+    }
+
+    /// Returns the latency in milliseconds of a stream that asks for `requested`.
+    /// Streams that don't ask get the room's default, then the server's, then as much as the preload cache holds.
+    pub fn stream_latency(&self, requested: Option<u32>) -> u32 {
+        let config = &self.context.config;
+        let max = (config.pipeline.stream_preload_cache_size_in_seconds * 1000.) as u32;
+
+        requested
+            .or(self.data().default_latency_in_ms.map(|l| l as u32))
+            .or(config.default_latency_in_ms)
+            .unwrap_or(max)
+            .min(max)
+    }
+
     /// Creates a stream connection to the room.
+    /// The `token` identifies the client, so that it is recognized when it reconnects.
     pub fn connect(
         &self,
         user_id: PrimaryKey,
         source: String,
         with_latency: Option<u32>,
+        encoder: EncoderKind,
+        token: Option<String>,
     ) -> Result<RoomConnectionHandle, RoomError> {
+        let _span = self.span().entered();
+
         // Ensure the user is actually in the room before doing anything else
         let member = self.member_by_user_id(user_id)?;
 
@@ -149,17 +821,28 @@ impl Room {
         self.ensure_activation();
 
         let player = self.player()?;
-        let stream = self
-            .context
-            .pipeline
-            .consume_player::<WaveEncoder>(player.id, with_latency);
+
+        // Held until the connection is added, so concurrent connects can't exceed the limits
+        let mut connections = self.connections.lock();
+        let mut user_connections = self.context.user_connections.lock();
+        self.check_connection_limits(&connections, &user_connections, user_id)?;
+
+        let latency = self.stream_latency(with_latency);
+        let stream =
+            encoder.consume_player(&self.context.pipeline, player.id, Some(latency), token);
 
         let connection = RoomConnection::new(user_id, stream.id, source.clone());
         let connection_id = connection.id;
 
-        self.connections.lock().push(connection);
+        connections.push(connection);
+        *user_connections.entry(user_id).or_default() += 1;
+        drop(user_connections);
+        drop(connections);
+
+        self.resume_if_paused_when_empty();
 
         info!(
+            room_id = self.id(), room:% = self.data().slug, user_id = user_id, source:% = source;
             "User {} connected to room {} via {}",
             member.user.display_name,
             self.data().title,
@@ -180,8 +863,56 @@ impl Room {
         ))
     }
 
+    fn check_connection_limits(
+        &self,
+        connections: &[RoomConnection],
+        user_connections: &HashMap<PrimaryKey, usize>,
+        user_id: PrimaryKey,
+    ) -> Result<(), RoomError> {
+        let config = &self.context.config;
+
+        if let Some(max) = config.max_connections_per_room {
+            if connections.len() >= max {
+                return Err(RoomError::TooManyConnections { max });
+            }
+        }
+
+        if let Some(max) = config.max_connections_per_user {
+            if user_connections.get(&user_id).copied().unwrap_or_default() >= max {
+                return Err(RoomError::TooManyUserConnections { max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates an anonymous stream of the room, for sampling it without joining.
+    /// Only public rooms can be previewed, and previews aren't counted as connections.
+    ///
+    /// Previews don't activate the room, so only rooms someone is listening to can be previewed.
+    pub fn preview(&self, encoder: EncoderKind) -> Result<Consumer, RoomError> {
+        let data = self.data();
+
+        if data.visibility != RoomVisibility::Public {
+            return Err(RoomError::RoomNotFound(data.slug));
+        }
+
+        let player = self.player()?;
+        let latency = self.stream_latency(None);
+        let consumer =
+            encoder.consume_player(&self.context.pipeline, player.id, Some(latency), None);
+
+        info!(
+            room_id = self.id(), room:% = data.slug, consumer_id = consumer.id.value();
+            "Started preview of room {}", data.title
+        );
+
+        Ok(consumer)
+    }
+
     /// Called when a [RoomConnectionHandle] is dropped
     pub fn remove_connection(&self, connection_id: RoomConnectionId) {
+        let _span = self.span().entered();
         let mut connections = self.connections.lock();
 
         let connection = connections
@@ -196,6 +927,8 @@ impl Room {
             .unwrap_or_else(|| "Unknown".to_string());
 
         info!(
+            room_id = self.id(), room:% = self.data().slug,
+            user_id = connection.user_id, source:% = connection.source;
             "User {} disconnected from room {} via {}",
             member,
             self.data().title,
@@ -208,7 +941,82 @@ impl Room {
             source: connection.source.to_owned(),
         });
 
-        connections.retain(|c| c.id != connection_id)
+        let user_id = connection.user_id;
+        connections.retain(|c| c.id != connection_id);
+
+        let mut user_connections = self.context.user_connections.lock();
+        if let Some(count) = user_connections.get_mut(&user_id) {
+            *count = count.saturating_sub(1);
+
+            if *count == 0 {
+                user_connections.remove(&user_id);
+            }
+        }
+        drop(user_connections);
+
+        let still_connected = connections.iter().any(|c| c.user_id == user_id);
+        let is_empty = connections.is_empty();
+        drop(connections);
+
+        if !still_connected {
+            self.apply_disconnect_policy(user_id);
+        }
+
+        if is_empty {
+            self.apply_empty_room_policy();
+        }
+    }
+
+    /// Pauses the room once nobody is listening, if its policy says so
+    fn apply_empty_room_policy(&self) {
+        if self.data().empty_room_policy != EmptyRoomPolicy::PauseWhenEmpty {
+            return;
+        }
+
+        // A room that was paused by a member stays paused when someone connects
+        if let Some(player) = self.player().ok().filter(|p| p.is_playing()) {
+            info!(
+                room_id = self.id(), room:% = self.data().slug;
+                "Pausing room {}, since nobody is listening", self.data().title
+            );
+
+            self.paused_when_empty.store(true);
+            player.pause();
+        }
+    }
+
+    /// Resumes the room if it was paused because nobody was listening
+    fn resume_if_paused_when_empty(&self) {
+        if !self.paused_when_empty.swap(false) {
+            return;
+        }
+
+        if let Ok(player) = self.player() {
+            info!(
+                room_id = self.id(), room:% = self.data().slug;
+                "Resuming room {}", self.data().title
+            );
+
+            player.play();
+        }
+    }
+
+    /// Handles the queued items of a member that has no connections left, according to the room's policy
+    fn apply_disconnect_policy(&self, user_id: PrimaryKey) {
+        if self.data().disconnect_policy != DisconnectPolicy::RemoveQueued {
+            return;
+        }
+
+        let state = self.state.lock();
+
+        if let RoomState::Active { queue, .. } = &*state {
+            let removed = queue.remove_submitted_by(user_id);
+            info!(
+                room_id = self.id(), user_id = user_id;
+                "Removed {} queued items of disconnected user {}",
+                removed, user_id
+            );
+        }
     }
 
     /// Returns the current connections. This can be the same member multiple times.
@@ -216,6 +1024,26 @@ impl Room {
         self.connections.lock().clone()
     }
 
+    /// Returns how many clients are listening to the room, where a client that reconnected counts once.
+    /// Previews count as listeners too, but a recording doesn't.
+    pub fn unique_listeners(&self) -> usize {
+        let Ok(player) = self.player() else {
+            return 0;
+        };
+
+        let recording = self.recording().is_some_and(|r| !r.is_stopped());
+
+        self.context
+            .pipeline
+            .unique_listeners(player.id)
+            .saturating_sub(recording as usize)
+    }
+
+    /// Returns a span to run operations on the room in, so what happens during them can be told apart by room.
+    pub fn span(&self) -> Span {
+        info_span!("room", room_id = self.id(), room = %self.data().slug)
+    }
+
     pub fn data(&self) -> RoomData {
         self.data.lock().clone()
     }
@@ -224,3 +1052,7 @@ impl Room {
         self.data().id
     }
 }
+
+fn track_gap_in_seconds(data: &RoomData) -> f32 {
+    data.track_gap_in_ms.max(0) as f32 / 1000.
+}
@@ -0,0 +1,105 @@
+use thiserror::Error;
+
+/// Slugs that would collide with the paths of room endpoints
+pub const RESERVED_SLUGS: [&str; 2] = ["invites", "members"];
+
+pub const MIN_SLUG_LENGTH: usize = 3;
+pub const MAX_SLUG_LENGTH: usize = 64;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SlugError {
+    #[error("Slug must be between {MIN_SLUG_LENGTH} and {MAX_SLUG_LENGTH} characters")]
+    InvalidLength,
+    #[error("Slug can only contain letters, numbers, and hyphens")]
+    InvalidCharacters,
+    #[error("Slug cannot start or end with a hyphen")]
+    OuterHyphen,
+    #[error("Slug {0} is reserved")]
+    Reserved(String),
+}
+
+/// Normalizes a slug to lowercase and validates it, returning the normalized slug.
+pub fn normalize_slug(slug: &str) -> Result<String, SlugError> {
+    let slug = slug.trim().to_lowercase();
+
+    if !(MIN_SLUG_LENGTH..=MAX_SLUG_LENGTH).contains(&slug.len()) {
+        return Err(SlugError::InvalidLength);
+    }
+
+    if !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(SlugError::InvalidCharacters);
+    }
+
+    if slug.starts_with('-') || slug.ends_with('-') {
+        return Err(SlugError::OuterHyphen);
+    }
+
+    if RESERVED_SLUGS.contains(&slug.as_str()) {
+        return Err(SlugError::Reserved(slug));
+    }
+
+    Ok(slug)
+}
+
+/// Derives a slug from a title, replacing anything that isn't a letter or number with a hyphen.
+/// The result still has to be validated, since the title may not contain enough usable characters.
+pub fn slug_from_title(title: &str) -> String {
+    let words: Vec<_> = title
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let mut slug = words.join("-");
+    slug.truncate(MAX_SLUG_LENGTH);
+
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_slug, slug_from_title, SlugError};
+
+    #[test]
+    fn test_accepted_slugs() {
+        assert_eq!(normalize_slug("room").unwrap(), "room");
+        assert_eq!(normalize_slug(" My-Room-2 ").unwrap(), "my-room-2");
+        assert_eq!(normalize_slug(&"a".repeat(64)).unwrap(), "a".repeat(64));
+    }
+
+    #[test]
+    fn test_rejected_slugs() {
+        assert_eq!(normalize_slug("ab"), Err(SlugError::InvalidLength));
+        assert_eq!(
+            normalize_slug(&"a".repeat(65)),
+            Err(SlugError::InvalidLength)
+        );
+        assert_eq!(normalize_slug("my room"), Err(SlugError::InvalidCharacters));
+        assert_eq!(
+            normalize_slug("room/../x"),
+            Err(SlugError::InvalidCharacters)
+        );
+        assert_eq!(normalize_slug("rööm"), Err(SlugError::InvalidCharacters));
+        assert_eq!(normalize_slug("-room"), Err(SlugError::OuterHyphen));
+        assert_eq!(normalize_slug("room-"), Err(SlugError::OuterHyphen));
+        assert_eq!(
+            normalize_slug("Invites"),
+            Err(SlugError::Reserved("invites".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_slug_from_title() {
+        assert_eq!(slug_from_title("Friday Night Jams!"), "friday-night-jams");
+        assert_eq!(
+            slug_from_title("  Lo-fi // beats, 24/7 "),
+            "lo-fi-beats-24-7"
+        );
+        assert_eq!(slug_from_title("???"), "");
+
+        let long = slug_from_title(&"word ".repeat(20));
+        assert!(long.len() <= 64);
+        assert!(!long.ends_with('-'));
+    }
+}
@@ -0,0 +1,146 @@
+use url::Url;
+
+use crate::Metadata;
+
+/// Returns true if an entry of a room's source lists matches the input.
+/// An entry matches either the name of the source, like `youtube`,
+/// or the domain of the canonical URL, including its subdomains.
+pub fn source_matches(entry: &str, metadata: &Metadata) -> bool {
+    let entry = entry.trim().to_lowercase();
+
+    if entry.is_empty() {
+        return false;
+    }
+
+    if metadata.source.to_lowercase() == entry {
+        return true;
+    }
+
+    Url::parse(&metadata.canonical)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .is_some_and(|host| host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+/// Returns the source that isn't allowed, if the lists reject the input.
+/// Denied entries take precedence, and an empty allowlist allows any source that isn't denied.
+pub fn disallowed_source(
+    allowed: &[String],
+    denied: &[String],
+    metadata: &Metadata,
+) -> Option<String> {
+    if let Some(entry) = denied.iter().find(|e| source_matches(e, metadata)) {
+        return Some(entry.trim().to_lowercase());
+    }
+
+    if !allowed.is_empty() && !allowed.iter().any(|e| source_matches(e, metadata)) {
+        return Some(metadata.source.clone());
+    }
+
+    None
+}
+
+/// Returns why the explicit content filter rejects the input, if it does.
+/// Inputs are rejected if their source flags them as explicit, or if their title or artist
+/// contains an entry of the blocklist, like a keyword or channel name, as whole words.
+pub fn explicit_content(blocklist: &[String], metadata: &Metadata) -> Option<String> {
+    if metadata.explicit {
+        return Some(format!("it is flagged as explicit by {}", metadata.source));
+    }
+
+    let title = words(&metadata.title);
+    let artist = words(metadata.artist.as_deref().unwrap_or_default());
+
+    blocklist
+        .iter()
+        .map(|entry| words(entry))
+        .filter(|entry| !entry.trim().is_empty())
+        .find(|entry| title.contains(entry.as_str()) || artist.contains(entry.as_str()))
+        .map(|entry| format!("it matches \"{}\" of the blocklist", entry.trim()))
+}
+
+/// Lowercases the words of the text and separates them with single spaces, padded with a space on each side.
+/// This way, a padded phrase is only contained in it as whole words.
+fn words(text: &str) -> String {
+    let words: Vec<_> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    format!(" {} ", words.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disallowed_source, explicit_content, source_matches};
+    use crate::Metadata;
+
+    fn metadata(source: &str, canonical: &str) -> Metadata {
+        Metadata {
+            title: "Title".to_string(),
+            artist: None,
+            canonical: canonical.to_string(),
+            source: source.to_string(),
+            duration: 0.,
+            artwork: None,
+            explicit: false,
+        }
+    }
+
+    #[test]
+    fn test_source_matches() {
+        let video = metadata("youtube", "https://www.youtube.com/v/abc");
+
+        assert!(source_matches("youtube", &video));
+        assert!(source_matches(" YouTube ", &video));
+        assert!(source_matches("youtube.com", &video));
+        assert!(source_matches("www.youtube.com", &video));
+        assert!(!source_matches("tube.com", &video));
+        assert!(!source_matches("", &video));
+
+        let file = metadata("file", "/music/track.wav");
+        assert!(source_matches("file", &file));
+        assert!(!source_matches("music", &file));
+    }
+
+    #[test]
+    fn test_disallowed_source() {
+        let video = metadata("youtube", "https://youtube.com/v/abc");
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(disallowed_source(&[], &[], &video), None);
+        assert_eq!(disallowed_source(&strings(&["youtube"]), &[], &video), None);
+        assert_eq!(
+            disallowed_source(&strings(&["wavedistrict"]), &[], &video),
+            Some("youtube".to_string())
+        );
+        assert_eq!(
+            disallowed_source(&strings(&["youtube"]), &strings(&["YouTube.com"]), &video),
+            Some("youtube.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_explicit_content() {
+        let blocklist = vec!["Bad Word".to_string(), "ass".to_string(), " ".to_string()];
+        let mut video = metadata("youtube", "https://youtube.com/v/abc");
+
+        assert_eq!(explicit_content(&blocklist, &video), None);
+
+        video.title = "The BAD-word song".to_string();
+        assert!(explicit_content(&blocklist, &video).is_some_and(|r| r.contains("bad word")));
+
+        // Only whole words match
+        video.title = "Classical music".to_string();
+        assert_eq!(explicit_content(&blocklist, &video), None);
+
+        video.artist = Some("Ass".to_string());
+        assert!(explicit_content(&blocklist, &video).is_some());
+
+        // Flags of the source count even without a blocklist
+        video.artist = None;
+        video.explicit = true;
+        assert!(explicit_content(&[], &video).is_some_and(|r| r.contains("youtube")));
+    }
+}
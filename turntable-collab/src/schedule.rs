@@ -0,0 +1,396 @@
+use std::{sync::Arc, thread};
+
+use chrono::{DateTime, Utc};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use futures_util::FutureExt;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use turntable_core::get_or_create_handle;
+
+use crate::{
+    AddEntry, CollabContext, DatabaseError, MissedSchedulePolicy, NewRoomSchedule, PrimaryKey,
+    Room, RoomError, RoomScheduleData, ScheduledAction, Track,
+};
+
+type Schedules = Arc<Mutex<Vec<RoomScheduleData>>>;
+
+/// Performs actions in rooms at the time they were scheduled for, like starting an event.
+/// Schedules are persisted, so they still happen after a restart.
+pub struct Scheduler {
+    context: CollabContext,
+    /// The schedules that haven't happened yet
+    schedules: Schedules,
+    /// Wakes the timer thread up, so it sees schedules that were added
+    wake: Sender<()>,
+}
+
+impl Scheduler {
+    pub fn new(context: &CollabContext) -> Self {
+        let schedules: Schedules = Default::default();
+        let (wake, woken) = unbounded();
+
+        spawn_timer_thread(context, &schedules, woken);
+
+        Self {
+            context: context.clone(),
+            schedules,
+            wake,
+        }
+    }
+
+    /// Restores the schedules from the database on init.
+    /// The ones that were due while turntable wasn't running are handled by the [MissedSchedulePolicy].
+    pub async fn restore(&self) -> Result<(), DatabaseError> {
+        info!("Restoring schedules...");
+
+        let policy = self.context.config.missed_schedule_policy;
+        let mut restored = vec![];
+
+        for schedule in self.context.database.list_room_schedules().await? {
+            if schedule.is_due() && policy == MissedSchedulePolicy::Skip {
+                warn!(
+                    room_id = schedule.room_id;
+                    "Skipping schedule {} that was missed at {}", schedule.id, schedule.fire_at
+                );
+                self.context
+                    .database
+                    .delete_room_schedule(schedule.id)
+                    .await?;
+
+                continue;
+            }
+
+            restored.push(schedule);
+        }
+
+        self.schedules.lock().extend(restored);
+        self.wake();
+
+        Ok(())
+    }
+
+    /// Schedules an action in a room on behalf of a member, which happens at `fire_at`.
+    /// An action scheduled in the past happens right away.
+    pub async fn schedule(
+        &self,
+        room_id: PrimaryKey,
+        user_id: PrimaryKey,
+        action: ScheduledAction,
+        fire_at: DateTime<Utc>,
+    ) -> Result<RoomScheduleData, RoomError> {
+        let room = room_by_id(&self.context, room_id)?;
+        let _ = room.member_by_user_id(user_id)?;
+
+        let schedule = self
+            .context
+            .database
+            .create_room_schedule(NewRoomSchedule {
+                room_id,
+                user_id,
+                action,
+                fire_at,
+            })
+            .await
+            .map_err(RoomError::Database)?;
+
+        self.schedules.lock().push(schedule.clone());
+        self.wake();
+
+        Ok(schedule)
+    }
+
+    /// Cancels a schedule that hasn't happened yet
+    pub async fn cancel(&self, schedule_id: PrimaryKey) -> Result<(), RoomError> {
+        self.context
+            .database
+            .delete_room_schedule(schedule_id)
+            .await
+            .map_err(RoomError::Database)?;
+
+        self.schedules.lock().retain(|s| s.id != schedule_id);
+
+        Ok(())
+    }
+
+    /// Returns the schedules of a room that haven't happened yet, the earliest first
+    pub fn list(&self, room_id: PrimaryKey) -> Vec<RoomScheduleData> {
+        let mut schedules: Vec<_> = self
+            .schedules
+            .lock()
+            .iter()
+            .filter(|s| s.room_id == room_id)
+            .cloned()
+            .collect();
+
+        schedules.sort_by_key(|s| (s.fire_at, s.id));
+        schedules
+    }
+
+    fn wake(&self) {
+        // The timer thread only stops once the scheduler is dropped
+        let _ = self.wake.send(());
+    }
+}
+
+fn room_by_id(context: &CollabContext, room_id: PrimaryKey) -> Result<Arc<Room>, RoomError> {
+    context
+        .rooms
+        .get(&room_id)
+        .map(|r| r.clone())
+        .ok_or(RoomError::RoomNotFound(room_id.to_string()))
+}
+
+/// Removes and returns the schedules that are due
+fn take_due(schedules: &Schedules) -> Vec<RoomScheduleData> {
+    let mut schedules = schedules.lock();
+    let (due, pending) = schedules.drain(..).partition(|s| s.is_due());
+
+    *schedules = pending;
+    due
+}
+
+fn spawn_timer_thread(context: &CollabContext, schedules: &Schedules, woken: Receiver<()>) {
+    let context = context.to_owned();
+    let schedules = schedules.to_owned();
+    // This thread isn't part of the runtime, so the handle is taken from the caller's
+    let handle = get_or_create_handle();
+
+    let run = move || loop {
+        let next = schedules.lock().iter().map(|s| s.fire_at).min();
+
+        let result = match next {
+            Some(next) => {
+                let timeout = (next - Utc::now()).to_std().unwrap_or_default();
+                woken.recv_timeout(timeout)
+            }
+            None => woken.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        if let Err(RecvTimeoutError::Disconnected) = result {
+            info!("Scheduler was dropped, exiting timer");
+            break;
+        }
+
+        for schedule in take_due(&schedules) {
+            handle.spawn(fire(context.clone(), schedule));
+        }
+    };
+
+    thread::Builder::new()
+        .name("scheduler-timer".to_string())
+        .spawn(run)
+        .expect("scheduler-timer thread is spawned");
+}
+
+/// Performs a scheduled action, unless it was cancelled in the meantime
+async fn fire(context: CollabContext, schedule: RoomScheduleData) {
+    // Only one caller can delete the schedule, so it can't happen twice
+    if let Err(err) = context.database.delete_room_schedule(schedule.id).await {
+        warn!(
+            room_id = schedule.room_id;
+            "Schedule {} did not happen: {}", schedule.id, err
+        );
+        return;
+    }
+
+    info!(
+        room_id = schedule.room_id;
+        "Performing schedule {} ({})", schedule.id, schedule.action.as_str()
+    );
+
+    if let Err(err) = perform(&context, &schedule).await {
+        error!(
+            room_id = schedule.room_id;
+            "Schedule {} failed: {}", schedule.id, err
+        );
+    }
+}
+
+async fn perform(context: &CollabContext, schedule: &RoomScheduleData) -> Result<(), RoomError> {
+    let room = room_by_id(context, schedule.room_id)?;
+
+    match &schedule.action {
+        ScheduledAction::Play => {
+            // Accessing the queue activates the room
+            let _ = room.queue()?;
+            room.player()?.play();
+        }
+        ScheduledAction::QueuePlaylist(urls) => {
+            let user = context
+                .database
+                .user_by_id(schedule.user_id)
+                .await
+                .map_err(RoomError::Database)?;
+
+            let entries: Vec<AddEntry> = urls
+                .iter()
+                .cloned()
+                .map(|url| {
+                    let inputs = context.inputs.clone();
+
+                    async move {
+                        let inputs = inputs.query(&url).await?;
+                        Ok(inputs.into_iter().map(Track::from).collect())
+                    }
+                    .boxed()
+                })
+                .collect();
+
+            room.add_entries(entries, &user);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use sqlx::PgPool;
+
+    use crate::{
+        Collab, CollabConfig, MemoryDatabase, MissedSchedulePolicy, NewRoom, NewRoomSchedule,
+        NewUser, PgDatabase, ScheduledAction,
+    };
+
+    async fn create_room(collab: &Collab) -> (i32, i32) {
+        let user = collab
+            .rooms
+            .context
+            .database
+            .create_user(NewUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+                superuser: false,
+            })
+            .await
+            .expect("user is created");
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "event".to_string(),
+                title: "Event".to_string(),
+                visibility: Default::default(),
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .expect("room is created");
+
+        (room.id(), user.id)
+    }
+
+    async fn wait_for(f: impl Fn() -> bool) {
+        for _ in 0..2000 {
+            if f() {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        panic!("condition was not met in time");
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_actions_fire() {
+        let collab = Collab::with_database(CollabConfig::default(), MemoryDatabase::new()).await;
+        let (room_id, user_id) = create_room(&collab).await;
+        let room = collab.rooms.room_by_id(room_id).unwrap();
+
+        let path = std::env::temp_dir().join("scheduled.mp3");
+        std::fs::write(&path, []).expect("file is written");
+
+        let fire_at = Utc::now() + chrono::Duration::milliseconds(200);
+        let playlist = ScheduledAction::QueuePlaylist(vec![format!("file://{}", path.display())]);
+
+        collab
+            .schedules
+            .schedule(room_id, user_id, playlist, fire_at)
+            .await
+            .expect("playlist is scheduled");
+
+        room.queue().unwrap();
+        room.player().unwrap().pause();
+        wait_for(|| !room.player().unwrap().is_playing()).await;
+
+        collab
+            .schedules
+            .schedule(room_id, user_id, ScheduledAction::Play, fire_at)
+            .await
+            .expect("play is scheduled");
+
+        assert_eq!(collab.schedules.list(room_id).len(), 2);
+        assert!(
+            room.queue().unwrap().tracks().0.is_empty(),
+            "nothing fired yet"
+        );
+
+        wait_for(|| !room.queue().unwrap().tracks().0.is_empty()).await;
+        wait_for(|| room.player().unwrap().is_playing()).await;
+
+        assert!(Utc::now() >= fire_at, "actions fired on time");
+        assert!(collab.schedules.list(room_id).is_empty());
+
+        let remaining = collab.rooms.context.database.list_room_schedules().await;
+        assert!(remaining.unwrap().is_empty(), "fired schedules are deleted");
+    }
+
+    #[sqlx::test]
+    async fn test_missed_schedule_policy(pool: PgPool) {
+        let collab =
+            Collab::with_database(CollabConfig::default(), PgDatabase::from_test_pool(&pool)).await;
+        let (room_id, user_id) = create_room(&collab).await;
+        let database = &collab.rooms.context.database;
+
+        for _ in 0..2 {
+            database
+                .create_room_schedule(NewRoomSchedule {
+                    room_id,
+                    user_id,
+                    action: ScheduledAction::Play,
+                    fire_at: Utc::now() - chrono::Duration::minutes(5),
+                })
+                .await
+                .expect("schedule is created");
+        }
+
+        drop(collab);
+
+        // Simulates a restart after the schedules were due
+        let skipping =
+            Collab::with_database(CollabConfig::default(), PgDatabase::from_test_pool(&pool)).await;
+        let remaining = skipping.rooms.context.database.list_room_schedules().await;
+
+        assert!(
+            remaining.unwrap().is_empty(),
+            "missed schedules are skipped"
+        );
+        assert!(skipping.schedules.list(room_id).is_empty());
+
+        let database = &skipping.rooms.context.database;
+        database
+            .create_room_schedule(NewRoomSchedule {
+                room_id,
+                user_id,
+                action: ScheduledAction::Play,
+                fire_at: Utc::now() - chrono::Duration::minutes(5),
+            })
+            .await
+            .expect("schedule is created");
+
+        let config = CollabConfig {
+            missed_schedule_policy: MissedSchedulePolicy::Fire,
+            ..Default::default()
+        };
+        let firing = Collab::with_database(config, PgDatabase::from_test_pool(&pool)).await;
+        let room = firing.rooms.room_by_id(room_id).unwrap();
+
+        wait_for(|| room.player().is_ok_and(|p| p.is_playing())).await;
+        wait_for(|| firing.schedules.list(room_id).is_empty()).await;
+    }
+}
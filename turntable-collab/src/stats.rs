@@ -0,0 +1,119 @@
+use chrono::{DateTime, Duration, Utc};
+use log::error;
+use turntable_core::get_or_create_handle;
+
+use crate::{
+    CollabContext, DatabaseError, LinearQueueItem, NewTrackPlay, PrimaryKey, SubmitterStatsData,
+    TrackStatsData,
+};
+
+/// Records and reads play statistics
+pub struct Stats {
+    context: CollabContext,
+}
+
+impl Stats {
+    pub fn new(context: &CollabContext) -> Self {
+        Self {
+            context: context.clone(),
+        }
+    }
+
+    /// Returns the most played tracks within the last `days`
+    pub async fn top_tracks(
+        &self,
+        days: u32,
+        limit: u32,
+    ) -> Result<Vec<TrackStatsData>, DatabaseError> {
+        let since = days_ago(days);
+
+        self.context.database.top_tracks(since, limit as i64).await
+    }
+
+    /// Returns the users whose submitted tracks were played the most within the last `days`
+    pub async fn top_submitters(
+        &self,
+        days: u32,
+        limit: u32,
+    ) -> Result<Vec<SubmitterStatsData>, DatabaseError> {
+        let since = days_ago(days);
+
+        self.context
+            .database
+            .top_submitters(since, limit as i64)
+            .await
+    }
+}
+
+/// Returns the time `days` ago, or the unix epoch if that is further back, since no play is older than that
+fn days_ago(days: u32) -> DateTime<Utc> {
+    Utc::now()
+        .checked_sub_signed(Duration::days(days as i64))
+        .unwrap_or(DateTime::UNIX_EPOCH)
+        .max(DateTime::UNIX_EPOCH)
+}
+
+/// Returns true if enough of a track was heard for it to count as a play
+pub fn is_play(time_played: f32, length: Option<f32>, threshold: f32) -> bool {
+    length
+        .filter(|l| *l > 0.)
+        .map(|l| time_played / l >= threshold)
+        .unwrap_or(false)
+}
+
+/// Persists a track that ended in a room, in the background
+pub fn record_track_end(
+    context: &CollabContext,
+    room_id: PrimaryKey,
+    item: &LinearQueueItem,
+    time_played: f32,
+    counted_as_play: bool,
+) {
+    // Plays are attributed to users, so tracks turntable added itself aren't recorded
+    let Some(user_id) = item.submitter.user_id() else {
+        return;
+    };
+
+    let new_play = NewTrackPlay {
+        canonical: item.track.metadata.canonical.clone(),
+        title: item.track.metadata.title.clone(),
+        artist: item.track.metadata.artist.clone(),
+        source: item.track.metadata.source.clone(),
+        room_id,
+        user_id,
+        time_played,
+        skipped: !counted_as_play,
+    };
+
+    let database = context.database.clone();
+
+    get_or_create_handle().spawn(async move {
+        if let Err(err) = database.create_track_play(new_play).await {
+            error!("Failed to record track play: {}", err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::{days_ago, is_play};
+
+    #[test]
+    fn test_is_play() {
+        assert!(is_play(60., Some(100.), 0.5));
+        assert!(is_play(100., Some(100.), 0.5));
+        assert!(!is_play(10., Some(100.), 0.5));
+        assert!(!is_play(10., None, 0.5));
+        assert!(!is_play(10., Some(0.), 0.5));
+    }
+
+    #[test]
+    fn test_days_ago() {
+        let since = days_ago(30);
+        assert!(Utc::now() - since >= Duration::days(30));
+
+        assert_eq!(days_ago(u32::MAX), DateTime::UNIX_EPOCH);
+    }
+}
@@ -7,11 +7,23 @@ use crate::{input::Input, Metadata};
 
 pub type TrackId = Id<Track>;
 
+/// How far down the gain of a track can be adjusted, in dB
+pub const MIN_TRACK_GAIN_IN_DB: f32 = -30.;
+/// How far up the gain of a track can be adjusted, in dB.
+/// Anything louder would mostly be flattened by the limiter.
+pub const MAX_TRACK_GAIN_IN_DB: f32 = 12.;
+
 /// A single track in a queue
 #[derive(Debug, Clone)]
 pub struct Track {
     pub id: TrackId,
     pub metadata: Metadata,
+    /// The time in seconds playback should start at
+    pub start: Option<f32>,
+    /// The time in seconds playback should end at
+    pub end: Option<f32>,
+    /// The gain in dB applied when the track plays, if adjusted
+    pub gain: Option<f32>,
 
     input: Arc<Input>,
     state: Arc<Mutex<TrackState>>,
@@ -22,6 +34,7 @@ enum TrackState {
     #[default]
     Inactive,
     Active(SinkId),
+    #[allow(dead_code)]
     Error(String),
 }
 
@@ -49,6 +62,79 @@ impl QueueItem for Track {
     fn loadable(&self) -> BoxedLoadable {
         self.input.loadable()
     }
+
+    fn start(&self) -> Option<f32> {
+        self.start
+    }
+
+    fn end(&self) -> Option<f32> {
+        self.end
+    }
+
+    fn gain(&self) -> Option<f32> {
+        self.gain
+    }
+}
+
+impl Track {
+    /// Returns the metadata of the input if it changed since the track was created, like after it was activated.
+    pub fn updated_metadata(&self) -> Option<Metadata> {
+        let metadata = self.input.metadata();
+        (metadata != self.metadata).then_some(metadata)
+    }
+
+    /// Only plays the part of the track between `start` and `end`, in seconds
+    pub fn with_trim(mut self, start: Option<f32>, end: Option<f32>) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    /// Adjusts the gain of the track in dB, or resets it with [None].
+    /// The gain is clamped between [MIN_TRACK_GAIN_IN_DB] and [MAX_TRACK_GAIN_IN_DB].
+    pub fn set_gain(&mut self, gain: Option<f32>) {
+        self.gain = gain.map(|g| g.clamp(MIN_TRACK_GAIN_IN_DB, MAX_TRACK_GAIN_IN_DB));
+    }
+
+    /// Returns a copy of the track to queue again, like one from the history.
+    /// It has a new id, and is activated anew when it plays.
+    pub fn requeued(&self) -> Self {
+        Self {
+            id: TrackId::new(),
+            state: Default::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns how many seconds of the track play, taking the trim into account, if the length is known
+    pub fn played_length(&self) -> Option<f32> {
+        let length = self.known_length()?;
+        let end = self.end.map_or(length, |e| e.min(length));
+
+        Some((end - self.start.unwrap_or_default()).max(0.))
+    }
+
+    /// Stops playback once the track has played for `max` seconds, if its length isn't known.
+    /// Tracks with a known length play fully, since they can be checked before they're queued.
+    pub fn with_max_duration(mut self, max: f32) -> Self {
+        if self.known_length().is_none() {
+            let max_end = self.start.unwrap_or_default() + max;
+            self.end = Some(self.end.map_or(max_end, |e| e.min(max_end)));
+        }
+
+        self
+    }
+
+    /// Returns the name of the source of the track, like `youtube`
+    pub fn source(&self) -> &'static str {
+        self.input.source()
+    }
+
+    /// Returns the length of the input, if known.
+    /// A length of 0 is unknown too, like for playlist entries that are missing their duration.
+    fn known_length(&self) -> Option<f32> {
+        self.input.length().filter(|l| *l > 0.)
+    }
 }
 
 impl From<Input> for Track {
@@ -56,6 +142,9 @@ impl From<Input> for Track {
         Self {
             state: Default::default(),
             metadata: input.metadata(),
+            start: None,
+            end: None,
+            gain: None,
             input: Arc::new(input),
             id: TrackId::new(),
         }
@@ -1,4 +1,4 @@
-use std::mem::size_of;
+use std::{mem::size_of, time::Duration};
 
 /// A single audio sample
 pub type Sample = f32;
@@ -21,12 +21,84 @@ pub struct Config {
     /// Lower values increase chance of buffer underruns,
     /// whilst higher values increase latency.
     pub stream_preload_cache_size_in_seconds: f32,
-    /// How many seconds of audio can exist between the currently playing offset of a sink.
+    /// How many seconds of audio behind the playback offset of a sink are kept in memory.
     ///
-    /// Higher values means more memory usage but more lenient seeking, lower values
-    /// mean less memory usage but a higher likelihood of buffering when seeking too far from the
-    /// playback offset.
-    pub sink_preload_window_in_seconds: f32,
+    /// Higher values means more memory usage but seeking backwards is less likely to load again,
+    /// lower values mean less memory usage but a higher likelihood of buffering when seeking back.
+    pub sink_retain_behind_in_seconds: f32,
+    /// How many seconds of audio ahead of the playback offset of a sink are kept in memory.
+    ///
+    /// This bounds memory when seeking far backwards in a loaded sink, and should be
+    /// at least [Config::preload_size_in_seconds] so preloaded audio isn't cleared.
+    pub sink_retain_ahead_in_seconds: f32,
+    /// How many seconds of audio to load right away after seeking to a position that isn't loaded.
+    ///
+    /// Lower values resume playback sooner, after which regular preloading takes over.
+    pub seek_preload_size_in_seconds: f32,
+    /// How many seconds to wait for another seek of the same player before acting on one.
+    ///
+    /// Seeks in quick succession, like when dragging a seek bar, only go to the last position,
+    /// so positions that are skipped over aren't loaded. If this is 0, every seek is acted upon.
+    pub seek_coalesce_window_in_seconds: f32,
+    /// How many seconds of decoded audio can be cached, so that sources played again don't have to be decoded again.
+    ///
+    /// Only sources that opt into caching, like local files, are cached. If this is 0, nothing is cached.
+    pub decoded_cache_size_in_seconds: f32,
+    /// How many threads decode audio, shared by all sinks being loaded.
+    ///
+    /// These are separate from the async runtime's blocking threads, so that decoding and unrelated blocking work
+    /// can't hold up each other. Higher values let more sinks load at once, at the cost of more threads.
+    pub decode_threads: usize,
+    /// What happens when seeking outside of the current item.
+    pub seek_behavior: SeekBehavior,
+    /// How many times in a row a load can fail with an error that may be temporary, like a network error,
+    /// before the sink is given up on and skipped. Until then, the sink is loaded again like usual.
+    ///
+    /// Errors that won't go away by trying again, like corrupt data, always skip the sink right away.
+    pub load_retries: usize,
+    /// How audio that doesn't match [Config::sample_rate] is resampled.
+    pub resampler_quality: ResamplerQuality,
+    /// The level in dBFS that played audio is limited to, so that loud sources don't clip.
+    ///
+    /// Audio well below it passes through unchanged. If this is [None], audio isn't limited.
+    pub limiter_ceiling_in_db: Option<f32>,
+    /// How many sinks can be activated at once, if limited.
+    ///
+    /// Once reached, sinks that are only loaded ahead of time are not activated, but the one that is playing always is.
+    pub max_activated_sinks: Option<usize>,
+    /// How many seconds of audio sinks can hold in memory in total, if limited.
+    /// Like [Config::max_activated_sinks], this only keeps sinks that aren't playing from being activated.
+    pub max_sink_memory_in_seconds: Option<f32>,
+    /// How many seconds of silence at the start of a source are skipped at most, for players that trim it.
+    ///
+    /// Silence that goes on for longer is likely part of the source, like a quiet intro, so only this much is skipped.
+    pub max_leading_silence_in_seconds: f32,
+    /// How many seconds a long-lived thread of the pipeline can go without a heartbeat before it's considered stalled.
+    ///
+    /// A stalled thread, like one that panicked, makes the pipeline unhealthy.
+    pub stall_threshold_in_seconds: f32,
+}
+
+/// What happens when seeking outside of the current item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeekBehavior {
+    /// The position is clamped to the start or end of the item
+    #[default]
+    Clamp,
+    /// Seeking before the start goes to the previous item in the queue, and seeking past the end goes to the next.
+    /// Only items with a known length can be seeked past the end of.
+    CrossBoundary,
+}
+
+/// The tradeoff between quality and speed when resampling audio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    /// Less CPU usage and latency, with a slightly softer cutoff of high frequencies
+    Low,
+    #[default]
+    Medium,
+    /// A sharper cutoff of high frequencies, at the cost of more CPU usage and latency
+    High,
 }
 
 impl Config {
@@ -62,9 +134,47 @@ impl Config {
         (self.stream_preload_cache_size_in_seconds * self.samples_per_sec() as f32) as usize
     }
 
-    /// How many samples between the playback offset can be stored in a sink
-    pub fn sink_preload_window_size(&self) -> usize {
-        (self.sink_preload_window_in_seconds * self.samples_per_sec() as f32) as usize
+    /// How many samples behind the playback offset are kept in a sink
+    pub fn sink_retain_behind_size(&self) -> usize {
+        (self.sink_retain_behind_in_seconds * self.samples_per_sec() as f32) as usize
+    }
+
+    /// How many samples ahead of the playback offset are kept in a sink
+    pub fn sink_retain_ahead_size(&self) -> usize {
+        (self.sink_retain_ahead_in_seconds * self.samples_per_sec() as f32) as usize
+    }
+
+    /// How many samples are loaded right away after seeking
+    pub fn seek_preload_size_in_samples(&self) -> usize {
+        (self.seek_preload_size_in_seconds * self.samples_per_sec() as f32) as usize
+    }
+
+    /// How long to wait for another seek before acting on one
+    pub fn seek_coalesce_window(&self) -> Duration {
+        Duration::from_secs_f32(self.seek_coalesce_window_in_seconds)
+    }
+
+    /// How many samples can be stored in the decoded cache
+    pub fn decoded_cache_size_in_samples(&self) -> usize {
+        (self.decoded_cache_size_in_seconds * self.samples_per_sec() as f32) as usize
+    }
+
+    pub fn max_sink_memory_in_samples(&self) -> Option<usize> {
+        self.max_sink_memory_in_seconds
+            .map(|s| self.seconds_to_samples(s))
+    }
+
+    /// How many samples of silence at the start of a source are skipped at most
+    pub fn max_leading_silence_in_samples(&self) -> usize {
+        let samples = self.seconds_to_samples(self.max_leading_silence_in_seconds.max(0.));
+
+        // Skipping part of a frame would swap the channels
+        samples - samples % self.channel_count
+    }
+
+    /// How long a thread can go without a heartbeat before it's considered stalled
+    pub fn stall_threshold(&self) -> Duration {
+        Duration::from_secs_f32(self.stall_threshold_in_seconds)
     }
 
     /// Returns the number of samples for any given number of seconds
@@ -103,8 +213,31 @@ impl Default for Config {
             buffer_size_in_seconds: 0.1,
             // One second of latency should be OK for most modern networks
             stream_preload_cache_size_in_seconds: 1.,
-            // 5 minutes of stored audio is more than enough
-            sink_preload_window_in_seconds: 60. * 5.,
+            // 5 minutes of stored audio in either direction is more than enough
+            sink_retain_behind_in_seconds: 60. * 5.,
+            sink_retain_ahead_in_seconds: 60. * 5.,
+            // Enough to resume playback while the rest is preloaded
+            seek_preload_size_in_seconds: 1.,
+            // Short enough not to be noticed, long enough to catch a dragged seek bar
+            seek_coalesce_window_in_seconds: 0.05,
+            // Caching is opt-in, since it trades memory for less decoding
+            decoded_cache_size_in_seconds: 0.,
+            // Enough to load a few sinks at once, like after a seek and for the next item
+            decode_threads: 4,
+            seek_behavior: SeekBehavior::Clamp,
+            // Enough to get through a short network hiccup
+            load_retries: 3,
+            // Good enough for most listeners
+            resampler_quality: ResamplerQuality::Medium,
+            // Just below full scale, so lossy encoders have a little headroom
+            limiter_ceiling_in_db: Some(-0.3),
+            // Cleanup keeps memory in check on its own, so the limits are opt-in
+            max_activated_sinks: None,
+            max_sink_memory_in_seconds: None,
+            // Long enough for the silence of most uploads, short enough to leave quiet intros alone
+            max_leading_silence_in_seconds: 5.,
+            // Far longer than any loop of a thread takes, so only a thread that is really stuck is flagged
+            stall_threshold_in_seconds: 5.,
         }
     }
 }
@@ -1,7 +1,9 @@
+pub use crossbeam::channel::{RecvError, RecvTimeoutError, TryRecvError};
+
 use crossbeam::channel::{Receiver, Sender};
-use log::{error, info, trace};
+use log::{debug, error, info, trace, warn};
 
-use crate::{PlayerId, PlayerState, SinkId, SinkLoadState};
+use crate::{IngestionError, Levels, PlayerId, PlayerState, SinkId, SinkLoadState};
 
 pub type EventSender = Sender<PipelineEvent>;
 pub type EventReceiver = Receiver<PipelineEvent>;
@@ -32,6 +34,19 @@ pub enum PipelineEvent {
     },
     /// A player advanced to the next queue item.
     PlayerAdvanced { player_id: PlayerId },
+    /// A player's playback speed has changed.
+    PlayerSpeedUpdate { player_id: PlayerId, speed: f32 },
+    /// A player's timeline was dropped, to be rebuilt from its queue.
+    PlayerReseated { player_id: PlayerId },
+    /// The levels of what a player played recently, only emitted while subscribed to.
+    PlayerLevels { player_id: PlayerId, levels: Levels },
+    /// Samples were cleared from memory, either by removing unused sinks or trimming playing ones.
+    SinksCleared {
+        /// The sinks that samples were cleared from.
+        ids: Vec<SinkId>,
+        /// The total amount of samples freed.
+        samples_freed: usize,
+    },
     /// A queue item has been ingested
     QueueItemActivated {
         /// The id of the player the queue item's queue belongs to.
@@ -48,7 +63,7 @@ pub enum PipelineEvent {
         /// The custom identifier of the queue item.
         item_id: String,
         /// The error that happened while activating the queue item.
-        error: String,
+        error: IngestionError,
     },
 }
 
@@ -70,35 +85,67 @@ pub enum PipelineAction {
         /// The position to seek to, in seconds.
         position: f32,
     },
+    /// The player of the given id should play at the given speed.
+    SetPlayerSpeed { player_id: PlayerId, speed: f32 },
+    /// The player of the given id should rebuild its timeline from its queue and resume playing.
+    ReseatPlayer { player_id: PlayerId },
 }
 
 impl PipelineEvent {
     pub fn log(&self) {
         match self {
             PipelineEvent::SinkLoadStateUpdate { sink_id, new_state } => {
-                info!("Sink #{} load state updated: {:?}", sink_id, new_state,);
+                info!(
+                    sink_id = sink_id.value();
+                    "Sink #{} load state updated: {:?}", sink_id, new_state
+                );
             }
             PipelineEvent::PlayerStateUpdate {
                 player_id,
                 new_state,
             } => {
-                info!("Player #{} state update: {:?}", player_id, new_state)
+                info!(
+                    player_id = player_id.value();
+                    "Player #{} state update: {:?}", player_id, new_state
+                )
             }
             PipelineEvent::PlayerTimeUpdate {
                 player_id,
                 position,
                 total_position: _,
             } => {
-                trace!("Player #{} time update: {}", player_id, position)
+                trace!(
+                    player_id = player_id.value();
+                    "Player #{} time update: {}", player_id, position
+                )
             }
             PipelineEvent::PlayerAdvanced { player_id } => {
-                info!("Player #{} advanced", player_id)
+                info!(player_id = player_id.value(); "Player #{} advanced", player_id)
+            }
+            PipelineEvent::PlayerSpeedUpdate { player_id, speed } => {
+                info!(
+                    player_id = player_id.value();
+                    "Player #{} speed update: {}x", player_id, speed
+                )
+            }
+            PipelineEvent::PlayerReseated { player_id } => {
+                warn!(player_id = player_id.value(); "Player #{} was reseated", player_id)
+            }
+            PipelineEvent::PlayerLevels { player_id, levels } => {
+                trace!(
+                    player_id = player_id.value();
+                    "Player #{} levels: {:?}", player_id, levels
+                )
+            }
+            PipelineEvent::SinksCleared { ids, samples_freed } => {
+                debug!("Cleared {} samples from sinks {:?}", samples_freed, ids)
             }
             PipelineEvent::QueueItemActivated {
                 player_id,
                 new_sink_id,
                 item_id,
             } => info!(
+                player_id = player_id.value(), sink_id = new_sink_id.value();
                 "Queue item {} of player #{} activated with sink #{}",
                 item_id, player_id, new_sink_id
             ),
@@ -107,6 +154,7 @@ impl PipelineEvent {
                 item_id,
                 error,
             } => error!(
+                player_id = player_id.value();
                 "Queue item {} of player #{} failed to activate: {}",
                 item_id, player_id, error
             ),
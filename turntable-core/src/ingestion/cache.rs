@@ -0,0 +1,143 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use parking_lot::Mutex;
+
+use crate::Sample;
+
+/// Identifies the decoded audio of a [Loadable].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    /// What the audio is loaded from, like the path of a file.
+    pub source: String,
+    /// The version of the source, like the modification time of a file.
+    /// When this changes, previously cached samples of the source are discarded.
+    pub version: String,
+}
+
+/// A cache of decoded samples that is limited by the total amount of samples.
+/// When full, the least recently used entries are evicted.
+pub struct DecodedCache {
+    max_size: usize,
+    entries: Mutex<HashMap<String, CachedSamples>>,
+}
+
+struct CachedSamples {
+    version: String,
+    samples: Arc<Vec<Sample>>,
+    used_at: Instant,
+}
+
+impl DecodedCache {
+    /// Creates a cache that holds at most `max_size` samples. If this is 0, nothing is cached.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            entries: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_size > 0
+    }
+
+    /// Returns the cached samples for a key, discarding them if the source has changed.
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<Vec<Sample>>> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get_mut(&key.source)?;
+
+        if entry.version != key.version {
+            entries.remove(&key.source);
+            return None;
+        }
+
+        entry.used_at = Instant::now();
+        Some(entry.samples.clone())
+    }
+
+    /// Caches the samples of a key, replacing any older version of the source.
+    /// Nothing is cached if the samples don't fit.
+    pub fn insert(&self, key: CacheKey, samples: Vec<Sample>) {
+        let mut entries = self.entries.lock();
+        entries.remove(&key.source);
+
+        if samples.len() > self.max_size {
+            return;
+        }
+
+        // Evict the least recently used entries until the new one fits
+        while Self::size(&entries) + samples.len() > self.max_size {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.used_at)
+                .map(|(k, _)| k.clone());
+
+            match oldest {
+                Some(source) => entries.remove(&source),
+                None => break,
+            };
+        }
+
+        entries.insert(
+            key.source,
+            CachedSamples {
+                version: key.version,
+                samples: samples.into(),
+                used_at: Instant::now(),
+            },
+        );
+    }
+
+    fn size(entries: &HashMap<String, CachedSamples>) -> usize {
+        entries.values().map(|e| e.samples.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheKey, DecodedCache};
+
+    fn key(source: &str, version: &str) -> CacheKey {
+        CacheKey {
+            source: source.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cache_limits() {
+        let cache = DecodedCache::new(10);
+
+        cache.insert(key("a", "1"), vec![0.; 4]);
+        cache.insert(key("b", "1"), vec![0.; 4]);
+        cache.get(&key("a", "1"));
+        cache.insert(key("c", "1"), vec![0.; 4]);
+
+        assert!(cache.get(&key("a", "1")).is_some());
+        assert!(
+            cache.get(&key("b", "1")).is_none(),
+            "least recently used is evicted"
+        );
+        assert!(cache.get(&key("c", "1")).is_some());
+
+        cache.insert(key("d", "1"), vec![0.; 11]);
+        assert!(cache.get(&key("d", "1")).is_none(), "too large to cache");
+
+        assert!(!DecodedCache::new(0).is_enabled());
+    }
+
+    #[test]
+    fn test_cache_invalidation() {
+        let cache = DecodedCache::new(10);
+
+        cache.insert(key("a", "1"), vec![1.; 4]);
+        assert!(cache.get(&key("a", "2")).is_none(), "source has changed");
+        assert!(
+            cache.get(&key("a", "1")).is_none(),
+            "stale entry is discarded"
+        );
+
+        cache.insert(key("a", "2"), vec![2.; 4]);
+        cache.insert(key("a", "3"), vec![3.; 4]);
+        assert_eq!(*cache.get(&key("a", "3")).unwrap(), vec![3.; 4]);
+    }
+}
@@ -0,0 +1,78 @@
+use thiserror::Error;
+
+/// Describes why a source could not be ingested.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IngestionError {
+    /// The container format of the source is not supported.
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+    /// The source contains audio, but it can't be decoded.
+    #[error("Unsupported codec: {0}")]
+    UnsupportedCodec(String),
+    /// The source has no audio track that can be played.
+    #[error("No audio track found")]
+    NoAudioTrack,
+    /// The source is malformed or incomplete.
+    #[error("Corrupt container: {0}")]
+    CorruptContainer(String),
+    /// The source could not be read.
+    #[error("IO error: {0}")]
+    Io(String),
+    /// The sink is already being activated elsewhere, so the source wasn't ingested again.
+    #[error("Already activating")]
+    AlreadyActivating,
+    /// Activating the sink would exceed the sink limits of the config, so it was left inactive.
+    #[error("Sink limit reached")]
+    LimitReached,
+    #[error("{0}")]
+    Other(String),
+}
+
+/// The category of an [IngestionError], to tell failures apart without parsing their message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestionErrorKind {
+    UnsupportedFormat,
+    UnsupportedCodec,
+    NoAudioTrack,
+    CorruptContainer,
+    Io,
+    AlreadyActivating,
+    LimitReached,
+    Other,
+}
+
+impl IngestionErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnsupportedFormat => "unsupported_format",
+            Self::UnsupportedCodec => "unsupported_codec",
+            Self::NoAudioTrack => "no_audio_track",
+            Self::CorruptContainer => "corrupt_container",
+            Self::Io => "io",
+            Self::AlreadyActivating => "already_activating",
+            Self::LimitReached => "limit_reached",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl IngestionError {
+    pub fn kind(&self) -> IngestionErrorKind {
+        match self {
+            Self::UnsupportedFormat(_) => IngestionErrorKind::UnsupportedFormat,
+            Self::UnsupportedCodec(_) => IngestionErrorKind::UnsupportedCodec,
+            Self::NoAudioTrack => IngestionErrorKind::NoAudioTrack,
+            Self::CorruptContainer(_) => IngestionErrorKind::CorruptContainer,
+            Self::Io(_) => IngestionErrorKind::Io,
+            Self::AlreadyActivating => IngestionErrorKind::AlreadyActivating,
+            Self::LimitReached => IngestionErrorKind::LimitReached,
+            Self::Other(_) => IngestionErrorKind::Other,
+        }
+    }
+
+    /// Returns true if ingesting the same source again may succeed.
+    /// This is only the case when the source could not be read, as the contents won't change otherwise.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Io(_))
+    }
+}
@@ -0,0 +1,380 @@
+use std::{error::Error, f32::consts::TAU, io::SeekFrom, sync::Arc, thread, time::Duration};
+
+use async_trait::async_trait;
+use crossbeam::atomic::AtomicCell;
+
+use crate::{
+    BoxedLoadable, BoxedQueueItem, Config, Ingest, Ingestion, IngestionError, IntoLoadable,
+    LoadRequest, Loadable, LoaderLength, PipelineContext, Queue, QueueItem, ReadResult, Sample,
+    SinkId,
+};
+
+/// The samples a [FakeInput] consists of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FakePattern {
+    /// Rises from just above 0 to 1 at the last sample, so every sample is different
+    Ramp,
+    /// A sine of the given frequency in Hz at half of full scale, the same on every channel
+    Sine(f32),
+    /// Every sample has the given value
+    Constant(Sample),
+}
+
+impl FakePattern {
+    /// Returns the sample at an offset of a pattern that is `length` samples long
+    pub fn sample(&self, offset: usize, length: usize, config: &Config) -> Sample {
+        match self {
+            Self::Ramp => (offset + 1) as f32 / length as f32,
+            Self::Sine(frequency) => {
+                let frame = (offset / config.channel_count) as f32;
+                (frame * frequency * TAU / config.sample_rate as f32).sin() * 0.5
+            }
+            Self::Constant(value) => *value,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 5] {
+        let (tag, parameter) = match self {
+            Self::Ramp => (0, 0.),
+            Self::Sine(frequency) => (1, frequency),
+            Self::Constant(value) => (2, value),
+        };
+
+        let mut bytes = [tag, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(&parameter.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: [u8; 5]) -> Option<Self> {
+        let parameter = f32::from_le_bytes(bytes[1..].try_into().ok()?);
+
+        match bytes[0] {
+            0 => Some(Self::Ramp),
+            1 => Some(Self::Sine(parameter)),
+            2 => Some(Self::Constant(parameter)),
+            _ => None,
+        }
+    }
+}
+
+/// An input for [FakeIngestion], which loads `length` samples of a pattern into a sink.
+///
+/// Reading it returns a description of the audio instead of encoded audio, which [FakeIngestion] generates the samples from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FakeInput {
+    pub pattern: FakePattern,
+    /// How many samples are loaded
+    pub length: usize,
+    /// The length reported when ingested, which is [None] for a live source
+    pub expected_length: Option<usize>,
+}
+
+impl FakeInput {
+    const ENCODED_SIZE: usize = 22;
+
+    /// Creates an input of `length` samples, with a known length
+    pub fn new(pattern: FakePattern, length: usize) -> Self {
+        Self {
+            pattern,
+            length,
+            expected_length: Some(length),
+        }
+    }
+
+    /// Reports a different length when ingested, or none to act like a live source
+    pub fn with_expected_length(self, expected_length: Option<usize>) -> Self {
+        Self {
+            expected_length,
+            ..self
+        }
+    }
+
+    /// Returns every sample that is loaded from this input
+    pub fn samples(&self, config: &Config) -> Vec<Sample> {
+        (0..self.length)
+            .map(|offset| self.pattern.sample(offset, self.length, config))
+            .collect()
+    }
+
+    fn to_bytes(self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0; Self::ENCODED_SIZE];
+
+        bytes[..5].copy_from_slice(&self.pattern.to_bytes());
+        bytes[5..13].copy_from_slice(&(self.length as u64).to_le_bytes());
+        bytes[13] = self.expected_length.is_some() as u8;
+        bytes[14..]
+            .copy_from_slice(&(self.expected_length.unwrap_or_default() as u64).to_le_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let pattern = FakePattern::from_bytes(bytes.get(..5)?.try_into().ok()?)?;
+        let length = u64::from_le_bytes(bytes.get(5..13)?.try_into().ok()?) as usize;
+        let expected_length = u64::from_le_bytes(bytes.get(14..22)?.try_into().ok()?) as usize;
+
+        Some(Self {
+            pattern,
+            length,
+            expected_length: (bytes[13] == 1).then_some(expected_length),
+        })
+    }
+}
+
+#[async_trait]
+impl Loadable for FakeInput {
+    async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+        let bytes = self.to_bytes();
+        let amount = bytes.len().min(buf.len());
+
+        buf[..amount].copy_from_slice(&bytes[..amount]);
+        Ok(ReadResult::End(amount))
+    }
+
+    async fn length(&self) -> Option<LoaderLength> {
+        Some(LoaderLength::Bytes(Self::ENCODED_SIZE))
+    }
+
+    async fn seek(&self, _seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+        Ok(0)
+    }
+}
+
+/// An ingestion that loads generated patterns instead of decoding audio, see [FakeInput].
+/// It needs no files or network, and always loads the same samples, so tests can assert the exact audio that is played.
+pub struct FakeIngestion {
+    config: Config,
+    /// How many of the next loads fail
+    load_failures: AtomicCell<usize>,
+}
+
+impl FakeIngestion {
+    /// Fails the next loads with an error that may be temporary, like a connection that was reset
+    pub fn fail_loads(&self, failures: usize) {
+        self.load_failures.store(failures);
+    }
+}
+
+#[async_trait]
+impl Ingestion for FakeIngestion {
+    type Loader = FakeInput;
+
+    fn new(context: &PipelineContext) -> Self {
+        Self {
+            config: context.config.clone(),
+            load_failures: Default::default(),
+        }
+    }
+
+    async fn ingest<L>(&self, input: L) -> Result<Ingest<FakeInput>, IngestionError>
+    where
+        L: IntoLoadable + Send + Sync,
+    {
+        let loadable = input.into_loadable();
+        let mut buf = [0; FakeInput::ENCODED_SIZE];
+
+        let read = loadable
+            .read(&mut buf)
+            .await
+            .map_err(|e| IngestionError::Other(e.to_string()))?;
+
+        let input = match read {
+            ReadResult::More(amount) | ReadResult::End(amount) => {
+                FakeInput::from_bytes(&buf[..amount])
+            }
+        };
+
+        let input = input.ok_or(IngestionError::UnsupportedFormat(
+            "Not a fake input".to_string(),
+        ))?;
+
+        Ok(Ingest {
+            expected_length: input.expected_length,
+            loader: input,
+        })
+    }
+
+    async fn request_load(&self, request: LoadRequest<FakeInput>) {
+        if self
+            .load_failures
+            .fetch_update(|f| f.checked_sub(1))
+            .is_ok()
+        {
+            request
+                .write_guard
+                .retryable_error("Connection reset".to_string());
+            return;
+        }
+
+        let input = &request.loader;
+        let end = (request.offset + request.amount).min(input.length);
+
+        let samples: Vec<_> = (request.offset..end)
+            .map(|offset| input.pattern.sample(offset, input.length, &self.config))
+            .collect();
+
+        request.write_guard.write(request.offset, &samples);
+
+        if end == input.length {
+            request.write_guard.end();
+        }
+    }
+
+    fn name() -> String {
+        "Fake".to_string()
+    }
+}
+
+/// A queue item of a [FakeInput], whose first activations can be made to fail
+#[derive(Debug, Clone)]
+pub struct FakeItem {
+    pub input: FakeInput,
+    id: String,
+    gain: Option<f32>,
+    sink_id: Arc<AtomicCell<Option<SinkId>>>,
+    /// How many of the next activations fail
+    failures: Arc<AtomicCell<usize>>,
+}
+
+impl FakeItem {
+    pub fn new(input: FakeInput) -> Self {
+        Self {
+            input,
+            id: "item".to_string(),
+            gain: None,
+            sink_id: Default::default(),
+            failures: Default::default(),
+        }
+    }
+
+    /// Gives the item an id, to tell the items of a queue apart
+    pub fn with_id(self, id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            ..self
+        }
+    }
+
+    /// Adjusts the volume of the item by decibels, like a track with a gain adjustment
+    pub fn with_gain(self, gain: f32) -> Self {
+        Self {
+            gain: Some(gain),
+            ..self
+        }
+    }
+
+    /// Fails the next activations, like a source that is unavailable for a while
+    pub fn with_failures(self, failures: usize) -> Self {
+        self.failures.store(failures);
+        self
+    }
+}
+
+impl QueueItem for FakeItem {
+    fn length(&self) -> Option<f32> {
+        None
+    }
+
+    fn register_sink(&self, sink_id: SinkId) {
+        self.sink_id.store(Some(sink_id))
+    }
+
+    fn sink_id(&self) -> Option<SinkId> {
+        self.sink_id.load()
+    }
+
+    fn item_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn loadable(&self) -> BoxedLoadable {
+        match self.failures.fetch_update(|f| f.checked_sub(1)) {
+            Ok(_) => UnavailableInput.boxed(),
+            Err(_) => self.input.boxed(),
+        }
+    }
+
+    fn gain(&self) -> Option<f32> {
+        self.gain
+    }
+}
+
+/// An input that can't be read, so ingesting it fails
+struct UnavailableInput;
+
+#[async_trait]
+impl Loadable for UnavailableInput {
+    async fn read(&self, _buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+        Err("Fake input is unavailable".into())
+    }
+
+    async fn length(&self) -> Option<LoaderLength> {
+        None
+    }
+
+    async fn seek(&self, _seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+        Ok(0)
+    }
+}
+
+/// A queue of a single [FakeItem] that never moves on
+pub struct FakeQueue(pub FakeItem);
+
+impl Queue for FakeQueue {
+    fn peek(&self) -> Vec<BoxedQueueItem> {
+        vec![BoxedQueueItem::new(self.0.clone())]
+    }
+
+    fn next(&self) {}
+
+    fn previous(&self) {}
+
+    fn reset(&self) {}
+
+    fn skip(&self, _id: &str) {}
+}
+
+/// Blocks until the condition is met, panicking if it takes more than 5 seconds
+pub fn wait_for(condition: impl Fn() -> bool) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("condition was not met in time");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Config, Ingestion, PipelineContext, SinkManager};
+
+    use super::{FakeIngestion, FakeInput, FakePattern};
+
+    #[tokio::test]
+    async fn test_loads_pattern() {
+        let config = Config::default();
+        let context = PipelineContext::with_config(&config);
+        let manager = SinkManager::new(&context, FakeIngestion::new(&context));
+
+        let input = FakeInput::new(FakePattern::Sine(440.), 1000);
+        let live = FakeInput::new(FakePattern::Ramp, 10).with_expected_length(None);
+
+        let sink = manager.prepare();
+        manager.activate(sink.id, input).await.unwrap();
+        manager.request_load(sink.id, 0, 600).await;
+        manager.request_load(sink.id, 600, 600).await;
+
+        assert_eq!(sink.expected_length(), Some(1000));
+        assert_eq!(sink.complete_samples(), Some(input.samples(&config)));
+
+        let sink = manager.prepare();
+        manager.activate(sink.id, live).await.unwrap();
+        assert_eq!(sink.expected_length(), None, "length is not reported");
+
+        manager.request_load(sink.id, 0, 100).await;
+        assert_eq!(sink.complete_samples(), Some(live.samples(&config)));
+    }
+}
@@ -1,7 +1,14 @@
 use async_trait::async_trait;
-use std::{error::Error, io::SeekFrom};
+use std::{
+    error::Error,
+    io::SeekFrom,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use crate::Config;
+use crate::{CacheKey, Config};
 
 /// Represents a type that can load raw audio bytes from any source.
 /// Activated inputs typically implement this trait.
@@ -39,6 +46,21 @@ where
     /// * `seek` - The position to seek to.
     async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>>;
 
+    /// Returns a key identifying the decoded audio of the source, which allows it to be cached.
+    /// Only sources that always decode to the same audio for a key, like local files, should return one.
+    ///
+    /// By default, this returns [None], so the source is never cached.
+    async fn cache_key(&self) -> Option<CacheKey> {
+        None
+    }
+
+    /// Describes the source for diagnostics, like its URL, so errors can be traced back to it.
+    ///
+    /// By default, this returns [None].
+    fn description(&self) -> Option<String> {
+        None
+    }
+
     /// Shorthand for creating a [BoxedLoadable].
     fn boxed(self) -> BoxedLoadable
     where
@@ -79,6 +101,21 @@ pub enum ReadResult {
     End(usize),
 }
 
+/// Signals that an in-flight load is obsolete, and should stop as soon as possible.
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct LoadCancellation(Arc<AtomicBool>);
+
+impl LoadCancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 /// [Loadable] trait object.
 pub struct BoxedLoadable(Box<dyn Loadable>);
 
@@ -103,6 +140,14 @@ impl Loadable for BoxedLoadable {
     async fn activate(&self) -> Result<(), Box<dyn Error>> {
         self.0.activate().await
     }
+
+    async fn cache_key(&self) -> Option<CacheKey> {
+        self.0.cache_key().await
+    }
+
+    fn description(&self) -> Option<String> {
+        self.0.description()
+    }
 }
 
 pub trait IntoLoadable
@@ -2,13 +2,25 @@
 
 use async_trait::async_trait;
 use dashmap::DashMap;
-use std::{error::Error, sync::Arc};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
 
-use crate::PipelineContext;
+use crate::{Config, PipelineContext, PipelineEvent, Sample};
 
+mod cache;
+mod error;
+#[cfg(any(test, feature = "testing"))]
+mod fake;
 mod loading;
 mod sink;
 
+pub use cache::*;
+pub use error::*;
+#[cfg(any(test, feature = "testing"))]
+pub use fake::*;
 pub use loading::*;
 pub use sink::*;
 
@@ -24,7 +36,7 @@ where
     fn new(context: &PipelineContext) -> Self;
 
     /// Ingests a new source, returning an [Ingest] when successful.
-    async fn ingest<L>(&self, input: L) -> Result<Ingest<Self::Loader>, Box<dyn Error>>
+    async fn ingest<L>(&self, input: L) -> Result<Ingest<Self::Loader>, IngestionError>
     where
         L: IntoLoadable + Send + Sync;
 
@@ -32,6 +44,14 @@ where
     /// Note: This function must not be called on the playback thread.
     async fn request_load(&self, request: LoadRequest<Self::Loader>);
 
+    /// Makes the loader skip the silence at the start of its source, up to `max` samples.
+    /// Offsets loaded afterwards start after the skipped silence.
+    ///
+    /// Returns how many samples were skipped, which is 0 if the ingestion doesn't support it.
+    async fn trim_leading_silence(&self, _loader: Arc<Self::Loader>, _max: usize) -> usize {
+        0
+    }
+
     fn name() -> String;
 }
 
@@ -60,10 +80,42 @@ where
     I: Ingestion,
 {
     context: PipelineContext,
-    loaders: DashMap<SinkId, Arc<I::Loader>>,
+    loaders: DashMap<SinkId, SinkLoader<I::Loader>>,
+    /// The cache keys of sinks that are being decoded, which are cached once fully loaded
+    pending_cache_keys: DashMap<SinkId, CacheKey>,
+    cache: DecodedCache,
     ingestion: I,
 }
 
+/// How many sinks are activated, and how much audio they hold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinkUsage {
+    /// Sinks that are activated or being activated. Inactive sinks hold no audio, so they aren't counted.
+    pub activated_sinks: usize,
+    pub samples_in_memory: usize,
+}
+
+impl SinkUsage {
+    /// Returns true if any of the sink limits of the config are reached
+    pub fn is_at_limit(&self, config: &Config) -> bool {
+        let too_many = config
+            .max_activated_sinks
+            .is_some_and(|max| self.activated_sinks >= max);
+
+        let too_large = config
+            .max_sink_memory_in_samples()
+            .is_some_and(|max| self.samples_in_memory >= max);
+
+        too_many || too_large
+    }
+}
+
+/// Loads samples into a sink, either by decoding or from the cache
+enum SinkLoader<L> {
+    Ingested(Arc<L>),
+    Cached(Arc<Vec<Sample>>),
+}
+
 impl<I> SinkManager<I>
 where
     I: Ingestion,
@@ -71,6 +123,8 @@ where
     pub fn new(context: &PipelineContext, ingestion: I) -> Self {
         Self {
             loaders: Default::default(),
+            pending_cache_keys: Default::default(),
+            cache: DecodedCache::new(context.config.decoded_cache_size_in_samples()),
             context: context.clone(),
             ingestion,
         }
@@ -84,8 +138,11 @@ where
         sink
     }
 
-    /// Attempts to activate a sink with a loader
-    pub async fn activate<L>(&self, sink_id: SinkId, loader: L)
+    /// Attempts to activate a sink with a loader, failing the sink if it can't be ingested.
+    /// If the decoded samples of the loader are cached, it is not ingested.
+    ///
+    /// Returns [IngestionError::AlreadyActivating] if another call is activating or has activated the sink.
+    pub async fn activate<L>(&self, sink_id: SinkId, loader: L) -> Result<(), IngestionError>
     where
         L: IntoLoadable + Send + Sync,
     {
@@ -93,21 +150,100 @@ where
             .context
             .sinks
             .get(&sink_id)
-            .expect("sink exists when trying to activate");
+            .expect("sink exists when trying to activate")
+            .clone();
 
-        let guard = sink.activate();
-        let ingest = self.ingestion.ingest(loader).await;
+        let guard = sink.activate().ok_or(IngestionError::AlreadyActivating)?;
+        let loadable = loader.into_loadable();
+        let description = loadable.description();
+
+        // The cache holds the whole source, so sinks without the leading silence can't use it
+        let trim_leading_silence = sink.trims_leading_silence();
+        let cache_key = match self.cache.is_enabled() && !trim_leading_silence {
+            true => loadable.cache_key().await,
+            false => None,
+        };
+
+        if let Some(samples) = cache_key.as_ref().and_then(|k| self.cache.get(k)) {
+            let length = samples.len();
+
+            self.loaders.insert(sink_id, SinkLoader::Cached(samples));
+            guard.activate(Some(length));
+            return Ok(());
+        }
+
+        let ingest = self.ingestion.ingest(loadable).await;
 
         match ingest {
             Ok(ingest) => {
-                self.loaders.insert(sink_id, ingest.loader.into());
-                guard.activate(ingest.expected_length);
+                if let Some(key) = cache_key {
+                    self.pending_cache_keys.insert(sink_id, key);
+                }
+
+                let loader: Arc<_> = ingest.loader.into();
+                let mut expected_length = ingest.expected_length;
+
+                if trim_leading_silence {
+                    let max = self.context.config.max_leading_silence_in_samples();
+                    let skipped = self
+                        .ingestion
+                        .trim_leading_silence(loader.clone(), max)
+                        .await;
+
+                    expected_length = expected_length.map(|l| l.saturating_sub(skipped));
+                }
+
+                self.loaders.insert(sink_id, SinkLoader::Ingested(loader));
+                guard.activate(expected_length);
+
+                Ok(())
             }
-            Err(err) => guard.fail(&err.to_string()),
-        };
+            Err(err) => {
+                guard.fail(ActivationError::new(&err, description));
+                Err(err)
+            }
+        }
     }
 
-    /// Requests the ingestion to load data into a sink
+    /// Activates a sink that is only loaded ahead of time, unless that would exceed the sink limits.
+    /// Sinks that can be cleared are cleared right away to make room, before giving up.
+    ///
+    /// Returns [IngestionError::LimitReached] if the sink was left inactive.
+    /// Sinks that are playing should be activated with [SinkManager::activate] instead, which is never refused.
+    pub async fn activate_speculatively<L>(
+        &self,
+        sink_id: SinkId,
+        loader: L,
+    ) -> Result<(), IngestionError>
+    where
+        L: IntoLoadable + Send + Sync,
+    {
+        let config = &self.context.config;
+
+        if self.usage().is_at_limit(config) {
+            self.clear_inactive();
+
+            if self.usage().is_at_limit(config) {
+                return Err(IngestionError::LimitReached);
+            }
+        }
+
+        self.activate(sink_id, loader).await
+    }
+
+    /// Returns how many sinks are activated, and how much audio they hold
+    pub fn usage(&self) -> SinkUsage {
+        self.context.sinks.iter().filter(|s| s.is_active()).fold(
+            SinkUsage::default(),
+            |usage, sink| SinkUsage {
+                activated_sinks: usage.activated_sinks + 1,
+                samples_in_memory: usage.samples_in_memory + sink.samples_in_memory(),
+            },
+        )
+    }
+
+    /// Requests the ingestion to load data into a sink.
+    /// Nothing is loaded if the sink is already being loaded into.
     pub async fn request_load(&self, sink_id: SinkId, offset: usize, amount: usize) {
         let sink = self
             .context
@@ -115,37 +251,488 @@ where
             .get(&sink_id)
             .expect("sink exists when trying to load");
 
-        let loader = self
+        let Some(write_guard) = sink.try_write() else {
+            return;
+        };
+
+        let loader = match &*self
             .loaders
             .get(&sink_id)
             .expect("loader exists when trying to load")
-            .clone();
+        {
+            SinkLoader::Ingested(loader) => loader.clone(),
+            SinkLoader::Cached(samples) => {
+                let start = offset.min(samples.len());
+                let end = offset.saturating_add(amount).min(samples.len());
+
+                if start < end {
+                    write_guard.write(start, &samples[start..end]);
+                }
+
+                if end == samples.len() {
+                    write_guard.end();
+                }
+
+                return;
+            }
+        };
 
         self.ingestion
             .request_load(LoadRequest {
-                write_guard: sink.write(),
+                write_guard,
                 loader,
                 offset,
                 amount,
             })
-            .await
+            .await;
+
+        self.cache_if_complete(&sink);
+    }
+
+    /// Caches the samples of a sink once they are completely decoded
+    fn cache_if_complete(&self, sink: &Sink) {
+        if !self.pending_cache_keys.contains_key(&sink.id) {
+            return;
+        }
+
+        let Some(samples) = sink.complete_samples() else {
+            return;
+        };
+
+        if let Some((_, key)) = self.pending_cache_keys.remove(&sink.id) {
+            self.cache.insert(key, samples);
+        }
     }
 
+    /// Loads data into a sink right away, instead of waiting for the preloader.
+    ///
+    /// If a load is in progress, this waits for it to finish first.
+    /// That is quick when the load was cancelled, like it is when seeking to a position that isn't loaded.
+    pub async fn request_priority_load(&self, sink_id: SinkId, offset: usize, amount: usize) {
+        // If the load doesn't finish in time, the preloader takes over
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        loop {
+            let Some(sink) = self.context.sinks.get(&sink_id).map(|s| s.clone()) else {
+                return;
+            };
+
+            if !sink.is_being_written() {
+                break;
+            }
+
+            if Instant::now() > deadline {
+                return;
+            }
+
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        self.request_load(sink_id, offset, amount).await
+    }
+
+    /// Removes sinks that haven't been used for a while, emitting [PipelineEvent::SinksCleared] if any were.
     pub fn clear_inactive(&self) -> Vec<SinkId> {
-        let clearable_sink_ids: Vec<_> = self
+        let (clearable_sink_ids, freed): (Vec<_>, Vec<_>) = self
             .context
             .sinks
             .iter()
-            .filter_map(|s| if s.is_clearable() { Some(s.id) } else { None })
-            .collect();
+            .filter(|s| s.is_clearable())
+            .map(|s| (s.id, s.samples_in_memory()))
+            .unzip();
 
         self.loaders
             .retain(|id, _| !clearable_sink_ids.contains(id));
 
+        self.pending_cache_keys
+            .retain(|id, _| !clearable_sink_ids.contains(id));
+
         self.context
             .sinks
             .retain(|id, _| !clearable_sink_ids.contains(id));
 
+        // Emitted after the stores are released, so receivers can't hold them up
+        if !clearable_sink_ids.is_empty() {
+            self.context.emit(PipelineEvent::SinksCleared {
+                ids: clearable_sink_ids.clone(),
+                samples_freed: freed.into_iter().sum(),
+            });
+        }
+
         clearable_sink_ids
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        error::Error,
+        io::SeekFrom,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use async_trait::async_trait;
+    use crossbeam::channel::unbounded;
+
+    use crate::{
+        Config, FakeIngestion, FakeInput, FakePattern, Introspect, Loadable, LoaderLength,
+        PipelineContext, PipelineEvent, ReadResult,
+    };
+
+    use super::{
+        ActivationIntrospection, CacheKey, Ingest, Ingestion, IngestionError, IngestionErrorKind,
+        IntoLoadable, LoadRequest, Sink, SinkLoadState, SinkManager,
+    };
+
+    /// An ingestion that can't ingest anything, for testing the manager alone
+    struct NoIngestion;
+
+    #[async_trait]
+    impl Ingestion for NoIngestion {
+        type Loader = ();
+
+        fn new(_context: &PipelineContext) -> Self {
+            Self
+        }
+
+        async fn ingest<L>(&self, _input: L) -> Result<Ingest<()>, IngestionError>
+        where
+            L: IntoLoadable + Send + Sync,
+        {
+            Err(IngestionError::UnsupportedFormat(
+                "not supported".to_string(),
+            ))
+        }
+
+        async fn request_load(&self, _request: LoadRequest<()>) {}
+
+        fn name() -> String {
+            "None".to_string()
+        }
+    }
+
+    #[test]
+    fn test_clear_inactive_emits_event() {
+        let (event_sender, event_receiver) = unbounded();
+        let context = PipelineContext {
+            event_sender,
+            ..PipelineContext::with_config(&Config::default())
+        };
+
+        let manager = SinkManager::new(&context, NoIngestion);
+
+        let in_use = manager.prepare();
+        let inactive = manager.prepare();
+        let loaded = Arc::new(Sink::with_activation(&context, Some(10)));
+        context.sinks.insert(loaded.id, loaded.clone());
+
+        loaded.write().write(0, &[1., 2., 3., 4.]);
+        inactive.expire();
+        loaded.expire();
+
+        let mut cleared = manager.clear_inactive();
+        let mut expected = vec![inactive.id, loaded.id];
+
+        cleared.sort_by_key(|id| id.value());
+        expected.sort_by_key(|id| id.value());
+
+        assert_eq!(cleared, expected);
+        assert!(context.sinks.contains_key(&in_use.id), "used sink is kept");
+
+        let event = event_receiver
+            .try_iter()
+            .find_map(|e| match e {
+                PipelineEvent::SinksCleared { ids, samples_freed } => Some((ids, samples_freed)),
+                _ => None,
+            })
+            .expect("event is emitted");
+
+        let (mut ids, samples_freed) = event;
+        ids.sort_by_key(|id| id.value());
+
+        assert_eq!(ids, expected);
+        assert_eq!(samples_freed, 4);
+    }
+
+    /// A loadable that fails to ingest with [NoIngestion], but describes itself
+    struct DescribedLoadable;
+
+    #[async_trait]
+    impl Loadable for DescribedLoadable {
+        async fn read(&self, _buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+            Ok(ReadResult::End(0))
+        }
+
+        async fn length(&self) -> Option<LoaderLength> {
+            None
+        }
+
+        async fn seek(&self, _seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+            Ok(0)
+        }
+
+        fn description(&self) -> Option<String> {
+            Some("https://example.com/track".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_activation_error_introspection() {
+        let context = PipelineContext::default();
+        let manager = SinkManager::new(&context, NoIngestion);
+
+        let sink = manager.prepare();
+        let result = manager.activate(sink.id, DescribedLoadable).await;
+        assert!(matches!(result, Err(IngestionError::UnsupportedFormat(_))));
+
+        match sink.introspect().activation_state {
+            ActivationIntrospection::Error {
+                reason,
+                kind,
+                input,
+            } => {
+                assert_eq!(reason, "Unsupported format: not supported");
+                assert_eq!(kind, IngestionErrorKind::UnsupportedFormat);
+                assert_eq!(input.as_deref(), Some("https://example.com/track"));
+            }
+            state => panic!("sink is not errored: {:?}", state),
+        }
+    }
+
+    /// An ingestion that counts how often it ingests and decodes
+    #[derive(Default)]
+    struct CountingIngestion {
+        ingests: AtomicUsize,
+        decodes: AtomicUsize,
+    }
+
+    const DECODED: [f32; 4] = [1., 2., 3., 4.];
+
+    #[async_trait]
+    impl Ingestion for CountingIngestion {
+        type Loader = ();
+
+        fn new(_context: &PipelineContext) -> Self {
+            Self::default()
+        }
+
+        async fn ingest<L>(&self, _input: L) -> Result<Ingest<()>, IngestionError>
+        where
+            L: IntoLoadable + Send + Sync,
+        {
+            self.ingests.fetch_add(1, Ordering::SeqCst);
+
+            Ok(Ingest {
+                expected_length: None,
+                loader: (),
+            })
+        }
+
+        async fn request_load(&self, request: LoadRequest<()>) {
+            self.decodes.fetch_add(1, Ordering::SeqCst);
+
+            request.write_guard.write(0, &DECODED);
+            request.write_guard.end();
+        }
+
+        fn name() -> String {
+            "Counting".to_string()
+        }
+    }
+
+    /// A loadable that can be cached with the given version
+    struct VersionedLoadable(&'static str);
+
+    #[async_trait]
+    impl Loadable for VersionedLoadable {
+        async fn read(&self, _buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+            Ok(ReadResult::End(0))
+        }
+
+        async fn length(&self) -> Option<LoaderLength> {
+            None
+        }
+
+        async fn seek(&self, _seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+            Ok(0)
+        }
+
+        async fn cache_key(&self) -> Option<CacheKey> {
+            Some(CacheKey {
+                source: "file".to_string(),
+                version: self.0.to_string(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_ingestion_skips_decoding() {
+        let context = PipelineContext::with_config(&Config {
+            decoded_cache_size_in_seconds: 1.,
+            ..Default::default()
+        });
+
+        let manager = &SinkManager::new(&context, CountingIngestion::default());
+        let ingestion = &manager.ingestion;
+
+        let play = |version| async move {
+            let sink = manager.prepare();
+            manager
+                .activate(sink.id, VersionedLoadable(version))
+                .await
+                .unwrap();
+            manager.request_load(sink.id, 0, 100).await;
+
+            let mut buf = [0.; 4];
+            assert_eq!(sink.read(0, &mut buf).amount, 4);
+            assert_eq!(buf, DECODED);
+            assert_eq!(
+                sink.complete_samples(),
+                Some(DECODED.to_vec()),
+                "end is known"
+            );
+        };
+
+        play("1").await;
+        assert_eq!(ingestion.ingests.load(Ordering::SeqCst), 1);
+        assert_eq!(ingestion.decodes.load(Ordering::SeqCst), 1);
+
+        play("1").await;
+        assert_eq!(ingestion.ingests.load(Ordering::SeqCst), 1, "cache is used");
+        assert_eq!(ingestion.decodes.load(Ordering::SeqCst), 1, "cache is used");
+
+        play("2").await;
+        assert_eq!(
+            ingestion.ingests.load(Ordering::SeqCst),
+            2,
+            "source changed"
+        );
+        assert_eq!(
+            ingestion.decodes.load(Ordering::SeqCst),
+            2,
+            "source changed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_opt_in() {
+        let context = PipelineContext::with_config(&Config::default());
+        let manager = SinkManager::new(&context, CountingIngestion::default());
+
+        for _ in 0..2 {
+            let sink = manager.prepare();
+            manager
+                .activate(sink.id, VersionedLoadable("1"))
+                .await
+                .unwrap();
+            manager.request_load(sink.id, 0, 100).await;
+        }
+
+        assert_eq!(manager.ingestion.decodes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_activation() {
+        let context = PipelineContext::with_config(&Config::default());
+        let manager = Arc::new(SinkManager::new(&context, CountingIngestion::default()));
+        let sink = manager.prepare();
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let manager = manager.clone();
+                let sink_id = sink.id;
+
+                tokio::spawn(async move { manager.activate(sink_id, VersionedLoadable("1")).await })
+            })
+            .collect();
+
+        let mut results = vec![];
+        for handle in handles {
+            results.push(handle.await.expect("activation doesn't panic"));
+        }
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results.contains(&Err(IngestionError::AlreadyActivating)));
+        assert!(sink.is_activated());
+        assert_eq!(manager.ingestion.ingests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sink_limits() {
+        let context = PipelineContext::with_config(&Config {
+            max_activated_sinks: Some(2),
+            ..Default::default()
+        });
+        let manager = SinkManager::new(&context, CountingIngestion::default());
+
+        for version in ["1", "2"] {
+            let sink = manager.prepare();
+            manager
+                .activate_speculatively(sink.id, VersionedLoadable(version))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(manager.usage().activated_sinks, 2);
+
+        let upcoming = manager.prepare();
+        assert_eq!(
+            manager
+                .activate_speculatively(upcoming.id, VersionedLoadable("3"))
+                .await,
+            Err(IngestionError::LimitReached)
+        );
+        assert!(upcoming.is_activatable(), "refused sink is left inactive");
+
+        // The sink that is playing is activated regardless
+        let playing = manager.prepare();
+        manager
+            .activate(playing.id, VersionedLoadable("4"))
+            .await
+            .unwrap();
+
+        assert!(playing.is_activated());
+        assert_eq!(manager.usage().activated_sinks, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retryable_load_errors() {
+        let context = PipelineContext::with_config(&Config {
+            load_retries: 1,
+            ..Default::default()
+        });
+
+        let manager = SinkManager::new(&context, FakeIngestion::new(&context));
+        let input = FakeInput::new(FakePattern::Ramp, 4);
+        manager.ingestion.fail_loads(1);
+
+        let sink = manager.prepare();
+        manager.activate(sink.id, input).await.unwrap();
+
+        manager.request_load(sink.id, 0, 100).await;
+        assert_eq!(
+            sink.load_state(),
+            SinkLoadState::Idle,
+            "sink isn't skipped after one error"
+        );
+
+        manager.request_load(sink.id, 0, 100).await;
+        assert_eq!(
+            sink.complete_samples(),
+            Some(input.samples(&context.config))
+        );
+
+        // Retries are exhausted when failing too often in a row
+        manager.ingestion.fail_loads(2);
+
+        let sink = manager.prepare();
+        manager.activate(sink.id, input).await.unwrap();
+
+        manager.request_load(sink.id, 0, 100).await;
+        manager.request_load(sink.id, 0, 100).await;
+        assert!(matches!(sink.load_state(), SinkLoadState::Error(_)));
+    }
+}
@@ -1,11 +1,12 @@
 use std::{sync::Arc, time::Instant};
 
 use crate::{
-    util::MultiRangeBufferIntrospection, BufferRead, BufferVoidDistance, Id, IdType, Introspect,
-    MultiRangeBuffer, PipelineContext, PipelineEvent, Sample,
+    util::MultiRangeBufferIntrospection, BufferRead, BufferVoidDistance, Config, Id, IdType,
+    IngestionError, IngestionErrorKind, Introspect, LoadCancellation, MultiRangeBuffer,
+    PipelineContext, PipelineEvent, Sample,
 };
 use crossbeam::atomic::AtomicCell;
-use log::info;
+use log::{info, warn};
 use parking_lot::{Mutex, RwLock};
 
 pub type SinkId = Id<Sink>;
@@ -25,6 +26,27 @@ pub struct Sink {
     has_write_ref: AtomicCell<bool>,
     /// The time since the sink was last interacted with.
     duration_since_interaction: AtomicCell<Instant>,
+    /// How many samples have been read from the sink during playback.
+    samples_played: AtomicCell<usize>,
+    /// The part of the sink that should be played.
+    trim: AtomicCell<SinkTrim>,
+    /// The gain in dB applied to the samples when played.
+    gain: AtomicCell<f32>,
+    /// Whether silence at the start of the source is skipped when it is ingested.
+    trim_leading_silence: AtomicCell<bool>,
+    /// Cancels the load of the current write reference, if any.
+    load_cancellation: Mutex<LoadCancellation>,
+    /// How many loads in a row failed with an error that may be temporary.
+    failed_loads: AtomicCell<usize>,
+}
+
+/// Describes the part of a [Sink] that should be played, in samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SinkTrim {
+    /// The offset playback starts at when the sink becomes current.
+    pub start: usize,
+    /// The offset playback ends at. If this exceeds the sink's length, the sink plays until its natural end.
+    pub end: Option<usize>,
 }
 
 /// Represents the load state of a [Sink].
@@ -53,7 +75,26 @@ pub enum SinkActivation {
     /// The sink has been successfully activated and is ready to be loaded to
     Activated(MultiRangeBuffer),
     /// An error occurred with sink activation and the sink will be skipped
-    Error(String),
+    Error(ActivationError),
+}
+
+/// Why a sink could not be activated
+#[derive(Debug, Clone)]
+pub struct ActivationError {
+    pub reason: String,
+    pub kind: IngestionErrorKind,
+    /// The description of the source that failed, if it has one
+    pub input: Option<String>,
+}
+
+impl ActivationError {
+    pub fn new(error: &IngestionError, input: Option<String>) -> Self {
+        Self {
+            reason: error.to_string(),
+            kind: error.kind(),
+            input,
+        }
+    }
 }
 
 /// A reference to a sink that determines if it is used by a [Timeline].
@@ -68,6 +109,7 @@ pub struct SinkGuard {
 pub struct WriteGuard {
     id: SinkId,
     context: PipelineContext,
+    cancellation: LoadCancellation,
 }
 
 /// A reference to a sink that allows activation.
@@ -90,6 +132,12 @@ impl Sink {
             has_write_ref: Default::default(),
             has_activation_guard: Default::default(),
             duration_since_interaction: Instant::now().into(),
+            samples_played: Default::default(),
+            trim: Default::default(),
+            gain: Default::default(),
+            trim_leading_silence: Default::default(),
+            load_cancellation: Default::default(),
+            failed_loads: Default::default(),
         }
     }
 
@@ -114,39 +162,82 @@ impl Sink {
     }
 
     /// Prepares activation by returning an activation guard.
-    pub fn activate(&self) -> ActivationGuard {
-        let mut activation = self.activation.write();
+    /// Returns none if the sink is already being activated, or was activated already.
+    pub fn activate(&self) -> Option<ActivationGuard> {
+        // Only one caller can claim the activation, even if several check the sink at the same time
+        if self
+            .has_activation_guard
+            .compare_exchange(false, true)
+            .is_err()
+        {
+            return None;
+        }
 
-        assert!(
-            matches!(
-                *activation,
-                SinkActivation::Inactive | SinkActivation::Error(_)
-            ),
-            "Sink is inactive when trying to activate"
-        );
+        let mut activation = self.activation.write();
 
-        assert!(
-            !self.has_activation_guard.load(),
-            "Sink already has activation guard"
-        );
+        if !matches!(
+            *activation,
+            SinkActivation::Inactive | SinkActivation::Error(_)
+        ) {
+            self.has_activation_guard.store(false);
+            return None;
+        }
 
-        info!("Activating sink #{}", self.id);
+        info!(sink_id = self.id.value(); "Activating sink #{}", self.id);
 
-        self.has_activation_guard.store(true);
         self.interact();
 
         *activation = SinkActivation::Activating;
 
-        ActivationGuard {
+        Some(ActivationGuard {
             id: self.id,
             context: self.context.clone(),
             finished: false.into(),
-        }
+        })
     }
 
     /// Reads samples from the sink at the given offset.
     pub fn read(&self, offset: usize, buf: &mut [Sample]) -> BufferRead {
-        self.read_buffer(|buffer| buffer.read(offset, buf))
+        let result = self.read_buffer(|buffer| buffer.read(offset, buf));
+        self.samples_played.fetch_add(result.amount);
+
+        result
+    }
+
+    /// Returns how many samples have been read from the sink.
+    /// Unlike the playback offset, this excludes any parts that were skipped by seeking.
+    pub fn samples_played(&self) -> usize {
+        self.samples_played.load()
+    }
+
+    /// Sets the part of the sink that should be played.
+    pub fn set_trim(&self, trim: SinkTrim) {
+        self.trim.store(trim);
+    }
+
+    /// Returns the part of the sink that should be played.
+    pub fn trim(&self) -> SinkTrim {
+        self.trim.load()
+    }
+
+    /// Sets whether silence at the start of the source is skipped when it is ingested.
+    pub fn set_trim_leading_silence(&self, trim: bool) {
+        self.trim_leading_silence.store(trim);
+    }
+
+    /// Returns true if silence at the start of the source is skipped when it is ingested.
+    pub fn trims_leading_silence(&self) -> bool {
+        self.trim_leading_silence.load()
+    }
+
+    /// Sets the gain in dB applied to the samples when played.
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain);
+    }
+
+    /// Returns the gain in dB applied to the samples when played.
+    pub fn gain(&self) -> f32 {
+        self.gain.load()
     }
 
     /// Returns a write reference to the sink.
@@ -164,15 +255,49 @@ impl Sink {
         );
 
         self.has_write_ref.store(true);
+        self.create_write_guard()
+    }
+
+    /// Like [Sink::write], but returns None instead of panicking if the sink is already being written to or isn't idle.
+    pub fn try_write(&self) -> Option<WriteGuard> {
+        // Claim the write reference atomically, so two loads can't both get one
+        if self.has_write_ref.compare_exchange(false, true).is_err() {
+            return None;
+        }
+
+        if self.load_state() != SinkLoadState::Idle {
+            self.has_write_ref.store(false);
+            return None;
+        }
+
+        Some(self.create_write_guard())
+    }
+
+    /// Returns true if a load is currently writing to the sink.
+    pub fn is_being_written(&self) -> bool {
+        self.has_write_ref.load()
+    }
+
+    fn create_write_guard(&self) -> WriteGuard {
         self.set_load_state(SinkLoadState::Loading);
         self.interact();
 
+        let cancellation = LoadCancellation::default();
+        *self.load_cancellation.lock() = cancellation.clone();
+
         WriteGuard {
             context: self.context.clone(),
             id: self.id,
+            cancellation,
         }
     }
 
+    /// Signals the in-flight load of the sink to stop, if any.
+    /// The load will not write any more samples into the sink.
+    pub fn cancel_load(&self) {
+        self.load_cancellation.lock().cancel();
+    }
+
     pub fn load_state(&self) -> SinkLoadState {
         self.load_state.lock().clone()
     }
@@ -193,13 +318,56 @@ impl Sink {
     }
 
     /// Clears the samples in the sink outside the given window.
-    fn clear_outside(&self, offset: usize, window: usize, chunk_size: usize) {
+    /// Clears samples outside the window around the offset, returning how many samples were freed.
+    fn clear_outside(
+        &self,
+        offset: usize,
+        behind: usize,
+        ahead: usize,
+        chunk_size: usize,
+    ) -> usize {
         self.write_buffer(|buffer| {
-            let start = offset.saturating_sub(window);
-            let end = offset + window;
+            let start = offset.saturating_sub(behind);
+            let end = offset.saturating_add(ahead);
+            let size = buffer.size();
 
             buffer.retain_range(start, end, chunk_size);
-        });
+            size - buffer.size()
+        })
+    }
+
+    /// Returns how many samples are stored in the sink.
+    pub fn samples_in_memory(&self) -> usize {
+        match &*self.activation.read() {
+            SinkActivation::Activated(buffer) => buffer.size(),
+            _ => 0,
+        }
+    }
+
+    /// Returns what is loaded in the sink, if it is activated.
+    /// Instead of waiting for the buffer while it is being written to, [None] is returned.
+    pub fn try_introspect_buffer(&self) -> Option<MultiRangeBufferIntrospection> {
+        match &*self.activation.try_read()? {
+            SinkActivation::Activated(buffer) => Some(buffer.introspect()),
+            _ => None,
+        }
+    }
+
+    /// Returns why the sink failed to activate, if it did.
+    /// Like [Sink::try_introspect_buffer], [None] is returned instead of waiting for the sink.
+    pub fn try_activation_error(&self) -> Option<ActivationError> {
+        match &*self.activation.try_read()? {
+            SinkActivation::Error(error) => Some(error.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns all samples of the sink, if it has been loaded completely and none were cleared.
+    pub fn complete_samples(&self) -> Option<Vec<Sample>> {
+        match &*self.activation.read() {
+            SinkActivation::Activated(buffer) => buffer.complete_samples(),
+            _ => None,
+        }
     }
 
     /// Finalizes the end of the sink, having it be known
@@ -208,6 +376,7 @@ impl Sink {
             buffer.truncate();
 
             info!(
+                sink_id = self.id.value();
                 "Sink #{} ended at length {} with discrepancy of {}",
                 self.id,
                 buffer.length().unwrap_or_default(),
@@ -229,6 +398,15 @@ impl Sink {
         )
     }
 
+    /// Returns true if the sink failed to activate or load, or is being activated or loaded into.
+    /// Work in flight can't be told apart from work that is stuck, so both count.
+    pub fn is_failed_or_busy(&self) -> bool {
+        let failed = matches!(self.load_state(), SinkLoadState::Error(_))
+            || matches!(*self.activation.read(), SinkActivation::Error(_));
+
+        failed || self.has_write_ref.load() || self.has_activation_guard.load()
+    }
+
     /// Returns true if the sink is inactive
     pub fn is_activatable(&self) -> bool {
         matches!(*self.activation.read(), SinkActivation::Inactive)
@@ -240,6 +418,14 @@ impl Sink {
         matches!(*self.activation.read(), SinkActivation::Activated(_))
     }
 
+    /// Returns true if the sink is activated or being activated
+    pub fn is_active(&self) -> bool {
+        matches!(
+            *self.activation.read(),
+            SinkActivation::Activated(_) | SinkActivation::Activating
+        )
+    }
+
     /// Returns true if the sink can be cleared from memory.
     pub fn is_clearable(&self) -> bool {
         let has_read_ref = self.has_guard.load();
@@ -255,6 +441,13 @@ impl Sink {
         !has_read_ref && !has_write_ref
     }
 
+    /// Makes the sink appear as if it hasn't been interacted with for a long time.
+    #[cfg(test)]
+    pub(crate) fn expire(&self) {
+        let expired_at = Instant::now() - std::time::Duration::from_secs(3600);
+        self.duration_since_interaction.store(expired_at);
+    }
+
     fn set_load_state(&self, state: SinkLoadState) {
         let mut current_load_state = self.load_state.lock();
 
@@ -271,6 +464,9 @@ impl Sink {
     fn clear_guard(&self) {
         self.has_guard.store(false);
         self.interact();
+
+        // The sink is no longer played, so there is no point in loading more.
+        self.cancel_load();
     }
 
     fn clear_activation_guard(&self) {
@@ -292,7 +488,12 @@ impl Sink {
     fn internal_write(&self, offset: usize, samples: &[Sample]) {
         self.write_buffer(|buffer| buffer.write(offset, samples));
 
+        if !samples.is_empty() {
+            self.failed_loads.store(0);
+        }
+
         info!(
+            sink_id = self.id.value();
             "Wrote {} samples at offset {} into sink #{}",
             samples.len(),
             offset,
@@ -300,6 +501,23 @@ impl Sink {
         );
     }
 
+    /// Records a load that failed with an error that may be temporary.
+    /// The sink is only set to the error state once [Config::load_retries] is exceeded.
+    fn fail_load(&self, error: String) {
+        let failed_loads = self.failed_loads.fetch_add(1) + 1;
+
+        if failed_loads > self.context.config.load_retries {
+            self.set_load_state(SinkLoadState::Error(error));
+            return;
+        }
+
+        warn!(
+            sink_id = self.id.value();
+            "Load of sink #{} failed, trying again ({}/{}): {}",
+            self.id, failed_loads, self.context.config.load_retries, error
+        );
+    }
+
     fn interact(&self) {
         self.duration_since_interaction.store(Instant::now());
     }
@@ -359,8 +577,15 @@ impl SinkGuard {
         self.get_sink().can_load_more()
     }
 
-    pub fn clear_outside(&self, offset: usize, window: usize, chunk_size: usize) {
-        self.get_sink().clear_outside(offset, window, chunk_size);
+    pub fn clear_outside(
+        &self,
+        offset: usize,
+        behind: usize,
+        ahead: usize,
+        chunk_size: usize,
+    ) -> usize {
+        self.get_sink()
+            .clear_outside(offset, behind, ahead, chunk_size)
     }
 
     pub fn is_activated(&self) -> bool {
@@ -370,6 +595,42 @@ impl SinkGuard {
     pub fn expected_length(&self) -> Option<usize> {
         self.get_sink().expected_length()
     }
+
+    pub fn try_introspect_buffer(&self) -> Option<MultiRangeBufferIntrospection> {
+        self.get_sink().try_introspect_buffer()
+    }
+
+    pub fn try_activation_error(&self) -> Option<ActivationError> {
+        self.get_sink().try_activation_error()
+    }
+
+    pub fn trim(&self) -> SinkTrim {
+        self.get_sink().trim()
+    }
+
+    pub fn cancel_load(&self) {
+        self.get_sink().cancel_load()
+    }
+}
+
+impl SinkTrim {
+    /// Creates a trim from an optional start and end in seconds, aligned to whole frames.
+    pub fn from_seconds(config: &Config, start: Option<f32>, end: Option<f32>) -> Self {
+        let to_frame = |seconds: f32| {
+            let samples = config.seconds_to_samples(seconds.max(0.));
+            samples - samples % config.channel_count
+        };
+
+        Self {
+            start: start.map(to_frame).unwrap_or_default(),
+            end: end.map(to_frame),
+        }
+    }
+
+    /// Returns how many samples can be played from the offset until the end of the trim, if there is one.
+    pub fn distance_from_end(&self, offset: usize) -> Option<usize> {
+        self.end.map(|end| end.saturating_sub(offset))
+    }
 }
 
 impl WriteGuard {
@@ -382,14 +643,30 @@ impl WriteGuard {
     }
 
     pub fn write(&self, offset: usize, samples: &[Sample]) {
+        // An obsolete load must not write stale samples.
+        if self.is_cancelled() {
+            return;
+        }
+
         self.get_sink().internal_write(offset, samples);
     }
 
+    /// Returns true if the load using this guard is obsolete and should stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
     /// Sets the sink to the given error state.
     pub fn error(&self, error: String) {
         self.get_sink().set_load_state(SinkLoadState::Error(error));
     }
 
+    /// Fails the load with an error that may go away by loading again, like a network error.
+    /// The sink keeps being loaded until it fails too many times in a row, see [Config::load_retries].
+    pub fn retryable_error(&self, error: String) {
+        self.get_sink().fail_load(error);
+    }
+
     /// Finalizes the end of the sink, having it be known
     pub fn end(&self) {
         self.get_sink().end()
@@ -409,6 +686,7 @@ impl ActivationGuard {
         self.finished.store(true);
 
         info!(
+            sink_id = self.id.value();
             "Sink #{} activated with length: {:?}",
             self.id, expected_length
         );
@@ -417,12 +695,18 @@ impl ActivationGuard {
             SinkActivation::Activated(MultiRangeBuffer::new(expected_length));
     }
 
-    pub fn fail(self, reason: &str) {
+    pub fn fail(self, error: ActivationError) {
         self.finished.store(true);
 
-        info!("Sink #{} failed to activate: {}", self.id, reason);
+        info!(
+            sink_id = self.id.value();
+            "Sink #{} failed to activate: {} ({})",
+            self.id,
+            error.reason,
+            error.input.as_deref().unwrap_or("unknown input")
+        );
 
-        *self.get_sink().activation.write() = SinkActivation::Error(reason.to_string());
+        *self.get_sink().activation.write() = SinkActivation::Error(error);
     }
 }
 
@@ -477,6 +761,9 @@ pub enum ActivationIntrospection {
     },
     Error {
         reason: String,
+        kind: IngestionErrorKind,
+        /// The description of the source that failed, if it has one
+        input: Option<String>,
     },
 }
 
@@ -508,7 +795,9 @@ impl Introspect<ActivationIntrospection> for SinkActivation {
                 buffer: buffer.introspect(),
             },
             SinkActivation::Error(err) => ActivationIntrospection::Error {
-                reason: err.clone(),
+                reason: err.reason.clone(),
+                kind: err.kind,
+                input: err.input.clone(),
             },
         }
     }
@@ -1,7 +1,11 @@
 use crossbeam::channel::unbounded;
 use dashmap::DashMap;
 use log::info;
-use std::{sync::Arc, thread};
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
 mod config;
 mod events;
@@ -19,6 +23,9 @@ pub use playback::*;
 pub use queuing::*;
 pub use util::*;
 
+/// How often threads waiting on a channel check if they should shut down
+pub(crate) const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 // Reduces verbosity
 pub type Store<Id, T> = Arc<DashMap<Id, T>>;
 pub type ArcedStore<Id, T> = Store<Id, Arc<T>>;
@@ -48,6 +55,9 @@ pub struct PipelineContext {
     pub sinks: ArcedStore<SinkId, Sink>,
     pub players: ArcedStore<PlayerId, Player>,
     pub queues: Store<PlayerId, BoxedQueue>,
+
+    pub shutdown: Shutdown,
+    pub heartbeats: Heartbeats,
 }
 
 impl<I> Pipeline<I>
@@ -67,6 +77,9 @@ where
             sinks: Default::default(),
             players: Default::default(),
             queues: Default::default(),
+
+            shutdown: Default::default(),
+            heartbeats: Default::default(),
         };
 
         let sink_manager = Arc::new(SinkManager::new(&context, I::new(&context)));
@@ -75,7 +88,12 @@ where
         let queuing = Arc::new(Queuing::new(&context, sink_manager.clone()));
         let playback = Playback::new(&context, sink_manager.clone(), output.clone());
 
-        spawn_action_handler_thread(&context, queuing.clone(), action_receiver);
+        spawn_action_handler_thread(
+            &context,
+            queuing.clone(),
+            sink_manager.clone(),
+            action_receiver,
+        );
 
         info!("Initialized pipeline with ingestion {}", I::name());
 
@@ -104,18 +122,75 @@ where
     }
 
     /// Creates a consumer for a player.
-    pub fn consume_player<E>(&self, player_id: PlayerId, with_latency: Option<u32>) -> Consumer
+    /// Consumers with the same `token` are the same client, which is counted as one listener.
+    pub fn consume_player<E>(
+        &self,
+        player_id: PlayerId,
+        with_latency: Option<u32>,
+        token: Option<String>,
+    ) -> Consumer
     where
         E: Encoder,
     {
-        self.output.consume_player::<E>(player_id, with_latency)
+        self.output
+            .consume_player::<E>(player_id, with_latency, token)
+    }
+
+    /// Returns how many unique listeners are consuming a player.
+    pub fn unique_listeners(&self, player_id: PlayerId) -> usize {
+        self.output.unique_listeners(player_id)
+    }
+
+    /// Receive events from the pipeline, blocking until one is available.
+    /// Returns an error if the pipeline has shut down.
+    pub fn wait_for_event(&self) -> Result<PipelineEvent, RecvError> {
+        self.event_receiver.recv()
+    }
+
+    /// Receive an event from the pipeline if one is available, without blocking.
+    pub fn try_wait_for_event(&self) -> Result<PipelineEvent, TryRecvError> {
+        self.event_receiver.try_recv()
+    }
+
+    /// Receive events from the pipeline, blocking until one is available or the timeout passes.
+    pub fn wait_for_event_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<PipelineEvent, RecvTimeoutError> {
+        self.event_receiver.recv_timeout(timeout)
+    }
+
+    /// Stops the threads of the pipeline, blocking until they have exited.
+    /// The pipeline can't be used after this.
+    pub fn shutdown(&self) {
+        info!("Shutting down pipeline...");
+        self.context.shutdown.shutdown();
+    }
+
+    /// Returns how much each encoder has encoded, and how long it took.
+    pub fn encoder_stats(&self) -> Vec<EncoderStatsIntrospection> {
+        self.output.introspect()
     }
 
-    /// Receive events from the pipeline.
-    pub fn wait_for_event(&self) -> PipelineEvent {
-        self.event_receiver
-            .recv()
-            .expect("event is received without error")
+    /// Returns how many sinks are activated, and how much audio they hold.
+    pub fn sink_usage(&self) -> SinkUsage {
+        self.sink_manager.usage()
+    }
+
+    /// Returns how many of the threads of the pipeline are still running.
+    pub fn running_threads(&self) -> usize {
+        self.context.shutdown.running()
+    }
+
+    /// Returns the threads of the pipeline that stopped making progress, like ones that panicked.
+    /// After a shutdown, every thread is stalled.
+    pub fn health(&self) -> PipelineHealth {
+        PipelineHealth {
+            stalled_threads: self
+                .context
+                .heartbeats
+                .stalled(self.context.config.stall_threshold()),
+        }
     }
 }
 
@@ -143,18 +218,58 @@ impl PipelineContext {
             ..Default::default()
         }
     }
+
+    /// Creates a new context with the given config, and returns the receiver of its events.
+    /// Only used in tests.
+    #[cfg(test)]
+    pub fn with_event_receiver(config: &Config) -> (Self, EventReceiver) {
+        let (action_sender, _) = unbounded();
+        let (event_sender, event_receiver) = unbounded();
+
+        let context = Self {
+            config: config.clone(),
+            action_sender,
+            event_sender,
+            ..Default::default()
+        };
+
+        (context, event_receiver)
+    }
 }
 
-fn spawn_action_handler_thread(
+fn spawn_action_handler_thread<I>(
     context: &PipelineContext,
     queueing: Arc<Queuing>,
+    manager: Arc<SinkManager<I>>,
     action_receiver: ActionReceiver,
-) {
+) where
+    I: Ingestion + 'static,
+{
+    let context = context.clone();
     let players = context.players.clone();
     let config = context.config.clone();
+    let shutdown = context.shutdown.clone();
+    let handle = get_or_create_handle();
+    let heartbeat = context.heartbeats.register("core-actions");
+
+    // An action that ended a seek window early, which is handled next
+    let mut next = None;
 
     let run = move || loop {
-        let action = action_receiver.recv().unwrap();
+        heartbeat.beat();
+
+        if context.shutdown.is_requested() {
+            break;
+        }
+
+        let action = match next.take() {
+            Some(action) => action,
+            None => match action_receiver.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok(action) => action,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
 
         match action {
             PipelineAction::NotifyQueueUpdate { player_id } => {
@@ -172,18 +287,87 @@ fn spawn_action_handler_thread(
                 player_id,
                 position,
             } => {
+                let (position, interrupted_by) = coalesce_seeks(
+                    &action_receiver,
+                    player_id,
+                    position,
+                    config.seek_coalesce_window(),
+                );
+                next = interrupted_by;
+
                 let player = players.get(&player_id).expect("player exists");
+
+                if player.seek_across_boundary(position) {
+                    continue;
+                }
+
                 let position_in_samples = config.seconds_to_samples(position);
 
-                player.seek(position_in_samples);
+                // Load the new position right away, instead of waiting for the preloader to get to it.
+                if let Some(preload) = player.seek(position_in_samples) {
+                    let manager = manager.clone();
+                    let amount = config.seek_preload_size_in_samples();
+
+                    handle.spawn(async move {
+                        manager
+                            .request_priority_load(preload.sink_id, preload.offset, amount)
+                            .await
+                    });
+                }
+            }
+            PipelineAction::SetPlayerSpeed { player_id, speed } => {
+                let player = players.get(&player_id).expect("player exists");
+                player.set_speed(speed);
+            }
+            PipelineAction::ReseatPlayer { player_id } => {
+                let player = players.get(&player_id).expect("player exists");
+                player.reseat();
+                replace_failed_sinks(&context, &manager, player_id);
+                player.play();
+
+                // This goes through the same path as any queue update, which sets the sinks and preloads them
+                queueing.notify_queue_update(player_id);
             }
         }
     };
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("core-actions".to_string())
         .spawn(run)
         .expect("core-actions thread is spawned");
+
+    shutdown.register(thread);
+}
+
+/// Waits `window` for more seeks of the same player, and returns the position of the last one.
+/// Earlier seeks are never acted upon, and the load of the one that is cancels any load still in flight.
+///
+/// Any other action ends the wait early, and is returned so that it is still handled after the seek.
+fn coalesce_seeks(
+    action_receiver: &ActionReceiver,
+    player_id: PlayerId,
+    mut position: f32,
+    window: Duration,
+) -> (f32, Option<PipelineAction>) {
+    if window.is_zero() {
+        return (position, None);
+    }
+
+    // The window isn't extended by later seeks, so a seek is never held up for longer than it
+    let deadline = Instant::now() + window;
+
+    loop {
+        match action_receiver.recv_deadline(deadline) {
+            Ok(PipelineAction::SeekPlayer {
+                player_id: id,
+                position: superseding,
+            }) if id == player_id => {
+                position = superseding;
+            }
+            Ok(action) => return (position, Some(action)),
+            Err(_) => return (position, None),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -193,6 +377,7 @@ pub struct PipelineIntrospection {
     pub sinks: Vec<SinkIntrospection>,
     pub players: Vec<PlayerIntrospection>,
     pub streams: Vec<StreamIntrospection>,
+    pub encoders: Vec<EncoderStatsIntrospection>,
     /// The amount of bytes the pipeline is taking up in total
     pub size: usize,
 }
@@ -202,7 +387,7 @@ where
     I: Ingestion,
 {
     fn introspect(&self) -> PipelineIntrospection {
-        let streams = self.output.introspect();
+        let streams: Vec<StreamIntrospection> = self.output.introspect();
         let sinks: Vec<_> = self.context.sinks.iter().map(|s| s.introspect()).collect();
         let players: Vec<_> = self
             .context
@@ -227,6 +412,7 @@ where
             config: self.context.config.clone(),
             ingestion: I::name(),
             streams,
+            encoders: self.output.introspect(),
             players,
             sinks,
             size,
@@ -236,7 +422,7 @@ where
 
 // Realistically, the context should always be created by the pipeline.
 // However, in a test, this may not be possible.
-#[cfg(test)]
+#[cfg(any(test, feature = "testing"))]
 impl Default for PipelineContext {
     fn default() -> Self {
         let (action_sender, _) = unbounded();
@@ -250,6 +436,136 @@ impl Default for PipelineContext {
             sinks: Default::default(),
             players: Default::default(),
             queues: Default::default(),
+
+            shutdown: Default::default(),
+            heartbeats: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use crate::{
+        wait_for, Config, FakeIngestion, FakeInput, FakeItem, FakePattern, FakeQueue, Pipeline,
+        PipelineEvent, PlayerState, QueueItem,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reseat_restores_playback() {
+        let config = Config::default();
+        let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config.clone());
+        let player = pipeline.create_player();
+
+        let item = FakeItem::new(FakeInput::new(
+            FakePattern::Constant(0.5),
+            config.seconds_to_samples(60.),
+        ));
+        let queue = pipeline.create_queue(player.id, |notifier| {
+            notifier.notify();
+            FakeQueue(item)
+        });
+
+        wait_for(|| player.current_state() == PlayerState::Playing);
+        let sink_id = queue.0.sink_id();
+
+        // Corrupts the timeline, like a player that lost track of its sinks
+        let stuck = pipeline.context.players.get(&player.id).unwrap().clone();
+        stuck.set_sinks(vec![]);
+
+        wait_for(|| player.current_state() == PlayerState::Idle);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            player.current_state(),
+            PlayerState::Idle,
+            "the player doesn't recover by itself"
+        );
+
+        player.reseat();
+
+        wait_for(|| player.current_state() == PlayerState::Playing);
+        assert_eq!(
+            player.current_sink(),
+            sink_id,
+            "the queue's item plays again"
+        );
+
+        let reseated = std::iter::from_fn(|| pipeline.try_wait_for_event().ok()).any(
+            |e| matches!(e, PipelineEvent::PlayerReseated { player_id } if player_id == player.id),
+        );
+        assert!(reseated);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reseat_activates_failed_items_again() {
+        let config = Config::default();
+        let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config.clone());
+        let player = pipeline.create_player();
+
+        // The source is unavailable at first, like during a network outage
+        let item = FakeItem::new(FakeInput::new(
+            FakePattern::Constant(0.5),
+            config.seconds_to_samples(60.),
+        ))
+        .with_failures(1);
+        let queue = pipeline.create_queue(player.id, |notifier| {
+            notifier.notify();
+            FakeQueue(item)
+        });
+
+        wait_for(|| {
+            std::iter::from_fn(|| pipeline.try_wait_for_event().ok())
+                .any(|e| matches!(e, PipelineEvent::QueueItemActivationError { .. }))
+        });
+
+        let failed_sink_id = queue.0.sink_id();
+        thread::sleep(Duration::from_millis(200));
+        assert_ne!(
+            player.current_state(),
+            PlayerState::Playing,
+            "the failed item doesn't play"
+        );
+
+        player.reseat();
+
+        wait_for(|| player.current_state() == PlayerState::Playing);
+        assert_ne!(queue.0.sink_id(), failed_sink_id, "the item has a new sink");
+        assert_eq!(player.current_sink(), queue.0.sink_id());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_health_flags_stopped_threads() {
+        let config = Config {
+            stall_threshold_in_seconds: 0.2,
+            ..Default::default()
+        };
+        let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config);
+
+        thread::sleep(Duration::from_millis(400));
+        assert!(pipeline.health().is_healthy(), "threads keep beating");
+
+        // Threads that stopped don't beat anymore, just like ones that panicked
+        pipeline.shutdown();
+        thread::sleep(Duration::from_millis(400));
+
+        let mut stalled: Vec<_> = pipeline
+            .health()
+            .stalled_threads
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        stalled.sort();
+
+        assert_eq!(
+            stalled,
+            [
+                "cleanup",
+                "core-actions",
+                "output",
+                "playback",
+                "queue-update-task"
+            ]
+        );
+    }
+}
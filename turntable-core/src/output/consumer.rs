@@ -1,11 +1,15 @@
-use crossbeam::channel::{unbounded, Receiver, Sender};
+use crossbeam::{
+    atomic::AtomicCell,
+    channel::{unbounded, Receiver, Sender},
+};
 use parking_lot::Mutex;
 use std::{
+    panic::{self, AssertUnwindSafe},
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use super::{Encoder, EncoderIntrospection, Stream};
+use super::{Encoder, EncoderIntrospection, EncoderStats, Stream};
 use crate::{Config, Id, IdType, Introspect, Sample};
 
 pub type ConsumerId = Id<Consumer>;
@@ -14,42 +18,80 @@ pub type ConsumerId = Id<Consumer>;
 /// then provides the encoded data to the end-user.
 pub struct Consumer {
     pub id: ConsumerId,
+    /// A stable token provided by the client, like its stream key,
+    /// so that a client reconnecting can be told apart from a new one.
+    pub token: Option<String>,
     /// The stream this consumer belongs to.
     stream: Weak<Stream>,
     /// The encoder that will be used to encode the audio data.
     encoder: Arc<Mutex<Box<dyn Encoder>>>,
     /// Receives a unit type when new samples are available
     receiver: Receiver<()>,
+    config: Config,
+    /// How many samples the producer pushed, which is only changed while the encoder is locked
+    samples_pushed: Arc<AtomicCell<usize>>,
+    /// Until audio is returned for the first time, waiting for it gives up at this time
+    start_deadline: Mutex<Option<Instant>>,
 }
 
 /// The producer part of a consumer
 pub struct Producer {
     encoder: Arc<Mutex<Box<dyn Encoder>>>,
+    /// The token of the consumer
+    token: Option<String>,
+    /// Shared by all producers with the same encoder
+    stats: Arc<EncoderStats>,
     /// Used to notify the consumer of new samples
     sender: Sender<()>,
+    samples_pushed: Arc<AtomicCell<usize>>,
+}
+
+/// What a [Consumer] does when it starts before any audio is available
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConsumerStart {
+    /// Wait for audio like at any other time
+    #[default]
+    Wait,
+    /// Start with this much silence, so that there is data to return right away
+    Silence(Duration),
+    /// Give up if no audio is available within this time
+    Timeout(Duration),
 }
 
 impl Consumer {
-    pub fn new<E>(config: Config, stream: Weak<Stream>) -> (Self, Producer)
+    pub fn new<E>(
+        config: Config,
+        stream: Weak<Stream>,
+        stats: Arc<EncoderStats>,
+        token: Option<String>,
+    ) -> (Self, Producer)
     where
         E: Encoder,
     {
-        let encoder = E::new(config);
+        let encoder = E::new(config.clone());
         let boxed_encoder: Box<dyn Encoder> = Box::new(encoder);
         let arced_encoder = Arc::new(Mutex::new(boxed_encoder));
 
         let (sender, receiver) = unbounded();
+        let samples_pushed: Arc<AtomicCell<usize>> = Default::default();
 
         let me = Self {
             stream,
             id: ConsumerId::new(),
+            token: token.clone(),
             encoder: arced_encoder.clone(),
             receiver,
+            config,
+            samples_pushed: samples_pushed.clone(),
+            start_deadline: Default::default(),
         };
 
         let producer = Producer {
             encoder: arced_encoder,
+            token,
+            stats,
             sender,
+            samples_pushed,
         };
 
         (me, producer)
@@ -60,6 +102,35 @@ impl Consumer {
         self.encoder.lock().content_type()
     }
 
+    /// Returns how many bytes the given duration of audio is encoded into, including any header, if it's known up front.
+    /// The duration is rounded down to whole frames.
+    pub fn expected_size(&self, duration: Duration) -> Option<usize> {
+        let samples = self.config.seconds_to_samples(duration.as_secs_f32());
+        let samples = samples - samples % self.config.channel_count;
+
+        self.encoder.lock().expected_size(samples)
+    }
+
+    /// Decides what happens if no audio is available when the consumer starts.
+    /// This should be called right after the consumer is created, before reading from it.
+    pub fn start_with(&self, start: ConsumerStart) {
+        match start {
+            ConsumerStart::Wait => {}
+            ConsumerStart::Silence(duration) => {
+                let mut encoder = self.encoder.lock();
+
+                // Silence would interrupt the audio if the producer already pushed some
+                if self.samples_pushed.load() == 0 {
+                    let length = self.config.seconds_to_samples(duration.as_secs_f32());
+                    encoder.encode(&vec![0.; length]);
+                }
+            }
+            ConsumerStart::Timeout(timeout) => {
+                *self.start_deadline.lock() = Some(Instant::now() + timeout);
+            }
+        }
+    }
+
     /// Returns the encoded data from the enccoder.
     /// If no data is available yet, it will block until there is.
     pub fn bytes(&self) -> Option<Vec<u8>> {
@@ -69,14 +140,20 @@ impl Consumer {
 
             // Immediately return bytes if they're available
             if let Some(bytes) = bytes {
+                *self.start_deadline.lock() = None;
                 return Some(bytes);
             }
 
             // If we don't drop this before waiting, we will deadlock.
             drop(encoder);
 
+            let timeout = match *self.start_deadline.lock() {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::from_secs(3),
+            };
+
             // Wait for more samples
-            let result = self.receiver.recv_timeout(Duration::from_secs(3));
+            let result = self.receiver.recv_timeout(timeout);
 
             // If something goes wrong or it times out, just break out of the loop.
             if result.is_err() {
@@ -87,12 +164,35 @@ impl Consumer {
 }
 
 impl Producer {
+    /// Returns the token of the consumer, if it has one
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
     /// Push the provided samples to the consumer and encode them.
-    pub fn push(&self, samples: &[Sample]) {
-        self.encoder.lock().encode(samples);
+    ///
+    /// Returns false if the encoder panicked, since its state can't be trusted anymore.
+    /// The producer should be dropped then, which ends the consumer.
+    pub fn push(&self, samples: &[Sample]) -> bool {
+        let mut encoder = self.encoder.lock();
+
+        let started = Instant::now();
+        let encoded = panic::catch_unwind(AssertUnwindSafe(|| encoder.encode(samples)));
+
+        if encoded.is_err() {
+            return false;
+        }
+
+        self.stats.record(samples.len(), started.elapsed());
+        self.samples_pushed.fetch_add(samples.len());
+
+        drop(encoder);
+
+        // Notify the consumer of new samples so we can avoid busywaiting.
+        // The consumer may have been dropped while this was encoding.
+        let _ = self.sender.send(());
 
-        // Notify the consumer of new samples so we can avoid busywaiting
-        self.sender.send(()).expect("notifies consumer");
+        true
     }
 }
 
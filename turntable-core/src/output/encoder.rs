@@ -1,3 +1,11 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use crate::{Config, Introspect, Sample};
 
 /// Represents a type that encodes [Sample]s into a desired audio format to be consumed by the end-user.
@@ -20,6 +28,12 @@ where
     /// Returns the content type of the encoded data.
     fn content_type(&self) -> String;
 
+    /// Returns how many bytes encoding the given amount of samples results in, including any header,
+    /// or [None] if that isn't known up front, like when the size depends on the audio.
+    fn expected_size(&self, _samples: usize) -> Option<usize> {
+        None
+    }
+
     /// Returns a human friendly name
     fn name() -> String
     where
@@ -33,3 +47,44 @@ pub struct EncoderIntrospection {
     /// Size of the data currently stored in the encoder in bytes
     pub size: usize,
 }
+
+/// Counts how much an encoder has encoded across all of its consumers, and how long it took.
+#[derive(Debug, Default)]
+pub struct EncoderStats {
+    samples: AtomicU64,
+    calls: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl EncoderStats {
+    /// Records a call to [Encoder::encode].
+    pub fn record(&self, samples: usize, elapsed: Duration) {
+        self.samples.fetch_add(samples as u64, Ordering::Relaxed);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct EncoderStatsIntrospection {
+    /// The name of the encoder
+    pub name: String,
+    /// How many samples have been encoded
+    pub samples_encoded: u64,
+    /// How many times samples were encoded
+    pub calls: u64,
+    /// How long encoding took in total
+    pub time_spent: Duration,
+}
+
+impl Introspect<EncoderStatsIntrospection> for (&String, &Arc<EncoderStats>) {
+    fn introspect(&self) -> EncoderStatsIntrospection {
+        EncoderStatsIntrospection {
+            name: self.0.clone(),
+            samples_encoded: self.1.samples.load(Ordering::Relaxed),
+            calls: self.1.calls.load(Ordering::Relaxed),
+            time_spent: Duration::from_nanos(self.1.nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use std::{sync::Arc, thread};
 
-use crate::{Config, Introspect, PipelineContext, PlayerId, Sample};
-use crossbeam::channel::{unbounded, Receiver, Sender};
+use crate::{
+    ArcedStore, Config, Heartbeat, Introspect, PipelineContext, PlayerId, Sample, Shutdown,
+    SHUTDOWN_POLL_INTERVAL,
+};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use dashmap::DashMap;
 
 mod consumer;
@@ -18,6 +21,7 @@ pub struct Output {
     config: Config,
     streams: Arc<DashMap<PlayerId, Arc<Stream>>>,
     sample_sender: Sender<ProcessedSamples>,
+    encoder_stats: ArcedStore<String, EncoderStats>,
 }
 
 struct ProcessedSamples {
@@ -30,23 +34,34 @@ impl Output {
         let (sample_sender, sample_receiver) = unbounded();
         let streams = Arc::new(DashMap::new());
 
-        spawn_output_thread(sample_receiver, streams.clone());
+        spawn_output_thread(
+            &context.shutdown,
+            context.heartbeats.register("output"),
+            sample_receiver,
+            streams.clone(),
+        );
 
         Self {
             config: context.config.clone(),
             streams,
             sample_sender,
+            encoder_stats: Default::default(),
         }
     }
 
     /// Creates a new stream for the given player.
     pub fn register_player(&self, player_id: PlayerId) {
-        let new_stream = Stream::new(self.config.clone());
+        let new_stream = Stream::new(self.config.clone(), self.encoder_stats.clone());
         self.streams.insert(player_id, new_stream);
     }
 
     /// Gets a consumer for the associated player, with the given encoder.
-    pub fn consume_player<E>(&self, player_id: PlayerId, with_latency: Option<u32>) -> Consumer
+    pub fn consume_player<E>(
+        &self,
+        player_id: PlayerId,
+        with_latency: Option<u32>,
+        token: Option<String>,
+    ) -> Consumer
     where
         E: Encoder,
     {
@@ -55,9 +70,11 @@ impl Output {
             .get(&player_id)
             .expect("consume_player() is not called with a player that does not exist");
 
-        let consumer = stream.consume::<E>(with_latency);
+        let is_reconnection = token.as_deref().is_some_and(|t| stream.has_token(t));
+        let consumer = stream.consume::<E>(with_latency, token);
 
         info!(
+            player_id = player_id.value(), consumer_id = consumer.id.value(), reconnection = is_reconnection;
             "Created {} consumer #{} of player #{}",
             E::name(),
             consumer.id,
@@ -67,30 +84,68 @@ impl Output {
         consumer
     }
 
+    /// Returns how many unique listeners are consuming the associated player.
+    pub fn unique_listeners(&self, player_id: PlayerId) -> usize {
+        self.streams
+            .get(&player_id)
+            .map(|s| s.unique_listeners())
+            .unwrap_or_default()
+    }
+
     /// Pushes samples to the associated player's stream.
     pub fn push(&self, player_id: PlayerId, samples: Vec<Sample>) {
-        self.sample_sender
-            .send(ProcessedSamples { player_id, samples })
-            .expect("processed samples are sent");
+        // The output thread is only gone if the pipeline shut down
+        let _ = self
+            .sample_sender
+            .send(ProcessedSamples { player_id, samples });
     }
 }
 
 fn spawn_output_thread(
+    shutdown: &Shutdown,
+    heartbeat: Heartbeat,
     receiver: Receiver<ProcessedSamples>,
     streams: Arc<DashMap<PlayerId, Arc<Stream>>>,
 ) {
+    let requested = shutdown.clone();
+
     let run = move || loop {
-        let processed_samples = receiver.recv().expect("processed samples are received");
+        heartbeat.beat();
+
+        if requested.is_requested() {
+            break;
+        }
+
+        let processed_samples = match receiver.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(processed_samples) => processed_samples,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
 
         if let Some(stream) = streams.get(&processed_samples.player_id) {
             stream.push(&processed_samples.samples);
         }
     };
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("output".to_string())
         .spawn(run)
         .expect("output thread is spawned");
+
+    shutdown.register(thread);
+}
+
+impl Introspect<Vec<EncoderStatsIntrospection>> for Output {
+    fn introspect(&self) -> Vec<EncoderStatsIntrospection> {
+        let mut stats: Vec<_> = self
+            .encoder_stats
+            .iter()
+            .map(|x| (x.key(), x.value()).introspect())
+            .collect();
+
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
 }
 
 impl Introspect<Vec<StreamIntrospection>> for Output {
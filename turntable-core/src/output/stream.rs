@@ -1,11 +1,14 @@
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Weak},
+};
 
 use dashmap::DashMap;
-use log::info;
+use log::{error, info};
 use parking_lot::Mutex;
 
-use super::{Consumer, ConsumerId, ConsumerPairIntrospection, Encoder};
-use crate::{Config, IdType, Introspect, PlayerId, Producer, Sample};
+use super::{Consumer, ConsumerId, ConsumerPairIntrospection, Encoder, EncoderStats};
+use crate::{ArcedStore, Config, IdType, Introspect, PlayerId, Producer, Sample};
 
 /// A stream is the destination of a [Player], and manages consumers for said player.
 ///
@@ -19,12 +22,15 @@ pub struct Stream {
     preload_cache: Mutex<Vec<Sample>>,
     /// The producer parts of consumers that have been created for this stream.
     producers: DashMap<ConsumerId, Producer>,
+    /// The stats of each encoder, by name
+    encoder_stats: ArcedStore<String, EncoderStats>,
 }
 
 impl Stream {
-    pub fn new(config: Config) -> Arc<Self> {
+    pub fn new(config: Config, encoder_stats: ArcedStore<String, EncoderStats>) -> Arc<Self> {
         Arc::new_cyclic(|me| Self {
             config,
+            encoder_stats,
             me: me.clone(),
             producers: Default::default(),
             preload_cache: Default::default(),
@@ -33,11 +39,14 @@ impl Stream {
 
     /// Gets a new consumer for this stream.
     /// - `with_latency` sets the latency to the provided milliseconds. Defaults to preload cache size.
-    pub fn consume<E>(&self, with_latency: Option<u32>) -> Consumer
+    /// - `token` identifies the client, so its consumers count as one listener. See [Stream::unique_listeners].
+    pub fn consume<E>(&self, with_latency: Option<u32>, token: Option<String>) -> Consumer
     where
         E: Encoder,
     {
-        let (consumer, producer) = Consumer::new::<E>(self.config.clone(), self.me.clone());
+        let stats = self.encoder_stats.entry(E::name()).or_default().clone();
+        let (consumer, producer) =
+            Consumer::new::<E>(self.config.clone(), self.me.clone(), stats, token);
 
         let max_latency_in_samples = self.config.stream_preload_cache_size();
         let latency_in_samples = with_latency
@@ -48,15 +57,43 @@ impl Stream {
         let preload_cache = self.preload_cache.lock();
         let used_preload_cache = preload_cache.len().saturating_sub(latency_in_samples);
 
-        producer.push(&preload_cache[used_preload_cache..]);
-        self.producers.insert(consumer.id, producer);
+        // The consumer ends right away if its encoder panics, since the producer is dropped
+        if producer.push(&preload_cache[used_preload_cache..]) {
+            self.producers.insert(consumer.id, producer);
+        } else {
+            error!(consumer_id = consumer.id.value(); "Encoder of consumer #{} panicked", consumer.id);
+        }
 
         consumer
     }
 
+    /// Returns true if a consumer with the given token is connected,
+    /// which means a new consumer with the same token is the client reconnecting.
+    pub fn has_token(&self, token: &str) -> bool {
+        self.producers.iter().any(|p| p.token() == Some(token))
+    }
+
+    /// Returns how many listeners are consuming this stream.
+    /// Consumers with the same token count as one, and consumers without a token count on their own.
+    pub fn unique_listeners(&self) -> usize {
+        let mut tokens = HashSet::new();
+        let mut anonymous = 0;
+
+        for producer in self.producers.iter() {
+            match producer.token() {
+                Some(token) => {
+                    tokens.insert(token.to_string());
+                }
+                None => anonymous += 1,
+            }
+        }
+
+        tokens.len() + anonymous
+    }
+
     /// Removes a producer from this stream.
     pub fn remove(&self, consumer_id: ConsumerId) {
-        info!("Dropped consumer #{}", consumer_id);
+        info!(consumer_id = consumer_id.value(); "Dropped consumer #{}", consumer_id);
         self.producers.remove(&consumer_id);
     }
 
@@ -64,9 +101,19 @@ impl Stream {
     ///
     /// Note: This function must not be called on the playback thread.
     pub fn push(&self, samples: &[Sample]) {
-        for producer in self.producers.iter() {
-            producer.push(samples);
-        }
+        // A panicking encoder only ends its own consumer, the others keep receiving audio
+        self.producers.retain(|consumer_id, producer| {
+            let pushed = producer.push(samples);
+
+            if !pushed {
+                error!(
+                    consumer_id = consumer_id.value();
+                    "Encoder of consumer #{} panicked, dropping it", consumer_id
+                );
+            }
+
+            pushed
+        });
 
         self.push_preload(samples)
     }
@@ -110,3 +157,142 @@ impl Introspect<StreamIntrospection> for (&PlayerId, &Arc<Stream>) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::{Config, Encoder, EncoderIntrospection, Introspect, Sample};
+
+    use super::Stream;
+
+    /// Returns how many samples it encoded, as bytes
+    struct CountingEncoder(Vec<u8>);
+
+    /// Panics once panicking is enabled
+    struct PanickingEncoder;
+
+    static SHOULD_PANIC: AtomicBool = AtomicBool::new(false);
+
+    impl Encoder for CountingEncoder {
+        fn new(_: Config) -> Self {
+            Self(vec![])
+        }
+
+        fn encode(&mut self, samples: &[Sample]) {
+            self.0.extend(samples.iter().map(|_| 1));
+        }
+
+        fn bytes(&mut self) -> Option<Vec<u8>> {
+            Some(self.0.drain(..).collect()).filter(|b: &Vec<u8>| !b.is_empty())
+        }
+
+        fn content_type(&self) -> String {
+            "test/counting".to_string()
+        }
+
+        fn name() -> String {
+            "counting".to_string()
+        }
+    }
+
+    impl Encoder for PanickingEncoder {
+        fn new(_: Config) -> Self {
+            Self
+        }
+
+        fn encode(&mut self, _: &[Sample]) {
+            if SHOULD_PANIC.load(Ordering::Relaxed) {
+                panic!("encoder is broken");
+            }
+        }
+
+        fn bytes(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn content_type(&self) -> String {
+            "test/panicking".to_string()
+        }
+
+        fn name() -> String {
+            "panicking".to_string()
+        }
+    }
+
+    impl Introspect<EncoderIntrospection> for CountingEncoder {
+        fn introspect(&self) -> EncoderIntrospection {
+            EncoderIntrospection {
+                name: Self::name(),
+                size: self.0.len(),
+            }
+        }
+    }
+
+    impl Introspect<EncoderIntrospection> for PanickingEncoder {
+        fn introspect(&self) -> EncoderIntrospection {
+            EncoderIntrospection {
+                name: Self::name(),
+                size: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_panicking_encoder() {
+        let stream = Stream::new(Config::default(), Arc::default());
+
+        let first = stream.consume::<CountingEncoder>(Some(0), None);
+        let broken = stream.consume::<PanickingEncoder>(Some(0), None);
+        let second = stream.consume::<CountingEncoder>(Some(0), None);
+
+        SHOULD_PANIC.store(true, Ordering::Relaxed);
+        stream.push(&[0.; 64]);
+
+        assert_eq!(
+            stream.producers.len(),
+            2,
+            "only the broken consumer is dropped"
+        );
+        assert_eq!(broken.bytes(), None, "broken consumer ends");
+
+        stream.push(&[0.; 64]);
+
+        for consumer in [first, second] {
+            assert_eq!(consumer.bytes().map(|b| b.len()), Some(128));
+        }
+    }
+
+    #[test]
+    fn test_unique_listeners() {
+        let stream = Stream::new(Config::default(), Arc::default());
+        let token = || Some("stream-key".to_string());
+
+        let first = stream.consume::<CountingEncoder>(Some(0), token());
+        assert!(stream.has_token("stream-key"));
+
+        // The same client reconnecting before its first connection was dropped
+        let reconnected = stream.consume::<CountingEncoder>(Some(0), token());
+        assert_eq!(reconnected.token, token());
+        assert_eq!(stream.unique_listeners(), 1, "same token counts once");
+
+        let _other = stream.consume::<CountingEncoder>(Some(0), Some("other-key".to_string()));
+        let _anonymous = stream.consume::<CountingEncoder>(Some(0), None);
+        let _other_anonymous = stream.consume::<CountingEncoder>(Some(0), None);
+        assert_eq!(stream.unique_listeners(), 4);
+
+        stream.remove(first.id);
+        assert_eq!(
+            stream.unique_listeners(),
+            4,
+            "reconnection is still listening"
+        );
+
+        stream.remove(reconnected.id);
+        assert!(!stream.has_token("stream-key"));
+        assert_eq!(stream.unique_listeners(), 3);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam::atomic::AtomicCell;
+
+use crate::Sample;
+
+/// How often the levels of a player are emitted at most
+pub const LEVELS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The peak and RMS level of each channel, where 1.0 is full scale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Levels {
+    pub peak: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+/// Measures the levels of the samples a player processes, over [LEVELS_INTERVAL] at a time.
+pub struct LevelMeter {
+    channel_count: usize,
+    peak: Vec<f32>,
+    sum_of_squares: Vec<f32>,
+    frames: usize,
+    last_taken: Option<Instant>,
+}
+
+/// Makes a player measure its levels while at least one subscription exists.
+pub struct LevelsSubscription {
+    subscribers: Arc<AtomicCell<usize>>,
+}
+
+impl LevelMeter {
+    pub fn new(channel_count: usize) -> Self {
+        Self {
+            channel_count,
+            peak: vec![0.; channel_count],
+            sum_of_squares: vec![0.; channel_count],
+            frames: 0,
+            last_taken: None,
+        }
+    }
+
+    /// Adds interleaved samples to the current measurement.
+    pub fn measure(&mut self, samples: &[Sample]) {
+        for frame in samples.chunks_exact(self.channel_count) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.peak[channel] = self.peak[channel].max(sample.abs());
+                self.sum_of_squares[channel] += sample * sample;
+            }
+        }
+
+        self.frames += samples.len() / self.channel_count;
+    }
+
+    /// Returns the levels measured since they were last taken, and starts a new measurement.
+    /// [None] is returned if they were taken less than [LEVELS_INTERVAL] ago, or nothing was measured.
+    pub fn take(&mut self, now: Instant) -> Option<Levels> {
+        let is_due = self
+            .last_taken
+            .is_none_or(|last| now.duration_since(last) >= LEVELS_INTERVAL);
+
+        if !is_due || self.frames == 0 {
+            return None;
+        }
+
+        let frames = self.frames as f32;
+        let levels = Levels {
+            peak: self.peak.clone(),
+            rms: self
+                .sum_of_squares
+                .iter()
+                .map(|sum| (sum / frames).sqrt())
+                .collect(),
+        };
+
+        self.peak.fill(0.);
+        self.sum_of_squares.fill(0.);
+        self.frames = 0;
+        self.last_taken = Some(now);
+
+        Some(levels)
+    }
+}
+
+impl LevelsSubscription {
+    pub(crate) fn new(subscribers: &Arc<AtomicCell<usize>>) -> Self {
+        subscribers.fetch_add(1);
+
+        Self {
+            subscribers: subscribers.clone(),
+        }
+    }
+}
+
+impl Drop for LevelsSubscription {
+    fn drop(&mut self) {
+        self.subscribers.fetch_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_is_throttled() {
+        let mut meter = LevelMeter::new(2);
+        let now = Instant::now();
+
+        assert_eq!(meter.take(now), None, "nothing was measured");
+
+        meter.measure(&[0.5, -0.25, -1., 0.]);
+        let levels = meter.take(now).expect("levels are taken");
+
+        assert_eq!(levels.peak, vec![1., 0.25]);
+        assert_eq!(levels.rms[1], (0.25f32 * 0.25 / 2.).sqrt());
+
+        meter.measure(&[0.5, 0.5]);
+        assert_eq!(meter.take(now + LEVELS_INTERVAL / 2), None);
+
+        // What was measured in between is kept until the interval passed
+        let levels = meter.take(now + LEVELS_INTERVAL).unwrap();
+        assert_eq!(levels.peak, vec![0.5, 0.5]);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{Config, Sample};
+
+/// How far below the ceiling the limiter starts to engage, in dB
+const KNEE_WIDTH_IN_DB: f32 = 3.;
+/// How long it takes for the gain to recover after a peak, in seconds
+const RELEASE_IN_SECONDS: f32 = 0.05;
+
+/// Keeps samples from going past a ceiling, so that loud audio doesn't clip.
+///
+/// Audio below the knee passes through unchanged. Peaks above it are reduced right away,
+/// after which the gain recovers smoothly, so the limiter is only heard on overshoot.
+#[derive(Debug)]
+pub struct Limiter {
+    channel_count: usize,
+    /// The level samples never exceed, or [None] if limiting is disabled
+    ceiling: Option<f32>,
+    /// The level the limiter starts to engage at
+    knee: f32,
+    /// How much of the distance to the target gain is recovered per frame
+    release: f32,
+    /// The gain currently applied
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(config: &Config) -> Self {
+        let ceiling = config.limiter_ceiling_in_db.map(db_to_amplitude);
+        let knee = ceiling.unwrap_or(1.) * db_to_amplitude(-KNEE_WIDTH_IN_DB);
+        let release_frames = RELEASE_IN_SECONDS * config.sample_rate as f32;
+
+        Self {
+            channel_count: config.channel_count,
+            ceiling,
+            knee,
+            release: 1. - (-1. / release_frames).exp(),
+            gain: 1.,
+        }
+    }
+
+    /// Limits interleaved samples in place
+    pub fn process(&mut self, samples: &mut [Sample]) {
+        let Some(ceiling) = self.ceiling else {
+            return;
+        };
+
+        for frame in samples.chunks_mut(self.channel_count) {
+            let peak = frame.iter().fold(0., |peak: f32, s| peak.max(s.abs()));
+            let target = self.target_gain(peak, ceiling);
+
+            // Peaks are caught right away, and the gain only recovers gradually
+            if target < self.gain {
+                self.gain = target;
+            } else {
+                self.gain += (target - self.gain) * self.release;
+            }
+
+            if self.gain < 1. {
+                // Clamping only guards against rounding, the gain already keeps samples below the ceiling
+                for sample in frame {
+                    *sample = (*sample * self.gain).clamp(-ceiling, ceiling);
+                }
+            }
+        }
+    }
+
+    /// Returns the gain that brings a peak under the ceiling with a soft knee.
+    /// Peaks map onto a curve that eases from the knee towards the ceiling without reaching it.
+    fn target_gain(&self, peak: f32, ceiling: f32) -> f32 {
+        if peak <= self.knee {
+            return 1.;
+        }
+
+        let range = ceiling - self.knee;
+        let limited = self.knee + range * ((peak - self.knee) / range).tanh();
+
+        limited / peak
+    }
+}
+
+pub(crate) fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(amplitude: f32) -> Vec<Sample> {
+        (0..44100)
+            .flat_map(|i| {
+                let s = (i as f32 * 440. / 44100. * std::f32::consts::TAU).sin() * amplitude;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_over_unity_is_limited() {
+        let config = Config::default();
+        let ceiling = db_to_amplitude(config.limiter_ceiling_in_db.unwrap());
+        let mut limiter = Limiter::new(&config);
+
+        let mut samples = sine(2.);
+        limiter.process(&mut samples);
+
+        let peak = samples.iter().fold(0., |p: f32, s| p.max(s.abs()));
+        assert!(
+            peak <= ceiling,
+            "peak {} is above the ceiling {}",
+            peak,
+            ceiling
+        );
+        assert!(peak > ceiling * 0.9, "peaks are limited, not muted");
+    }
+
+    #[test]
+    fn test_below_knee_is_unchanged() {
+        let mut limiter = Limiter::new(&Config::default());
+
+        let mut samples = sine(0.5);
+        limiter.process(&mut samples);
+
+        assert_eq!(samples, sine(0.5));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = Config {
+            limiter_ceiling_in_db: None,
+            ..Default::default()
+        };
+        let mut limiter = Limiter::new(&config);
+
+        let mut samples = sine(2.);
+        limiter.process(&mut samples);
+
+        assert_eq!(samples, sine(2.));
+    }
+}
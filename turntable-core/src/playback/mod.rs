@@ -1,18 +1,27 @@
 use log::info;
 use std::{
+    collections::HashMap,
     sync::Arc,
     thread,
     time::{Duration, Instant},
 };
 use tokio::time::sleep;
 
+mod levels;
+mod limiter;
 mod player;
+mod preloading;
+mod speed;
 mod timeline;
 
+pub use levels::*;
+pub use limiter::*;
 pub use player::*;
+pub use preloading::*;
+pub use speed::*;
 pub use timeline::*;
 
-use crate::{get_or_create_handle, Ingestion, Output, PipelineContext, SinkManager};
+use crate::{get_or_create_handle, Ingestion, Output, PipelineContext, PipelineEvent, SinkManager};
 
 /// The playback type is responsible for managing players, processing playback, and preloading sinks as needed.
 pub struct Playback {
@@ -44,7 +53,7 @@ impl Playback {
         let player = Player::new(&self.context, self.output.clone());
         let context = player.context();
 
-        info!("Created player #{}", player.id);
+        info!(player_id = player.id.value(); "Created player #{}", player.id);
 
         self.output.register_player(player.id);
         self.context.players.insert(player.id, player.into());
@@ -55,12 +64,16 @@ impl Playback {
 
 fn spawn_processing_thread(context: &PipelineContext) {
     let players = context.players.clone();
+    let shutdown = context.shutdown.clone();
     let tick_rate = Duration::from_secs_f32(context.config.buffer_size_in_seconds);
+    let heartbeat = context.heartbeats.register("playback");
 
     let run = move || {
         let mut next = Instant::now();
 
-        loop {
+        while !shutdown.is_requested() {
+            heartbeat.beat();
+
             for player in players.iter() {
                 player.process();
             }
@@ -70,38 +83,56 @@ fn spawn_processing_thread(context: &PipelineContext) {
         }
     };
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("playback".to_string())
         .spawn(run)
         .expect("playback thread spawns");
+
+    context.shutdown.register(thread);
 }
 
 fn spawn_cleanup_thread<I>(context: &PipelineContext, manager: Arc<SinkManager<I>>)
 where
     I: Ingestion + 'static,
 {
-    let players = context.players.clone();
+    let context = context.clone();
+    let shutdown = context.shutdown.clone();
+    let heartbeat = context.heartbeats.register("cleanup");
 
-    let run = move || loop {
-        for player in players.iter() {
-            player.clear_superflous();
-        }
+    let run = move || {
+        while !context.shutdown.is_requested() {
+            heartbeat.beat();
+
+            let (ids, freed): (Vec<_>, Vec<_>) = context
+                .players
+                .iter()
+                .filter_map(|player| player.clear_superflous())
+                .unzip();
+
+            // The players are no longer borrowed, so emitting can't hold up the store.
+            if !ids.is_empty() {
+                context.emit(PipelineEvent::SinksCleared {
+                    ids,
+                    samples_freed: freed.into_iter().sum(),
+                });
+            }
 
-        let cleared_sinks = manager.clear_inactive();
+            manager.clear_inactive();
 
-        if !cleared_sinks.is_empty() {
-            info!("Cleared Sinks: {:?}", cleared_sinks)
+            thread::sleep(Duration::from_millis(50))
         }
-
-        thread::sleep(Duration::from_millis(50))
     };
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("cleanup".to_string())
         .spawn(run)
         .expect("cleanup thread spawns");
+
+    shutdown.register(thread);
 }
 
+/// Preloads the players as needed, checking each one as often as [PreloadCadence] decides.
+/// Players are checked right away after seeking or changing what they play.
 fn spawn_preloading_task<I>(context: &PipelineContext, manager: Arc<SinkManager<I>>)
 where
     I: Ingestion + 'static,
@@ -109,10 +140,27 @@ where
     let handle = get_or_create_handle();
     let players = context.players.clone();
     let config = context.config.clone();
+    let shutdown = context.shutdown.clone();
+    let cadence = PreloadCadence::new(config.clone());
 
     handle.spawn(async move {
-        loop {
+        let mut next_checks = HashMap::new();
+
+        while !shutdown.is_requested() {
+            next_checks.retain(|id, _| players.contains_key(id));
+
             for player in players.iter() {
+                let now = Instant::now();
+                let requested = player.take_preload_request();
+                let is_due = next_checks.get(&player.id).is_none_or(|next| now >= *next);
+
+                if !requested && !is_due {
+                    continue;
+                }
+
+                let interval = cadence.interval(player.id, player.buffered_ahead(), player.speed());
+                next_checks.insert(player.id, now + interval);
+
                 let preloads = player.preload();
 
                 for preload in preloads {
@@ -126,7 +174,7 @@ where
                 }
             }
 
-            sleep(Duration::from_millis(50)).await;
+            sleep(MIN_PRELOAD_INTERVAL).await;
         }
     });
 }
@@ -1,13 +1,15 @@
-use std::{ops::Rem, sync::Arc};
+use std::{ops::Rem, sync::Arc, time::Instant};
 
 use crossbeam::atomic::AtomicCell;
+use parking_lot::Mutex;
 
 use crate::{
-    Id, IdType, Introspect, Output, PipelineAction, PipelineContext, PipelineEvent, Queue, Sink,
-    SinkId, Timeline, TimelinePreload,
+    change_speed, samples_for_speed, Id, IdType, Introspect, LevelMeter, LevelsSubscription,
+    Limiter, Output, PipelineAction, PipelineContext, PipelineEvent, Queue, Sample, SeekBehavior,
+    Sink, SinkId, Timeline, TimelinePreload, MAX_SPEED, MIN_SPEED,
 };
 
-use super::TimelineIntrospection;
+use super::{limiter::db_to_amplitude, SinkBufferHealth, TimelineIntrospection};
 
 pub type PlayerId = Id<Player>;
 
@@ -19,7 +21,15 @@ pub struct Player {
     timeline: Arc<Timeline>,
     output: Arc<Output>,
     state: Arc<AtomicCell<PlayerState>>,
-    should_play: AtomicCell<bool>,
+    should_play: Arc<AtomicCell<bool>>,
+    /// The playback speed, where 1.0 is normal speed.
+    speed: Arc<AtomicCell<f32>>,
+    /// How many want the levels, which are only measured if any do.
+    levels_subscribers: Arc<AtomicCell<usize>>,
+    /// Whether silence at the start of items is skipped, see [PlayerContext::set_trim_leading_silence].
+    trim_leading_silence: Arc<AtomicCell<bool>>,
+    meter: Mutex<LevelMeter>,
+    limiter: Mutex<Limiter>,
 }
 
 /// A type used to control a player and read its state.
@@ -28,6 +38,10 @@ pub struct PlayerContext {
     context: PipelineContext,
     timeline: Arc<Timeline>,
     state: Arc<AtomicCell<PlayerState>>,
+    should_play: Arc<AtomicCell<bool>>,
+    speed: Arc<AtomicCell<f32>>,
+    levels_subscribers: Arc<AtomicCell<usize>>,
+    trim_leading_silence: Arc<AtomicCell<bool>>,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -48,9 +62,14 @@ impl Player {
 
         Self {
             timeline: Timeline::new(config.clone()).into(),
-            should_play: true.into(),
+            should_play: Arc::new(true.into()),
             context: context.clone(),
             state: Default::default(),
+            speed: Arc::new(1.0.into()),
+            levels_subscribers: Default::default(),
+            trim_leading_silence: Default::default(),
+            meter: LevelMeter::new(config.channel_count).into(),
+            limiter: Limiter::new(&config).into(),
             id: PlayerId::new(),
             output,
         }
@@ -60,6 +79,21 @@ impl Player {
         self.timeline.preload()
     }
 
+    /// Returns how many samples are loaded ahead of playback, see [Timeline::buffered_ahead].
+    pub fn buffered_ahead(&self) -> Option<usize> {
+        self.timeline.buffered_ahead()
+    }
+
+    /// Returns true if the player should be preloaded right away, see [Timeline::take_preload_request].
+    pub fn take_preload_request(&self) -> bool {
+        self.timeline.take_preload_request()
+    }
+
+    /// Returns the playback speed, where 1.0 is normal speed.
+    pub fn speed(&self) -> f32 {
+        self.speed.load()
+    }
+
     pub fn set_sinks(&self, sinks: Vec<Arc<Sink>>) {
         self.timeline.set_sinks(sinks);
     }
@@ -67,12 +101,20 @@ impl Player {
     /// Processes the timeline and pushes the samples to the output stream.
     /// If there are no sinks to play, the samples pushed are silence.
     pub fn process(&self) {
-        let mut samples = vec![0.; self.context.config.buffer_size_in_samples()];
+        let buffer_size = self.context.config.buffer_size_in_samples();
+        let speed = self.speed.load();
+
+        // At a different speed, more or less samples are played in the same amount of time.
+        let amount_to_read =
+            samples_for_speed(buffer_size, self.context.config.channel_count, speed);
+
+        let mut samples = vec![0.; amount_to_read];
         let mut amount_read = 0;
 
         // If the player is not supposed to play, we just push silence.
         if !self.should_play.load() {
-            self.output.push(self.id, samples);
+            samples.resize(buffer_size, 0.);
+            self.push(samples);
             self.set_state_if_different(PlayerState::Idle);
 
             return;
@@ -100,6 +142,12 @@ impl Player {
         }
 
         for read in reads {
+            // The samples are already silent
+            if read.is_gap {
+                amount_read += read.amount;
+                continue;
+            }
+
             let slice = &mut samples[amount_read..];
 
             let sink = self
@@ -109,6 +157,15 @@ impl Player {
                 .expect("Sink exists when trying to read from it");
 
             let result = sink.read(read.offset, slice);
+            let gain = sink.gain();
+
+            if gain != 0. {
+                let amplitude = db_to_amplitude(gain);
+                slice[..result.amount]
+                    .iter_mut()
+                    .for_each(|s| *s *= amplitude);
+            }
+
             amount_read += result.amount;
         }
 
@@ -121,12 +178,37 @@ impl Player {
             self.emit_time()
         }
 
+        if speed != 1.0 {
+            samples = change_speed(&samples, self.context.config.channel_count, speed);
+            samples.resize(buffer_size, 0.);
+        }
+
+        // Limiting comes last, so nothing done to the samples before can make them clip
+        self.limiter.lock().process(&mut samples);
+        self.push(samples);
+    }
+
+    /// Pushes the processed samples to the output, measuring their levels if subscribed to.
+    fn push(&self, samples: Vec<Sample>) {
+        if self.levels_subscribers.load() > 0 {
+            let mut meter = self.meter.lock();
+            meter.measure(&samples);
+
+            if let Some(levels) = meter.take(Instant::now()) {
+                self.context.emit(PipelineEvent::PlayerLevels {
+                    player_id: self.id,
+                    levels,
+                });
+            }
+        }
+
         self.output.push(self.id, samples);
     }
 
     /// Clears samples that are not needed, to save memory.
-    pub fn clear_superflous(&self) {
-        self.timeline.clear_superflous();
+    /// Returns the sink and how many samples were freed, if any were.
+    pub fn clear_superflous(&self) -> Option<(SinkId, usize)> {
+        self.timeline.clear_superflous()
     }
 
     /// Starts playback if possible.
@@ -139,21 +221,101 @@ impl Player {
         self.should_play.store(false);
     }
 
+    /// Drops the timeline, so it can be rebuilt from the queue when it is in a bad state.
+    /// The in-flight loads of its sinks are cancelled, since they may be what is stuck.
+    pub fn reseat(&self) {
+        for sink_id in self.timeline.sink_ids() {
+            if let Some(sink) = self.context.sinks.get(&sink_id) {
+                sink.cancel_load();
+            }
+        }
+
+        self.timeline.set_sinks(vec![]);
+        self.context
+            .emit(PipelineEvent::PlayerReseated { player_id: self.id });
+    }
+
+    /// Sets the playback speed, clamped between [MIN_SPEED] and [MAX_SPEED].
+    /// The pitch is not preserved.
+    pub fn set_speed(&self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+
+        if self.speed.swap(speed) != speed {
+            self.context.emit(PipelineEvent::PlayerSpeedUpdate {
+                player_id: self.id,
+                speed,
+            });
+        }
+    }
+
     /// Seeks to a specific offset.
-    pub fn seek(&self, offset: usize) {
+    /// Returns what should be loaded right away, if nothing is loaded at the new offset.
+    pub fn seek(&self, offset: usize) -> Option<TimelinePreload> {
         // Prevent seeking to an incomplete frame
         let remainder = offset.rem(self.context.config.channel_count);
         let safe_offset = offset.saturating_sub(remainder);
 
-        self.timeline.seek(safe_offset);
+        let preload = self.timeline.seek(safe_offset);
         self.emit_time();
+
+        preload
+    }
+
+    /// Goes to the next or previous item in the queue if the position is outside of the current one,
+    /// and seeking across items is enabled. Returns true if it did, otherwise the position should be seeked to.
+    /// * `position` is the time in seconds.
+    pub fn seek_across_boundary(&self, position: f32) -> bool {
+        if self.context.config.seek_behavior != SeekBehavior::CrossBoundary {
+            return false;
+        }
+
+        let Some(queue) = self.context.queues.get(&self.id) else {
+            return false;
+        };
+
+        let start = self.timeline.current_sink().and_then(|id| {
+            let sink = self.context.sinks.get(&id)?;
+            Some(self.context.config.samples_to_seconds(sink.trim().start))
+        });
+
+        // There is nothing to go back from if nothing is playing
+        if start.is_some_and(|start| position < start) {
+            // Stays at the start if there is no previous item
+            self.seek(0);
+            queue.previous();
+
+            return true;
+        }
+
+        let end = self
+            .timeline
+            .current_end()
+            .map(|e| self.context.config.samples_to_seconds(e));
+
+        if end.is_some_and(|end| position >= end) {
+            drop(queue);
+            self.advance_queue_if_exists();
+
+            return true;
+        }
+
+        false
     }
 
     /// Returns the context for this player.
+    /// Returns true if silence at the start of items is skipped
+    pub fn trims_leading_silence(&self) -> bool {
+        self.trim_leading_silence.load()
+    }
+
     pub fn context(&self) -> PlayerContext {
         PlayerContext {
             id: self.id,
             state: self.state.clone(),
+            should_play: self.should_play.clone(),
+            speed: self.speed.clone(),
+            levels_subscribers: self.levels_subscribers.clone(),
+            trim_leading_silence: self.trim_leading_silence.clone(),
             context: self.context.clone(),
             timeline: self.timeline.clone(),
         }
@@ -209,6 +371,49 @@ impl PlayerContext {
             .dispatch(PipelineAction::PausePlayer { player_id: self.id });
     }
 
+    /// Sets the playback speed, where 1.0 is normal speed.
+    /// The pitch is not preserved, so faster playback also sounds higher.
+    pub fn set_speed(&self, speed: f32) {
+        self.context.dispatch(PipelineAction::SetPlayerSpeed {
+            player_id: self.id,
+            speed,
+        });
+    }
+
+    /// Rebuilds the timeline from the queue and resumes playback, to recover a player that is stuck.
+    /// The current item starts over, since where it was can't be trusted.
+    /// Items whose sinks failed or are still loading get new ones, so they're activated again.
+    pub fn reseat(&self) {
+        self.context
+            .dispatch(PipelineAction::ReseatPlayer { player_id: self.id });
+    }
+
+    /// Sets how many seconds of silence are played between items.
+    /// The silence doesn't count towards the position of either item.
+    pub fn set_gap(&self, seconds: f32) {
+        let channel_count = self.context.config.channel_count;
+        let samples = self.context.config.seconds_to_samples(seconds.max(0.));
+
+        // The gap has to be whole frames, or the channels would be swapped after it
+        self.timeline.set_gap(samples - samples % channel_count);
+    }
+
+    /// Sets whether silence at the start of items is skipped, up to [crate::Config::max_leading_silence_in_seconds].
+    /// Items are only trimmed when they are ingested, so this doesn't affect those that already were.
+    pub fn set_trim_leading_silence(&self, trim: bool) {
+        self.trim_leading_silence.store(trim);
+    }
+
+    /// Makes the player emit [PipelineEvent::PlayerLevels] until the subscription is dropped.
+    pub fn subscribe_levels(&self) -> LevelsSubscription {
+        LevelsSubscription::new(&self.levels_subscribers)
+    }
+
+    /// Returns the current playback speed.
+    pub fn speed(&self) -> f32 {
+        self.speed.load()
+    }
+
     /// Seeks to a specific time.
     /// * `position` is the time in seconds.
     pub fn seek(&self, position: f32) {
@@ -238,10 +443,50 @@ impl PlayerContext {
         self.timeline.current_sink()
     }
 
+    /// Returns the expected length of the currently playing sink in samples.
+    /// [None] if nothing is playing, the sink isn't activated yet, or the sink is infinite.
+    pub fn current_length(&self) -> Option<usize> {
+        let sink_id = self.current_sink()?;
+        let sink = self.context.sinks.get(&sink_id)?;
+
+        if !sink.is_activated() {
+            return None;
+        }
+
+        sink.expected_length()
+    }
+
+    /// Returns the expected length of the currently playing sink in seconds.
+    pub fn current_duration(&self) -> Option<f32> {
+        self.current_length()
+            .map(|l| self.context.config.samples_to_seconds(l))
+    }
+
+    /// Returns how much of the current and upcoming sinks is loaded.
+    /// [None] if the timeline was busy, in which case this can be called again later.
+    pub fn buffer_health(&self) -> Option<Vec<SinkBufferHealth>> {
+        self.timeline.buffer_health()
+    }
+
+    /// Returns how long a sink has been played for in seconds, excluding parts skipped by seeking.
+    pub fn time_played(&self, sink_id: SinkId) -> Option<f32> {
+        self.context.sinks.get(&sink_id).map(|sink| {
+            self.context
+                .config
+                .samples_to_seconds(sink.samples_played())
+        })
+    }
+
     /// Returns the current state of the player
     pub fn current_state(&self) -> PlayerState {
         self.state.load()
     }
+
+    /// Returns true if the player is not paused.
+    /// It may still be buffering, or have nothing to play.
+    pub fn is_playing(&self) -> bool {
+        self.should_play.load()
+    }
 }
 
 #[derive(Debug)]
@@ -262,3 +507,219 @@ impl Introspect<PlayerIntrospection> for Player {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{Config, Encoder, EncoderIntrospection};
+
+    #[test]
+    fn test_levels_of_sine() {
+        let (context, events) = PipelineContext::with_event_receiver(&Config::default());
+        let player = Player::new(&context, Arc::new(Output::new(&context)));
+
+        // One second of a 1 kHz sine, half as loud on the right channel
+        let sample_rate = context.config.sample_rate;
+        let samples: Vec<_> = (0..sample_rate)
+            .flat_map(|i| {
+                let sample = (2. * PI * 1000. * i as f32 / sample_rate as f32).sin() * 0.8;
+                [sample, sample / 2.]
+            })
+            .collect();
+
+        let sink = Arc::new(Sink::with_activation(&context, Some(samples.len())));
+        context.sinks.insert(sink.id, sink.clone());
+        sink.write().write(0, &samples);
+        player.set_sinks(vec![sink]);
+
+        let levels = || {
+            events.try_iter().find_map(|e| match e {
+                PipelineEvent::PlayerLevels { levels, .. } => Some(levels),
+                _ => None,
+            })
+        };
+
+        player.process();
+        assert!(
+            levels().is_none(),
+            "levels aren't measured without subscribers"
+        );
+
+        let subscription = player.context().subscribe_levels();
+        player.process();
+
+        let levels = levels().expect("levels are emitted");
+        let expected_peak = [0.8, 0.4];
+
+        for (channel, peak) in expected_peak.into_iter().enumerate() {
+            assert!((levels.peak[channel] - peak).abs() < 0.01);
+            assert!((levels.rms[channel] - peak / 2f32.sqrt()).abs() < 0.01);
+        }
+
+        drop(subscription);
+        assert_eq!(player.levels_subscribers.load(), 0);
+    }
+
+    /// Outputs the samples it was given as bytes, so they can be compared
+    struct RawEncoder(Vec<u8>);
+
+    impl Encoder for RawEncoder {
+        fn new(_: Config) -> Self {
+            Self(vec![])
+        }
+
+        fn encode(&mut self, samples: &[Sample]) {
+            self.0.extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+        }
+
+        fn bytes(&mut self) -> Option<Vec<u8>> {
+            Some(self.0.drain(..).collect()).filter(|b: &Vec<u8>| !b.is_empty())
+        }
+
+        fn content_type(&self) -> String {
+            "test/raw".to_string()
+        }
+
+        fn name() -> String {
+            "raw".to_string()
+        }
+    }
+
+    impl Introspect<EncoderIntrospection> for RawEncoder {
+        fn introspect(&self) -> EncoderIntrospection {
+            EncoderIntrospection {
+                name: Self::name(),
+                size: self.0.len(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_gap_between_sinks() {
+        let config = Config {
+            // Makes one second equal to ten samples, which is also the size of a buffer.
+            sample_rate: 10,
+            channel_count: 1,
+            buffer_size_in_seconds: 1.,
+            ..Default::default()
+        };
+        let (context, _events) = PipelineContext::with_event_receiver(&config);
+        let output = Arc::new(Output::new(&context));
+        let player = Player::new(&context, output.clone());
+
+        output.register_player(player.id);
+        let consumer = output.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+        let first = Arc::new(Sink::with_activation(&context, Some(4)));
+        let second = Arc::new(Sink::with_activation(&context, Some(4)));
+        context.sinks.insert(first.id, first.clone());
+        context.sinks.insert(second.id, second.clone());
+
+        first.write().write(0, &[0.25; 4]);
+        second.write().write(0, &[0.5; 4]);
+        player.set_sinks(vec![first, second.clone()]);
+        player.context().set_gap(0.3);
+
+        player.process();
+        assert_eq!(player.timeline.current_sink(), Some(second.id));
+        assert_eq!(
+            player.timeline.current_offset(),
+            3,
+            "the gap doesn't count towards the position"
+        );
+
+        player.process();
+
+        let mut played = vec![];
+        while played.len() < 20 {
+            let bytes = consumer.bytes().expect("samples are output");
+            played.extend(
+                bytes
+                    .chunks(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap())),
+            );
+        }
+
+        let mut expected = vec![0.25; 4];
+        expected.extend([0.; 3]);
+        expected.extend([0.5; 4]);
+        expected.resize(20, 0.);
+
+        assert_eq!(played, expected);
+    }
+
+    /// Counts how often it was moved, without containing any items
+    #[derive(Default)]
+    struct CountingQueue {
+        next: AtomicCell<usize>,
+        previous: AtomicCell<usize>,
+    }
+
+    impl Queue for CountingQueue {
+        fn peek(&self) -> Vec<crate::BoxedQueueItem> {
+            vec![]
+        }
+
+        fn next(&self) {
+            self.next.fetch_add(1);
+        }
+
+        fn previous(&self) {
+            self.previous.fetch_add(1);
+        }
+
+        fn reset(&self) {}
+
+        fn skip(&self, _id: &str) {}
+    }
+
+    #[test]
+    fn test_seek_across_boundary() {
+        let config = Config {
+            seek_behavior: SeekBehavior::CrossBoundary,
+            ..Default::default()
+        };
+        let (context, _events) = PipelineContext::with_event_receiver(&config);
+        let player = Player::new(&context, Arc::new(Output::new(&context)));
+
+        let queue = Arc::new(CountingQueue::default());
+        context
+            .queues
+            .insert(player.id, crate::BoxedQueue::new(queue.clone()));
+
+        // A track of 2 seconds
+        let sink = Arc::new(Sink::with_activation(
+            &context,
+            Some(config.seconds_to_samples(2.)),
+        ));
+        context.sinks.insert(sink.id, sink.clone());
+        player.set_sinks(vec![sink]);
+
+        assert!(!player.seek_across_boundary(1.), "within the track");
+        assert!(player.seek_across_boundary(2.5));
+        assert_eq!(
+            queue.next.load(),
+            1,
+            "seeking past the end goes to the next"
+        );
+
+        assert!(player.seek_across_boundary(-1.));
+        assert_eq!(
+            queue.previous.load(),
+            1,
+            "seeking before the start goes to the previous"
+        );
+
+        // Clamping is the default
+        let (context, _events) = PipelineContext::with_event_receiver(&Config::default());
+        let player = Player::new(&context, Arc::new(Output::new(&context)));
+        context
+            .queues
+            .insert(player.id, crate::BoxedQueue::new(queue.clone()));
+
+        assert!(!player.seek_across_boundary(-1.));
+        assert_eq!(queue.previous.load(), 1);
+    }
+}
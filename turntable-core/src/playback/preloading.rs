@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use crate::{Config, PlayerId};
+
+/// How often a player close to a void is checked for preloading
+pub const MIN_PRELOAD_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a well-buffered player can go without being checked for preloading
+pub const MAX_PRELOAD_INTERVAL: Duration = Duration::from_secs(1);
+/// How much later than needed a player can be checked, as a fraction of its interval
+const PRELOAD_JITTER: f32 = 0.25;
+
+/// Decides how long to wait before checking a player for preloading again.
+///
+/// A player with less loaded ahead of it than the preload threshold is checked every [MIN_PRELOAD_INTERVAL].
+/// Otherwise, it is checked around when playback would reach the threshold, but at most every [MAX_PRELOAD_INTERVAL].
+///
+/// Each player is checked a little later than that depending on its id, so players that were set up together
+/// don't keep requesting loads at the same time.
+pub struct PreloadCadence {
+    config: Config,
+}
+
+impl PreloadCadence {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    /// Returns how long to wait before checking the player again.
+    ///
+    /// * `buffered` - How many samples are loaded ahead of playback, if anything is playing.
+    /// * `speed` - The playback speed of the player.
+    pub fn interval(&self, player_id: PlayerId, buffered: Option<usize>, speed: f32) -> Duration {
+        let threshold = self.config.preload_threshold_in_samples();
+
+        let interval = match buffered {
+            Some(buffered) if buffered > threshold => {
+                let seconds = self.config.samples_to_seconds(buffered - threshold) / speed;
+
+                Duration::from_secs_f32(seconds).clamp(MIN_PRELOAD_INTERVAL, MAX_PRELOAD_INTERVAL)
+            }
+            // Nothing is playing yet, or more has to be loaded soon
+            _ => MIN_PRELOAD_INTERVAL,
+        };
+
+        interval + interval.mul_f32(PRELOAD_JITTER * stagger_fraction(player_id))
+    }
+}
+
+/// Spreads player ids evenly between 0 and 1, so consecutive ids are far apart.
+fn stagger_fraction(player_id: PlayerId) -> f32 {
+    const GOLDEN_RATIO_FRACTION: f64 = 0.618_033_988_75;
+
+    (player_id.value() as f64 * GOLDEN_RATIO_FRACTION).fract() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_void_is_preloaded_sooner() {
+        let config = Config::default();
+        let cadence = PreloadCadence::new(config.clone());
+        let player_id = PlayerId::new();
+
+        let threshold = config.preload_threshold_in_samples();
+        let near_void = cadence.interval(player_id, Some(config.seconds_to_samples(1.)), 1.);
+        let well_buffered = cadence.interval(player_id, Some(threshold * 2), 1.);
+
+        assert!(near_void < well_buffered);
+        assert!(near_void < MIN_PRELOAD_INTERVAL.mul_f32(1. + PRELOAD_JITTER));
+        assert!(well_buffered >= MAX_PRELOAD_INTERVAL);
+
+        // Faster playback reaches the threshold sooner
+        let slightly_ahead = threshold + config.seconds_to_samples(0.5);
+        let normal = cadence.interval(player_id, Some(slightly_ahead), 1.);
+        let fast = cadence.interval(player_id, Some(slightly_ahead), 2.);
+
+        assert!(fast < normal);
+    }
+
+    #[test]
+    fn test_players_are_staggered() {
+        let config = Config::default();
+        let cadence = PreloadCadence::new(config.clone());
+        let buffered = Some(config.preload_threshold_in_samples() * 2);
+
+        let first = cadence.interval(PlayerId::new(), buffered, 1.);
+        let second = cadence.interval(PlayerId::new(), buffered, 1.);
+
+        assert_ne!(first, second);
+        assert!(first.max(second) <= MAX_PRELOAD_INTERVAL.mul_f32(1. + PRELOAD_JITTER));
+    }
+}
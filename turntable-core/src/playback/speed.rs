@@ -0,0 +1,80 @@
+use crate::Sample;
+
+/// The slowest supported playback speed
+pub const MIN_SPEED: f32 = 0.5;
+/// The fastest supported playback speed
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Changes the speed of interleaved samples by resampling them with linear interpolation.
+///
+/// Note: This does not preserve pitch, so faster playback also sounds higher.
+/// A speed of 1.0 returns the samples unchanged.
+pub fn change_speed(samples: &[Sample], channel_count: usize, speed: f32) -> Vec<Sample> {
+    if speed == 1.0 {
+        return samples.to_vec();
+    }
+
+    let input_frames = samples.len() / channel_count;
+    let output_frames = (input_frames as f32 / speed).round() as usize;
+
+    let mut result = Vec::with_capacity(output_frames * channel_count);
+
+    for frame in 0..output_frames {
+        let position = frame as f32 * speed;
+        let index = position.trunc() as usize;
+        let fraction = position.fract();
+
+        for channel in 0..channel_count {
+            let sample_at = |i: usize| {
+                samples
+                    .get(i.min(input_frames.saturating_sub(1)) * channel_count + channel)
+                    .copied()
+                    .unwrap_or_default()
+            };
+
+            let current = sample_at(index);
+            let next = sample_at(index + 1);
+
+            result.push(current + (next - current) * fraction);
+        }
+    }
+
+    result
+}
+
+/// Returns how many samples have to be read to produce `amount` samples at the given speed.
+/// This is always a whole amount of frames.
+pub fn samples_for_speed(amount: usize, channel_count: usize, speed: f32) -> usize {
+    let frames = amount / channel_count;
+    (frames as f32 * speed).round() as usize * channel_count
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_double_speed_halves_samples() {
+        let samples: Vec<_> = (0..200).map(|s| s as f32).collect();
+        let result = change_speed(&samples, 2, 2.0);
+
+        assert_eq!(result.len(), samples.len() / 2);
+        // Every other frame is kept, and channels stay in place
+        assert_eq!(&result[..4], &[0., 1., 4., 5.]);
+    }
+
+    #[test]
+    fn test_normal_speed_is_unchanged() {
+        let samples: Vec<_> = (0..200).map(|s| s as f32).collect();
+
+        assert_eq!(change_speed(&samples, 2, 1.0), samples);
+        assert_eq!(samples_for_speed(200, 2, 1.0), 200);
+    }
+
+    #[test]
+    fn test_samples_for_speed() {
+        assert_eq!(samples_for_speed(200, 2, 2.0), 400);
+        assert_eq!(samples_for_speed(200, 2, 0.5), 100);
+        assert_eq!(samples_for_speed(200, 2, 1.25), 250);
+    }
+}
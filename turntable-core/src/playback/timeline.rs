@@ -3,7 +3,7 @@ use std::sync::Arc;
 use crossbeam::atomic::AtomicCell;
 use parking_lot::Mutex;
 
-use crate::{Config, IdType, Introspect, Sink, SinkGuard, SinkId};
+use crate::{ActivationError, BufferHealth, Config, IdType, Introspect, Sink, SinkGuard, SinkId};
 
 /// The timeline keeps track of a sequence of sinks, manages advancement of playback, and returns what sinks to preload.
 #[derive(Default)]
@@ -15,6 +15,14 @@ pub struct Timeline {
     offset: AtomicCell<usize>,
     /// The total playback offset of the timeline.
     total_offset: AtomicCell<usize>,
+    /// The id of the first sink, kept separately so it can be read without waiting for the sinks lock.
+    current_sink: AtomicCell<Option<SinkId>>,
+    /// Set when what to preload may have changed suddenly, like after seeking or changing the sinks.
+    preload_requested: AtomicCell<bool>,
+    /// How many samples of silence are played between sinks.
+    gap: AtomicCell<usize>,
+    /// How many samples of silence are left to play before the first sink.
+    gap_remaining: AtomicCell<usize>,
 }
 
 impl Timeline {
@@ -24,9 +32,18 @@ impl Timeline {
             sinks: Default::default(),
             offset: Default::default(),
             total_offset: Default::default(),
+            current_sink: Default::default(),
+            preload_requested: Default::default(),
+            gap: Default::default(),
+            gap_remaining: Default::default(),
         }
     }
 
+    /// Sets how many samples of silence are played when advancing from one sink to the next.
+    pub fn set_gap(&self, samples: usize) {
+        self.gap.store(samples);
+    }
+
     /// Sets the sinks to play and preload.
     ///
     /// Calling this function will not reset the playback offset to 0 if the first sink is not different from the current one.
@@ -39,14 +56,29 @@ impl Timeline {
 
         let mut current_sinks = self.sinks.lock();
 
-        // Reset the timeline if the first sink is different.
+        // Reset the timeline if the first sink is different, starting at its trimmed start.
         if current_sink_id != new_first_sink_id {
-            self.reset();
+            let start = sinks.first().map(|s| s.trim().start).unwrap_or_default();
+            self.offset.store(start);
+            // Skipping to another sink plays it right away
+            self.gap_remaining.store(0);
         }
 
-        // Clear the current guards.
-        current_sinks.drain(..);
-        *current_sinks = sinks.into_iter().map(|s| s.guard()).collect();
+        // Sinks that stay in the timeline keep their guard, so their loads carry on.
+        // The guards of the sinks that left are dropped, which cancels their loads.
+        let mut previous: Vec<_> = current_sinks.drain(..).collect();
+
+        *current_sinks = sinks
+            .into_iter()
+            .map(|s| match previous.iter().position(|g| g.id == s.id) {
+                Some(index) => previous.swap_remove(index),
+                None => s.guard(),
+            })
+            .collect();
+
+        drop(previous);
+        self.current_sink.store(new_first_sink_id);
+        self.preload_requested.store(true);
     }
 
     /// Advances the playback offset and returns the sinks that the player should read from.
@@ -68,7 +100,30 @@ impl Timeline {
         let mut remaining = amount;
         let mut playback_offset = self.offset.load();
 
-        for sink in playable_sinks.iter() {
+        for (index, sink) in playable_sinks.iter().enumerate() {
+            let trim = sink.trim();
+
+            // We moved on from the previous sink, so this one starts at its trimmed start, after the gap.
+            if index > 0 {
+                playback_offset = trim.start;
+                self.offset.store(playback_offset);
+                self.gap_remaining.store(self.gap.load());
+            }
+
+            // The gap is silence, so it is played even if the sink isn't activated yet.
+            let gap = self.gap_remaining.load().min(remaining);
+
+            if gap > 0 {
+                remaining -= gap;
+                self.gap_remaining.fetch_sub(gap);
+                result.push(TimelineRead {
+                    sink_id: sink.id,
+                    offset: playback_offset,
+                    amount: gap,
+                    is_gap: true,
+                });
+            }
+
             // We've satisified the amount of samples the player wants to play
             // Or the sink isn't activated, and we need to wait
             if remaining == 0 || !sink.is_activated() {
@@ -77,8 +132,12 @@ impl Timeline {
 
             let available_until_void = sink.distance_from_void(playback_offset);
             let available_until_end = sink.distance_from_end(playback_offset);
+            let available_until_trim_end = trim.distance_from_end(playback_offset);
 
-            let amount_to_read = available_until_void.distance.min(remaining);
+            let amount_to_read = available_until_void
+                .distance
+                .min(remaining)
+                .min(available_until_trim_end.unwrap_or(usize::MAX));
             let new_offset = playback_offset + amount_to_read;
 
             // There are samples to read from this sink.
@@ -88,22 +147,22 @@ impl Timeline {
                     sink_id: sink.id,
                     offset: playback_offset,
                     amount: amount_to_read,
+                    is_gap: false,
                 });
 
                 self.total_offset.fetch_add(amount_to_read);
                 self.offset.store(new_offset);
             }
 
-            // Reset offset for the next sink.
-            playback_offset = 0;
-
             // Let's break down the conditions for moving on to the next sink.
             // 1. The sink is sealed/not loadable, meaning there won't be any more samples to load, and
             // 2. There are no more remaining samples to read, or
-            // 3. We're at the end
+            // 3. We're at the end, or
+            // 4. We're at the end of the trim
             let should_move_on = (!sink.can_load_more()
                 && available_until_void.distance.saturating_sub(amount_to_read) == 0)
-                || available_until_end.saturating_sub(amount_to_read) == 0;
+                || available_until_end.saturating_sub(amount_to_read) == 0
+                || available_until_trim_end.is_some_and(|d| d.saturating_sub(amount_to_read) == 0);
 
             // Stop here if we're not moving on to the next sink.
             if !should_move_on {
@@ -117,6 +176,9 @@ impl Timeline {
 
         // Remove the sinks we just played from the list.
         playable_sinks.retain(|s| !sinks_to_remove.contains(&s.id));
+        self.current_sink
+            .store(playable_sinks.first().map(|s| s.id));
+
         result
     }
 
@@ -132,7 +194,14 @@ impl Timeline {
         let mut playback_offset = self.offset.load();
         let mut result = vec![];
 
-        for sink in sinks.iter() {
+        for (index, sink) in sinks.iter().enumerate() {
+            let trim = sink.trim();
+
+            // Upcoming sinks start playing at their trimmed start.
+            if index > 0 {
+                playback_offset = trim.start;
+            }
+
             // Wait for sink activation
             if !sink.is_activated() {
                 break;
@@ -141,11 +210,15 @@ impl Timeline {
             let available_until_void = sink.distance_from_void(playback_offset);
             let available_until_end = sink.distance_from_end(playback_offset);
             let in_full_end_range = sink.in_full_end_range(playback_offset);
+            let loaded_until_trim_end = trim
+                .distance_from_end(playback_offset)
+                .is_some_and(|d| available_until_void.distance >= d);
 
             // No need to preload if we're under the threshold, or if we satisfied the remaining to load, or if the remaining samples loaded are at the end.
             if available_until_void.distance >= threshold
                 || remaining_to_load == 0
                 || in_full_end_range
+                || loaded_until_trim_end
             {
                 break;
             }
@@ -162,28 +235,40 @@ impl Timeline {
 
                 remaining_to_load -= how_much_can_preload;
             }
-
-            // Set the preload offset to the start for the next sink.
-            playback_offset = 0;
         }
 
         result
     }
 
+    /// Returns how many samples are loaded ahead of the playback offset of the current sink.
+    /// [None] is returned if there is no sink, or it isn't activated yet.
+    pub fn buffered_ahead(&self) -> Option<usize> {
+        let sinks = self.sinks.lock();
+        let sink = sinks.first().filter(|s| s.is_activated())?;
+
+        Some(sink.distance_from_void(self.offset.load()).distance)
+    }
+
+    /// Returns true if the timeline should be preloaded right away, resetting the request.
+    pub fn take_preload_request(&self) -> bool {
+        self.preload_requested.swap(false)
+    }
+
     /// Clears samples that are not needed, to save memory.
-    pub fn clear_superflous(&self) {
+    /// Returns the sink and how many samples were freed, if any were.
+    pub fn clear_superflous(&self) -> Option<(SinkId, usize)> {
         let sinks = self.sinks.lock();
         let offset = self.offset.load();
 
-        if let Some(first_sink) = sinks.first() {
-            if first_sink.is_activated() {
-                first_sink.clear_outside(
-                    offset,
-                    self.config.sink_preload_window_size(),
-                    self.config.channel_count,
-                );
-            }
-        }
+        let first_sink = sinks.first().filter(|s| s.is_activated())?;
+        let freed = first_sink.clear_outside(
+            offset,
+            self.config.sink_retain_behind_size(),
+            self.config.sink_retain_ahead_size(),
+            self.config.channel_count,
+        );
+
+        (freed > 0).then_some((first_sink.id, freed))
     }
 
     /// Resets the current sink to the beginning.
@@ -192,18 +277,56 @@ impl Timeline {
     }
 
     /// Seeks to a specific offset in the timeline.
-    pub fn seek(&self, offset: usize) {
-        let expected_length = self
-            .sinks
-            .lock()
-            .first()
-            .filter(|s| s.is_activated())
-            .and_then(|s| s.expected_length());
+    /// Returns what to load right away, if nothing is loaded at the new offset.
+    pub fn seek(&self, offset: usize) -> Option<TimelinePreload> {
+        let sinks = self.sinks.lock();
+        let first = sinks.first();
+
+        let trim = first.map(|s| s.trim()).unwrap_or_default();
 
-        // Prevents seeking beyond the sink length if it is known
-        let safe_offset = expected_length.map(|l| offset.min(l)).unwrap_or(offset);
+        // Prevents seeking beyond the sink length if it is known, and outside the trim
+        let safe_offset = first
+            .and_then(Self::end_of)
+            .map(|e| offset.min(e))
+            .unwrap_or(offset);
+        let safe_offset = safe_offset.max(trim.start);
 
         self.offset.store(safe_offset);
+        self.gap_remaining.store(0);
+        self.preload_requested.store(true);
+
+        // If nothing is loaded at the new offset, a load in progress is for the old position.
+        // It is stopped, so the sink can be loaded at the new offset sooner.
+        let sink = first.filter(|s| s.is_activated() && s.can_load_more())?;
+
+        if sink.distance_from_void(safe_offset).distance > 0 {
+            return None;
+        }
+
+        sink.cancel_load();
+
+        Some(TimelinePreload {
+            sink_id: sink.id,
+            offset: safe_offset,
+        })
+    }
+
+    /// Returns the offset the current sink stops playing at, if known.
+    pub fn current_end(&self) -> Option<usize> {
+        self.sinks.lock().first().and_then(Self::end_of)
+    }
+
+    /// Returns where a sink stops playing, which is the end of its trim or its length if known
+    fn end_of(sink: &SinkGuard) -> Option<usize> {
+        let expected_length = sink
+            .is_activated()
+            .then(|| sink.expected_length())
+            .flatten();
+
+        match (expected_length, sink.trim().end) {
+            (Some(length), Some(end)) => Some(length.min(end)),
+            (length, end) => length.or(end),
+        }
     }
 
     /// Returns the offset of the current sink.
@@ -218,13 +341,47 @@ impl Timeline {
 
     /// Returns the id of the currently playing sink.
     pub fn current_sink(&self) -> Option<SinkId> {
-        self.sinks.lock().first().map(|s| s.id)
+        self.current_sink.load()
+    }
+
+    /// Returns the ids of the sinks in the timeline, in the order they play.
+    pub fn sink_ids(&self) -> Vec<SinkId> {
+        self.sinks.lock().iter().map(|s| s.id).collect()
     }
 
     /// Returns true if the timeline is empty.
     pub fn is_empty(&self) -> bool {
         self.sinks.lock().is_empty()
     }
+
+    /// Returns how much of each sink is loaded from where it plays.
+    /// Instead of waiting while the timeline is advanced, [None] is returned.
+    pub fn buffer_health(&self) -> Option<Vec<SinkBufferHealth>> {
+        let sinks = self.sinks.try_lock()?;
+        let offset = self.offset.load();
+
+        let health = sinks
+            .iter()
+            .enumerate()
+            .map(|(index, sink)| {
+                // Upcoming sinks start playing at their trimmed start.
+                let offset = if index == 0 {
+                    offset
+                } else {
+                    sink.trim().start
+                };
+
+                SinkBufferHealth {
+                    sink_id: sink.id.value(),
+                    offset,
+                    buffer: sink.try_introspect_buffer().map(|b| b.health(offset)),
+                    error: sink.try_activation_error(),
+                }
+            })
+            .collect();
+
+        Some(health)
+    }
 }
 
 /// Instructs a [Player] what sink to read from, and where to start reading from.
@@ -234,6 +391,8 @@ pub struct TimelineRead {
     pub offset: usize,
     /// How many samples to read from the offset.
     pub amount: usize,
+    /// True if the samples are silence played before the sink, instead of being read from it.
+    pub is_gap: bool,
 }
 
 /// Instructs [Playback] what sinks to preload.
@@ -244,6 +403,18 @@ pub struct TimelinePreload {
     pub offset: usize,
 }
 
+/// Describes what is loaded of a sink in the timeline, see [Timeline::buffer_health].
+#[derive(Debug)]
+pub struct SinkBufferHealth {
+    pub sink_id: IdType,
+    /// The offset playback is at, or starts from if the sink is upcoming
+    pub offset: usize,
+    /// [None] if the sink is not activated, or its buffer was being written to
+    pub buffer: Option<BufferHealth>,
+    /// Why the sink failed to activate, if it did
+    pub error: Option<ActivationError>,
+}
+
 #[derive(Debug)]
 pub struct TimelineIntrospection {
     pub sinks: Vec<IdType>,
@@ -264,7 +435,7 @@ impl Introspect<TimelineIntrospection> for Timeline {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::PipelineContext;
+    use crate::{PipelineContext, RangeInstrospection, SinkTrim};
 
     #[test]
     fn test_advancement() {
@@ -383,4 +554,229 @@ mod tests {
         let preload = timeline.preload();
         assert_eq!(preload.len(), 2, "returns two preloads");
     }
+
+    #[test]
+    fn test_trimmed_advancement() {
+        let config = Config {
+            // Makes one second equal to one sample.
+            sample_rate: 1,
+            channel_count: 1,
+            ..Default::default()
+        };
+
+        let context = PipelineContext::with_config(&config);
+        let timeline = Timeline::new(config.clone());
+
+        let first = Arc::new(Sink::with_activation(&context, Some(10)));
+        let second = Arc::new(Sink::with_activation(&context, Some(10)));
+
+        // Play 2..6 of the first, and 3.. of the second with an end beyond its length.
+        first.set_trim(SinkTrim::from_seconds(&config, Some(2.), Some(6.)));
+        second.set_trim(SinkTrim::from_seconds(&config, Some(3.), Some(20.)));
+
+        context.sinks.insert(first.id, first.clone());
+        context.sinks.insert(second.id, second.clone());
+
+        let samples: Vec<_> = (0..10).map(|s| s as f32).collect();
+        first.write().write(0, &samples);
+        second.write().write(0, &samples);
+
+        timeline.set_sinks(vec![first.clone(), second.clone()]);
+        assert_eq!(timeline.current_offset(), 2, "starts at the trimmed start");
+
+        let reads = timeline.advance(3);
+        assert_eq!(reads.len(), 1, "only the first sink is read");
+        assert_eq!(reads[0].offset, 2);
+        assert_eq!(reads[0].amount, 3);
+
+        // Only one sample remains before the trimmed end.
+        let reads = timeline.advance(3);
+        assert_eq!(reads.len(), 2, "moves on at the trimmed end");
+        assert_eq!(reads[0].offset, 5);
+        assert_eq!(reads[0].amount, 1, "stops at the trimmed end");
+        assert_eq!(reads[1].sink_id, second.id);
+        assert_eq!(reads[1].offset, 3, "next sink starts at its trimmed start");
+        assert_eq!(reads[1].amount, 2);
+
+        // The end exceeds the length, so the second sink ends naturally.
+        let reads = timeline.advance(10);
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads[0].offset, 5);
+        assert_eq!(reads[0].amount, 5, "plays until the natural end");
+        assert!(timeline.is_empty(), "all sinks were consumed");
+    }
+
+    #[test]
+    fn test_trimmed_seek() {
+        let config = Config {
+            sample_rate: 1,
+            channel_count: 1,
+            ..Default::default()
+        };
+
+        let context = PipelineContext::with_config(&config);
+        let timeline = Timeline::new(config.clone());
+
+        let sink = Arc::new(Sink::with_activation(&context, Some(10)));
+        sink.set_trim(SinkTrim::from_seconds(&config, Some(2.), Some(6.)));
+
+        context.sinks.insert(sink.id, sink.clone());
+        timeline.set_sinks(vec![sink.clone()]);
+
+        timeline.seek(0);
+        assert_eq!(timeline.current_offset(), 2, "cannot seek before the start");
+
+        timeline.seek(8);
+        assert_eq!(timeline.current_offset(), 6, "cannot seek past the end");
+    }
+
+    #[test]
+    fn test_seek_preload() {
+        let config = Config {
+            sample_rate: 1,
+            channel_count: 1,
+            ..Default::default()
+        };
+
+        let context = PipelineContext::with_config(&config);
+        let timeline = Timeline::new(config.clone());
+
+        let sink = Arc::new(Sink::with_activation(&context, Some(10)));
+        context.sinks.insert(sink.id, sink.clone());
+        timeline.set_sinks(vec![sink.clone()]);
+
+        sink.write().write(0, &[1., 2., 3.]);
+
+        assert!(timeline.seek(1).is_none(), "the new offset is loaded");
+
+        let guard = sink.try_write().expect("sink is idle");
+        assert!(sink.try_write().is_none(), "only one load can write");
+
+        let preload = timeline.seek(6).expect("the new offset is not loaded");
+        assert_eq!(preload.sink_id, sink.id);
+        assert_eq!(preload.offset, 6);
+        assert!(guard.is_cancelled(), "the load for the old offset stops");
+
+        drop(guard);
+        assert!(sink.try_write().is_some(), "sink can be loaded again");
+    }
+
+    #[test]
+    fn test_set_sinks_keeps_loads_of_remaining_sinks() {
+        let context = PipelineContext::default();
+        let timeline = Timeline::new(Config::default());
+
+        let playing = Arc::new(Sink::with_activation(&context, None));
+        let upcoming = Arc::new(Sink::with_activation(&context, None));
+        context.sinks.insert(playing.id, playing.clone());
+        context.sinks.insert(upcoming.id, upcoming.clone());
+
+        timeline.set_sinks(vec![playing.clone()]);
+        let load = playing.try_write().expect("sink is idle");
+
+        // Like a track being added to the queue while the current one loads
+        timeline.set_sinks(vec![playing.clone(), upcoming.clone()]);
+        assert!(!load.is_cancelled(), "the playing sink keeps loading");
+        assert_eq!(timeline.current_sink(), Some(playing.id));
+
+        timeline.set_sinks(vec![upcoming.clone()]);
+        assert!(load.is_cancelled(), "the sink left the timeline");
+    }
+
+    #[test]
+    fn test_clear_superflous_retains_behind() {
+        let config = Config {
+            // Makes one second equal to one frame of two samples.
+            sample_rate: 1,
+            channel_count: 2,
+            sink_retain_behind_in_seconds: 3.,
+            sink_retain_ahead_in_seconds: 2.,
+            ..Default::default()
+        };
+
+        let context = PipelineContext::with_config(&config);
+        let timeline = Timeline::new(config.clone());
+
+        let sink = Arc::new(Sink::with_activation(&context, Some(40)));
+        context.sinks.insert(sink.id, sink.clone());
+        timeline.set_sinks(vec![sink.clone()]);
+
+        let samples: Vec<_> = (0..40).map(|s| s as f32).collect();
+        sink.write().write(0, &samples);
+
+        timeline.seek(20);
+        assert_eq!(timeline.clear_superflous(), Some((sink.id, 28)));
+
+        let ranges = sink.try_introspect_buffer().unwrap().ranges;
+        assert_eq!(
+            ranges,
+            vec![RangeInstrospection {
+                offset: 14,
+                length: 12
+            }],
+            "keeps more behind than ahead"
+        );
+
+        assert!(
+            timeline.seek(16).is_none(),
+            "seeking back within the window does not load again"
+        );
+        assert_eq!(timeline.clear_superflous(), Some((sink.id, 4)));
+
+        let ranges = sink.try_introspect_buffer().unwrap().ranges;
+        assert_eq!(
+            ranges,
+            vec![RangeInstrospection {
+                offset: 14,
+                length: 8
+            }],
+            "samples behind the offset survive"
+        );
+    }
+
+    #[test]
+    fn test_buffer_health() {
+        let context = PipelineContext::default();
+        let timeline = Timeline::new(context.config.clone());
+
+        let first = Arc::new(Sink::with_activation(&context, Some(10)));
+        let second = Arc::new(Sink::prepare(&context));
+
+        context.sinks.insert(first.id, first.clone());
+        context.sinks.insert(second.id, second.clone());
+        timeline.set_sinks(vec![first.clone(), second.clone()]);
+
+        first.write().write(0, &[1., 2., 3., 4.]);
+        first.write().write(6, &[7., 8.]);
+        timeline.advance(2);
+
+        let health = timeline.buffer_health().expect("timeline is idle");
+        assert_eq!(health.len(), 2);
+
+        let buffer = health[0].buffer.as_ref().expect("first sink is activated");
+        assert_eq!(health[0].offset, 2);
+        assert_eq!(buffer.loaded_ahead, 2, "loaded until the first gap");
+        assert_eq!(
+            buffer.gaps,
+            vec![
+                RangeInstrospection {
+                    offset: 4,
+                    length: 2
+                },
+                RangeInstrospection {
+                    offset: 8,
+                    length: 2
+                }
+            ],
+            "gaps include the missing end"
+        );
+
+        assert!(health[1].buffer.is_none(), "second sink is not activated");
+
+        let _sinks = timeline.sinks.lock();
+        assert!(
+            timeline.buffer_health().is_none(),
+            "does not wait while the timeline is busy"
+        );
+    }
 }
@@ -3,13 +3,13 @@ mod queue_item;
 
 use std::{sync::Arc, thread};
 
-use crossbeam::channel::{unbounded, Receiver, Sender};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 pub use queue::*;
 pub use queue_item::*;
 
 use crate::{
-    util::get_or_create_handle, Ingestion, PipelineAction, PipelineContext, PlayerId, Sink,
-    SinkManager,
+    util::get_or_create_handle, Ingestion, IngestionError, PipelineAction, PipelineContext,
+    PipelineEvent, PlayerId, Sink, SinkManager, SinkTrim, SHUTDOWN_POLL_INTERVAL,
 };
 
 /// A type passed to a queue to allow it to notify the Pipeline that it changed.
@@ -72,7 +72,8 @@ impl Queuing {
 
     /// Notifies the queue system that a queue has been updated.
     pub fn notify_queue_update(&self, player_id: PlayerId) {
-        self.sender.send(player_id).unwrap();
+        // The update thread is only gone if the pipeline shut down
+        let _ = self.sender.send(player_id);
     }
 }
 
@@ -85,18 +86,33 @@ fn spawn_update_task_thread<I>(
 {
     let handle = get_or_create_handle();
     let context = context.clone();
+    let shutdown = context.shutdown.clone();
+    let heartbeat = context.heartbeats.register("queue-update-task");
 
     let run = move || loop {
-        let player_id = receiver.recv().unwrap();
+        heartbeat.beat();
+
+        if context.shutdown.is_requested() {
+            break;
+        }
+
+        let player_id = match receiver.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(player_id) => player_id,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
         let fut = update_sinks(&context, manager.clone(), player_id);
 
         handle.block_on(fut)
     };
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("queue-update-task".to_string())
         .spawn(run)
         .expect("queue-update-task thread is spawned");
+
+    shutdown.register(thread);
 }
 
 /// Called when a queue is updated.
@@ -110,17 +126,21 @@ async fn update_sinks<I>(
     let queue = context.queues.get(&player_id).expect("queue exists");
     let player = context.players.get(&player_id).expect("player exists");
     let items = queue.peek();
+    let prewarmed = queue.prewarmed();
 
-    // If there's nothing in the queue, we don't need to do anything.
+    // If there's nothing in the queue, we don't need to do anything,
+    // other than stopping an item that was taken out of the queue while it was playing.
     if items.is_empty() {
+        player.set_sinks(vec![]);
         return;
     }
 
-    let sinks_to_play = ensure_sinks_for_items(context, &items, &manager);
+    let sinks_to_play =
+        ensure_sinks_for_items(context, &items, &manager, player.trims_leading_silence());
     player.set_sinks(sinks_to_play);
 
     let context = context.clone();
-    activate_necessary_items(context, items, manager).await;
+    activate_necessary_items(context, player_id, items, prewarmed, manager).await;
 }
 
 /// Ensures that all the items have an associated sink before activation
@@ -128,6 +148,7 @@ fn ensure_sinks_for_items<I>(
     context: &PipelineContext,
     items: &[BoxedQueueItem],
     manager: &Arc<SinkManager<I>>,
+    trim_leading_silence: bool,
 ) -> Vec<Arc<Sink>>
 where
     I: Ingestion + 'static,
@@ -135,22 +156,74 @@ where
     items
         .iter()
         .map(|i| {
-            i.sink_id()
+            let sink = i
+                .sink_id()
                 .and_then(|id| context.sinks.get(&id).map(|s| s.clone()))
                 .unwrap_or_else(|| {
-                    let sink = manager.prepare();
-                    i.register_sink(sink.id);
+                    prepare_sink_for_item(context, i, manager, trim_leading_silence)
+                });
 
-                    sink
-                })
+            // Unlike the trim, the gain can change while the item is playing
+            sink.set_gain(i.gain().unwrap_or_default());
+            sink
         })
         .collect()
 }
 
-/// Activates items as necessary
+/// Prepares a new sink for an item and registers it, replacing the one it had, if any
+fn prepare_sink_for_item<I>(
+    context: &PipelineContext,
+    item: &BoxedQueueItem,
+    manager: &Arc<SinkManager<I>>,
+    trim_leading_silence: bool,
+) -> Arc<Sink>
+where
+    I: Ingestion + 'static,
+{
+    let sink = manager.prepare();
+    item.register_sink(sink.id);
+
+    let trim = SinkTrim::from_seconds(&context.config, item.start(), item.end());
+    sink.set_trim(trim);
+    sink.set_trim_leading_silence(trim_leading_silence);
+
+    sink
+}
+
+/// Gives the items of a player's queue a new sink if theirs failed or may be stuck, so they're activated again.
+/// The old sinks are cleared once nothing holds on to them anymore.
+pub(crate) fn replace_failed_sinks<I>(
+    context: &PipelineContext,
+    manager: &Arc<SinkManager<I>>,
+    player_id: PlayerId,
+) where
+    I: Ingestion + 'static,
+{
+    let Some(queue) = context.queues.get(&player_id) else {
+        return;
+    };
+
+    let player = context.players.get(&player_id).expect("player exists");
+
+    for item in queue.peek() {
+        let failed = item
+            .sink_id()
+            .and_then(|id| context.sinks.get(&id))
+            .is_some_and(|sink| sink.is_failed_or_busy());
+
+        if failed {
+            prepare_sink_for_item(context, &item, manager, player.trims_leading_silence());
+        }
+    }
+}
+
+/// Activates items as necessary.
+/// Prewarmed items are activated and loaded right after the current one, even if they're further ahead.
 async fn activate_necessary_items<I>(
     context: PipelineContext,
+    player_id: PlayerId,
     items: Vec<BoxedQueueItem>,
+    prewarmed: Vec<String>,
     manager: Arc<SinkManager<I>>,
 ) where
     I: Ingestion + 'static,
@@ -180,6 +253,17 @@ async fn activate_necessary_items<I>(
         })
         .collect();
 
+    let prewarmed: Vec<_> = prewarmed.into_iter().take(MAX_PREWARMED_ITEMS).collect();
+    let (mut prewarmed_pairs, pairs): (Vec<_>, Vec<_>) = pairs
+        .into_iter()
+        .partition(|(index, _, item)| *index > 0 && prewarmed.contains(&item.item_id()));
+
+    // The first hint is the most likely to be played
+    prewarmed_pairs
+        .sort_by_key(|(_, _, item)| prewarmed.iter().position(|id| *id == item.item_id()));
+
+    let mut to_activate = vec![];
+
     for (checked_sinks, sink, item) in pairs {
         // Always activate at least two ahead.
         if remaining_length <= 0. && checked_sinks >= 3 {
@@ -187,9 +271,167 @@ async fn activate_necessary_items<I>(
         }
 
         remaining_length -= item.length().unwrap_or_default();
+        to_activate.push((sink, item, false));
+    }
 
-        if sink.is_activatable() {
+    // The current item is always first, since it is the one playing
+    let after_current = to_activate.len().min(1);
+    to_activate.splice(
+        after_current..after_current,
+        prewarmed_pairs
+            .into_iter()
+            .map(|(_, sink, item)| (sink, item, true)),
+    );
+
+    for (index, (sink, item, is_prewarmed)) in to_activate.into_iter().enumerate() {
+        if !sink.is_activatable() {
+            continue;
+        }
+
+        // Only the items after the current one can be refused by the sink limits
+        let result = if index == 0 {
             manager.activate(sink.id, item.loadable()).await
+        } else {
+            manager
+                .activate_speculatively(sink.id, item.loadable())
+                .await
+        };
+
+        match result {
+            Ok(_) => {
+                context.emit(PipelineEvent::QueueItemActivated {
+                    player_id,
+                    new_sink_id: sink.id,
+                    item_id: item.item_id(),
+                });
+
+                // The preloader only loads what is about to play, so the start is loaded here instead
+                if is_prewarmed {
+                    let amount = context.config.seek_preload_size_in_samples();
+                    manager
+                        .request_load(sink.id, sink.trim().start, amount)
+                        .await;
+                }
+            }
+            // Another update got to it first
+            Err(IngestionError::AlreadyActivating) => {}
+            // It is activated once it is closer to playing, and there is room for it
+            Err(IngestionError::LimitReached) => {}
+            Err(error) => context.emit(PipelineEvent::QueueItemActivationError {
+                player_id,
+                item_id: item.item_id(),
+                error,
+            }),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        BoxedQueueItem, Config, FakeIngestion, FakeInput, FakeItem, FakePattern, Ingestion,
+        PipelineContext, PipelineEvent, PlayerId, SinkManager,
+    };
+
+    use super::{activate_necessary_items, ensure_sinks_for_items};
+
+    /// Items of unknown length, so only the minimum amount of items ahead of them are activated
+    fn items(ids: &[&str]) -> Vec<FakeItem> {
+        ids.iter()
+            .map(|id| FakeItem::new(FakeInput::new(FakePattern::Constant(1.), 44100)).with_id(id))
+            .collect()
+    }
+
+    fn boxed(items: &[FakeItem]) -> Vec<BoxedQueueItem> {
+        items.iter().cloned().map(BoxedQueueItem::new).collect()
+    }
+
+    #[tokio::test]
+    async fn test_prewarmed_items_are_activated_first() {
+        let (context, events) = PipelineContext::with_event_receiver(&Config::default());
+        let manager = Arc::new(SinkManager::new(&context, FakeIngestion::new(&context)));
+
+        let items = boxed(&items(&["a", "b", "c", "d", "e", "f"]));
+        let sinks = ensure_sinks_for_items(&context, &items, &manager, false);
+
+        // Only the first two hints are used
+        let prewarmed = vec!["f".to_string(), "e".to_string(), "d".to_string()];
+        activate_necessary_items(context, PlayerId::new(), items, prewarmed, manager).await;
+
+        let activated: Vec<_> = events
+            .try_iter()
+            .filter_map(|e| match e {
+                PipelineEvent::QueueItemActivated { item_id, .. } => Some(item_id),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(activated, ["a", "f", "e", "b", "c"]);
+        assert!(!sinks[3].is_activated(), "hints past the limit are ignored");
+
+        let is_loaded = |index: usize| sinks[index].read(0, &mut [0.; 2]).amount > 0;
+
+        assert!(is_loaded(5) && is_loaded(4));
+        assert!(!is_loaded(1), "other items are loaded later");
+    }
+
+    #[tokio::test]
+    async fn test_sink_limits_spare_the_current_item() {
+        let (context, events) = PipelineContext::with_event_receiver(&Config {
+            max_activated_sinks: Some(2),
+            ..Default::default()
+        });
+        let manager = Arc::new(SinkManager::new(&context, FakeIngestion::new(&context)));
+
+        // Sinks of items that were just played fill up the limit
+        let played: Vec<_> = (0..2).map(|_| manager.prepare()).collect();
+        for (sink, item) in played.iter().zip(items(&["x", "y"])) {
+            manager.activate(sink.id, item.input).await.unwrap();
+        }
+
+        let items = items(&["a", "b", "c"]);
+        let sinks = ensure_sinks_for_items(&context, &boxed(&items), &manager, false);
+
+        // The items are peeked from the queue again on every update
+        let activate = || {
+            activate_necessary_items(
+                context.clone(),
+                PlayerId::new(),
+                boxed(&items),
+                vec![],
+                manager.clone(),
+            )
+        };
+        let activated = || -> Vec<_> {
+            events
+                .try_iter()
+                .filter_map(|e| match e {
+                    PipelineEvent::QueueItemActivated { item_id, .. } => Some(item_id),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        activate().await;
+
+        assert_eq!(
+            activated(),
+            ["a"],
+            "only the current item goes over the limit"
+        );
+        assert!(sinks[1].is_activatable(), "refused items are left inactive");
+
+        // Once the played sinks can be cleared, they make room for the next item
+        for sink in &played {
+            sink.expire();
+        }
+
+        activate().await;
+
+        assert_eq!(activated(), ["b"]);
+        assert!(played.iter().all(|s| !context.sinks.contains_key(&s.id)));
+        assert!(sinks[2].is_activatable());
+    }
+}
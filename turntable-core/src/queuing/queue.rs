@@ -1,6 +1,9 @@
 use crate::BoxedQueueItem;
 use std::sync::Arc;
 
+/// How many upcoming items of a queue can be activated early at most, so the entire queue can't be.
+pub const MAX_PREWARMED_ITEMS: usize = 2;
+
 /// Represents a type that acts as a consumable queue.
 pub trait Queue
 where
@@ -22,6 +25,12 @@ where
     ///
     /// Implementors are expected to remove the item from the queue without notifying.
     fn skip(&self, id: &str);
+
+    /// Returns the ids of upcoming items to activate and start loading before the others,
+    /// like one that is likely to be skipped to. Only the first [MAX_PREWARMED_ITEMS] are used.
+    fn prewarmed(&self) -> Vec<String> {
+        vec![]
+    }
 }
 
 /// [Queue] trait object.
@@ -56,6 +65,10 @@ impl Queue for BoxedQueue {
     fn skip(&self, id: &str) {
         self.0.skip(id)
     }
+
+    fn prewarmed(&self) -> Vec<String> {
+        self.0.prewarmed()
+    }
 }
 
 impl<T> Queue for Arc<T>
@@ -81,4 +94,8 @@ where
     fn skip(&self, id: &str) {
         self.as_ref().skip(id)
     }
+
+    fn prewarmed(&self) -> Vec<String> {
+        self.as_ref().prewarmed()
+    }
 }
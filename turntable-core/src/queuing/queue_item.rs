@@ -23,6 +23,21 @@ where
 
     /// Returns the item's loadable.
     fn loadable(&self) -> BoxedLoadable;
+
+    /// Returns the time in seconds playback of the item should start at, if trimmed.
+    fn start(&self) -> Option<f32> {
+        None
+    }
+
+    /// Returns the time in seconds playback of the item should end at, if trimmed.
+    fn end(&self) -> Option<f32> {
+        None
+    }
+
+    /// Returns the gain in dB applied to the item when played, if adjusted.
+    fn gain(&self) -> Option<f32> {
+        None
+    }
 }
 
 /// [QueueItem] trait object.
@@ -58,4 +73,16 @@ impl QueueItem for BoxedQueueItem {
     fn loadable(&self) -> BoxedLoadable {
         self.0.loadable()
     }
+
+    fn start(&self) -> Option<f32> {
+        self.0.start()
+    }
+
+    fn end(&self) -> Option<f32> {
+        self.0.end()
+    }
+
+    fn gain(&self) -> Option<f32> {
+        self.0.gain()
+    }
 }
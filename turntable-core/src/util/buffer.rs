@@ -1,7 +1,4 @@
-use std::{
-    collections::VecDeque,
-    ops::{Add, Mul},
-};
+use std::collections::VecDeque;
 
 use crate::{Config, Sample, VecDequeExt};
 
@@ -29,6 +26,7 @@ impl RangeBuffer {
     }
 
     /// Reads samples to the provided slice at the given absolute offset.
+    /// Nothing is read at or past the end, which can happen at track boundaries when advancing quickly.
     fn read(&self, offset: usize, buf: &mut [Sample]) -> usize {
         let available_amount_at_offset = self
             .data
@@ -40,10 +38,9 @@ impl RangeBuffer {
         let absolute_start = self.offset;
         let absolute_end = absolute_start + self.data.len();
 
-        assert!(
-            offset <= absolute_end,
-            "attempt to read from RangeBuffer at offset beyond end"
-        );
+        if offset >= absolute_end {
+            return 0;
+        }
 
         let relative_start = offset.saturating_sub(absolute_start);
         let relative_end =
@@ -59,31 +56,36 @@ impl RangeBuffer {
     }
 
     /// Clears all samples outside the given window.
-    /// End and start are clamped to chunk_size's start and end.
+    /// Start and end are widened to whole chunks, so the chunks containing them are always kept.
+    /// Partial chunks at the edges of the buffer are cleared, unless nothing else would be kept.
     fn retain_range(&mut self, start: usize, end: usize, chunk_size: usize) {
-        let total_length = self.data.len();
+        let (absolute_start, absolute_end) = self.range();
 
-        let absolute_start = self.offset;
-        let absolute_end = (absolute_start + total_length).saturating_sub(1);
+        let window_start = start / chunk_size * chunk_size;
+        let window_end = (end / chunk_size * chunk_size).saturating_add(chunk_size - 1);
 
-        let absolute_chunk_start = (absolute_start + 1).saturating_div(chunk_size);
-        let absolute_chunk_end =
-            (absolute_end.add(1).saturating_sub(chunk_size)).saturating_div(chunk_size);
+        let kept_start = window_start.max(absolute_start);
+        let kept_end = window_end.min(absolute_end);
 
-        let safe_start = start.saturating_div(chunk_size).max(absolute_chunk_start) * chunk_size;
-        let safe_end = end
-            .saturating_div(chunk_size)
-            .min(absolute_chunk_end)
-            .mul(chunk_size)
-            .saturating_add(chunk_size.saturating_sub(1));
+        if self.data.is_empty() || kept_start > kept_end {
+            self.data.clear();
+            return;
+        }
 
-        let relative_start = safe_start.saturating_sub(absolute_start);
-        let relative_end = safe_end.saturating_sub(absolute_start);
+        // Trimming the partial chunks keeps the channels of the remaining samples aligned
+        let whole_start = kept_start.div_ceil(chunk_size) * chunk_size;
+        let whole_end = (kept_end + 1) / chunk_size * chunk_size;
 
-        drop(self.data.drain(..relative_start));
-        self.data.truncate(relative_end - relative_start + 1);
+        let (kept_start, kept_end) = if whole_start < whole_end {
+            (whole_start, whole_end - 1)
+        } else {
+            (kept_start, kept_end)
+        };
 
-        self.offset = relative_start + absolute_start;
+        drop(self.data.drain(..kept_start - absolute_start));
+        self.data.truncate(kept_end - kept_start + 1);
+
+        self.offset = kept_start;
     }
 
     /// Returns the amount of samples in the buffer so far.
@@ -339,6 +341,25 @@ impl MultiRangeBuffer {
         actual_length as i32 - expected_length as i32
     }
 
+    /// Returns all samples in the buffer, if the end is known and there are no gaps
+    pub fn complete_samples(&self) -> Option<Vec<Sample>> {
+        let length = self.actual_length?;
+        let [range] = self.ranges.as_slice() else {
+            return None;
+        };
+
+        if range.offset != 0 || range.length() != length {
+            return None;
+        }
+
+        Some(range.data.iter().copied().collect())
+    }
+
+    /// Returns how many samples are stored in the buffer
+    pub fn size(&self) -> usize {
+        self.ranges.iter().map(|r| r.length()).sum()
+    }
+
     /// Returns the ranges of data in this buffer
     pub fn ranges(&self) -> Vec<BufferRange> {
         self.ranges
@@ -357,7 +378,7 @@ impl MultiRangeBuffer {
             return vec![];
         }
 
-        ranges.sort_by(|a, b| a.offset.cmp(&b.offset));
+        ranges.sort_by_key(|a| a.offset);
 
         let mut merged_ranges = vec![];
         let mut current_range = ranges.remove(0);
@@ -384,7 +405,7 @@ impl MultiRangeBuffer {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct RangeInstrospection {
     pub offset: usize,
     pub length: usize,
@@ -423,6 +444,61 @@ impl Introspect<MultiRangeBufferIntrospection> for MultiRangeBuffer {
     }
 }
 
+/// Describes what is loaded in a buffer from an offset onwards.
+#[derive(Debug, PartialEq)]
+pub struct BufferHealth {
+    /// How many samples are loaded without interruption from the offset
+    pub loaded_ahead: usize,
+    /// The parts after the offset that are not loaded, up to the expected length if known
+    pub gaps: Vec<RangeInstrospection>,
+    pub expected_length: Option<usize>,
+}
+
+impl MultiRangeBufferIntrospection {
+    /// Returns how much is loaded from the offset, and where samples are missing.
+    pub fn health(&self, offset: usize) -> BufferHealth {
+        let mut ranges: Vec<_> = self
+            .ranges
+            .iter()
+            .filter(|r| r.offset + r.length > offset)
+            .collect();
+
+        ranges.sort_by_key(|r| r.offset);
+
+        let mut gaps = vec![];
+        let mut cursor = offset;
+        let mut loaded_until = offset;
+
+        for range in ranges {
+            if range.offset > cursor {
+                gaps.push(RangeInstrospection {
+                    offset: cursor,
+                    length: range.offset - cursor,
+                });
+            }
+
+            cursor = cursor.max(range.offset + range.length);
+
+            if gaps.is_empty() {
+                loaded_until = cursor;
+            }
+        }
+
+        if let Some(end) = self.expected_length.filter(|l| *l > cursor) {
+            gaps.push(RangeInstrospection {
+                offset: cursor,
+                length: end - cursor,
+            });
+        }
+
+        BufferHealth {
+            loaded_ahead: loaded_until - offset,
+            gaps,
+            expected_length: self.expected_length,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{assign_slice, assign_slice_with_offset};
@@ -638,6 +714,27 @@ mod test {
         assert_eq!(amount, 2, "amount read is correct");
     }
 
+    #[test]
+    fn test_read_at_end() {
+        let offset = 20;
+        let mut buffer = RangeBuffer::new(offset);
+
+        buffer.write(&[1., 2., 3., 4., 5.]);
+
+        let mut buf = vec![0.; 10];
+        assert_eq!(
+            buffer.read(offset + 5, &mut buf),
+            0,
+            "nothing is read at the end"
+        );
+        assert_eq!(
+            buffer.read(offset + 6, &mut buf),
+            0,
+            "nothing is read past the end"
+        );
+        assert!(buf.iter().all(|s| *s == 0.), "buf is untouched");
+    }
+
     #[test]
     fn test_read_multi() {
         let mut buffer = MultiRangeBuffer::new(Some(29));
@@ -703,6 +800,41 @@ mod test {
         assert!(at_last_sample.is_end, "void after last sample is the end");
     }
 
+    #[test]
+    fn test_retain_range_keeps_chunk_of_offset() {
+        // The play head is in the last frame, which is still being written
+        let mut buffer = RangeBuffer::new(0);
+        buffer.write(&[1., 2., 3., 4., 5.]);
+        buffer.retain_range(4, 4, 2);
+
+        assert_eq!(buffer.offset, 4);
+        assert_eq!(buffer.consume_to_vec(), vec![5.], "partial chunk is kept");
+
+        // Three channels, where the offset is the second sample of a frame
+        let mut buffer = RangeBuffer::new(0);
+        let samples: Vec<_> = (0..12).map(|s| s as f32).collect();
+        buffer.write(&samples);
+        buffer.retain_range(4, 4, 3);
+
+        assert_eq!(buffer.offset, 3);
+        assert_eq!(
+            buffer.consume_to_vec(),
+            vec![3., 4., 5.],
+            "whole frame is kept"
+        );
+
+        // Partial chunks at the edges are cleared when whole ones are kept
+        let mut buffer = RangeBuffer::new(4);
+        buffer.write(&samples);
+        buffer.retain_range(0, 20, 3);
+
+        assert_eq!(buffer.offset, 6);
+        assert_eq!(
+            buffer.consume_to_vec(),
+            (2..11).map(|s| s as f32).collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn test_retain_window() {
         let mut buffer = MultiRangeBuffer::new(None);
@@ -0,0 +1,112 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam::atomic::AtomicCell;
+use dashmap::DashMap;
+
+/// Keeps track of when each long-lived thread of a pipeline last made progress,
+/// so that a thread that stopped without a word, like one that panicked, can be noticed.
+#[derive(Debug, Clone, Default)]
+pub struct Heartbeats {
+    threads: Arc<DashMap<String, Heartbeat>>,
+}
+
+/// The heartbeat of a single thread, which it beats every time it goes around its loop.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    last: Arc<AtomicCell<Instant>>,
+}
+
+/// A thread that hasn't made progress for longer than it should have
+#[derive(Debug, Clone, PartialEq)]
+pub struct StalledThread {
+    pub name: String,
+    /// How long ago the thread last beat
+    pub since: Duration,
+}
+
+/// Whether the threads of a pipeline are making progress
+#[derive(Debug, Clone, Default)]
+pub struct PipelineHealth {
+    pub stalled_threads: Vec<StalledThread>,
+}
+
+impl Heartbeats {
+    /// Registers a thread by name and returns its heartbeat, which counts as a beat.
+    /// Registering the same name again replaces the previous heartbeat, like when a thread is restarted.
+    pub fn register(&self, name: impl Into<String>) -> Heartbeat {
+        let heartbeat = Heartbeat {
+            last: Arc::new(AtomicCell::new(Instant::now())),
+        };
+
+        self.threads.insert(name.into(), heartbeat.clone());
+        heartbeat
+    }
+
+    /// Returns the threads that didn't beat within the threshold, those stalled for the longest first.
+    pub fn stalled(&self, threshold: Duration) -> Vec<StalledThread> {
+        let now = Instant::now();
+
+        let mut stalled: Vec<_> = self
+            .threads
+            .iter()
+            .map(|entry| StalledThread {
+                name: entry.key().clone(),
+                since: now.saturating_duration_since(entry.value().last.load()),
+            })
+            .filter(|thread| thread.since > threshold)
+            .collect();
+
+        stalled.sort_by(|a, b| b.since.cmp(&a.since).then_with(|| a.name.cmp(&b.name)));
+        stalled
+    }
+}
+
+impl Heartbeat {
+    /// Marks the thread as having made progress just now.
+    pub fn beat(&self) {
+        self.last.store(Instant::now());
+    }
+}
+
+impl PipelineHealth {
+    /// Returns true if no thread is stalled.
+    pub fn is_healthy(&self) -> bool {
+        self.stalled_threads.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_stalled_thread_is_flagged() {
+        let heartbeats = Heartbeats::default();
+        let threshold = Duration::from_millis(50);
+
+        let alive = heartbeats.register("alive");
+        let _stuck = heartbeats.register("stuck");
+
+        assert!(heartbeats.stalled(threshold).is_empty());
+
+        // Only one of the threads keeps beating, like when the other one panicked
+        for _ in 0..10 {
+            alive.beat();
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let stalled = heartbeats.stalled(threshold);
+        assert_eq!(stalled.len(), 1);
+        assert_eq!(stalled[0].name, "stuck");
+        assert!(stalled[0].since > threshold);
+
+        // A restarted thread registers again
+        heartbeats.register("stuck");
+        assert!(heartbeats.stalled(threshold).is_empty());
+    }
+}
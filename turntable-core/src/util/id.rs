@@ -30,6 +30,14 @@ impl<T> Id<T> {
         }
     }
 
+    /// Creates an id with an existing value, like one received from a client.
+    pub fn from_value(value: IdType) -> Self {
+        Self {
+            value,
+            kind: PhantomData,
+        }
+    }
+
     pub fn value(&self) -> u64 {
         self.value
     }
@@ -1,12 +1,16 @@
 mod buffer;
 mod ext;
+mod heartbeat;
 mod id;
 mod introspection;
+mod shutdown;
 
 pub use buffer::*;
 pub use ext::*;
+pub use heartbeat::*;
 pub use id::*;
 pub use introspection::*;
+pub use shutdown::*;
 
 use tokio::runtime::{Handle, Runtime};
 
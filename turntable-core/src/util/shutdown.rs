@@ -0,0 +1,53 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use log::warn;
+use parking_lot::Mutex;
+
+/// Keeps track of the threads of a pipeline, so they can be told to stop and be joined.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Shutdown {
+    /// Registers a thread to be joined on shutdown.
+    pub fn register(&self, handle: JoinHandle<()>) {
+        self.handles.lock().push(handle);
+    }
+
+    /// Returns true if the threads should stop.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    /// Tells the threads to stop, blocking until all of them have exited.
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::Release);
+
+        let handles: Vec<_> = self.handles.lock().drain(..).collect();
+
+        for handle in handles {
+            let name = handle.thread().name().unwrap_or("unnamed").to_string();
+
+            if handle.join().is_err() {
+                warn!("Thread {} panicked before shutting down", name);
+            }
+        }
+    }
+
+    /// Returns how many of the registered threads are still running.
+    pub fn running(&self) -> usize {
+        self.handles
+            .lock()
+            .iter()
+            .filter(|h| !h.is_finished())
+            .count()
+    }
+}
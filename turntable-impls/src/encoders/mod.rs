@@ -1,3 +1,263 @@
+mod pcm_encoder;
+mod raw_encoder;
 mod wave_encoder;
 
+pub use pcm_encoder::*;
+pub use raw_encoder::*;
 pub use wave_encoder::*;
+
+use turntable_core::{Config, Consumer, Ingestion, Pipeline, PlayerId};
+
+/// The encoders that can be selected at runtime, for example through content negotiation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncoderKind {
+    #[default]
+    Wave,
+    /// 32-bit float PCM
+    Pcm,
+    /// Signed 16-bit PCM
+    PcmS16,
+    /// Signed 24-bit PCM
+    PcmS24,
+}
+
+impl EncoderKind {
+    /// All selectable encoders, in order of preference
+    pub const ALL: [EncoderKind; 4] = [
+        EncoderKind::Wave,
+        EncoderKind::Pcm,
+        EncoderKind::PcmS16,
+        EncoderKind::PcmS24,
+    ];
+
+    /// Returns the content type of the encoded data
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Wave => "audio/wav",
+            Self::Pcm => F32Le::CONTENT_TYPE,
+            Self::PcmS16 => S16Le::CONTENT_TYPE,
+            Self::PcmS24 => S24Le::CONTENT_TYPE,
+        }
+    }
+
+    /// Returns the name of the codec the audio is encoded with
+    pub fn codec(&self) -> &'static str {
+        match self {
+            Self::Wave => "pcm_s16le",
+            Self::Pcm => "pcm_f32le",
+            Self::PcmS16 => "pcm_s16le",
+            Self::PcmS24 => "pcm_s24le",
+        }
+    }
+
+    /// Returns how many bits an encoded sample takes up
+    pub fn bits_per_sample(&self) -> usize {
+        match self {
+            Self::Wave | Self::PcmS16 => 16,
+            Self::PcmS24 => 24,
+            Self::Pcm => 32,
+        }
+    }
+
+    /// Returns the extension of a file containing the encoded audio
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Wave => "wav",
+            Self::Pcm => "pcm",
+            Self::PcmS16 => S16Le::NAME,
+            Self::PcmS24 => S24Le::NAME,
+        }
+    }
+
+    /// Returns the header the encoded data starts with, which is empty if the format has none.
+    /// `data_length` is how many bytes follow the header, or `None` for a live stream.
+    pub fn header(&self, config: &Config, data_length: Option<usize>) -> Vec<u8> {
+        match self {
+            Self::Wave => WaveHeader::new(config)
+                .to_bytes_with_length(data_length.map(|l| l.min(u32::MAX as usize) as u32)),
+            Self::Pcm | Self::PcmS16 | Self::PcmS24 => vec![],
+        }
+    }
+
+    /// Returns the bitrate of the encoded audio in bits per second, excluding any headers
+    pub fn bitrate(&self, config: &Config) -> usize {
+        config.samples_per_sec() * self.bits_per_sample()
+    }
+
+    /// Returns the encoder matching a mime type, if any.
+    /// The sample format of `audio/pcm` is selected with a `format` parameter, and defaults to 32-bit float.
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.to_ascii_lowercase();
+        let mut parts = mime.split(';').map(str::trim);
+
+        let essence = parts.next().unwrap_or_default();
+        let format = parts.find_map(|p| p.strip_prefix("format=").map(|f| f.trim_matches('"')));
+
+        match (essence, format) {
+            ("audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave", _) => Some(Self::Wave),
+            ("audio/pcm", None) => Some(Self::Pcm),
+            ("audio/pcm", Some(format)) => match format {
+                F32Le::NAME => Some(Self::Pcm),
+                S16Le::NAME => Some(Self::PcmS16),
+                S24Le::NAME => Some(Self::PcmS24),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Creates a consumer for a player, using the encoder this represents.
+    pub fn consume_player<I>(
+        &self,
+        pipeline: &Pipeline<I>,
+        player_id: PlayerId,
+        with_latency: Option<u32>,
+        token: Option<String>,
+    ) -> Consumer
+    where
+        I: Ingestion,
+    {
+        match self {
+            Self::Wave => pipeline.consume_player::<WaveEncoder>(player_id, with_latency, token),
+            Self::Pcm => pipeline.consume_player::<PcmEncoder>(player_id, with_latency, token),
+            Self::PcmS16 => {
+                pipeline.consume_player::<PcmEncoder<S16Le>>(player_id, with_latency, token)
+            }
+            Self::PcmS24 => {
+                pipeline.consume_player::<PcmEncoder<S24Le>>(player_id, with_latency, token)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use turntable_core::{
+        ArcedStore, Config, ConsumerStart, Encoder, EncoderStats, Introspect, Pipeline, Stream,
+    };
+
+    use crate::{PcmEncoder, SymphoniaIngestion, WaveEncoder};
+
+    use super::EncoderKind;
+
+    #[test]
+    fn test_consumer_matches_kind() {
+        let pipeline = Pipeline::<SymphoniaIngestion>::new(Config::default());
+        let player = pipeline.create_player();
+
+        for kind in EncoderKind::ALL {
+            let consumer = kind.consume_player(&pipeline, player.id, None, None);
+            assert_eq!(consumer.content_type(), kind.content_type());
+        }
+    }
+
+    #[test]
+    fn test_header_matches_stream() {
+        let config = Config::default();
+        let pipeline = Pipeline::<SymphoniaIngestion>::new(config.clone());
+        let player = pipeline.create_player();
+
+        for kind in EncoderKind::ALL {
+            let consumer = kind.consume_player(&pipeline, player.id, None, None);
+
+            let bytes = consumer.bytes().expect("player outputs silence");
+            let header = kind.header(&config, None);
+            assert!(bytes.starts_with(&header));
+            assert_eq!(
+                (bytes.len() - header.len()) % (kind.bits_per_sample() / 8),
+                0
+            );
+        }
+
+        let header = EncoderKind::Wave.header(&config, Some(100));
+        assert_eq!(&header[4..8], &136u32.to_le_bytes());
+        assert_eq!(&header[40..44], &100u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_encoder_stats() {
+        let stats: ArcedStore<String, EncoderStats> = Default::default();
+        let stream = Stream::new(Config::default(), stats.clone());
+
+        let _pcm = stream.consume::<PcmEncoder>(Some(0), None);
+        let _other_pcm = stream.consume::<PcmEncoder>(Some(0), None);
+        let _wave = stream.consume::<WaveEncoder>(Some(0), None);
+
+        stream.push(&[0.5; 1000]);
+        stream.push(&[0.5; 500]);
+
+        let samples_encoded = |name: String| {
+            let stats = stats.get(&name).expect("encoder has stats");
+            (stats.key(), stats.value()).introspect().samples_encoded
+        };
+
+        // The stats are shared by every consumer of an encoder
+        assert_eq!(samples_encoded(<PcmEncoder>::name()), 3000);
+        assert_eq!(samples_encoded(WaveEncoder::name()), 1500);
+    }
+
+    #[test]
+    fn test_bitrate() {
+        let config = Config::default();
+
+        assert_eq!(EncoderKind::Wave.bitrate(&config), 44100 * 2 * 16);
+        assert_eq!(EncoderKind::Pcm.bitrate(&config), 44100 * 2 * 32);
+        assert_eq!(EncoderKind::PcmS24.bitrate(&config), 44100 * 2 * 24);
+    }
+
+    #[test]
+    fn test_from_mime() {
+        assert_eq!(EncoderKind::from_mime("audio/wav"), Some(EncoderKind::Wave));
+        assert_eq!(EncoderKind::from_mime(" Audio/PCM"), Some(EncoderKind::Pcm));
+        assert_eq!(EncoderKind::from_mime("audio/ogg"), None);
+
+        assert_eq!(
+            EncoderKind::from_mime("audio/pcm; format=s16le"),
+            Some(EncoderKind::PcmS16)
+        );
+        assert_eq!(
+            EncoderKind::from_mime("audio/pcm;format=\"s24le\""),
+            Some(EncoderKind::PcmS24)
+        );
+        assert_eq!(
+            EncoderKind::from_mime("audio/pcm;format=f32le"),
+            Some(EncoderKind::Pcm)
+        );
+        assert_eq!(EncoderKind::from_mime("audio/pcm;format=u8"), None);
+
+        // The content type of every encoder maps back to it
+        for kind in EncoderKind::ALL {
+            assert_eq!(EncoderKind::from_mime(kind.content_type()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_consumer_start() {
+        let config = Config::default();
+        let stream = Stream::new(config.clone(), Default::default());
+
+        // Nothing has been pushed to the stream, like when the player hasn't started yet
+        let consumer = stream.consume::<PcmEncoder>(None, None);
+        consumer.start_with(ConsumerStart::Silence(Duration::from_millis(100)));
+
+        let bytes = consumer.bytes().expect("silence is returned right away");
+        assert_eq!(bytes.len(), config.seconds_to_samples(0.1) * 4);
+        assert!(bytes.iter().all(|b| *b == 0));
+
+        let consumer = stream.consume::<PcmEncoder>(None, None);
+        consumer.start_with(ConsumerStart::Timeout(Duration::from_millis(50)));
+
+        let started = Instant::now();
+        assert_eq!(consumer.bytes(), None, "no audio arrived in time");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Audio that's already there isn't interrupted by silence
+        stream.push(&[0.5; 10]);
+
+        let consumer = stream.consume::<PcmEncoder>(None, None);
+        consumer.start_with(ConsumerStart::Silence(Duration::from_millis(100)));
+        assert_eq!(consumer.bytes().unwrap().len(), 10 * 4);
+    }
+}
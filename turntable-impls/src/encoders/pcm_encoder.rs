@@ -0,0 +1,253 @@
+use std::marker::PhantomData;
+
+use turntable_core::{Config, Encoder, EncoderIntrospection, Introspect, Sample};
+
+/// A sample format headerless PCM can be encoded in
+pub trait PcmFormat: 'static + Send + Sync {
+    /// The name of the format, as used in the `format` parameter of the content type
+    const NAME: &'static str;
+
+    /// The content type of PCM in this format
+    const CONTENT_TYPE: &'static str;
+
+    /// How many bytes a sample takes up
+    const BYTES: usize;
+
+    /// Appends a sample to the bytes, using the dither to quantize it if needed
+    fn write(sample: Sample, dither: &mut Dither, bytes: &mut Vec<u8>);
+}
+
+/// Interleaved 32-bit float little-endian samples
+pub struct F32Le;
+
+/// Interleaved signed 16-bit little-endian samples
+pub struct S16Le;
+
+/// Interleaved signed 24-bit little-endian samples, packed into 3 bytes each
+pub struct S24Le;
+
+impl PcmFormat for F32Le {
+    const NAME: &'static str = "f32le";
+    const CONTENT_TYPE: &'static str = "audio/pcm";
+    const BYTES: usize = 4;
+
+    fn write(sample: Sample, _dither: &mut Dither, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+}
+
+impl PcmFormat for S16Le {
+    const NAME: &'static str = "s16le";
+    const CONTENT_TYPE: &'static str = "audio/pcm;format=s16le";
+    const BYTES: usize = 2;
+
+    fn write(sample: Sample, dither: &mut Dither, bytes: &mut Vec<u8>) {
+        let value = dither.quantize(sample, i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl PcmFormat for S24Le {
+    const NAME: &'static str = "s24le";
+    const CONTENT_TYPE: &'static str = "audio/pcm;format=s24le";
+    const BYTES: usize = 3;
+
+    fn write(sample: Sample, dither: &mut Dither, bytes: &mut Vec<u8>) {
+        const MAX: i32 = (1 << 23) - 1;
+
+        let value = dither.quantize(sample, MAX as f32) as i32;
+        bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+    }
+}
+
+/// Triangular (TPDF) dither of one step, which turns the error from quantizing
+/// to an integer format into noise instead of distortion.
+pub struct Dither {
+    state: u32,
+}
+
+impl Dither {
+    fn new() -> Self {
+        Self { state: 0x9E37_79B9 }
+    }
+
+    /// Scales a sample to `max`, adds the dither and rounds it within range.
+    /// Digital silence stays silent.
+    fn quantize(&mut self, sample: Sample, max: f32) -> f32 {
+        if sample == 0. {
+            return 0.;
+        }
+
+        let noise = self.uniform() - self.uniform();
+        (sample * max + noise).round().clamp(-max - 1., max)
+    }
+
+    /// Returns a number in the range `0..1`, using xorshift
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Encodes [Sample]s into headerless interleaved PCM in the given format,
+/// which is 32-bit float little-endian by default.
+pub struct PcmEncoder<F: PcmFormat = F32Le> {
+    encoded_bytes: Vec<u8>,
+    dither: Dither,
+    format: PhantomData<F>,
+}
+
+impl<F: PcmFormat> Encoder for PcmEncoder<F> {
+    fn new(_config: Config) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            encoded_bytes: Vec::new(),
+            dither: Dither::new(),
+            format: PhantomData,
+        }
+    }
+
+    fn content_type(&self) -> String {
+        F::CONTENT_TYPE.to_string()
+    }
+
+    fn expected_size(&self, samples: usize) -> Option<usize> {
+        Some(samples * F::BYTES)
+    }
+
+    fn name() -> String
+    where
+        Self: Sized,
+    {
+        match F::NAME {
+            "f32le" => "PcmEncoder".to_string(),
+            name => format!("PcmEncoder ({name})"),
+        }
+    }
+
+    fn encode(&mut self, samples: &[Sample]) {
+        for sample in samples {
+            F::write(*sample, &mut self.dither, &mut self.encoded_bytes);
+        }
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        // Return nothing until there's data available
+        if self.encoded_bytes.is_empty() {
+            return None;
+        }
+
+        Some(std::mem::take(&mut self.encoded_bytes))
+    }
+}
+
+impl<F: PcmFormat> Introspect<EncoderIntrospection> for PcmEncoder<F> {
+    fn introspect(&self) -> EncoderIntrospection {
+        EncoderIntrospection {
+            name: Self::name(),
+            size: self.encoded_bytes.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turntable_core::{Config, Encoder};
+
+    use super::*;
+
+    fn encode<F: PcmFormat>(samples: &[Sample]) -> Vec<u8> {
+        let mut encoder = PcmEncoder::<F>::new(Config::default());
+        encoder.encode(samples);
+        encoder.bytes().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_f32_is_lossless() {
+        let input = [0.5, -0.25, 1., -1., 0.];
+        let bytes = encode::<F32Le>(&input);
+
+        assert_eq!(bytes.len(), input.len() * 4);
+
+        let output: Vec<_> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_s16_conversion() {
+        let bytes = encode::<S16Le>(&[0.5; 1000]);
+        assert_eq!(bytes.len(), 1000 * 2);
+
+        let output: Vec<_> = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32)
+            .collect();
+
+        // Dither stays within a step of the exact value, and averages out to it
+        let exact = 0.5 * i16::MAX as f32;
+        assert!(output.iter().all(|v| (v - exact).abs() <= 1.5));
+
+        let mean = output.iter().sum::<f32>() / output.len() as f32;
+        assert!((mean - exact).abs() < 0.1, "mean {mean} is biased");
+
+        let bytes = encode::<S16Le>(&[1., -1., 2., -2., 0.]);
+        let output: Vec<_> = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        assert!(output[0] >= i16::MAX - 1);
+        assert!(output[1] <= -i16::MAX + 1);
+        assert_eq!(&output[2..], [i16::MAX, i16::MIN, 0]);
+    }
+
+    #[test]
+    fn test_s24_conversion() {
+        let bytes = encode::<S24Le>(&[0.5; 1000]);
+        assert_eq!(bytes.len(), 1000 * 3);
+
+        // Sign extend the 3 bytes back into an i32
+        let output: Vec<_> = bytes
+            .chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32)
+            .collect();
+
+        let exact = 0.5 * ((1 << 23) - 1) as f32;
+        assert!(output.iter().all(|v| (v - exact).abs() <= 1.5));
+
+        let bytes = encode::<S24Le>(&[-0.5, 0.]);
+        let negative = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+
+        assert!((negative as f32 + exact).abs() <= 1.5);
+        assert_eq!(&bytes[3..], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_names() {
+        let encoder = PcmEncoder::<S16Le>::new(Config::default());
+        assert_eq!(encoder.content_type(), "audio/pcm;format=s16le");
+
+        assert_eq!(<PcmEncoder>::name(), "PcmEncoder");
+        assert_eq!(<PcmEncoder<S24Le>>::name(), "PcmEncoder (s24le)");
+    }
+
+    #[test]
+    fn test_expected_size() {
+        let input = [0.5; 100];
+
+        let f32 = PcmEncoder::<F32Le>::new(Config::default());
+        let s24 = PcmEncoder::<S24Le>::new(Config::default());
+
+        assert_eq!(f32.expected_size(100), Some(encode::<F32Le>(&input).len()));
+        assert_eq!(s24.expected_size(100), Some(encode::<S24Le>(&input).len()));
+    }
+}
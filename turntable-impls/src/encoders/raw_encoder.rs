@@ -0,0 +1,85 @@
+use turntable_core::{Config, Encoder, EncoderIntrospection, Introspect, Sample};
+
+/// Passes [Sample]s through unchanged, as interleaved 32-bit float little-endian bytes.
+///
+/// This is meant for tests and health checks, where the exact samples a player outputs need to be asserted.
+/// Use [crate::PcmEncoder] to serve raw audio to clients.
+pub struct RawEncoder {
+    encoded_bytes: Vec<u8>,
+}
+
+impl Encoder for RawEncoder {
+    fn new(_config: Config) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            encoded_bytes: Vec::new(),
+        }
+    }
+
+    fn content_type(&self) -> String {
+        "application/octet-stream".to_string()
+    }
+
+    fn expected_size(&self, samples: usize) -> Option<usize> {
+        Some(samples * size_of::<Sample>())
+    }
+
+    fn name() -> String
+    where
+        Self: Sized,
+    {
+        "Raw".to_string()
+    }
+
+    fn encode(&mut self, samples: &[Sample]) {
+        self.encoded_bytes
+            .extend(samples.iter().flat_map(|s| s.to_le_bytes()));
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        if self.encoded_bytes.is_empty() {
+            return None;
+        }
+
+        Some(std::mem::take(&mut self.encoded_bytes))
+    }
+}
+
+impl Introspect<EncoderIntrospection> for RawEncoder {
+    fn introspect(&self) -> EncoderIntrospection {
+        EncoderIntrospection {
+            name: Self::name(),
+            size: self.encoded_bytes.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turntable_core::{Config, Encoder};
+
+    use super::RawEncoder;
+
+    #[test]
+    fn test_round_trip() {
+        let samples = [0., 1., -1., 0.5, -0.25, f32::MIN_POSITIVE];
+        let mut encoder = RawEncoder::new(Config::default());
+
+        assert_eq!(encoder.bytes(), None, "nothing is encoded yet");
+
+        encoder.encode(&samples[..2]);
+        encoder.encode(&samples[2..]);
+
+        let bytes = encoder.bytes().expect("samples are encoded");
+        let decoded: Vec<_> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        assert_eq!(bytes.len(), samples.len() * 4);
+        assert_eq!(decoded, samples);
+        assert_eq!(encoder.bytes(), None, "bytes are consumed");
+    }
+}
@@ -1,5 +1,7 @@
 use turntable_core::{Config, Encoder, EncoderIntrospection, Introspect, Sample};
 
+use crate::EncoderKind;
+
 /// Encodes [Sample]s into a .wav file
 pub struct WaveEncoder {
     did_write_header: bool,
@@ -15,7 +17,7 @@ enum WaveHeaderValue {
 }
 
 #[derive(Debug, Clone)]
-struct WaveHeader {
+pub(crate) struct WaveHeader {
     channel_count: u16,
     sample_rate: u32,
     bit_depth: u16,
@@ -35,9 +37,6 @@ impl WaveHeader {
     // ChunkID: Contains the letters "RIFF" in ASCII form, change last number to 80 if "RIFX" is used
     const CHUNK_ID: WaveHeaderValue = WaveHeaderValue::Ascii("RIFF");
 
-    // This is set to max because it's a live audio stream.
-    const CHUNK_SIZE: WaveHeaderValue = WaveHeaderValue::FourBytes(i32::MAX as u32);
-
     // Format: Contains the letters "WAVE"
     const FORMAT: WaveHeaderValue = WaveHeaderValue::Ascii("WAVE");
 
@@ -53,7 +52,28 @@ impl WaveHeader {
     // Subchunk2ID: Contains the letters "data"
     const DATA_CHUNK_ID: WaveHeaderValue = WaveHeaderValue::Ascii("data");
 
+    pub fn new(config: &Config) -> Self {
+        Self {
+            channel_count: config.channel_count as u16,
+            sample_rate: config.sample_rate as u32,
+            bit_depth: EncoderKind::Wave.bits_per_sample() as u16,
+        }
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_length(None)
+    }
+
+    /// Returns the header for the given amount of data bytes, or a live stream if `None`
+    pub fn to_bytes_with_length(&self, data_length: Option<u32>) -> Vec<u8> {
+        // The sizes are set to max when it's a live audio stream
+        let (chunk_size, data_length) = match data_length {
+            Some(length) => (length.saturating_add(36), length),
+            None => (i32::MAX as u32, i32::MAX as u32),
+        };
+
+        let chunk_size = WaveHeaderValue::FourBytes(chunk_size);
+
         let num_channels = WaveHeaderValue::TwoBytes(self.channel_count);
         let sample_rate = WaveHeaderValue::FourBytes(self.sample_rate);
 
@@ -64,12 +84,11 @@ impl WaveHeader {
         let block_align = WaveHeaderValue::TwoBytes(self.channel_count * self.bit_depth / 8);
         let bits_per_sample = WaveHeaderValue::TwoBytes(self.bit_depth);
 
-        // This is set to max because turntable is a live audio stream
-        let data_chunk_size = WaveHeaderValue::FourBytes(i32::MAX as u32);
+        let data_chunk_size = WaveHeaderValue::FourBytes(data_length);
 
         [
             Self::CHUNK_ID,
-            Self::CHUNK_SIZE,
+            chunk_size,
             Self::FORMAT,
             Self::FMT_CHUNK_ID,
             Self::FMT_CHUNK_SIZE,
@@ -93,16 +112,10 @@ impl Encoder for WaveEncoder {
     where
         Self: Sized,
     {
-        let header = WaveHeader {
-            channel_count: config.channel_count as u16,
-            sample_rate: config.sample_rate as u32,
-            bit_depth: 16,
-        };
-
         Self {
             did_write_header: false,
             encoded_bytes: Vec::new(),
-            header,
+            header: WaveHeader::new(&config),
         }
     }
 
@@ -110,6 +123,13 @@ impl Encoder for WaveEncoder {
         "audio/wav".to_string()
     }
 
+    fn expected_size(&self, samples: usize) -> Option<usize> {
+        let bytes_per_sample = EncoderKind::Wave.bits_per_sample() / 8;
+
+        // The header is the same size whether or not the length is known
+        Some(self.header.to_bytes().len() + samples * bytes_per_sample)
+    }
+
     fn name() -> String
     where
         Self: Sized,
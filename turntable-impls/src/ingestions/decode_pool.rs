@@ -0,0 +1,130 @@
+use std::{
+    cell::OnceCell,
+    panic::{catch_unwind, AssertUnwindSafe},
+    thread,
+    time::Duration,
+};
+
+use crossbeam::channel::{unbounded, RecvTimeoutError, Sender};
+use log::error;
+use tokio::sync::oneshot;
+use turntable_core::{Heartbeat, Heartbeats};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How often an idle decode thread beats, to show it is still there
+const IDLE_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+
+thread_local! {
+    /// The heartbeat of the decode thread, unset on any other thread
+    static HEARTBEAT: OnceCell<Heartbeat> = const { OnceCell::new() };
+}
+
+/// A fixed amount of threads that decoding and seeking run on.
+///
+/// Decoding blocks, so it can't run on the async runtime. Running it on the runtime's blocking pool instead
+/// would share that pool with everything else, where unrelated blocking work could hold up playback and vice versa.
+///
+/// The threads exit when the pool is dropped. Until then, they beat while idle, and jobs beat with [DecodePool::beat] as they make progress.
+pub struct DecodePool {
+    sender: Sender<Job>,
+}
+
+impl DecodePool {
+    pub fn new(size: usize, heartbeats: &Heartbeats) -> Self {
+        let (sender, receiver) = unbounded::<Job>();
+
+        for index in 0..size.max(1) {
+            let receiver = receiver.clone();
+            let heartbeat = heartbeats.register(format!("decode-{}", index));
+
+            let run = move || {
+                HEARTBEAT.with(|h| h.set(heartbeat.clone()).expect("heartbeat is set once"));
+
+                loop {
+                    heartbeat.beat();
+
+                    let job = match receiver.recv_timeout(IDLE_HEARTBEAT_INTERVAL) {
+                        Ok(job) => job,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+
+                    // A panicking job must not take the thread with it
+                    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        error!("Decoding panicked on decode thread {}", index);
+                    }
+                }
+            };
+
+            thread::Builder::new()
+                .name(format!("decode-{}", index))
+                .spawn(run)
+                .expect("decode thread is spawned");
+        }
+
+        Self { sender }
+    }
+
+    /// Marks the decode thread this is called on as having made progress, so a long job doesn't look stalled.
+    /// Does nothing when not called from a decode thread.
+    pub fn beat() {
+        HEARTBEAT.with(|h| {
+            if let Some(heartbeat) = h.get() {
+                heartbeat.beat();
+            }
+        });
+    }
+
+    /// Runs the function on the pool, returning its result.
+    /// [None] is returned if the function panicked.
+    pub async fn run<F, T>(&self, f: F) -> Option<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        let job = Box::new(move || {
+            let _ = sender.send(f());
+        });
+
+        self.sender.send(job).expect("decode threads are running");
+        receiver.await.ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use turntable_core::Heartbeats;
+
+    use super::DecodePool;
+
+    #[tokio::test]
+    async fn test_long_job_beats() {
+        let heartbeats = Heartbeats::default();
+        let pool = DecodePool::new(1, &heartbeats);
+        let threshold = Duration::from_millis(200);
+
+        let stalled = pool
+            .run({
+                let heartbeats = heartbeats.clone();
+
+                move || {
+                    // Takes longer than the threshold, like decoding a long load
+                    for _ in 0..10 {
+                        thread::sleep(Duration::from_millis(50));
+                        DecodePool::beat();
+                    }
+
+                    heartbeats.stalled(threshold)
+                }
+            })
+            .await
+            .unwrap();
+
+        assert!(stalled.is_empty(), "busy decode thread isn't stalled");
+    }
+}
@@ -1,3 +1,5 @@
+mod decode_pool;
 mod symphonia_ingestion;
 
+pub use decode_pool::*;
 pub use symphonia_ingestion::*;
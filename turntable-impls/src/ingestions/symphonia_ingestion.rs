@@ -1,15 +1,18 @@
 use async_trait::async_trait;
 use crossbeam::atomic::AtomicCell;
+use log::warn;
 use parking_lot::Mutex;
 use rubato::{FftFixedInOut, Resampler};
 use std::{
+    borrow::Cow,
     error::Error,
     io::{ErrorKind as IoErrorKind, Read, Seek, SeekFrom},
+    sync::Arc,
 };
 use symphonia::core::{
-    audio::SampleBuffer,
+    audio::{Channels, SampleBuffer},
     codecs::{Decoder, CODEC_TYPE_NULL},
-    errors::Error as SymphoniaError,
+    errors::{Error as SymphoniaError, SeekErrorKind},
     formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track},
     io::{MediaSource, MediaSourceStream},
     meta::MetadataOptions,
@@ -18,17 +21,28 @@ use symphonia::core::{
 };
 use tokio::runtime::Handle;
 
+use super::DecodePool;
 use turntable_core::{
-    get_or_create_handle, BoxedLoadable, Config, Ingest, Ingestion, IntoLoadable, LoadRequest,
-    Loadable, LoaderLength, PipelineContext, ReadResult, Sample, WriteGuard,
+    get_or_create_handle, BoxedLoadable, Config, Ingest, Ingestion, IngestionError, IntoLoadable,
+    LoadRequest, Loadable, LoaderLength, PipelineContext, ReadResult, ResamplerQuality, Sample,
+    WriteGuard,
 };
 
 type SymphoniaResampler = FftFixedInOut<Sample>;
 
+/// Samples quieter than this, about -60 dBFS, count as silence when trimming the start of a source.
+const SILENCE_THRESHOLD: Sample = 0.001;
+
+/// How much audio is decoded at a time when seeking forward in a source that can't seek, in seconds.
+/// Packets are far shorter than this, so decoding a chunk doesn't go much past it.
+const FALLBACK_SEEK_CHUNK_IN_SECONDS: f32 = 1.;
+
 /// An ingestion implementation for Symphonia.
 pub struct SymphoniaIngestion {
     /// A runtime is needed to bridge synchronous Symphonia with asynchronous turntable.
     rt: Handle,
+    /// Decoding happens here instead of on the runtime's blocking threads.
+    pool: DecodePool,
     context: PipelineContext,
     format_options: FormatOptions,
 }
@@ -40,6 +54,7 @@ impl Ingestion for SymphoniaIngestion {
     fn new(context: &PipelineContext) -> Self {
         Self {
             rt: get_or_create_handle(),
+            pool: DecodePool::new(context.config.decode_threads, &context.heartbeats),
             context: context.clone(),
             format_options: FormatOptions {
                 enable_gapless: true,
@@ -49,13 +64,17 @@ impl Ingestion for SymphoniaIngestion {
         }
     }
 
-    async fn ingest<L>(&self, input: L) -> Result<Ingest<Self::Loader>, Box<dyn Error>>
+    async fn ingest<L>(&self, input: L) -> Result<Ingest<Self::Loader>, IngestionError>
     where
         L: IntoLoadable + Send + Sync,
     {
         let input = input.into_loadable();
 
-        input.activate().await?;
+        // Activation fails when the source can't be reached, which may be temporary
+        input
+            .activate()
+            .await
+            .map_err(|e| IngestionError::Io(e.to_string()))?;
 
         let potential_sink_length = input
             .length()
@@ -70,8 +89,8 @@ impl Ingestion for SymphoniaIngestion {
         let stream = MediaSourceStream::new(Box::new(loadable), Default::default());
         let format_options = self.format_options;
         let probed = self
-            .rt
-            .spawn_blocking(move || {
+            .pool
+            .run(move || {
                 symphonia::default::get_probe().format(
                     &Hint::default(),
                     stream,
@@ -79,14 +98,16 @@ impl Ingestion for SymphoniaIngestion {
                     &MetadataOptions::default(),
                 )
             })
-            .await??;
+            .await
+            .ok_or_else(|| IngestionError::Other("Probing panicked".to_string()))?
+            .map_err(|e| ingestion_error(e, IngestionError::UnsupportedFormat))?;
 
         let format_reader = probed.format;
         let audio_track = format_reader
             .tracks()
             .iter()
             .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .ok_or("Symphonia: No supported audio stream found")?;
+            .ok_or(IngestionError::NoAudioTrack)?;
 
         let sample_rate = audio_track
             .codec_params
@@ -94,15 +115,16 @@ impl Ingestion for SymphoniaIngestion {
             .map(|s| s as usize)
             .unwrap_or(self.context.config.sample_rate);
 
-        let resampler = DynamicResampler::new(sample_rate, &self.context.config)?;
+        let resampler = DynamicResampler::new(sample_rate, &self.context.config)
+            .map_err(|e| IngestionError::UnsupportedFormat(e.to_string()))?;
 
         let codec_params = audio_track.codec_params.clone();
         let decoder = self
-            .rt
-            .spawn_blocking(move || {
-                symphonia::default::get_codecs().make(&codec_params, &Default::default())
-            })
-            .await??;
+            .pool
+            .run(move || symphonia::default::get_codecs().make(&codec_params, &Default::default()))
+            .await
+            .ok_or_else(|| IngestionError::Other("Creating the decoder panicked".to_string()))?
+            .map_err(|e| ingestion_error(e, IngestionError::UnsupportedCodec))?;
 
         // Get the decoded length of the audio track, if possible.
         let potential_decoded_seconds = audio_track
@@ -126,6 +148,8 @@ impl Ingestion for SymphoniaIngestion {
             decoder: decoder.into(),
             track: audio_track.clone(),
             offset: Default::default(),
+            leading_silence: Default::default(),
+            head: Default::default(),
             resampler: resampler.into(),
             config: self.context.config.clone(),
             format_reader: format_reader.into(),
@@ -139,8 +163,8 @@ impl Ingestion for SymphoniaIngestion {
 
     async fn request_load(&self, request: LoadRequest<Self::Loader>) {
         let _ = self
-            .rt
-            .spawn_blocking(move || {
+            .pool
+            .run(move || {
                 request
                     .loader
                     .load(request.write_guard, request.offset, request.amount)
@@ -148,6 +172,20 @@ impl Ingestion for SymphoniaIngestion {
             .await;
     }
 
+    async fn trim_leading_silence(&self, loader: Arc<Self::Loader>, max: usize) -> usize {
+        let result = self
+            .pool
+            .run(move || loader.trim_leading_silence(max).map_err(|e| e.to_string()))
+            .await
+            .unwrap_or(Ok(0));
+
+        // The silence is played instead, since the source may still load fine from the start
+        result.unwrap_or_else(|e| {
+            warn!("Failed to trim leading silence: {}", e);
+            0
+        })
+    }
+
     fn name() -> String {
         "Symphonia".to_string()
     }
@@ -157,7 +195,12 @@ impl Ingestion for SymphoniaIngestion {
 pub struct Loader {
     config: Config,
     track: Track,
+    /// Where the decoder is in the source, which is ahead of the sink by the leading silence.
     offset: AtomicCell<usize>,
+    /// How many samples at the start of the source were skipped for being silent.
+    leading_silence: AtomicCell<usize>,
+    /// Samples decoded right after the leading silence while looking for its end, written by the first load.
+    head: Mutex<Vec<Sample>>,
     decoder: Mutex<Box<dyn Decoder>>,
     format_reader: Mutex<Box<dyn FormatReader>>,
     resampler: Mutex<DynamicResampler>,
@@ -169,12 +212,22 @@ impl Loader {
 
         match result {
             Ok(result) => {
-                if result.end_reached {
+                if result.end_reached && !result.cancelled {
                     guard.end()
                 }
             }
             Err(e) => {
-                guard.error(format!("{:?}", e));
+                let error = match e.downcast::<SymphoniaError>() {
+                    Ok(e) => ingestion_error(*e, IngestionError::UnsupportedCodec),
+                    Err(e) => IngestionError::Other(e.to_string()),
+                };
+
+                if error.is_retryable() {
+                    guard.retryable_error(error.to_string());
+                } else {
+                    guard.error(error.to_string());
+                }
+
                 return Err(());
             }
         }
@@ -189,27 +242,90 @@ impl Loader {
         amount: usize,
         write_ref: &WriteGuard,
     ) -> Result<LoadResult, Box<dyn Error>> {
+        let head = std::mem::take(&mut *self.head.lock());
+
+        // The decoder is right after the head, so decoding continues from there
+        if offset == 0 && !head.is_empty() {
+            write_ref.write(0, &head);
+
+            if head.len() >= amount {
+                return Ok(LoadResult {
+                    samples: head,
+                    end_reached: false,
+                    cancelled: false,
+                    error: None,
+                });
+            }
+
+            return self.load_into_sink(head.len(), amount - head.len(), write_ref);
+        }
+
+        // The sink starts after the leading silence, so its offsets are behind the source's
+        let source_offset = offset + self.leading_silence.load();
         let old_offset = self.offset.load();
-        let mut seeked_offset = offset;
+        let mut seeked_offset = source_offset;
 
-        if old_offset != offset {
-            seeked_offset = self.seek(offset)?;
+        if old_offset != source_offset {
+            seeked_offset = match self.seek(source_offset)? {
+                Some(seeked_offset) => seeked_offset,
+                None => {
+                    self.fallback_seek(old_offset, source_offset, || write_ref.is_cancelled())?
+                }
+            };
         }
 
-        let result = self.decode_until_filled(amount)?;
+        // If the seek fell short of the offset, decode the difference too
+        let amount = amount + source_offset.saturating_sub(seeked_offset);
+        let mut result = self.decode_until_filled(amount, || write_ref.is_cancelled())?;
+
+        if result.cancelled {
+            return Ok(result);
+        }
 
         // Skip the seek difference, to avoid artifacts.
-        let start = offset.saturating_sub(seeked_offset);
-        let samples = &result.samples[start..];
+        let start = source_offset.saturating_sub(seeked_offset);
+        let samples = result.samples.get(start..).unwrap_or_default();
 
         write_ref.write(offset, samples);
-        self.offset.store(old_offset + result.samples.len());
+
+        // The samples decoded before the error are kept, so loading again continues after them
+        if let Some(error) = result.error.take() {
+            return Err(error);
+        }
 
         Ok(result)
     }
 
+    // Decodes the start of the source to skip the silence there, up to the max amount of samples.
+    // The samples decoded after the silence are kept for the first load, so nothing is decoded twice.
+    fn trim_leading_silence(&self, max: usize) -> Result<usize, Box<dyn Error>> {
+        let channel_count = self.config.channel_count;
+
+        // Decoding a little past the max tells silence that goes on for longer apart from silence that ends right there
+        let result = self.decode_until_filled(max + channel_count, || false)?;
+        let mut samples = result.samples;
+
+        let silent = samples
+            .iter()
+            .position(|s| s.abs() > SILENCE_THRESHOLD)
+            .unwrap_or(samples.len());
+
+        // A source that is silent all the way through is left alone
+        let skipped = match silent == samples.len() && result.end_reached {
+            true => 0,
+            false => silent.min(max),
+        };
+        let skipped = skipped - skipped % channel_count;
+
+        self.leading_silence.store(skipped);
+        *self.head.lock() = samples.split_off(skipped);
+
+        Ok(skipped)
+    }
+
     // Attempts to seek to the given offset.
-    fn seek(&self, offset: usize) -> Result<usize, Box<dyn Error>> {
+    // Returns None if the format or source does not support seeking there.
+    fn seek(&self, offset: usize) -> Result<Option<usize>, Box<dyn Error>> {
         let mut format_reader = self.format_reader.lock();
 
         let samples_in_seconds = self.config.samples_to_seconds(offset);
@@ -218,13 +334,23 @@ impl Loader {
             seconds: samples_in_seconds.trunc() as u64,
         };
 
-        let seeked_to = format_reader.seek(
+        let seeked_to = match format_reader.seek(
             SeekMode::Accurate,
             SeekTo::Time {
                 time,
                 track_id: Some(self.track.id),
             },
-        )?;
+        ) {
+            Ok(seeked_to) => seeked_to,
+            Err(SymphoniaError::SeekError(
+                SeekErrorKind::Unseekable | SeekErrorKind::ForwardOnly,
+            )) => return Ok(None),
+            Err(err) => {
+                // Where the failed seek left the decoder isn't known
+                self.offset.store(usize::MAX);
+                return Err(err.into());
+            }
+        };
 
         let time = self
             .track
@@ -237,14 +363,72 @@ impl Loader {
         let seeked_to_offset = self.config.seconds_to_samples(seeked_to_seconds);
 
         self.offset.store(seeked_to_offset);
-        Ok(seeked_to_offset)
+        Ok(Some(seeked_to_offset))
     }
 
-    // Decode the amount of samples requested.
+    // Returns where decoding continues from when seeking is unsupported.
+    //
+    // Seeking forward is done by decoding from the current position and dropping the samples before the offset,
+    // which is slow but correct. This happens a chunk at a time, so a far seek doesn't hold the whole difference in memory,
+    // and stops early if the load is cancelled. The last chunk is left for the load to decode and skip,
+    // so no samples after the offset are dropped.
+    //
+    // Seeking backward isn't possible, and neither is seeking from an unknown position,
+    // since the audio at the current position would end up at the wrong offset.
+    fn fallback_seek(
+        &self,
+        old_offset: usize,
+        offset: usize,
+        is_cancelled: impl Fn() -> bool,
+    ) -> Result<usize, Box<dyn Error>> {
+        if old_offset == usize::MAX || old_offset >= offset {
+            return Err(format!(
+                "Cannot seek track {} to {}, since the source can't seek back",
+                self.track.id, offset
+            )
+            .into());
+        }
+
+        let chunk = self
+            .config
+            .seconds_to_samples(FALLBACK_SEEK_CHUNK_IN_SECONDS);
+
+        loop {
+            let current = self.offset.load();
+            let difference = offset.saturating_sub(current);
+
+            if difference <= chunk || is_cancelled() {
+                return Ok(current);
+            }
+
+            let result =
+                self.decode_until_filled((difference - chunk).min(chunk), &is_cancelled)?;
+
+            if let Some(error) = result.error {
+                return Err(error);
+            }
+
+            // The load finds out about these by itself, since decoding continues from here
+            if result.end_reached || result.cancelled {
+                return Ok(self.offset.load());
+            }
+        }
+    }
+
+    // Decode the amount of samples requested, advancing the offset by the samples returned.
     // Note: More samples may be returned than requested.
-    fn decode_until_filled(&self, amount: usize) -> Result<LoadResult, Box<dyn Error>> {
+    //
+    // An error after some samples were decoded is returned along with them, so they aren't lost
+    // and the offset stays where the decoder is.
+    fn decode_until_filled(
+        &self,
+        amount: usize,
+        is_cancelled: impl Fn() -> bool,
+    ) -> Result<LoadResult, Box<dyn Error>> {
         let mut last_samples_written_was_zero = false;
         let mut end_reached = false;
+        let mut cancelled = false;
+        let mut error: Option<Box<dyn Error>> = None;
 
         let mut decoder = self.decoder.lock();
         let mut format_reader = self.format_reader.lock();
@@ -256,19 +440,27 @@ impl Loader {
                 break;
             }
 
+            // Decoding a lot at once takes a while, which shouldn't look like the thread is stuck
+            DecodePool::beat();
+
+            // Bail out early, since the samples won't be used.
+            if is_cancelled() {
+                cancelled = true;
+                break;
+            }
+
             let packet = match format_reader.next_packet() {
-                Ok(packet) => Ok(packet),
+                Ok(packet) => packet,
                 // Assume the end of the stream.
-                Err(SymphoniaError::IoError(err)) => {
-                    if err.kind() == IoErrorKind::UnexpectedEof {
-                        end_reached = true;
-                        break;
-                    }
-
-                    return Err(err.into());
+                Err(SymphoniaError::IoError(err)) if err.kind() == IoErrorKind::UnexpectedEof => {
+                    end_reached = true;
+                    break;
+                }
+                Err(err) => {
+                    error = Some(err.into());
+                    break;
                 }
-                Err(e) => Err(e),
-            }?;
+            };
 
             if packet.track_id() != self.track.id {
                 continue;
@@ -276,12 +468,18 @@ impl Loader {
 
             match decoder.decode(&packet) {
                 Ok(decoded) => {
+                    let spec = *decoded.spec();
                     let mut sample_buffer =
-                        SampleBuffer::<Sample>::new(decoded.capacity() as u64, *decoded.spec());
+                        SampleBuffer::<Sample>::new(decoded.capacity() as u64, spec);
 
-                    // Acquire the samples from the decoder.
+                    // Acquire the samples from the decoder, and match them to the channels of the pipeline.
+                    // This has to happen before resampling, which expects the channel count of the pipeline.
                     sample_buffer.copy_interleaved_ref(decoded);
-                    let decoded_samples = sample_buffer.samples();
+                    let decoded_samples = adapt_channels(
+                        sample_buffer.samples(),
+                        spec.channels,
+                        self.config.channel_count,
+                    );
 
                     // Sometimes Symphonia does not err with UnexpectedEof, but instead returns no samples.
                     // This is a workaround to avoid an infinite loop.
@@ -291,39 +489,59 @@ impl Loader {
                     }
 
                     // Copy the samples into the buffer.
-                    samples.extend_from_slice(decoded_samples);
+                    samples.extend_from_slice(&decoded_samples);
                     last_samples_written_was_zero = samples.is_empty();
                 }
-                Err(SymphoniaError::IoError(err)) => {
-                    if err.kind() == IoErrorKind::UnexpectedEof {
-                        end_reached = true;
-                        break;
-                    }
-
-                    return Err(err.into());
+                Err(SymphoniaError::IoError(err)) if err.kind() == IoErrorKind::UnexpectedEof => {
+                    end_reached = true;
+                    break;
                 }
                 Err(SymphoniaError::DecodeError(_)) => {
                     // The packet failed to decode due to invalid data, skip the packet.
                     continue;
                 }
-                // Handle unknown errors.
                 Err(err) => {
-                    println!("unexpected error: {:?}", &err);
-                    return Err(err.into());
+                    error = Some(err.into());
+                    break;
                 }
             }
         }
 
+        if let Some(error) = error.take_if(|_| samples.is_empty()) {
+            return Err(error);
+        }
+
+        // Samples of a cancelled load are still resampled, so the offset stays where the decoder is
         let mut resampler = self.resampler.lock();
         let samples = resampler.process(samples);
 
+        self.offset.store(self.offset.load() + samples.len());
+
         Ok(LoadResult {
             samples,
             end_reached,
+            cancelled,
+            error,
         })
     }
 }
 
+/// Classifies a Symphonia error, using `unsupported` for features Symphonia doesn't support.
+fn ingestion_error(
+    error: SymphoniaError,
+    unsupported: impl FnOnce(String) -> IngestionError,
+) -> IngestionError {
+    match error {
+        SymphoniaError::Unsupported(what) => unsupported(what.to_string()),
+        SymphoniaError::DecodeError(what) => IngestionError::CorruptContainer(what.to_string()),
+        SymphoniaError::IoError(err) if err.kind() == IoErrorKind::UnexpectedEof => {
+            IngestionError::CorruptContainer("Unexpected end of file".to_string())
+        }
+        SymphoniaError::IoError(err) => IngestionError::Io(err.to_string()),
+        err => IngestionError::Other(err.to_string()),
+    }
+}
+
 /// Uninterleaves a chunk of samples into a vector where each sub-vector is a channel.
 fn uninterleave_samples(samples: Vec<Sample>, channels: usize) -> Vec<Vec<Sample>> {
     let mut uninterleaved_samples = vec![];
@@ -355,10 +573,98 @@ fn interleave_samples(samples: Vec<Vec<Sample>>) -> Vec<Sample> {
         .collect()
 }
 
+/// Returns how much a channel contributes to the left and right channel when mixing down to stereo.
+/// Channels on a side are mixed into that side at -3 dB, centre channels into both, and LFE is left out.
+fn stereo_gains(channel: Channels) -> (Sample, Sample) {
+    const SIDE: Sample = std::f32::consts::FRAC_1_SQRT_2;
+
+    let left = Channels::FRONT_LEFT_CENTRE
+        | Channels::REAR_LEFT
+        | Channels::SIDE_LEFT
+        | Channels::TOP_FRONT_LEFT
+        | Channels::TOP_REAR_LEFT
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::FRONT_LEFT_WIDE
+        | Channels::FRONT_LEFT_HIGH;
+
+    let right = Channels::FRONT_RIGHT_CENTRE
+        | Channels::REAR_RIGHT
+        | Channels::SIDE_RIGHT
+        | Channels::TOP_FRONT_RIGHT
+        | Channels::TOP_REAR_RIGHT
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::FRONT_RIGHT_WIDE
+        | Channels::FRONT_RIGHT_HIGH;
+
+    if channel == Channels::FRONT_LEFT {
+        (1., 0.)
+    } else if channel == Channels::FRONT_RIGHT {
+        (0., 1.)
+    } else if left.contains(channel) {
+        (SIDE, 0.)
+    } else if right.contains(channel) {
+        (0., SIDE)
+    } else if (Channels::LFE1 | Channels::LFE2).contains(channel) {
+        (0., 0.)
+    } else {
+        (SIDE, SIDE)
+    }
+}
+
+/// Adapts interleaved samples in the given channel layout to the channel count of the pipeline.
+/// Mono is copied into every channel, and other layouts are mixed down to stereo (or mono),
+/// scaled so the mix can't clip. Layouts that can't be mixed down keep the channels that fit.
+fn adapt_channels(samples: &[Sample], channels: Channels, target: usize) -> Cow<'_, [Sample]> {
+    let source = channels.count();
+
+    if source == target || source == 0 || target == 0 {
+        return Cow::Borrowed(samples);
+    }
+
+    let frames = samples.chunks_exact(source);
+
+    if source == 1 {
+        return frames
+            .flat_map(|frame| std::iter::repeat_n(frame[0], target))
+            .collect();
+    }
+
+    if target > 2 {
+        return frames
+            .flat_map(|frame| (0..target).map(|i| frame[i % source]))
+            .collect();
+    }
+
+    let gains: Vec<_> = channels.iter().map(stereo_gains).collect();
+    let left_sum: Sample = gains.iter().map(|g| g.0).sum();
+    let right_sum: Sample = gains.iter().map(|g| g.1).sum();
+    let scale = left_sum.max(right_sum).max(1.);
+
+    frames
+        .flat_map(|frame| {
+            let (left, right) = frame
+                .iter()
+                .zip(&gains)
+                .fold((0., 0.), |(l, r), (s, g)| (l + s * g.0, r + s * g.1));
+
+            let (left, right) = (left / scale, right / scale);
+
+            match target {
+                1 => vec![(left + right) / 2.],
+                _ => vec![left, right],
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 struct LoadResult {
     samples: Vec<Sample>,
     end_reached: bool,
+    /// The load was stopped early and nothing was written
+    cancelled: bool,
+    /// An error that stopped decoding after some samples were decoded, which are still written
+    error: Option<Box<dyn Error>>,
 }
 
 /// Bridges an async [Loadable] with a synchronous [MediaSource].
@@ -415,13 +721,21 @@ struct DynamicResampler {
 }
 
 impl DynamicResampler {
-    const CHUNK_SIZE: usize = 1024;
+    /// Returns the desired number of frames resampled at once for the given quality.
+    /// Larger chunks use a longer filter, which is more accurate but adds latency.
+    fn chunk_size(quality: ResamplerQuality) -> usize {
+        match quality {
+            ResamplerQuality::Low => 256,
+            ResamplerQuality::Medium => 1024,
+            ResamplerQuality::High => 4096,
+        }
+    }
 
     fn new(source_sample_rate: usize, config: &Config) -> Result<Self, Box<dyn Error>> {
         let resampler = SymphoniaResampler::new(
             source_sample_rate,
             config.sample_rate,
-            Self::CHUNK_SIZE,
+            Self::chunk_size(config.resampler_quality),
             config.channel_count,
         )?;
 
@@ -440,11 +754,14 @@ impl DynamicResampler {
             return samples;
         }
 
+        // The resampler rounds the chunk size to fit the ratio between the sample rates
+        let chunk_size = self.resampler.input_frames_next();
+
         let mut interleaved_result = vec![0f32; 0];
         let chunked_channels: Vec<_> = uninterleave_samples(samples, self.channel_count)
             .into_iter()
             .map(|c| {
-                c.chunks_exact(Self::CHUNK_SIZE)
+                c.chunks_exact(chunk_size)
                     .map(|c| c.to_vec())
                     .collect::<Vec<_>>()
             })
@@ -453,13 +770,10 @@ impl DynamicResampler {
         let chunk_amount = chunked_channels[0].len();
 
         for chunk_index in 0..chunk_amount {
-            let mut chunks = vec![];
-
-            // ok clippy
-            (0..self.channel_count).for_each(|channel_index| {
-                let channel_chunk = &chunked_channels[channel_index][chunk_index];
-                chunks.push(channel_chunk.to_owned());
-            });
+            let chunks: Vec<_> = chunked_channels
+                .iter()
+                .map(|channel| channel[chunk_index].to_owned())
+                .collect();
 
             let resampled = self
                 .resampler
@@ -488,6 +802,500 @@ impl DynamicResampler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+    use turntable_core::{Sink, SinkLoadState};
+
+    /// An in-memory wave file that cancels the load of a sink when read from, once armed.
+    #[derive(Clone)]
+    struct CancellingLoadable {
+        bytes: Arc<Vec<u8>>,
+        position: Arc<Mutex<usize>>,
+        cancels: Arc<Mutex<Option<Arc<Sink>>>>,
+        /// A read from this position fails once, like a network error.
+        /// Reads stop right before it, so no partially read packet is lost.
+        fails_at: Arc<Mutex<Option<usize>>>,
+        seekable: bool,
+    }
+
+    impl CancellingLoadable {
+        fn new(seconds: usize) -> Self {
+            Self::from_samples((0..seconds * 44100 * 2).map(|i| (i % 1000) as i16 + 1))
+        }
+
+        /// Creates a 44.1kHz stereo wave file of the interleaved samples
+        fn from_samples(samples: impl Iterator<Item = i16>) -> Self {
+            Self::from_channels(2, samples)
+        }
+
+        /// Creates a 44.1kHz wave file of the interleaved samples, with the given amount of channels
+        fn from_channels(channels: u16, samples: impl Iterator<Item = i16>) -> Self {
+            let data: Vec<u8> = samples.flat_map(|s| s.to_le_bytes()).collect();
+            let block_align = channels * 2;
+
+            let mut bytes = vec![];
+            bytes.extend_from_slice(b"RIFF");
+            bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(b"WAVEfmt ");
+            bytes.extend_from_slice(&16u32.to_le_bytes());
+            bytes.extend_from_slice(&1u16.to_le_bytes());
+            bytes.extend_from_slice(&channels.to_le_bytes());
+            bytes.extend_from_slice(&44100u32.to_le_bytes());
+            bytes.extend_from_slice(&(44100 * block_align as u32).to_le_bytes());
+            bytes.extend_from_slice(&block_align.to_le_bytes());
+            bytes.extend_from_slice(&16u16.to_le_bytes());
+            bytes.extend_from_slice(b"data");
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&data);
+
+            Self::from_bytes(bytes)
+        }
+
+        fn from_bytes(bytes: Vec<u8>) -> Self {
+            Self {
+                bytes: bytes.into(),
+                position: Default::default(),
+                cancels: Default::default(),
+                fails_at: Default::default(),
+                seekable: true,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Loadable for CancellingLoadable {
+        async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
+            if let Some(sink) = self.cancels.lock().as_ref() {
+                sink.cancel_load();
+            }
+
+            let mut position = self.position.lock();
+            let mut fails_at = self.fails_at.lock();
+
+            if *fails_at == Some(*position) {
+                *fails_at = None;
+                return Err("Connection reset".into());
+            }
+
+            let end = fails_at
+                .filter(|f| *f > *position)
+                .unwrap_or(self.bytes.len());
+            let remaining = &self.bytes[*position..end];
+            let amount = remaining.len().min(buf.len());
+
+            buf[..amount].copy_from_slice(&remaining[..amount]);
+            *position += amount;
+
+            if amount == 0 {
+                return Ok(ReadResult::End(0));
+            }
+
+            Ok(ReadResult::More(amount))
+        }
+
+        async fn length(&self) -> Option<LoaderLength> {
+            Some(LoaderLength::Bytes(self.bytes.len()))
+        }
+
+        async fn seekable(&self) -> bool {
+            self.seekable
+        }
+
+        async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
+            let mut position = self.position.lock();
+
+            *position = match seek {
+                SeekFrom::Start(offset) => offset as usize,
+                SeekFrom::End(offset) => (self.bytes.len() as i64 + offset) as usize,
+                SeekFrom::Current(offset) => (*position as i64 + offset) as usize,
+            };
+
+            Ok(*position)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancelled_load() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+
+        let sink = Arc::new(Sink::with_activation(&context, None));
+        context.sinks.insert(sink.id, sink.clone());
+
+        let loadable = CancellingLoadable::new(10);
+        let ingest = ingestion.ingest(loadable.clone()).await.unwrap();
+        let loader = Arc::new(ingest.loader);
+
+        // Cancel as soon as the load starts reading
+        *loadable.cancels.lock() = Some(sink.clone());
+
+        ingestion
+            .request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader: loader.clone(),
+                offset: 0,
+                amount: context.config.seconds_to_samples(10.),
+            })
+            .await;
+
+        let mut buf = vec![0.; 16];
+        assert_eq!(sink.read(0, &mut buf).amount, 0, "nothing was written");
+        assert_eq!(sink.load_state(), SinkLoadState::Idle);
+        assert_eq!(sink.expected_length(), None, "the end was not reached");
+
+        // A later load is unaffected by the cancelled one
+        *loadable.cancels.lock() = None;
+
+        ingestion
+            .request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader,
+                offset: 0,
+                amount: buf.len(),
+            })
+            .await;
+
+        assert_eq!(sink.read(0, &mut buf).amount, buf.len());
+        assert_eq!(buf[0], 1. / 32768.);
+        assert_eq!(buf[1], 2. / 32768.);
+    }
+
+    /// Ingests near-silent noise followed by a second of audio, trims it, and loads the start
+    async fn trim_and_load(
+        ingestion: &SymphoniaIngestion,
+        context: &PipelineContext,
+        silence: usize,
+    ) -> (usize, Arc<Sink>) {
+        let loadable =
+            CancellingLoadable::from_samples((0..silence + 88200).map(|i| match i < silence {
+                true => (i % 2) as i16 * 16,
+                false => (i % 1000) as i16 + 1,
+            }));
+
+        let loader = Arc::new(ingestion.ingest(loadable).await.unwrap().loader);
+        let max = context.config.max_leading_silence_in_samples();
+        let skipped = ingestion.trim_leading_silence(loader.clone(), max).await;
+
+        let sink = Arc::new(Sink::with_activation(context, None));
+        context.sinks.insert(sink.id, sink.clone());
+
+        ingestion
+            .request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader,
+                offset: 0,
+                amount: 1024,
+            })
+            .await;
+
+        (skipped, sink)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_leading_silence_is_trimmed() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+        let max = context.config.max_leading_silence_in_samples();
+        let mut buf = vec![0.; 2];
+
+        let silence = context.config.seconds_to_samples(1.);
+        let (skipped, sink) = trim_and_load(&ingestion, &context, silence).await;
+
+        assert_eq!(skipped, silence);
+        assert_eq!(sink.read(0, &mut buf).amount, buf.len());
+        assert_eq!(
+            buf[0],
+            (silence % 1000 + 1) as f32 / 32768.,
+            "the sink starts with the audio"
+        );
+
+        // Silence longer than the max is likely part of the track, so only the max is skipped
+        let silence = max + context.config.seconds_to_samples(1.);
+        let (skipped, sink) = trim_and_load(&ingestion, &context, silence).await;
+
+        assert_eq!(skipped, max);
+        assert_eq!(sink.read(0, &mut buf).amount, buf.len());
+        assert!(buf.iter().all(|s| s.abs() < SILENCE_THRESHOLD));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unseekable_source() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+
+        let sink = Arc::new(Sink::with_activation(&context, None));
+        context.sinks.insert(sink.id, sink.clone());
+
+        let loadable = CancellingLoadable {
+            seekable: false,
+            ..CancellingLoadable::new(3)
+        };
+
+        let ingest = ingestion.ingest(loadable).await.unwrap();
+        let loader = Arc::new(ingest.loader);
+
+        let load = |offset: usize| {
+            ingestion.request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader: loader.clone(),
+                offset,
+                amount: 1024,
+            })
+        };
+
+        // Seeking forward decodes up to the offset
+        let offset = context.config.seconds_to_samples(2.);
+        load(offset).await;
+
+        let mut buf = vec![0.; 2];
+        assert_eq!(sink.read(offset, &mut buf).amount, buf.len());
+        assert_eq!(buf[0], (offset % 1000 + 1) as f32 / 32768.);
+        assert_eq!(buf[1], ((offset + 1) % 1000 + 1) as f32 / 32768.);
+
+        // Seeking backward is not possible, and audio from elsewhere isn't written in its place
+        load(0).await;
+
+        assert!(matches!(sink.load_state(), SinkLoadState::Error(_)));
+        assert_eq!(sink.read(0, &mut buf).amount, 0, "nothing was written");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unseekable_seek_is_decoded_in_chunks() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+
+        let loadable = CancellingLoadable {
+            seekable: false,
+            ..CancellingLoadable::new(10)
+        };
+
+        let loader = Arc::new(ingestion.ingest(loadable).await.unwrap().loader);
+        let chunk = context
+            .config
+            .seconds_to_samples(FALLBACK_SEEK_CHUNK_IN_SECONDS);
+        let offset = context.config.seconds_to_samples(8.);
+
+        // The offset moves once per decode, so the steps between offsets are what was held at once
+        let (seeked_offset, offsets) = ingestion
+            .pool
+            .run(move || {
+                let offsets = Mutex::new(vec![]);
+                let seeked_offset = loader
+                    .fallback_seek(0, offset, || {
+                        offsets.lock().push(loader.offset.load());
+                        false
+                    })
+                    .unwrap();
+
+                (seeked_offset, offsets.into_inner())
+            })
+            .await
+            .unwrap();
+
+        let largest_step = offsets.windows(2).map(|w| w[1] - w[0]).max().unwrap();
+
+        assert!(largest_step > 0 && largest_step < chunk * 2);
+        assert!(seeked_offset <= offset && offset - seeked_offset <= chunk);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_continues_from_last_good_offset() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+
+        let sink = Arc::new(Sink::with_activation(&context, None));
+        context.sinks.insert(sink.id, sink.clone());
+
+        let loadable = CancellingLoadable {
+            seekable: false,
+            ..CancellingLoadable::new(3)
+        };
+
+        // Symphonia reads wave files in packets of 1152 frames, after the 44 byte header.
+        // The read fails a few packets in, after a load already decoded some of them.
+        let packet = 1152 * 4;
+        *loadable.fails_at.lock() = Some(44 + 3 * packet);
+
+        let loader = Arc::new(ingestion.ingest(loadable).await.unwrap().loader);
+        let amount = context.config.seconds_to_samples(1.);
+        let mut buf = vec![0.; amount];
+
+        let load = |offset: usize| {
+            ingestion.request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader: loader.clone(),
+                offset,
+                amount,
+            })
+        };
+
+        load(0).await;
+
+        let loaded = sink.read(0, &mut buf).amount;
+        assert!(loaded > 0, "the packets before the error are written");
+        assert!(loaded < amount, "the error stops the load");
+
+        // Loading again continues where the audio ended up, which is the same place in the source
+        load(loaded).await;
+
+        assert_eq!(sink.read(0, &mut buf).amount, amount);
+        assert!(buf
+            .iter()
+            .enumerate()
+            .all(|(i, s)| *s == (i % 1000 + 1) as f32 / 32768.));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingestion_errors() {
+        let context = PipelineContext::default();
+        let ingestion = SymphoniaIngestion::new(&context);
+
+        // The signature and header chunk of a PNG image
+        let mut image = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        image.extend_from_slice(&[0; 17]);
+
+        let error = ingestion
+            .ingest(CancellingLoadable::from_bytes(image))
+            .await
+            .err()
+            .expect("image is not ingested");
+
+        assert!(matches!(error, IngestionError::UnsupportedFormat(_)));
+        assert!(!error.is_retryable());
+
+        // Cut off in the middle of the format chunk
+        let mut truncated = CancellingLoadable::new(1).bytes.to_vec();
+        truncated.truncate(30);
+
+        let error = ingestion
+            .ingest(CancellingLoadable::from_bytes(truncated))
+            .await
+            .err()
+            .expect("truncated file is not ingested");
+
+        assert!(matches!(error, IngestionError::CorruptContainer(_)));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn test_decoding_with_busy_blocking_pool() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .max_blocking_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            // Occupy every blocking thread of the runtime, with more work waiting
+            for _ in 0..8 {
+                tokio::task::spawn_blocking(|| std::thread::sleep(Duration::from_secs(2)));
+            }
+
+            let context = PipelineContext::default();
+            let ingestion = SymphoniaIngestion::new(&context);
+
+            let sink = Arc::new(Sink::with_activation(&context, None));
+            context.sinks.insert(sink.id, sink.clone());
+
+            let started = Instant::now();
+            let ingest = ingestion.ingest(CancellingLoadable::new(3)).await.unwrap();
+
+            ingestion
+                .request_load(LoadRequest {
+                    write_guard: sink.write(),
+                    loader: Arc::new(ingest.loader),
+                    offset: 0,
+                    amount: context.config.seconds_to_samples(3.),
+                })
+                .await;
+
+            assert!(
+                started.elapsed() < Duration::from_secs(1),
+                "decoding does not wait for the blocking pool"
+            );
+
+            let mut buf = vec![0.; 2];
+            assert_eq!(sink.read(0, &mut buf).amount, buf.len());
+        });
+
+        rt.shutdown_background();
+    }
+
+    /// Ingests the wave file and loads the first frames of it into a sink
+    async fn load_start(context: &PipelineContext, loadable: CancellingLoadable) -> Vec<Sample> {
+        let ingestion = SymphoniaIngestion::new(context);
+        let loader = ingestion.ingest(loadable).await.unwrap().loader;
+
+        let sink = Arc::new(Sink::with_activation(context, None));
+        context.sinks.insert(sink.id, sink.clone());
+
+        ingestion
+            .request_load(LoadRequest {
+                write_guard: sink.write(),
+                loader: loader.into(),
+                offset: 0,
+                amount: 1024,
+            })
+            .await;
+
+        let mut buf = vec![0.; 1024];
+        assert_eq!(sink.read(0, &mut buf).amount, buf.len());
+
+        buf
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_channel_adaptation() {
+        let context = PipelineContext::default();
+        assert_eq!(context.config.channel_count, 2);
+
+        // Mono is duplicated into both channels
+        let mono = CancellingLoadable::from_channels(1, (0..44100).map(|i| (i % 1000) as i16 + 1));
+        let output = load_start(&context, mono).await;
+
+        for (i, frame) in output.chunks_exact(2).enumerate() {
+            let expected = ((i % 1000) + 1) as f32 / 32768.;
+            assert_eq!(frame, [expected, expected]);
+        }
+
+        // 5.1 in the usual order of front left, front right, centre, LFE, rear left and rear right
+        let surround = [8192, 0, 4096, 16384, 0, 4096];
+        let surround_loadable =
+            CancellingLoadable::from_channels(6, surround.into_iter().cycle().take(44100 * 6));
+        let output = load_start(&context, surround_loadable).await;
+
+        let [left, centre, rear] = [8192., 4096., 4096.].map(|s: f32| s / 32768.);
+        let side = std::f32::consts::FRAC_1_SQRT_2;
+        let scale = 1. + side * 2.;
+
+        let expected_left = (left + centre * side) / scale;
+        let expected_right = (centre * side + rear * side) / scale;
+
+        for frame in output.chunks_exact(2) {
+            assert!((frame[0] - expected_left).abs() < 1e-6, "{frame:?}");
+            assert!((frame[1] - expected_right).abs() < 1e-6, "{frame:?}");
+        }
+    }
+
+    #[test]
+    fn test_adapt_channels() {
+        let stereo = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+        let samples = [0.5, -0.5, 0.25, 0.25];
+
+        assert_eq!(adapt_channels(&samples, stereo, 2), samples.as_slice());
+        assert_eq!(adapt_channels(&samples, stereo, 1), [0., 0.25].as_slice());
+        assert_eq!(
+            adapt_channels(&[0.5, 0.25], Channels::FRONT_LEFT, 2),
+            [0.5, 0.5, 0.25, 0.25].as_slice()
+        );
+
+        // A full scale signal in every channel doesn't clip when mixed down
+        let surround = Channels::from_bits(0b11_1111).unwrap();
+        let mixed = adapt_channels(&[1.; 6], surround, 2);
+        assert!(mixed.iter().all(|s| *s <= 1.));
+    }
 
     #[test]
     fn test_uninterleave_samples() {
@@ -504,4 +1312,26 @@ mod tests {
 
         assert_eq!(result, vec![1., 2., 3., 4., 5., 6.]);
     }
+
+    #[test]
+    fn test_resampler_quality() {
+        for quality in [ResamplerQuality::Low, ResamplerQuality::High] {
+            let config = Config {
+                resampler_quality: quality,
+                ..Default::default()
+            };
+
+            let mut resampler = DynamicResampler::new(48000, &config).unwrap();
+            let chunk_in = resampler.resampler.input_frames_next();
+            let chunk_out = resampler.resampler.output_frames_next();
+            let delay = resampler.resampler.output_delay();
+
+            // One second of stereo audio
+            let input: Vec<Sample> = (0..48000 * 2).map(|i| (i % 100) as f32 / 100.).collect();
+            let output = resampler.process(input);
+
+            let expected_frames = (48000 / chunk_in) * chunk_out - delay;
+            assert_eq!(output.len(), expected_frames * 2, "{:?}", quality);
+        }
+    }
 }
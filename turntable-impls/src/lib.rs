@@ -1,7 +1,9 @@
 mod encoders;
 mod ingestions;
 mod loadables;
+mod queues;
 
 pub use encoders::*;
 pub use ingestions::*;
 pub use loadables::*;
+pub use queues::*;
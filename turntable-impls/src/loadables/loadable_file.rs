@@ -1,4 +1,9 @@
-use std::{error::Error, io::SeekFrom};
+use std::{
+    error::Error,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
 
 use async_trait::async_trait;
 use tokio::{
@@ -7,21 +12,33 @@ use tokio::{
     sync::Mutex,
 };
 
-use turntable_core::{Loadable, LoaderLength, ReadResult};
+use turntable_core::{CacheKey, Loadable, LoaderLength, ReadResult};
 
 /// Implements [Loadable] for a tokio [File]
-pub struct LoadableFile(Mutex<File>);
+pub struct LoadableFile {
+    file: Mutex<File>,
+    path: Option<PathBuf>,
+}
 
 impl LoadableFile {
     pub fn new(file: File) -> Self {
-        Self(Mutex::new(file))
+        Self {
+            file: Mutex::new(file),
+            path: None,
+        }
+    }
+
+    /// Allows the decoded samples of the file to be cached, keyed by its path and modification time.
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
     }
 }
 
 #[async_trait]
 impl Loadable for LoadableFile {
     async fn read(&self, buf: &mut [u8]) -> Result<ReadResult, Box<dyn Error>> {
-        let mut file = self.0.lock().await;
+        let mut file = self.file.lock().await;
         let result = file.read(buf).await?;
 
         if result == 0 {
@@ -32,7 +49,7 @@ impl Loadable for LoadableFile {
     }
 
     async fn length(&self) -> Option<LoaderLength> {
-        let file = self.0.lock().await;
+        let file = self.file.lock().await;
 
         file.metadata()
             .await
@@ -42,9 +59,57 @@ impl Loadable for LoadableFile {
     }
 
     async fn seek(&self, seek: SeekFrom) -> Result<usize, Box<dyn Error>> {
-        let mut file = self.0.lock().await;
+        let mut file = self.file.lock().await;
         let result = file.seek(seek).await?;
 
         Ok(result as usize)
     }
+
+    async fn cache_key(&self) -> Option<CacheKey> {
+        let path = self.path.as_ref()?;
+        let file = self.file.lock().await;
+
+        let modified = file.metadata().await.ok()?.modified().ok()?;
+        let version = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+
+        Some(CacheKey {
+            source: path.display().to_string(),
+            version: version.to_string(),
+        })
+    }
+
+    fn description(&self) -> Option<String> {
+        self.path.as_ref().map(|p| p.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use tokio::fs::File;
+    use turntable_core::Loadable;
+
+    use super::LoadableFile;
+
+    #[tokio::test]
+    async fn test_cache_key() {
+        let path = std::env::temp_dir().join("turntable_loadable_file_cache_key");
+        std::fs::write(&path, [0; 4]).unwrap();
+
+        let open = || async { LoadableFile::new(File::open(&path).await.unwrap()) };
+
+        assert!(open().await.cache_key().await.is_none(), "opt-in");
+
+        let key = open().await.with_path(&path).cache_key().await.unwrap();
+        assert_eq!(key.source, path.display().to_string());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+
+        let changed = open().await.with_path(&path).cache_key().await.unwrap();
+        assert_eq!(changed.source, key.source);
+        assert_ne!(changed.version, key.version, "modification changes the key");
+    }
 }
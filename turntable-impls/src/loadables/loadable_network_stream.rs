@@ -1,9 +1,12 @@
-use std::{error::Error, io::SeekFrom};
+use std::{collections::HashMap, error::Error, io::SeekFrom};
 
 use async_trait::async_trait;
 use crossbeam::atomic::AtomicCell;
 use parking_lot::Mutex;
-use reqwest::Client;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client,
+};
 use turntable_core::{assign_slice, Loadable, LoaderLength, ReadResult};
 
 /// A loadable that reads from a network stream.
@@ -25,12 +28,15 @@ impl LoadableNetworkStream {
     const MAX_CHUNK_SIZE: usize = 3_000_000; // 3MB
     const MIN_CHUNK_SIZE: usize = 500_000; // 500KB
 
+    /// The user agent sent unless another one is given with [LoadableNetworkStream::with_headers]
+    pub const DEFAULT_USER_AGENT: &'static str = concat!("turntable/", env!("CARGO_PKG_VERSION"));
+
     pub fn new<S>(url: S) -> Self
     where
         S: Into<String>,
     {
         let url = url.into();
-        let client = Client::new();
+        let client = Self::client(HeaderMap::new());
 
         Self {
             url,
@@ -44,6 +50,32 @@ impl LoadableNetworkStream {
         }
     }
 
+    /// Sends the given headers with every request, for upstreams that require them.
+    /// Headers with an invalid name or value are ignored.
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        let headers = headers
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+                let value = HeaderValue::from_str(&value).ok()?;
+
+                Some((name, value))
+            })
+            .collect();
+
+        self.client = Self::client(headers);
+        self
+    }
+
+    fn client(headers: HeaderMap) -> Client {
+        // The given headers are applied last, so they can override the user agent
+        Client::builder()
+            .user_agent(Self::DEFAULT_USER_AGENT)
+            .default_headers(headers)
+            .build()
+            .expect("client is built")
+    }
+
     async fn setup(&self) -> Result<(), Box<dyn Error>> {
         let response = self.client.head(&self.url).send().await?;
         let status = response.status();
@@ -212,4 +244,121 @@ impl Loadable for LoadableNetworkStream {
 
         Ok(safe_new_offset)
     }
+
+    fn description(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use parking_lot::Mutex;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use turntable_core::{Loadable, ReadResult};
+
+    use super::LoadableNetworkStream;
+
+    const BODY: &[u8] = b"turntable";
+
+    /// Serves [BODY] only to requests with the `X-Token: secret` header, and records the requests it receives.
+    async fn serve() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/audio", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<String>>> = Default::default();
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    if socket.read(&mut byte).await.unwrap() == 0 {
+                        break;
+                    }
+                    request.push(byte[0]);
+                }
+
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let authorized = request.contains("x-token: secret");
+                let is_head = request.starts_with("head");
+                received.lock().push(request);
+
+                let response = if authorized {
+                    let mut response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        BODY.len()
+                    )
+                    .into_bytes();
+
+                    if !is_head {
+                        response.extend_from_slice(BODY);
+                    }
+
+                    response
+                } else {
+                    b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_vec()
+                };
+
+                socket.write_all(&response).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_required_header_is_sent() {
+        let (url, requests) = serve().await;
+
+        let stream = LoadableNetworkStream::new(&url);
+        assert!(
+            stream.activate().await.is_err(),
+            "upstream rejects requests without the header"
+        );
+
+        let headers = HashMap::from([("X-Token".to_string(), "secret".to_string())]);
+        let stream = LoadableNetworkStream::new(&url).with_headers(headers);
+        stream
+            .activate()
+            .await
+            .expect("upstream accepts the request");
+
+        let mut buf = [0; 32];
+        let result = stream.read(&mut buf).await.unwrap();
+        assert!(matches!(result, ReadResult::End(n) if n == BODY.len()));
+        assert_eq!(&buf[..BODY.len()], BODY);
+
+        let requests = requests.lock();
+        let user_agent = format!(
+            "user-agent: {}",
+            LoadableNetworkStream::DEFAULT_USER_AGENT.to_lowercase()
+        );
+
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.contains(&user_agent)));
+        assert!(requests[1..].iter().all(|r| r.contains("x-token: secret")));
+    }
+
+    #[tokio::test]
+    async fn test_user_agent_can_be_overridden() {
+        let (url, requests) = serve().await;
+
+        let headers = HashMap::from([
+            ("X-Token".to_string(), "secret".to_string()),
+            ("User-Agent".to_string(), "custom".to_string()),
+        ]);
+
+        let stream = LoadableNetworkStream::new(&url).with_headers(headers);
+        stream.activate().await.unwrap();
+
+        assert!(requests.lock()[0].contains("user-agent: custom"));
+    }
 }
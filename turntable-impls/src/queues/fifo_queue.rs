@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+use turntable_core::{BoxedQueueItem, Queue, QueueItem, QueueNotifier};
+
+/// A queue that plays items in the order they were added.
+///
+/// This is meant for using the pipeline on its own, where the queue of a collab room isn't needed.
+/// Create it by passing [FifoQueue::new] to [turntable_core::Pipeline::create_queue], which provides the notifier.
+pub struct FifoQueue<T> {
+    notifier: QueueNotifier,
+    state: Mutex<FifoQueueState<T>>,
+}
+
+struct FifoQueueState<T> {
+    /// The item at the front is the one playing, or about to play
+    items: VecDeque<T>,
+    /// The items that were played, the last one being the most recent
+    history: Vec<T>,
+}
+
+impl<T> FifoQueue<T>
+where
+    T: QueueItem + Clone,
+{
+    pub fn new(notifier: QueueNotifier) -> Self {
+        Self {
+            notifier,
+            state: Mutex::new(FifoQueueState {
+                items: VecDeque::new(),
+                history: Vec::new(),
+            }),
+        }
+    }
+
+    /// Adds an item to the end of the queue.
+    pub fn push(&self, item: T) {
+        self.state.lock().items.push_back(item);
+        self.notifier.notify();
+    }
+
+    /// Removes the item with the given id, returning true if it was in the queue.
+    /// Removing the item at the front stops it, and plays the next one.
+    pub fn remove(&self, item_id: &str) -> bool {
+        let mut state = self.state.lock();
+        let len = state.items.len();
+
+        state.items.retain(|i| i.item_id() != item_id);
+        let removed = state.items.len() != len;
+
+        if removed {
+            self.notifier.notify();
+        }
+
+        removed
+    }
+
+    /// Removes all items and the history.
+    pub fn clear(&self) {
+        let mut state = self.state.lock();
+
+        state.items.clear();
+        state.history.clear();
+
+        self.notifier.notify();
+    }
+
+    /// Returns the items that are playing or will play, in order.
+    pub fn items(&self) -> Vec<T> {
+        self.state.lock().items.iter().cloned().collect()
+    }
+
+    /// Returns the items that were played, the last one being the most recent.
+    pub fn history(&self) -> Vec<T> {
+        self.state.lock().history.clone()
+    }
+}
+
+impl<T> Queue for FifoQueue<T>
+where
+    T: QueueItem + Clone,
+{
+    fn peek(&self) -> Vec<BoxedQueueItem> {
+        self.state
+            .lock()
+            .items
+            .iter()
+            .map(|i| BoxedQueueItem::new(i.clone()))
+            .collect()
+    }
+
+    fn next(&self) {
+        let mut state = self.state.lock();
+
+        if let Some(item) = state.items.pop_front() {
+            state.history.push(item);
+        }
+
+        self.notifier.notify();
+    }
+
+    fn previous(&self) {
+        let mut state = self.state.lock();
+
+        if let Some(item) = state.history.pop() {
+            state.items.push_front(item);
+        }
+
+        self.notifier.notify();
+    }
+
+    fn reset(&self) {
+        let mut state = self.state.lock();
+
+        // The most recent item is last, so it has to be put back first
+        while let Some(item) = state.history.pop() {
+            state.items.push_front(item);
+        }
+
+        self.notifier.notify();
+    }
+
+    fn skip(&self, id: &str) {
+        self.state.lock().items.retain(|i| i.item_id() != id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turntable_core::{
+        FakeInput, FakeItem, FakePattern, PipelineContext, PlayerId, Queue, QueueItem,
+        QueueNotifier,
+    };
+
+    use super::FifoQueue;
+
+    fn ids(items: Vec<FakeItem>) -> Vec<String> {
+        items.into_iter().map(|i| i.item_id()).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let context = PipelineContext::default();
+        let queue = FifoQueue::new(QueueNotifier::new(&context, PlayerId::new()));
+
+        for id in ["a", "b", "c", "d"] {
+            queue.push(FakeItem::new(FakeInput::new(FakePattern::Ramp, 10)).with_id(id));
+        }
+
+        assert_eq!(queue.peek().len(), 4);
+
+        queue.next();
+        queue.next();
+        assert_eq!(ids(queue.items()), vec!["c", "d"]);
+        assert_eq!(ids(queue.history()), vec!["a", "b"]);
+
+        queue.previous();
+        assert_eq!(ids(queue.items()), vec!["b", "c", "d"]);
+
+        queue.next();
+        queue.reset();
+        assert_eq!(
+            ids(queue.items()),
+            vec!["a", "b", "c", "d"],
+            "order is kept"
+        );
+
+        assert!(queue.remove("b"));
+        assert!(!queue.remove("b"));
+        queue.skip("c");
+        assert_eq!(ids(queue.items()), vec!["a", "d"]);
+
+        queue.clear();
+        assert!(queue.items().is_empty() && queue.history().is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+mod fifo_queue;
+
+pub use fifo_queue::*;
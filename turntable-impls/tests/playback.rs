@@ -0,0 +1,394 @@
+//! End-to-end smoke test of the pipeline, from file ingestion to an encoded consumer.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::atomic::AtomicCell;
+use parking_lot::Mutex;
+use turntable_core::{
+    ActivationIntrospection, BoxedLoadable, BoxedQueueItem, Config, FakeIngestion, FakeInput,
+    FakeItem, FakePattern, Introspect, Loadable, Pipeline, Queue, QueueItem, QueueNotifier, SinkId,
+};
+use turntable_impls::{FifoQueue, LoadableFile, RawEncoder, SymphoniaIngestion};
+
+/// 0.5 seconds of 44.1kHz stereo 16-bit PCM, containing no silent samples.
+const ASSET: &str = "tests/assets/smoke.wav";
+
+#[derive(Clone)]
+struct FileItem {
+    path: PathBuf,
+    sink_id: Arc<AtomicCell<Option<SinkId>>>,
+}
+
+impl QueueItem for FileItem {
+    fn length(&self) -> Option<f32> {
+        None
+    }
+
+    fn register_sink(&self, sink_id: SinkId) {
+        self.sink_id.store(Some(sink_id))
+    }
+
+    fn sink_id(&self) -> Option<SinkId> {
+        self.sink_id.load()
+    }
+
+    fn item_id(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn loadable(&self) -> BoxedLoadable {
+        let file = std::fs::File::open(&self.path).expect("asset exists");
+        LoadableFile::new(tokio::fs::File::from_std(file)).boxed()
+    }
+}
+
+/// A queue that plays a single item once.
+struct OnceQueue {
+    notifier: QueueNotifier,
+    item: Mutex<Option<FileItem>>,
+    finished: AtomicBool,
+}
+
+impl Queue for OnceQueue {
+    fn peek(&self) -> Vec<BoxedQueueItem> {
+        self.item
+            .lock()
+            .iter()
+            .map(|i| BoxedQueueItem::new(i.clone()))
+            .collect()
+    }
+
+    fn next(&self) {
+        self.item.lock().take();
+        self.finished.store(true, Ordering::SeqCst);
+        self.notifier.notify();
+    }
+
+    fn previous(&self) {}
+
+    fn reset(&self) {}
+
+    fn skip(&self, _id: &str) {
+        self.item.lock().take();
+    }
+}
+
+/// Reads the samples of a 16-bit PCM wave file the same way Symphonia converts them.
+fn expected_samples(path: &Path) -> Vec<f32> {
+    let bytes = std::fs::read(path).expect("asset exists");
+    let data_start = bytes
+        .windows(4)
+        .position(|w| w == b"data")
+        .expect("asset has a data chunk")
+        + 8;
+
+    bytes[data_start..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.)
+        .collect()
+}
+
+fn checksum(samples: &[f32]) -> u64 {
+    // FNV-1a over the bit patterns, so any reordering or corruption is caught
+    samples.iter().fold(0xcbf29ce484222325, |hash, s| {
+        (hash ^ s.to_bits() as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_file_playback_is_consumed_in_order() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(ASSET);
+    let expected = expected_samples(&path);
+
+    let pipeline: Pipeline<SymphoniaIngestion> = Pipeline::new(Config::default());
+    let player = pipeline.create_player();
+    let consumer = pipeline.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+    let queue = pipeline.create_queue(player.id, |notifier| OnceQueue {
+        notifier,
+        item: Mutex::new(Some(FileItem {
+            path: path.clone(),
+            sink_id: Default::default(),
+        })),
+        finished: AtomicBool::new(false),
+    });
+
+    queue.notifier.notify();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !queue.finished.load(Ordering::SeqCst) {
+        assert!(Instant::now() < deadline, "playback did not finish in time");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // Let the output thread flush the last processed buffer
+    thread::sleep(Duration::from_millis(300));
+
+    let bytes = consumer.bytes().expect("consumer received data");
+    let consumed: Vec<_> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+
+    // The player outputs silence before and after the track
+    let played: Vec<_> = consumed.into_iter().filter(|s| *s != 0.).collect();
+
+    assert_eq!(played.len(), expected.len());
+    assert_eq!(checksum(&played), checksum(&expected));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_fifo_queue_plays_items_in_order() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(ASSET);
+    let expected = expected_samples(&path);
+
+    let pipeline: Pipeline<SymphoniaIngestion> = Pipeline::new(Config::default());
+    let player = pipeline.create_player();
+    let consumer = pipeline.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+    let queue = pipeline.create_queue(player.id, FifoQueue::new);
+
+    // The same file twice, but as separate items with their own sinks
+    for _ in 0..2 {
+        queue.push(FileItem {
+            path: path.clone(),
+            sink_id: Default::default(),
+        });
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while queue.history().len() < 2 {
+        assert!(Instant::now() < deadline, "playback did not finish in time");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    assert!(queue.items().is_empty(), "both items were played");
+
+    // Let the output thread flush the last processed buffer
+    thread::sleep(Duration::from_millis(300));
+
+    let bytes = consumer.bytes().expect("consumer received data");
+    let played: Vec<_> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .filter(|s| *s != 0.)
+        .collect();
+
+    let expected_twice = [expected.clone(), expected].concat();
+
+    assert_eq!(played.len(), expected_twice.len());
+    assert_eq!(checksum(&played), checksum(&expected_twice));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_fake_playback_matches_pattern() {
+    let config = Config {
+        limiter_ceiling_in_db: None,
+        ..Default::default()
+    };
+
+    // Neither pattern contains silence, so it can be told apart from the silence around them
+    let inputs = [
+        FakeInput::new(FakePattern::Ramp, config.seconds_to_samples(0.3)),
+        FakeInput::new(FakePattern::Constant(-0.5), config.seconds_to_samples(0.2)),
+    ];
+    let expected: Vec<_> = inputs.iter().flat_map(|i| i.samples(&config)).collect();
+
+    let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config);
+    let player = pipeline.create_player();
+    let consumer = pipeline.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+    let queue = pipeline.create_queue(player.id, FifoQueue::new);
+
+    for (index, input) in inputs.into_iter().enumerate() {
+        queue.push(FakeItem::new(input).with_id(&index.to_string()));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while queue.history().len() < 2 {
+        assert!(Instant::now() < deadline, "playback did not finish in time");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // Let the output thread flush the last processed buffer
+    thread::sleep(Duration::from_millis(300));
+
+    let bytes = consumer.bytes().expect("consumer received data");
+    let played: Vec<_> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .filter(|s| *s != 0.)
+        .collect();
+
+    assert_eq!(played, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_track_gain_applies_to_its_sink() {
+    let config = Config {
+        limiter_ceiling_in_db: None,
+        ..Default::default()
+    };
+    let length = config.seconds_to_samples(0.2);
+
+    let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config);
+    let player = pipeline.create_player();
+    let consumer = pipeline.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+    let queue = pipeline.create_queue(player.id, FifoQueue::new);
+
+    // -6dB is about half the amplitude
+    let input = FakeInput::new(FakePattern::Constant(0.5), length);
+    queue.push(FakeItem::new(input).with_id("adjusted").with_gain(-6.));
+    queue.push(FakeItem::new(input).with_id("unadjusted"));
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while queue.history().len() < 2 {
+        assert!(Instant::now() < deadline, "playback did not finish in time");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // Let the output thread flush the last processed buffer
+    thread::sleep(Duration::from_millis(300));
+
+    let bytes = consumer.bytes().expect("consumer received data");
+    let played: Vec<_> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .filter(|s| *s != 0.)
+        .collect();
+
+    assert_eq!(played.len(), length * 2);
+
+    let (adjusted, unadjusted) = played.split_at(length);
+    assert!(
+        adjusted.iter().all(|s| (s - 0.2506).abs() < 0.001),
+        "gain is applied to the first item"
+    );
+    assert!(
+        unadjusted.iter().all(|s| *s == 0.5),
+        "gain is not applied to the next item"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pipeline_shutdown_joins_threads() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(ASSET);
+
+    // Pipelines are created repeatedly, like a long-lived process reconfiguring would
+    for _ in 0..3 {
+        let pipeline: Pipeline<SymphoniaIngestion> = Pipeline::new(Config::default());
+        let player = pipeline.create_player();
+        let consumer = pipeline.consume_player::<RawEncoder>(player.id, Some(0), None);
+
+        let queue = pipeline.create_queue(player.id, |notifier| OnceQueue {
+            notifier,
+            item: Mutex::new(Some(FileItem {
+                path: path.clone(),
+                sink_id: Default::default(),
+            })),
+            finished: AtomicBool::new(false),
+        });
+
+        queue.notifier.notify();
+        thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(pipeline.running_threads(), 5, "all threads are running");
+
+        let started = Instant::now();
+        pipeline.shutdown();
+
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "shutdown is quick"
+        );
+
+        // Take what was emitted before the threads were joined
+        while pipeline.try_wait_for_event().is_ok() {}
+        let _ = consumer.bytes();
+
+        thread::sleep(Duration::from_millis(200));
+
+        assert!(
+            pipeline.try_wait_for_event().is_err(),
+            "no events are emitted after shutdown"
+        );
+        assert!(
+            consumer.bytes().is_none(),
+            "no audio is output after shutdown"
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_seek_burst_loads_last_position() {
+    let config = Config {
+        preload_size_in_seconds: 5.,
+        preload_threshold_in_seconds: 1.,
+        seek_coalesce_window_in_seconds: 0.2,
+        ..Default::default()
+    };
+
+    let pipeline: Pipeline<FakeIngestion> = Pipeline::new(config.clone());
+    let player = pipeline.create_player();
+    let queue = pipeline.create_queue(player.id, FifoQueue::new);
+
+    player.pause();
+    queue.push(FakeItem::new(FakeInput::new(
+        FakePattern::Constant(0.5),
+        config.seconds_to_samples(120.),
+    )));
+
+    let loaded_ranges = || {
+        pipeline
+            .introspect()
+            .sinks
+            .into_iter()
+            .find_map(|s| match s.activation_state {
+                ActivationIntrospection::Activated { buffer } => Some(buffer.ranges),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+    let is_loaded = |position: f32| {
+        let offset = config.seconds_to_samples(position);
+        loaded_ranges()
+            .iter()
+            .any(|r| (r.offset..r.offset + r.length).contains(&offset))
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !is_loaded(0.) {
+        assert!(Instant::now() < deadline, "sink was not loaded in time");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // Like dragging a seek bar
+    for position in [20., 40., 60., 80.] {
+        player.seek(position);
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !is_loaded(80.) {
+        assert!(
+            Instant::now() < deadline,
+            "last position was not loaded in time"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(
+        [20., 40., 60.].iter().all(|p| !is_loaded(*p)),
+        "superseded positions are not loaded: {:?}",
+        loaded_ranges()
+    );
+    assert_eq!(player.current_time().round(), 80.);
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Path,
+    http::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE},
+    response::Response,
+    routing::get,
+};
+use log::warn;
+use parking_lot::Mutex;
+
+use crate::{
+    context::ServerContext,
+    errors::{ServerError, ServerResult},
+    Router, ServerConfig,
+};
+
+/// Served when the upstream artwork could not be fetched
+const PLACEHOLDER: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1"><rect width="1" height="1" fill="#222"/></svg>"##;
+
+/// How long fetching upstream artwork may take, so a slow upstream doesn't hold up requests
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches and caches track artwork, so it can be served from turntable's own origin.
+pub struct ArtworkProxy {
+    enabled: bool,
+    client: reqwest::Client,
+    /// Artwork larger than this isn't read, since it wouldn't fit in the cache
+    max_size: usize,
+    cache: Mutex<ArtworkCache>,
+}
+
+#[derive(Clone)]
+struct CachedArtwork {
+    bytes: Bytes,
+    content_type: String,
+    fetched_at: Instant,
+}
+
+/// A cache of artwork that is limited by the total size in bytes and the age of entries
+struct ArtworkCache {
+    entries: HashMap<String, CachedArtwork>,
+    max_size: usize,
+    ttl: Duration,
+}
+
+impl ArtworkProxy {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            enabled: config.proxy_artwork,
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .expect("client is built"),
+            max_size: config.artwork_cache_size,
+            cache: ArtworkCache::new(config.artwork_cache_size, config.artwork_cache_ttl).into(),
+        }
+    }
+
+    /// Returns the artwork URL that should be served to clients,
+    /// which references the proxy if it is enabled.
+    pub fn url(&self, canonical: &str, artwork: Option<&String>) -> Option<String> {
+        let artwork = artwork?;
+
+        if self.enabled {
+            return Some(format!("/v1/artwork/{}", artwork_key(canonical)));
+        }
+
+        Some(artwork.clone())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<CachedArtwork, Box<dyn Error>> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        let too_large = || format!("Artwork is larger than {} bytes", self.max_size);
+
+        let content_length = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+
+        if content_length.is_some_and(|l| l > self.max_size) {
+            return Err(too_large().into());
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        // The length may be missing or wrong, so the body is limited as it is read too
+        let mut bytes = vec![];
+
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > self.max_size {
+                return Err(too_large().into());
+            }
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(CachedArtwork {
+            bytes: bytes.into(),
+            content_type,
+            fetched_at: Instant::now(),
+        })
+    }
+}
+
+impl ArtworkCache {
+    fn new(max_size: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Default::default(),
+            max_size,
+            ttl,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<CachedArtwork> {
+        let ttl = self.ttl;
+        self.entries.retain(|_, a| a.fetched_at.elapsed() < ttl);
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: String, artwork: CachedArtwork) {
+        if artwork.bytes.len() > self.max_size {
+            return;
+        }
+
+        // Evict the oldest entries until the new one fits
+        while self.size() + artwork.bytes.len() > self.max_size {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, a)| a.fetched_at)
+                .map(|(k, _)| k.clone());
+
+            match oldest {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+
+        self.entries.insert(key, artwork);
+    }
+
+    fn size(&self) -> usize {
+        self.entries.values().map(|a| a.bytes.len()).sum()
+    }
+}
+
+/// Returns a stable key for the artwork of a track, derived from its canonical URL
+pub fn artwork_key(canonical: &str) -> String {
+    // FNV-1a, since it is stable across builds, unlike the std hasher
+    let hash = canonical.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+fn artwork_response(artwork: &CachedArtwork, max_age: Duration) -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, &artwork.content_type)
+        .header(
+            CACHE_CONTROL,
+            format!("public, max-age={}", max_age.as_secs()),
+        )
+        .body(Body::from(artwork.bytes.clone()))
+        .unwrap()
+}
+
+fn placeholder_response() -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, "image/svg+xml")
+        .header(CACHE_CONTROL, "no-store")
+        .body(Body::from(PLACEHOLDER))
+        .unwrap()
+}
+
+/// Gets the artwork of a track through turntable, if artwork proxying is enabled.
+#[utoipa::path(
+    get,
+    path = "/v1/artwork/{key}",
+    tag = "artwork",
+    params(
+        ("key" = String, Path, description = "The artwork key of a track"),
+    ),
+    responses(
+        (
+            status = 200,
+            content_type = "image/*",
+            description = "The artwork, or a placeholder if it could not be fetched or is too large to cache"
+        ),
+        (status = 404, description = "No track with this artwork key exists, or proxying is disabled")
+    )
+)]
+async fn artwork(context: ServerContext, Path(key): Path<String>) -> ServerResult<Response<Body>> {
+    let proxy = &context.artwork;
+    let not_found = || ServerError::NotFound {
+        resource: "artwork",
+        identifier: key.clone(),
+    };
+
+    if !proxy.enabled {
+        return Err(not_found());
+    }
+
+    let ttl = proxy.cache.lock().ttl;
+
+    if let Some(cached) = proxy.cache.lock().get(&key) {
+        return Ok(artwork_response(&cached, ttl));
+    }
+
+    let url = context
+        .collab
+        .rooms
+        .find_track(|t| t.metadata.artwork.is_some() && artwork_key(&t.metadata.canonical) == key)
+        .and_then(|t| t.metadata.artwork)
+        .ok_or_else(not_found)?;
+
+    match proxy.fetch(&url).await {
+        Ok(fetched) => {
+            proxy.cache.lock().insert(key, fetched.clone());
+            Ok(artwork_response(&fetched, ttl))
+        }
+        Err(e) => {
+            warn!("Failed to fetch artwork from {}: {}", url, e);
+            Ok(placeholder_response())
+        }
+    }
+}
+
+pub fn router() -> Router {
+    Router::new().route("/:key", get(artwork))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::Infallible,
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        body::{to_bytes, Body, Bytes},
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use futures_util::stream;
+    use sqlx::PgPool;
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+    use turntable_collab::{Input, NewPlainUser, NewRoom, RoomVisibility, Track};
+
+    use super::{artwork_key, ArtworkCache, ArtworkProxy, CachedArtwork};
+    use crate::{test_app_with_config, ServerConfig};
+
+    fn artwork(size: usize) -> CachedArtwork {
+        CachedArtwork {
+            bytes: Bytes::from(vec![0; size]),
+            content_type: "image/jpeg".to_string(),
+            fetched_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_artwork_key_is_stable() {
+        assert_eq!(
+            artwork_key("https://example.com"),
+            artwork_key("https://example.com")
+        );
+        assert_ne!(
+            artwork_key("https://example.com/a"),
+            artwork_key("https://example.com/b")
+        );
+        assert_eq!(artwork_key("").len(), 16);
+    }
+
+    #[test]
+    fn test_url_follows_its_proxy() {
+        let enabled = ArtworkProxy::new(&ServerConfig {
+            proxy_artwork: true,
+            ..Default::default()
+        });
+        let disabled = ArtworkProxy::new(&ServerConfig::default());
+
+        let upstream = "https://example.com/artwork.jpg".to_string();
+        let proxied = format!("/v1/artwork/{}", artwork_key("canonical"));
+
+        assert_eq!(enabled.url("canonical", Some(&upstream)), Some(proxied));
+        assert_eq!(disabled.url("canonical", Some(&upstream)), Some(upstream));
+        assert_eq!(enabled.url("canonical", None), None);
+    }
+
+    #[test]
+    fn test_cache_limits() {
+        let mut cache = ArtworkCache::new(10, Duration::from_secs(60));
+
+        cache.insert("a".to_string(), artwork(4));
+        cache.insert("b".to_string(), artwork(4));
+        cache.insert("c".to_string(), artwork(4));
+
+        assert!(cache.get("a").is_none(), "oldest entry is evicted");
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+
+        cache.insert("d".to_string(), artwork(11));
+        assert!(
+            cache.get("d").is_none(),
+            "entries larger than the cache are skipped"
+        );
+
+        let mut cache = ArtworkCache::new(10, Duration::ZERO);
+        cache.insert("a".to_string(), artwork(1));
+        assert!(cache.get("a").is_none(), "expired entries are not returned");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_is_limited_to_the_cache_size() {
+        // Artwork with a length, and artwork streamed without one
+        let upstream = Router::new()
+            .route("/small", get(|| async { vec![0u8; 8] }))
+            .route("/large", get(|| async { vec![0u8; 32] }))
+            .route(
+                "/streamed",
+                get(|| async {
+                    let chunks = (0..4).map(|_| Ok::<_, Infallible>(Bytes::from(vec![0u8; 8])));
+                    Body::from_stream(stream::iter(chunks))
+                }),
+            );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let proxy = ArtworkProxy::new(&ServerConfig {
+            artwork_cache_size: 16,
+            ..Default::default()
+        });
+
+        let url = |path: &str| format!("http://{}{}", address, path);
+
+        let small = proxy.fetch(&url("/small")).await.unwrap();
+        assert_eq!(small.bytes.len(), 8);
+
+        assert!(proxy.fetch(&url("/large")).await.is_err());
+        assert!(proxy.fetch(&url("/streamed")).await.is_err());
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_artwork_upstream_failure(pool: PgPool) {
+        let config = ServerConfig {
+            proxy_artwork: true,
+            ..Default::default()
+        };
+
+        let (app, context) = test_app_with_config(pool, config).await;
+        let rooms = &context.collab.rooms;
+
+        let user = context
+            .collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let path = std::env::temp_dir().join("turntable_artwork_proxy.wav");
+        std::fs::write(&path, []).unwrap();
+
+        let input = Input::query(&format!("file://{}", path.display()))
+            .await
+            .unwrap()
+            .remove(0);
+
+        let mut track = Track::from(input);
+        // Nothing listens on port 1, so fetching fails
+        track.metadata.artwork = Some("http://127.0.0.1:1/artwork.jpg".to_string());
+
+        let key = artwork_key(&track.metadata.canonical);
+        room.queue().unwrap().push(track, (&user).into()).unwrap();
+
+        let request = |key: &str| {
+            Request::get(format!("/v1/artwork/{}", key))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(&key)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/svg+xml");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).starts_with("<svg"));
+
+        let response = app.oneshot(request("unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
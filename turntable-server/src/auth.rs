@@ -11,7 +11,7 @@ use axum::{
 use turntable_collab::{Credentials, NewPlainUser, SessionData, UserData};
 
 use crate::{
-    errors::ServerResult,
+    errors::{ServerError, ServerResult},
     schemas::{LoginSchema, RegisterSchema, ValidatedJson},
     serialized::{LoginResult, ToSerialized, User},
     Router, ServerContext,
@@ -99,8 +99,16 @@ async fn register(
     ValidatedJson(body): ValidatedJson<RegisterSchema>,
 ) -> ServerResult<Json<User>> {
     if let Some(token) = body.invite_token {
-        // Ensure the invite actually exists
-        let _ = context.collab.rooms.invite_by_token(token).await?;
+        // Ensure the invite actually exists and can still be used
+        let invite = context.collab.rooms.invite_by_token(token).await?;
+
+        if invite.is_expired() {
+            return Err(ServerError::InviteExpired);
+        }
+
+        if invite.is_exhausted() {
+            return Err(ServerError::InviteExhausted);
+        }
 
         let new_user = context.collab.auth.register_basic(NewPlainUser {
             username: body.username,
@@ -172,3 +180,78 @@ pub fn router() -> Router {
         .route("/login", post(login))
         .route("/logout", post(logout))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use turntable_collab::{NewRoom, RoomVisibility};
+
+    use crate::test_app;
+
+    fn register_request(username: &str, invite_token: Option<&str>) -> Request<Body> {
+        let body = json!({
+            "username": username,
+            "password": "password",
+            "displayName": username,
+            "inviteToken": invite_token,
+        });
+
+        Request::post("/v1/auth/register")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_register_duplicate_username(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+
+        let response = app
+            .clone()
+            .oneshot(register_request("admin", None))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let user: Value = serde_json::from_slice(&body).unwrap();
+        let user_id = user["id"].as_i64().unwrap() as i32;
+
+        let room = context
+            .collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let invite = context
+            .collab
+            .rooms
+            .create_invite(user_id, room.id())
+            .await
+            .unwrap();
+
+        let response = app
+            .oneshot(register_request("admin", Some(&invite.token)))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("username"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// The configuration of the server
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How often a keepalive comment is sent on idle event streams,
+    /// so that proxies and load balancers don't drop the connection.
+    pub sse_keep_alive_interval: Duration,
+    /// The delay clients are told to wait before reconnecting to an event stream
+    pub sse_retry: Duration,
+    /// How many of the most recent events are kept, to replay them to clients that reconnect
+    pub sse_history_size: usize,
+    /// How many events are replayed to a reconnecting client at most.
+    /// Clients that missed more are told to fetch the full state again instead.
+    pub sse_max_replay: usize,
+    /// If track artwork should be served through turntable instead of linking to the upstream URL
+    pub proxy_artwork: bool,
+    /// How many bytes of proxied artwork can be cached
+    pub artwork_cache_size: usize,
+    /// How long proxied artwork is cached for
+    pub artwork_cache_ttl: Duration,
+    /// How long an anonymous preview stream of a public room lasts before it is closed
+    pub preview_duration: Duration,
+    /// How many preview streams an IP address can have open at once
+    pub max_previews_per_ip: usize,
+    /// How much silence a stream starts with when it's requested to start right away
+    pub stream_preroll: Duration,
+    /// How long a stream waits for audio before failing when it's requested to start with audio
+    pub stream_start_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            // Below the common 30-60 second idle timeout of proxies
+            sse_keep_alive_interval: Duration::from_secs(15),
+            sse_retry: Duration::from_secs(3),
+            sse_history_size: 512,
+            sse_max_replay: 128,
+            proxy_artwork: false,
+            // Roughly a few hundred thumbnails
+            artwork_cache_size: 32 * 1024 * 1024,
+            artwork_cache_ttl: Duration::from_secs(60 * 60 * 24),
+            preview_duration: Duration::from_secs(30),
+            max_previews_per_ip: 1,
+            // Enough for clients to recognize the format
+            stream_preroll: Duration::from_millis(200),
+            stream_start_timeout: Duration::from_secs(5),
+        }
+    }
+}
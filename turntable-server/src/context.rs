@@ -7,12 +7,15 @@ use axum::{
 };
 use turntable_collab::Collab;
 
-use crate::sse::ServerSentEvents;
+use crate::{artwork::ArtworkProxy, previews::Previews, sse::ServerSentEvents, ServerConfig};
 
 #[derive(Clone, FromRef)]
 pub struct ServerContext {
     pub collab: Arc<Collab>,
     pub sse: Arc<ServerSentEvents>,
+    pub artwork: Arc<ArtworkProxy>,
+    pub previews: Arc<Previews>,
+    pub config: Arc<ServerConfig>,
 }
 
 #[async_trait]
@@ -0,0 +1,315 @@
+use std::time::Duration;
+
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json,
+};
+
+use crate::{
+    auth::Session,
+    context::ServerContext,
+    errors::{ServerError, ServerResult},
+    serialized::{EncoderStats, SinkBufferHealth, SinkUsage, ToSerialized},
+    Router,
+};
+
+/// How long to keep trying to read the buffer health of a room before giving up
+const BUFFER_HEALTH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Gets how much of the current and upcoming items in a room are loaded, and why any failed to load, to diagnose stutter.
+#[utoipa::path(
+    get,
+    path = "/v1/debug/rooms/{id}/buffers",
+    tag = "debug",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = [SinkBufferHealth]),
+        (status = 400, description = "The room is not active"),
+        (status = 403, description = "The user is not a superuser"),
+        (status = 503, description = "The player was busy for too long")
+    )
+)]
+async fn room_buffers(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<Json<Vec<SinkBufferHealth>>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let room = context.collab.rooms.room_by_id(room_id)?;
+    let player = room.player()?;
+
+    // The timeline is only busy for a moment while playback advances,
+    // so try again instead of making playback wait for us, but not forever.
+    let health = tokio::time::timeout(BUFFER_HEALTH_TIMEOUT, async {
+        loop {
+            if let Some(health) = player.buffer_health() {
+                break health;
+            }
+
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .map_err(|_| ServerError::PlayerBusy)?;
+
+    let config = &context.collab.config.pipeline;
+
+    Ok(Json(
+        health.iter().map(|h| (h, config).to_serialized()).collect(),
+    ))
+}
+
+/// Rebuilds the player of a room from its queue and resumes playback, to recover a room that is stuck.
+/// The current track starts over.
+#[utoipa::path(
+    post,
+    path = "/v1/debug/rooms/{id}/reseat",
+    tag = "debug",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "The player is being rebuilt"),
+        (status = 400, description = "The room is not active"),
+        (status = 403, description = "The user is not a superuser")
+    )
+)]
+async fn reseat_room(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<()> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    context.collab.rooms.room_by_id(room_id)?.reseat()?;
+
+    Ok(())
+}
+
+/// Gets how much each encoder has encoded and how long it took, to tell which ones are expensive.
+#[utoipa::path(
+    get,
+    path = "/v1/debug/encoders",
+    tag = "debug",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = [EncoderStats]),
+        (status = 403, description = "The user is not a superuser")
+    )
+)]
+async fn encoders(
+    session: Session,
+    context: ServerContext,
+) -> ServerResult<Json<Vec<EncoderStats>>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let stats = context.collab.pipeline.encoder_stats();
+    let config = &context.collab.config.pipeline;
+
+    Ok(Json(
+        stats.iter().map(|s| (s, config).to_serialized()).collect(),
+    ))
+}
+
+/// Gets how many sinks are activated and how much audio they hold, compared to the limits.
+#[utoipa::path(
+    get,
+    path = "/v1/debug/sinks",
+    tag = "debug",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = SinkUsage),
+        (status = 403, description = "The user is not a superuser")
+    )
+)]
+async fn sinks(session: Session, context: ServerContext) -> ServerResult<Json<SinkUsage>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let usage = context.collab.pipeline.sink_usage();
+    let config = &context.collab.config.pipeline;
+
+    Ok(Json((&usage, config).to_serialized()))
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/rooms/:id/buffers", get(room_buffers))
+        .route("/rooms/:id/reseat", post(reseat_room))
+        .route("/encoders", get(encoders))
+        .route("/sinks", get(sinks))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header::AUTHORIZATION, Request, StatusCode},
+    };
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use turntable_collab::{Credentials, NewPlainUser, NewRoom, RoomVisibility};
+    use turntable_impls::EncoderKind;
+
+    use crate::test_app;
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_room_buffers_requires_superuser(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let new_user = |username: &str| NewPlainUser {
+            username: username.to_string(),
+            password: "password".to_string(),
+            display_name: username.to_string(),
+        };
+
+        let admin = collab
+            .auth
+            .register_superuser(new_user("admin"))
+            .await
+            .unwrap();
+        collab.auth.register_basic(new_user("user")).await.unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: admin.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // Activates the room
+        room.queue().unwrap();
+
+        let login = |username: &str| {
+            collab.auth.login(Credentials {
+                username: username.to_string(),
+                password: "password".to_string(),
+            })
+        };
+
+        let user_session = login("user").await.unwrap();
+        let admin_session = login("admin").await.unwrap();
+
+        let request = |token: &str| {
+            Request::get(format!("/v1/debug/rooms/{}/buffers", room.id()))
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(&user_session.token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.oneshot(request(&admin_session.token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let buffers: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(buffers, Value::Array(vec![]), "nothing is queued");
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_encoder_stats(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let new_user = |username: &str| NewPlainUser {
+            username: username.to_string(),
+            password: "password".to_string(),
+            display_name: username.to_string(),
+        };
+
+        let admin = collab
+            .auth
+            .register_superuser(new_user("admin"))
+            .await
+            .unwrap();
+        collab.auth.register_basic(new_user("user")).await.unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: admin.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // The player outputs silence, which is encoded for the listener
+        let _connection = room
+            .connect(
+                admin.id,
+                "test".to_string(),
+                Some(0),
+                EncoderKind::Pcm,
+                None,
+            )
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let login = |username: &str| {
+            collab.auth.login(Credentials {
+                username: username.to_string(),
+                password: "password".to_string(),
+            })
+        };
+
+        let user_session = login("user").await.unwrap();
+        let admin_session = login("admin").await.unwrap();
+
+        let request = |token: &str| {
+            Request::get("/v1/debug/encoders")
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(&user_session.token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app.oneshot(request(&admin_session.token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let encoders: Value = serde_json::from_slice(&body).unwrap();
+        let encoders = encoders.as_array().unwrap();
+
+        assert_eq!(encoders.len(), 1, "only used encoders are listed");
+        assert!(encoders[0]["samplesEncoded"].as_u64().unwrap() > 0);
+        assert!(encoders[0]["calls"].as_u64().unwrap() > 0);
+    }
+}
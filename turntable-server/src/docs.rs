@@ -35,3 +35,194 @@ impl Modify for Security {
 pub async fn docs() -> impl IntoResponse {
     Json(ApiDoc::openapi())
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        Router,
+    };
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use utoipa::{openapi::PathItemType, OpenApi};
+
+    use super::ApiDoc;
+    use crate::test_app;
+
+    /// Returned for requests that don't match any path, to tell them apart from handlers returning 404
+    const UNROUTED: StatusCode = StatusCode::IM_A_TEAPOT;
+
+    const METHODS: [(PathItemType, Method); 5] = [
+        (PathItemType::Get, Method::GET),
+        (PathItemType::Post, Method::POST),
+        (PathItemType::Put, Method::PUT),
+        (PathItemType::Delete, Method::DELETE),
+        (PathItemType::Patch, Method::PATCH),
+    ];
+
+    /// Replaces the path parameters of a documented path with a value
+    fn example_uri(path: &str) -> String {
+        path.split('/')
+            .map(|s| if s.starts_with('{') { "1" } else { s })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Returns true if the router has a route for the method and uri
+    async fn is_routed(app: &Router, method: Method, uri: &str) -> bool {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+
+        let status = app.clone().oneshot(request).await.unwrap().status();
+        status != UNROUTED && status != StatusCode::METHOD_NOT_ALLOWED
+    }
+
+    /// The sources of the routers nested under `/v1` in [crate::app], by the path they're nested at
+    const ROUTERS: [(&str, &str); 9] = [
+        ("/auth", include_str!("auth.rs")),
+        ("/rooms", include_str!("rooms.rs")),
+        ("/streams", include_str!("streaming.rs")),
+        ("/stats", include_str!("stats.rs")),
+        ("/preferences", include_str!("preferences.rs")),
+        ("/artwork", include_str!("artwork.rs")),
+        ("/debug", include_str!("debug.rs")),
+        ("/events", include_str!("sse.rs")),
+        ("/health", include_str!("health.rs")),
+    ];
+
+    /// Replaces the path parameters of a documented or routed path with `{}`
+    fn normalize_path(path: &str) -> String {
+        let path = path
+            .split('/')
+            .map(|s| {
+                if s.starts_with('{') || s.starts_with(':') {
+                    "{}"
+                } else {
+                    s
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        match path.strip_suffix('/') {
+            Some(path) if !path.is_empty() => path.to_string(),
+            _ => path,
+        }
+    }
+
+    /// Returns the method and path of every `.route()` declared in the source of a router
+    fn declared_routes(prefix: &str, source: &str) -> Vec<(Method, String)> {
+        let mut routes = vec![];
+        // Tests may route their own servers
+        let source = source.split("#[cfg(test)]").next().unwrap_or_default();
+
+        for route in source.split(".route(\"").skip(1) {
+            let Some((path, handlers)) = route.split_once('"') else {
+                continue;
+            };
+
+            let path = normalize_path(&format!("/v1{}{}", prefix, path));
+            let handlers = handlers.lines().next().unwrap_or_default();
+            let tokens: Vec<_> = handlers
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .collect();
+
+            for (_, method) in METHODS {
+                if tokens.contains(&method.as_str().to_lowercase().as_str()) {
+                    routes.push((method, path.clone()));
+                }
+            }
+        }
+
+        routes
+    }
+
+    #[test]
+    fn test_routers_are_nested_as_listed() {
+        let app = include_str!("lib.rs");
+        let nested = app.lines().filter(|l| l.contains("::router())")).count();
+
+        assert_eq!(nested, ROUTERS.len(), "a nested router is not listed");
+
+        for (prefix, _) in ROUTERS {
+            assert!(
+                app.contains(&format!(".nest(\"{}\", ", prefix)),
+                "{} is not nested",
+                prefix
+            );
+        }
+    }
+
+    #[test]
+    fn test_routed_paths_are_documented() {
+        let docs = ApiDoc::openapi();
+
+        let documented: BTreeSet<_> = docs
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                METHODS
+                    .into_iter()
+                    .filter(|(item_type, _)| item.operations.contains_key(item_type))
+                    .map(|(_, method)| format!("{} {}", method, normalize_path(path)))
+            })
+            .collect();
+
+        let routed: BTreeSet<_> = ROUTERS
+            .iter()
+            .flat_map(|(prefix, source)| declared_routes(prefix, source))
+            .map(|(method, path)| format!("{} {}", method, path))
+            .collect();
+
+        assert!(routed.len() > ROUTERS.len(), "no routes were found");
+
+        let undocumented: Vec<_> = routed.difference(&documented).collect();
+        let unrouted: Vec<_> = documented.difference(&routed).collect();
+
+        assert!(
+            undocumented.is_empty(),
+            "routed but not documented: {:?}",
+            undocumented
+        );
+        assert!(
+            unrouted.is_empty(),
+            "documented but not declared: {:?}",
+            unrouted
+        );
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_documented_routes_match_router(pool: PgPool) {
+        let (app, _) = test_app(pool).await;
+        let app = app.fallback(|| async { UNROUTED });
+
+        let docs = ApiDoc::openapi();
+        let mut unrouted = BTreeSet::new();
+
+        for (path, item) in docs.paths.paths.iter() {
+            let uri = example_uri(path);
+
+            for (item_type, method) in METHODS {
+                let documented = item.operations.contains_key(&item_type);
+
+                if documented && !is_routed(&app, method.clone(), &uri).await {
+                    unrouted.insert(format!("{} {}", method, path));
+                }
+            }
+        }
+
+        assert!(
+            unrouted.is_empty(),
+            "documented but not routed: {:?}",
+            unrouted
+        );
+        assert!(!is_routed(&app, Method::GET, "/v1/nonexistent").await);
+    }
+}
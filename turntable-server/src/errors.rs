@@ -4,7 +4,9 @@ use axum::{
 };
 use log::error;
 use thiserror::Error;
-use turntable_collab::{AuthError, DatabaseError, InputError, RoomError};
+use turntable_collab::{
+    AuthError, DatabaseError, InputError, PreferenceError, QueueError, RoomError,
+};
 
 pub type ServerResult<T> = Result<T, ServerError>;
 
@@ -29,15 +31,64 @@ pub enum ServerError {
     InvalidCredentials,
     #[error("A superuser already exists")]
     SuperuserExists,
+    #[error("Only superusers can do this")]
+    SuperuserRequired,
     // Rooms
     #[error("Room is not active")]
     RoomNotActive,
     #[error("User is not a member of this room")]
     UserNotInRoom,
+    #[error("Only owners of this room can do this")]
+    OwnerRequired,
     #[error("User does not own this stream key")]
     StreamKeyNotOwn,
     #[error("Stream key does not exist")]
     StreamKeyNotFound,
+    #[error("Stream key does not allow adding to the queue")]
+    StreamKeyCannotQueue,
+    #[error("Invalid slug: {0}")]
+    InvalidSlug(String),
+    #[error("The current item has no known length")]
+    UnknownLength,
+    #[error("Source {0} is not allowed in this room")]
+    SourceNotAllowed(String),
+    #[error("Track plays for {length} seconds, but this room allows at most {max} seconds")]
+    TrackTooLong { length: f32, max: i32 },
+    #[error("Track is blocked by the explicit content filter of this room, since {0}")]
+    ExplicitContent(String),
+    #[error("Room has too many connections, it allows at most {max}")]
+    TooManyConnections { max: usize },
+    #[error("User has too many stream connections, at most {max} are allowed across all rooms")]
+    TooManyUserConnections { max: usize },
+    #[error("Tracks were moved to the front recently, try again in {retry_after} seconds")]
+    MoveToFrontCooldown { retry_after: u64 },
+    #[error("Invite has expired")]
+    InviteExpired,
+    #[error("Invite has been used as many times as it can be")]
+    InviteExhausted,
+    #[error("Queue is full, it can contain at most {max} items")]
+    QueueFull { max: usize },
+    #[error("Room is already being recorded")]
+    AlreadyRecording,
+    #[error("Room is not being recorded")]
+    NotRecording,
+    #[error("Unsupported recording format: {0}")]
+    UnsupportedRecordingFormat(String),
+    #[error("Recorded file {0} does not exist, or is still being written")]
+    RecordedFileNotFound(String),
+    #[error("Too many previews are open, at most {max} can be open at once")]
+    TooManyPreviews { max: usize },
+    #[error("Room has no audio to stream yet")]
+    NoAudio,
+    #[error("Player was busy for too long")]
+    PlayerBusy,
+    #[error("Invalid schedule time: {0}")]
+    InvalidScheduleTime(String),
+    // Preferences
+    #[error("{0}")]
+    InvalidPreference(String),
+    #[error("Too many preferences, at most {max} can be set")]
+    TooManyPreferences { max: usize },
     // Inputs
     #[error("Input did not match")]
     InputNoMatch,
@@ -53,12 +104,15 @@ pub enum ServerError {
     InputFetchError(String),
     #[error("Failed to parse resource: {0}")]
     InputParseError(String),
+    #[error("Rate limited by the source, try again later")]
+    InputRateLimited,
 }
 
 impl ServerError {
     fn as_status_code(&self) -> StatusCode {
         match self {
             Self::SuperuserExists => StatusCode::CONFLICT,
+            Self::SuperuserRequired => StatusCode::FORBIDDEN,
             Self::InvalidCredentials => StatusCode::BAD_REQUEST,
             Self::Conflict {
                 resource: _,
@@ -71,13 +125,37 @@ impl ServerError {
             } => StatusCode::NOT_FOUND,
             Self::RoomNotActive => StatusCode::BAD_REQUEST,
             Self::UserNotInRoom => StatusCode::FORBIDDEN,
+            Self::OwnerRequired => StatusCode::FORBIDDEN,
             Self::StreamKeyNotFound => StatusCode::NOT_FOUND,
             Self::StreamKeyNotOwn => StatusCode::FORBIDDEN,
+            Self::StreamKeyCannotQueue => StatusCode::FORBIDDEN,
+            Self::InvalidSlug(_) => StatusCode::BAD_REQUEST,
+            Self::UnknownLength => StatusCode::BAD_REQUEST,
+            Self::SourceNotAllowed(_) => StatusCode::FORBIDDEN,
+            Self::TrackTooLong { length: _, max: _ } => StatusCode::BAD_REQUEST,
+            Self::ExplicitContent(_) => StatusCode::FORBIDDEN,
+            Self::QueueFull { max: _ } => StatusCode::BAD_REQUEST,
+            Self::TooManyConnections { max: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Self::TooManyUserConnections { max: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Self::MoveToFrontCooldown { retry_after: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Self::InviteExpired => StatusCode::GONE,
+            Self::InviteExhausted => StatusCode::GONE,
+            Self::AlreadyRecording => StatusCode::CONFLICT,
+            Self::NotRecording => StatusCode::BAD_REQUEST,
+            Self::UnsupportedRecordingFormat(_) => StatusCode::BAD_REQUEST,
+            Self::RecordedFileNotFound(_) => StatusCode::NOT_FOUND,
+            Self::TooManyPreviews { max: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Self::NoAudio => StatusCode::GATEWAY_TIMEOUT,
+            Self::PlayerBusy => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InvalidScheduleTime(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidPreference(_) => StatusCode::BAD_REQUEST,
+            Self::TooManyPreferences { max: _ } => StatusCode::BAD_REQUEST,
             Self::InputNotFound => StatusCode::NOT_FOUND,
             Self::InputNoMatch => StatusCode::BAD_REQUEST,
             Self::UnsupportedInputType => StatusCode::BAD_REQUEST,
             Self::InputInvalid(_) => StatusCode::BAD_REQUEST,
             Self::InputUnavailable => StatusCode::BAD_REQUEST,
+            Self::InputRateLimited => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -102,6 +180,7 @@ impl From<AuthError> for ServerError {
         match value {
             AuthError::InvalidCredentials => Self::InvalidCredentials,
             AuthError::SuperuserExists => Self::SuperuserExists,
+            AuthError::Db(e) => e.into(),
             e => Self::Unknown(e.to_string()),
         }
     }
@@ -140,13 +219,51 @@ impl From<RoomError> for ServerError {
             },
             RoomError::RoomNotActive => Self::RoomNotActive,
             RoomError::UserNotInRoom => Self::UserNotInRoom,
+            RoomError::OwnerRequired => Self::OwnerRequired,
             RoomError::StreamKeyNotFound => Self::StreamKeyNotFound,
             RoomError::StreamKeyNotOwn => Self::StreamKeyNotOwn,
+            RoomError::StreamKeyCannotQueue => Self::StreamKeyCannotQueue,
+            RoomError::InvalidSlug(e) => Self::InvalidSlug(e.to_string()),
+            RoomError::UnknownLength => Self::UnknownLength,
+            RoomError::SourceNotAllowed(s) => Self::SourceNotAllowed(s),
+            RoomError::TrackTooLong { length, max } => Self::TrackTooLong { length, max },
+            RoomError::ExplicitContent(reason) => Self::ExplicitContent(reason),
+            RoomError::TooManyConnections { max } => Self::TooManyConnections { max },
+            RoomError::TooManyUserConnections { max } => Self::TooManyUserConnections { max },
+            RoomError::MoveToFrontCooldown { retry_after } => Self::MoveToFrontCooldown {
+                retry_after: retry_after.as_secs().max(1),
+            },
+            RoomError::InviteExpired => Self::InviteExpired,
+            RoomError::InviteExhausted => Self::InviteExhausted,
+            RoomError::AddNotFound(id) => Self::NotFound {
+                resource: "add",
+                identifier: id.to_string(),
+            },
+            RoomError::Input(e) => e.into(),
+            RoomError::AlreadyRecording => Self::AlreadyRecording,
+            RoomError::NotRecording => Self::NotRecording,
+            RoomError::Recording(e) => Self::Unknown(e.to_string()),
+            RoomError::RecordedFileNotFound(name) => Self::RecordedFileNotFound(name),
+            RoomError::Queue(QueueError::Full { max }) => Self::QueueFull { max },
+            RoomError::Queue(QueueError::TrackNotFound(id)) => Self::NotFound {
+                resource: "track",
+                identifier: id.to_string(),
+            },
             RoomError::Database(e) => e.into(),
         }
     }
 }
 
+impl From<PreferenceError> for ServerError {
+    fn from(value: PreferenceError) -> Self {
+        match value {
+            PreferenceError::TooMany { max } => Self::TooManyPreferences { max },
+            PreferenceError::Database(e) => e.into(),
+            e => Self::InvalidPreference(e.to_string()),
+        }
+    }
+}
+
 impl From<InputError> for ServerError {
     fn from(value: InputError) -> Self {
         match value {
@@ -157,7 +274,53 @@ impl From<InputError> for ServerError {
             InputError::UnsupportedType => Self::UnsupportedInputType,
             InputError::ParseError(e) => Self::InputParseError(e),
             InputError::Unavailable => Self::InputUnavailable,
+            InputError::RateLimited { .. } => Self::InputRateLimited,
             InputError::Other(e) => Self::Unknown(e),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use axum::http::StatusCode;
+    use turntable_collab::{AuthError, DatabaseError, RoomError};
+
+    use super::ServerError;
+
+    fn conflict() -> DatabaseError {
+        DatabaseError::Conflict {
+            resource: "user",
+            field: "username",
+            value: "taken".to_string(),
+        }
+    }
+
+    fn not_found() -> DatabaseError {
+        DatabaseError::NotFound {
+            resource: "room",
+            identifier: "slug",
+        }
+    }
+
+    #[test]
+    fn test_database_error_status() {
+        let error: ServerError = conflict().into();
+        assert_eq!(error.as_status_code(), StatusCode::CONFLICT);
+        assert!(error.to_string().contains("username"));
+
+        let error: ServerError = not_found().into();
+        assert_eq!(error.as_status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_wrapped_database_error_status() {
+        let error: ServerError = AuthError::Db(conflict()).into();
+        assert_eq!(error.as_status_code(), StatusCode::CONFLICT);
+
+        let error: ServerError = RoomError::Database(conflict()).into();
+        assert_eq!(error.as_status_code(), StatusCode::CONFLICT);
+
+        let error: ServerError = AuthError::Db(not_found()).into();
+        assert_eq!(error.as_status_code(), StatusCode::NOT_FOUND);
+    }
+}
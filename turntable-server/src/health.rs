@@ -0,0 +1,85 @@
+use axum::{http::StatusCode, routing::get, Json};
+
+use crate::{
+    context::ServerContext,
+    serialized::{Health, ToSerialized},
+    Router,
+};
+
+/// Gets whether the threads of the pipeline are making progress, for readiness checks.
+/// A thread that stopped, like one that panicked, makes the server unhealthy until it is restarted.
+#[utoipa::path(
+    get,
+    path = "/v1/health",
+    tag = "health",
+    responses(
+        (status = 200, body = Health),
+        (status = 503, body = Health, description = "A thread of the pipeline is stalled")
+    )
+)]
+async fn health(context: ServerContext) -> (StatusCode, Json<Health>) {
+    let health = context.collab.pipeline.health();
+
+    let status = if health.is_healthy() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(health.to_serialized()))
+}
+
+pub fn router() -> Router {
+    Router::new().route("/", get(health))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+    };
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use turntable_collab::CollabConfig;
+    use turntable_core::Config;
+
+    use crate::{test_app_with_configs, ServerConfig};
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_health_reports_stalled_threads(pool: PgPool) {
+        let collab_config = CollabConfig {
+            pipeline: Config {
+                stall_threshold_in_seconds: 0.2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (app, context) =
+            test_app_with_configs(pool, collab_config, ServerConfig::default()).await;
+
+        let request = || Request::get("/v1/health").body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The threads stop beating, like they would if they died
+        context.collab.pipeline.shutdown();
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let health: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(health["healthy"], false);
+        assert!(health["stalledThreads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|t| t["name"] == "playback"));
+    }
+}
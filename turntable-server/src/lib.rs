@@ -1,6 +1,8 @@
-use axum::{routing::get, Router as AxumRouter};
+use artwork::ArtworkProxy;
+use axum::{middleware, routing::get, Router as AxumRouter};
 use context::ServerContext;
 use log::info;
+use previews::Previews;
 use sse::ServerSentEvents;
 use std::{
     net::{Ipv6Addr, SocketAddr},
@@ -11,27 +13,56 @@ use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 use turntable_collab::Collab;
 
+mod artwork;
 mod auth;
+mod config;
 mod context;
+mod debug;
 mod docs;
 mod errors;
+mod health;
+mod logging;
+mod preferences;
+mod previews;
+mod ranges;
 mod rooms;
 mod schemas;
 mod serialized;
 mod sse;
+mod stats;
 mod streaming;
 
+pub use config::ServerConfig;
+
 type Router = AxumRouter<ServerContext>;
 
 /// Starts the turntable server
-pub async fn run_server(collab: &Arc<Collab>, port: u16) {
+pub async fn run_server(collab: &Arc<Collab>, port: u16, config: ServerConfig) {
     let context = ServerContext {
         collab: collab.to_owned(),
-        sse: ServerSentEvents::new(),
+        sse: ServerSentEvents::new(&config),
+        artwork: ArtworkProxy::new(&config).into(),
+        previews: Previews::new(&config).into(),
+        config: config.into(),
     };
 
     let addr: SocketAddr = (Ipv6Addr::UNSPECIFIED, port).into();
+    let root_router = app(&context);
+
+    let listener = TcpListener::bind(&addr).await.expect("listens on address");
+
+    spawn_event_thread(&context);
+
+    info!("Listening on http://localhost:{}", port);
 
+    // Previews are limited by the address of the client
+    let service = root_router.into_make_service_with_connect_info::<SocketAddr>();
+
+    axum::serve(listener, service).await.unwrap();
+}
+
+/// Creates the router with all routes of the server
+fn app(context: &ServerContext) -> AxumRouter {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
@@ -41,31 +72,32 @@ pub async fn run_server(collab: &Arc<Collab>, port: u16) {
         .nest("/auth", auth::router())
         .nest("/rooms", rooms::router())
         .nest("/streams", streaming::router())
-        .nest("/events", sse::router());
+        .nest("/stats", stats::router())
+        .nest("/preferences", preferences::router())
+        .nest("/artwork", artwork::router())
+        .nest("/debug", debug::router())
+        .nest("/events", sse::router())
+        .nest("/health", health::router());
 
-    let root_router = Router::new()
+    Router::new()
         .nest("/v1", version_one_router)
         .route("/api.json", get(docs::docs))
         .with_state(context.clone())
-        .layer(cors);
-
-    let listener = TcpListener::bind(&addr).await.expect("listens on address");
-
-    spawn_event_thread(&context);
-
-    info!("Listening on http://localhost:{}", port);
-
-    axum::serve(listener, root_router.into_make_service())
-        .await
-        .unwrap();
+        .layer(middleware::from_fn(logging::log_request))
+        .layer(cors)
 }
 
 fn spawn_event_thread(context: &ServerContext) {
     let context = context.to_owned();
 
-    let run = move || loop {
-        let event = context.collab.wait_for_event();
-        context.sse.broadcast(event.into());
+    let run = move || {
+        while let Ok(event) = context.collab.wait_for_event() {
+            if let Some(event) = sse::server_event(&context, event) {
+                context.sse.broadcast(event);
+            }
+        }
+
+        info!("Collab events stopped, exiting server-sent events");
     };
 
     thread::Builder::new()
@@ -73,3 +105,42 @@ fn spawn_event_thread(context: &ServerContext) {
         .spawn(run)
         .expect("server-sent-events thread spawns");
 }
+
+/// Creates a server context and router backed by the given pool.
+/// Only used in tests.
+#[cfg(test)]
+async fn test_app(pool: sqlx::PgPool) -> (AxumRouter, ServerContext) {
+    test_app_with_config(pool, ServerConfig::default()).await
+}
+
+/// Like [test_app], but with the given server configuration.
+/// Only used in tests.
+#[cfg(test)]
+async fn test_app_with_config(
+    pool: sqlx::PgPool,
+    config: ServerConfig,
+) -> (AxumRouter, ServerContext) {
+    test_app_with_configs(pool, Default::default(), config).await
+}
+
+/// Like [test_app], but with the given collab and server configuration.
+/// Only used in tests.
+#[cfg(test)]
+async fn test_app_with_configs(
+    pool: sqlx::PgPool,
+    collab_config: turntable_collab::CollabConfig,
+    config: ServerConfig,
+) -> (AxumRouter, ServerContext) {
+    use turntable_collab::PgDatabase;
+
+    let collab = Collab::with_database(collab_config, PgDatabase::from_test_pool(&pool)).await;
+    let context = ServerContext {
+        collab: Arc::new(collab),
+        sse: ServerSentEvents::new(&config),
+        artwork: ArtworkProxy::new(&config).into(),
+        previews: Previews::new(&config).into(),
+        config: config.into(),
+    };
+
+    (app(&context), context)
+}
@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use log::info;
+use tracing::{info_span, Instrument};
+
+/// Logs every request with its route, status, and how long it took to respond, and runs it in a span of its route.
+/// The route is logged as declared instead of the requested path, so tokens in paths and queries are never logged.
+pub async fn log_request(
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let route = matched_path
+        .as_ref()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let span = info_span!("request", method = %method, route = %route);
+    let started_at = Instant::now();
+    let response = next.run(request).instrument(span).await;
+
+    let status = response.status().as_u16();
+    let elapsed_ms = started_at.elapsed().as_millis() as u64;
+
+    info!(
+        method:% = method, route:% = route, status = status, elapsed_ms = elapsed_ms;
+        "{} {} responded with {} in {}ms", method, route, status, elapsed_ms
+    );
+
+    response
+}
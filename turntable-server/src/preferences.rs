@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::Path,
+    routing::{get, put},
+    Json,
+};
+
+use crate::{
+    auth::Session,
+    context::ServerContext,
+    errors::ServerResult,
+    schemas::{PreferenceSchema, ValidatedJson},
+    Router,
+};
+
+/// Gets the preferences of the logged in user by key.
+#[utoipa::path(
+    get,
+    path = "/v1/preferences",
+    tag = "preferences",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = HashMap<String, String>)
+    )
+)]
+async fn preferences(
+    session: Session,
+    context: ServerContext,
+) -> ServerResult<Json<HashMap<String, String>>> {
+    let preferences = context.collab.preferences.get(session.user.id).await?;
+
+    Ok(Json(preferences))
+}
+
+/// Sets a preference of the logged in user, and returns all of them.
+#[utoipa::path(
+    put,
+    path = "/v1/preferences/{key}",
+    tag = "preferences",
+    request_body = PreferenceSchema,
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = HashMap<String, String>),
+        (status = 400, description = "The key or value is invalid, or the user has too many preferences")
+    )
+)]
+async fn set_preference(
+    session: Session,
+    context: ServerContext,
+    Path(key): Path<String>,
+    ValidatedJson(body): ValidatedJson<PreferenceSchema>,
+) -> ServerResult<Json<HashMap<String, String>>> {
+    let preferences = &context.collab.preferences;
+    preferences.set(session.user.id, &key, &body.value).await?;
+
+    Ok(Json(preferences.get(session.user.id).await?))
+}
+
+/// Removes a preference of the logged in user.
+#[utoipa::path(
+    delete,
+    path = "/v1/preferences/{key}",
+    tag = "preferences",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "The preference was removed"),
+        (status = 404, description = "The preference is not set")
+    )
+)]
+async fn remove_preference(
+    session: Session,
+    context: ServerContext,
+    Path(key): Path<String>,
+) -> ServerResult<()> {
+    context
+        .collab
+        .preferences
+        .remove(session.user.id, &key)
+        .await?;
+
+    Ok(())
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(preferences))
+        .route("/:key", put(set_preference).delete(remove_preference))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{
+            header::{AUTHORIZATION, CONTENT_TYPE},
+            Request, StatusCode,
+        },
+    };
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use turntable_collab::{Credentials, NewPlainUser};
+
+    use crate::test_app;
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_set_and_get_preferences(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        collab
+            .auth
+            .register_basic(NewPlainUser {
+                username: "listener".to_string(),
+                password: "password".to_string(),
+                display_name: "listener".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let session = collab
+            .auth
+            .login(Credentials {
+                username: "listener".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let set = |key: &str, value: &str| {
+            Request::put(format!("/v1/preferences/{}", key))
+                .header(AUTHORIZATION, format!("Bearer {}", session.token))
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "value": value }).to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(set("encoder", "audio/wav"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(set("encoder", "audio/ogg"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "unsupported formats are rejected"
+        );
+
+        let request = Request::get("/v1/preferences")
+            .header(AUTHORIZATION, format!("Bearer {}", session.token))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let preferences: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(preferences, json!({ "encoder": "audio/wav" }));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{collections::HashMap, convert::Infallible, net::IpAddr, sync::Arc, time::Duration};
+
+use futures_util::{stream, Stream, StreamExt};
+use parking_lot::Mutex;
+use tokio::task::spawn_blocking;
+use turntable_core::Consumer;
+
+use crate::{
+    errors::{ServerError, ServerResult},
+    ServerConfig,
+};
+
+/// Keeps track of the preview streams open per IP address, so previews can't be used for unlimited listening.
+pub struct Previews {
+    duration: Duration,
+    max_per_ip: usize,
+    active: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// Counts as an open preview of an IP address until it is dropped
+pub struct PreviewPermit {
+    ip: IpAddr,
+    active: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Previews {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            duration: config.preview_duration,
+            max_per_ip: config.max_previews_per_ip,
+            active: Default::default(),
+        }
+    }
+
+    /// Reserves a preview for the IP address, if it doesn't have too many open already.
+    pub fn acquire(&self, ip: IpAddr) -> ServerResult<PreviewPermit> {
+        let mut active = self.active.lock();
+        let count = active.entry(ip).or_default();
+
+        if *count >= self.max_per_ip {
+            return Err(ServerError::TooManyPreviews {
+                max: self.max_per_ip,
+            });
+        }
+
+        *count += 1;
+
+        Ok(PreviewPermit {
+            ip,
+            active: self.active.clone(),
+        })
+    }
+
+    /// Returns how many bytes a preview encoded by the consumer consists of, if the encoder knows up front.
+    pub fn length(&self, consumer: &Consumer) -> Option<usize> {
+        consumer.expected_size(self.duration)
+    }
+
+    /// Returns the encoded audio of the consumer, ending once the preview duration has passed.
+    /// If the `length` of the preview is known, it ends after exactly that many bytes instead, so it matches the length clients are told.
+    /// The permit is released when the stream ends or is dropped.
+    pub fn stream(
+        &self,
+        consumer: Consumer,
+        permit: PreviewPermit,
+        length: Option<usize>,
+    ) -> impl Stream<Item = Result<Vec<u8>, Infallible>> {
+        let consumer = Arc::new(consumer);
+
+        let bytes = stream::unfold((consumer, length), |(consumer, remaining)| async move {
+            // Ends right after the last byte, instead of waiting for audio that would be cut off
+            if remaining == Some(0) {
+                return None;
+            }
+
+            let cloned = consumer.clone();
+            let mut bytes = spawn_blocking(move || cloned.bytes())
+                .await
+                .expect("infallible")?;
+
+            let remaining = remaining.map(|remaining| {
+                bytes.truncate(remaining);
+                remaining - bytes.len()
+            });
+
+            Some((bytes, (consumer, remaining)))
+        });
+
+        let limited = match length {
+            Some(_) => bytes.left_stream(),
+            None => bytes
+                .take_until(tokio::time::sleep(self.duration))
+                .right_stream(),
+        };
+
+        limited.map(move |bytes| {
+            let _ = &permit;
+            Ok(bytes)
+        })
+    }
+}
+
+impl Drop for PreviewPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock();
+
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::ServerConfig;
+
+    use super::Previews;
+
+    #[test]
+    fn test_limit_per_ip() {
+        let previews = Previews::new(&ServerConfig {
+            max_previews_per_ip: 2,
+            ..Default::default()
+        });
+
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        let a = previews.acquire(first).unwrap();
+        let _b = previews.acquire(first).unwrap();
+        assert!(previews.acquire(first).is_err(), "limit is reached");
+        assert!(previews.acquire(second).is_ok(), "limit is per address");
+
+        drop(a);
+        assert!(previews.acquire(first).is_ok(), "permit is released");
+        assert!(previews.active.lock().get(&second).is_none());
+    }
+}
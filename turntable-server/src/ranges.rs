@@ -0,0 +1,166 @@
+use std::{io::SeekFrom, path::Path};
+
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::Response,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::errors::{ServerError, ServerResult};
+
+/// What part of a resource with a known length is requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// The whole resource, because no range or an unsupported range was requested
+    Full,
+    /// The bytes from `start` up to and including `end`
+    Partial { start: u64, end: u64 },
+    /// The range is outside of the resource
+    Unsatisfiable,
+}
+
+/// Parses the value of a Range header for a resource of the given length.
+/// Only a single range of bytes is supported, and anything else is ignored in favor of the whole resource.
+pub fn parse_range(header: Option<&str>, length: u64) -> RangeRequest {
+    let Some(range) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+
+    if range.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = range.split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let (start, end) = (start.trim(), end.trim());
+
+    // A suffix range, like "-500" for the last 500 bytes
+    if start.is_empty() {
+        return match end.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(suffix) if length > 0 => RangeRequest::Partial {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            },
+            Ok(_) => RangeRequest::Unsatisfiable,
+            Err(_) => RangeRequest::Full,
+        };
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+
+    let end = match end {
+        "" => length.saturating_sub(1),
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end.min(length.saturating_sub(1)),
+            _ => return RangeRequest::Full,
+        },
+    };
+
+    if start >= length {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Partial { start, end }
+}
+
+/// Serves a finished file, or the part of it requested with a Range header
+pub async fn serve_file(
+    path: &Path,
+    content_type: &str,
+    range: Option<&str>,
+) -> ServerResult<Response<Body>> {
+    let mut file = File::open(path)
+        .await
+        .map_err(|e| ServerError::Unknown(e.to_string()))?;
+
+    let length = file
+        .metadata()
+        .await
+        .map_err(|e| ServerError::Unknown(e.to_string()))?
+        .len();
+
+    let response = Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .header(ACCEPT_RANGES, "bytes");
+
+    let (start, end) = match parse_range(range, length) {
+        RangeRequest::Full => {
+            let body = Body::from_stream(ReaderStream::new(file));
+
+            return Ok(response
+                .status(StatusCode::OK)
+                .header(CONTENT_LENGTH, length)
+                .body(body)
+                .unwrap());
+        }
+        RangeRequest::Unsatisfiable => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{}", length))
+                .body(Body::empty())
+                .unwrap());
+        }
+        RangeRequest::Partial { start, end } => (start, end),
+    };
+
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|e| ServerError::Unknown(e.to_string()))?;
+
+    let window = end - start + 1;
+    let body = Body::from_stream(ReaderStream::new(file.take(window)));
+
+    Ok(response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(CONTENT_LENGTH, window)
+        .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length))
+        .body(body)
+        .unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_range, RangeRequest};
+
+    #[test]
+    fn test_parse_range() {
+        let partial = |start, end| RangeRequest::Partial { start, end };
+
+        assert_eq!(parse_range(None, 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=0-49"), 100), partial(0, 49));
+        assert_eq!(parse_range(Some("bytes=50-"), 100), partial(50, 99));
+        assert_eq!(parse_range(Some("bytes=90-200"), 100), partial(90, 99));
+        assert_eq!(parse_range(Some("bytes=-10"), 100), partial(90, 99));
+        assert_eq!(parse_range(Some("bytes=-200"), 100), partial(0, 99));
+
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-0"), 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=0-"), 0),
+            RangeRequest::Unsatisfiable
+        );
+
+        assert_eq!(parse_range(Some("bytes=0-1, 5-6"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=5-1"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), RangeRequest::Full);
+    }
+}
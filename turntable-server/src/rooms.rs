@@ -1,16 +1,39 @@
-use axum::{extract::Path, response::IntoResponse, routing::{get, post}, Json};
-use futures_util::future::join_all;
-use turntable_collab::{Input, NewRoom, Track as CollabTrack};
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, RANGE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::FutureExt;
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+use turntable_collab::{
+    AddEntry, NewRoom, PendingAddId, Playlist, RecordingOptions, ScheduledAction,
+    Track as CollabTrack, TrackId, MAX_HISTORY_LENGTH,
+};
 use turntable_core::Queue as CoreQueue;
+use turntable_impls::EncoderKind;
 
 use crate::{
     auth::Session,
     context::ServerContext,
-    errors::ServerResult,
+    errors::{ServerError, ServerResult},
+    ranges::serve_file,
     schemas::{
-        InputSchema, JoinWithInviteSchema, NewRoomSchema, NewStreamKeySchema, RoomActionSchema, ValidatedJson
+        InputSchema, JoinWithInviteSchema, NewRecordingSchema, NewRoomSchema, NewScheduleSchema,
+        NewStreamKeySchema, RoomActionSchema, ScheduledActionKind, ValidatedJson,
     },
-    serialized::{Queue, Room, RoomInvite, StreamKey, ToSerialized}, Router
+    serialized::{
+        BatchEntry, NowPlaying, PendingAdd, Queue, QueueItem, Recording, Room, RoomInvite,
+        RoomSchedule, StreamKey, ToSerialized,
+    },
+    Router,
 };
 
 #[utoipa::path(
@@ -24,13 +47,13 @@ use crate::{
         (status = 200, body = Vec<Room>)
     )
 )]
-async fn list_rooms(_session: Session, context: ServerContext) -> impl IntoResponse {
-    let rooms: Vec<_> = context
+async fn list_rooms(session: Session, context: ServerContext) -> impl IntoResponse {
+    let rooms: Vec<Room> = context
         .collab
         .rooms
-        .list_all()
+        .list_all(session.user.id)
         .into_iter()
-        .map(|r| r.to_serialized())
+        .map(|r| (&r, &*context.artwork).to_serialized())
         .collect();
 
     Json(rooms)
@@ -48,13 +71,46 @@ async fn list_rooms(_session: Session, context: ServerContext) -> impl IntoRespo
     )
 )]
 async fn room(
-    _session: Session,
+    session: Session,
     context: ServerContext,
     Path(slug): Path<String>,
 ) -> ServerResult<Json<Room>> {
-    let room = context.collab.rooms.room_by_slug(&slug)?;
+    let room = context.collab.rooms.room_by_slug(&slug, session.user.id)?;
+
+    Ok(Json((&room, &*context.artwork).to_serialized()))
+}
+
+/// Gets what is playing in a room, for clients that poll instead of listening to events.
+#[utoipa::path(
+    get,
+    path = "/v1/rooms/{slug}/now-playing",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = NowPlaying, description = "What is playing, which may be cached for a second"),
+        (status = 404, description = "The room does not exist, or is not active")
+    )
+)]
+async fn now_playing(
+    session: Session,
+    context: ServerContext,
+    Path(slug): Path<String>,
+) -> ServerResult<impl IntoResponse> {
+    let room = context.collab.rooms.room_by_slug(&slug, session.user.id)?;
 
-    Ok(Json(room.to_serialized()))
+    // An inactive room has no player, so nothing is playing in it
+    if room.player().is_err() {
+        return Err(ServerError::NotFound {
+            resource: "active room",
+            identifier: slug,
+        });
+    }
+
+    let now_playing: NowPlaying = (&room, &*context.artwork).to_serialized();
+
+    Ok(([(CACHE_CONTROL, "private, max-age=1")], Json(now_playing)))
 }
 
 #[utoipa::path(
@@ -69,15 +125,35 @@ async fn room(
         (status = 200, body = Room)
     )
 )]
-async fn create_room(session: Session, context: ServerContext, ValidatedJson(body): ValidatedJson<NewRoomSchema>) -> ServerResult<Json<Room>> {
-    let room = context.collab.rooms.create_room(NewRoom {
-        slug: body.slug,
-        title: body.title,
-        description: body.description,
-        user_id: session.user.id
-    }).await?;
-
-    Ok(Json(room.to_serialized()))
+async fn create_room(
+    session: Session,
+    context: ServerContext,
+    ValidatedJson(body): ValidatedJson<NewRoomSchema>,
+) -> ServerResult<Json<Room>> {
+    let room = context
+        .collab
+        .rooms
+        .create_room(NewRoom {
+            slug: body.slug.unwrap_or_default(),
+            title: body.title,
+            description: body.description,
+            visibility: body.visibility.map(|v| v.into()).unwrap_or_default(),
+            max_queue_size: body.max_queue_size,
+            max_track_duration: body.max_track_duration,
+            track_gap_in_ms: body.track_gap_in_ms.unwrap_or_default(),
+            allowed_sources: body.allowed_sources.unwrap_or_default(),
+            denied_sources: body.denied_sources.unwrap_or_default(),
+            disconnect_policy: body.disconnect_policy.map(|p| p.into()).unwrap_or_default(),
+            empty_room_policy: body.empty_room_policy.map(|p| p.into()).unwrap_or_default(),
+            default_latency_in_ms: body.default_latency_in_ms,
+            trim_leading_silence: body.trim_leading_silence.unwrap_or_default(),
+            jingle: body.jingle,
+            explicit_filter: body.explicit_filter.unwrap_or_default(),
+            user_id: session.user.id,
+        })
+        .await?;
+
+    Ok(Json((&room, &*context.artwork).to_serialized()))
 }
 
 #[utoipa::path(
@@ -109,8 +185,22 @@ async fn stream_keys(session: Session, context: ServerContext, Path(room_id): Pa
         (status = 200, body = StreamKey)
     )
 )]
-async fn create_stream_key(session: Session, context: ServerContext, Path(room_id): Path<i32>, ValidatedJson(body): ValidatedJson<NewStreamKeySchema>) -> ServerResult<Json<StreamKey>> {
-    let new_key = context.collab.rooms.create_stream_key(room_id, session.user.id, body.source).await?;
+async fn create_stream_key(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    ValidatedJson(body): ValidatedJson<NewStreamKeySchema>,
+) -> ServerResult<Json<StreamKey>> {
+    let new_key = context
+        .collab
+        .rooms
+        .create_stream_key(
+            room_id,
+            session.user.id,
+            body.source,
+            body.can_queue.unwrap_or_default(),
+        )
+        .await?;
 
     Ok(Json(new_key.to_serialized()))
 }
@@ -126,11 +216,55 @@ async fn create_stream_key(session: Session, context: ServerContext, Path(room_i
         (status = 200, body = Queue)
     )
 )]
-async fn queue(_session: Session, context: ServerContext, Path(room_id): Path<i32>) -> ServerResult<Json<Queue>> {
-    let room = context.collab.rooms.room_by_id(room_id)?;
+async fn queue(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<Json<Queue>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
     let queue = room.queue()?;
 
-    Ok(Json(queue.tracks().to_serialized()))
+    Ok(Json((&queue.tracks(), &*context.artwork).to_serialized()))
+}
+
+#[derive(Debug, Deserialize)]
+struct QueueHistoryParams {
+    limit: Option<usize>,
+}
+
+/// Gets the tracks that played most recently, the most recent first.
+#[utoipa::path(
+    get,
+    path = "/v1/rooms/{id}/queue/history",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    params(
+        ("limit" = Option<usize>, Query, description = "How many tracks to return at most. Defaults to 20, and only the last 200 played tracks are kept.")
+    ),
+    responses(
+        (status = 200, body = Vec<QueueItem>)
+    )
+)]
+async fn queue_history(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    Query(params): Query<QueueHistoryParams>,
+) -> ServerResult<Json<Vec<QueueItem>>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let limit = params.limit.unwrap_or(20).min(MAX_HISTORY_LENGTH);
+
+    Ok(Json(
+        (&room.queue()?.history(limit), &*context.artwork).to_serialized(),
+    ))
 }
 
 #[utoipa::path(
@@ -142,28 +276,223 @@ async fn queue(_session: Session, context: ServerContext, Path(room_id): Path<i3
         ("BearerAuth" = [])
     ),
     responses(
-        (status = 200, description = "Item(s) were added to the queue")
+        (status = 200, body = PendingAdd, description = "Item(s) are added to the queue as they resolve, and progress is reported with events, including why entries failed")
     )
 )]
-async fn add_to_queue(session: Session, context: ServerContext, Path(room_id): Path<i32>, ValidatedJson(body): ValidatedJson<InputSchema>) -> ServerResult<()> {
-    let room = context.collab.rooms.room_by_id(room_id)?;
-    let queue = room.queue()?;
+async fn add_to_queue(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    ValidatedJson(body): ValidatedJson<InputSchema>,
+) -> ServerResult<Json<PendingAdd>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let (start, end) = (body.start, body.end);
 
-    let futs: Vec<_> = body.query.iter().map(|q| Input::query(q)).collect();
-    let results = join_all(futs).await;
-    let mut tracks: Vec<CollabTrack> = vec![];
+    let entries: Vec<AddEntry> = body
+        .query
+        .into_iter()
+        .map(|q| {
+            let inputs = context.collab.inputs.clone();
 
-    for result in results {
-        let inputs = result?;
+            async move {
+                let inputs = inputs.query(&q).await?;
+                Ok(inputs
+                    .into_iter()
+                    .map(|i| CollabTrack::from(i).with_trim(start, end))
+                    .collect())
+            }
+            .boxed()
+        })
+        .collect();
 
-        for input in inputs {
-            tracks.push(input.into())
-        }
-    }
+    let pending = room.add_entries(entries, &session.user);
 
-    for track in tracks {
-        queue.push(track, session.user.id)
-    }
+    Ok(Json(pending.to_serialized()))
+}
+
+/// Adds several queries at once, like URLs pasted on separate lines.
+#[utoipa::path(
+    post,
+    path = "/v1/rooms/{id}/queue/batch",
+    tag = "rooms",
+    request_body = InputSchema,
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = Vec<BatchEntry>, description = "All queries were resolved, and the ones that could be were added in order. A query that couldn't be added has an error instead.")
+    )
+)]
+async fn add_batch_to_queue(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    ValidatedJson(body): ValidatedJson<InputSchema>,
+) -> ServerResult<Json<Vec<BatchEntry>>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let (start, end) = (body.start, body.end);
+
+    // Each query can contain several on separate lines, which is what pasting them gives
+    let queries: Vec<String> = body
+        .query
+        .iter()
+        .flat_map(|q| Playlist::parse_m3u(q))
+        .collect();
+
+    let entries: Vec<AddEntry> = queries
+        .iter()
+        .cloned()
+        .map(|q| {
+            let inputs = context.collab.inputs.clone();
+
+            async move {
+                let inputs = inputs.query(&q).await?;
+                Ok(inputs
+                    .into_iter()
+                    .map(|i| CollabTrack::from(i).with_trim(start, end))
+                    .collect())
+            }
+            .boxed()
+        })
+        .collect();
+
+    let results = room.add_batch(entries, (&session.user).into()).await?;
+    let entries = queries
+        .into_iter()
+        .zip(results)
+        .map(|(q, r)| BatchEntry::new(q, r))
+        .collect();
+
+    Ok(Json(entries))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportPlaylistParams {
+    history: Option<bool>,
+}
+
+/// Exports the queue as an M3U playlist of the canonical URLs of its tracks, which can be imported again.
+#[utoipa::path(
+    get,
+    path = "/v1/rooms/{id}/queue/playlist",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    params(
+        ("history" = Option<bool>, Query, description = "Whether to include the tracks that already played before the queue. Defaults to false.")
+    ),
+    responses(
+        (status = 200, content_type = "audio/x-mpegurl", description = "The playlist. Tracks without a shareable URL, like local files, are left out and noted in comments.")
+    )
+)]
+async fn export_playlist(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    Query(params): Query<ExportPlaylistParams>,
+) -> ServerResult<impl IntoResponse> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let playlist = room.export_playlist(params.history.unwrap_or_default())?;
+    let disposition = format!("attachment; filename=\"{}.m3u\"", room.data().slug);
+
+    Ok((
+        [
+            (CONTENT_TYPE, Playlist::CONTENT_TYPE.to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        playlist.to_m3u(),
+    ))
+}
+
+/// Adds the tracks of an M3U playlist, or a list of URLs with one on each line, to the queue.
+#[utoipa::path(
+    post,
+    path = "/v1/rooms/{id}/queue/playlist",
+    tag = "rooms",
+    request_body(content = String, content_type = "audio/x-mpegurl"),
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = PendingAdd, description = "Item(s) are added to the queue as they resolve, and progress is reported with events, including why entries failed")
+    )
+)]
+async fn import_playlist(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    body: String,
+) -> ServerResult<Json<PendingAdd>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+
+    let entries: Vec<AddEntry> = Playlist::parse_m3u(&body)
+        .into_iter()
+        .map(|q| {
+            let inputs = context.collab.inputs.clone();
+
+            async move {
+                let inputs = inputs.query(&q).await?;
+                Ok(inputs.into_iter().map(CollabTrack::from).collect())
+            }
+            .boxed()
+        })
+        .collect();
+
+    let pending = room.add_entries(entries, &session.user);
+
+    Ok(Json(pending.to_serialized()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelAddParams {
+    remove_added: Option<bool>,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/rooms/{id}/queue/adds/{add_id}",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    params(
+        ("removeAdded" = Option<bool>, Query, description = "Whether to also remove the items the add already added. Defaults to false.")
+    ),
+    responses(
+        (status = 200, description = "No more items will be added by the add"),
+        (status = 404, description = "The add does not exist, belongs to another user, or has already finished")
+    )
+)]
+async fn cancel_add(
+    session: Session,
+    context: ServerContext,
+    Path((room_id, add_id)): Path<(i32, u64)>,
+    params: Query<CancelAddParams>,
+) -> ServerResult<()> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    room.cancel_add(
+        session.user.id,
+        PendingAddId::from_value(add_id),
+        params.remove_added.unwrap_or_default(),
+    )?;
 
     Ok(())
 }
@@ -182,6 +511,128 @@ async fn invite_by_token(context: ServerContext, Path(token): Path<String>) -> S
     Ok(Json(invite.to_serialized()))
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateInviteParams {
+    expires_in: Option<u32>,
+    max_uses: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/rooms/{id}/schedules",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = Vec<RoomSchedule>, description = "The actions that haven't happened yet, the earliest first"),
+        (status = 403, description = "The user is not a member of the room")
+    )
+)]
+async fn schedules(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<Json<Vec<RoomSchedule>>> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let _ = room.member_by_user_id(session.user.id)?;
+
+    let schedules = context
+        .collab
+        .schedules
+        .list(room_id)
+        .iter()
+        .map(|s| s.to_serialized())
+        .collect();
+
+    Ok(Json(schedules))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/rooms/{id}/schedules",
+    tag = "rooms",
+    request_body = NewScheduleSchema,
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = RoomSchedule, description = "The action will happen at the given time, even if turntable restarts"),
+        (status = 400, description = "The time is not valid RFC 3339"),
+        (status = 403, description = "The user is not a member of the room")
+    )
+)]
+async fn create_schedule(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    ValidatedJson(body): ValidatedJson<NewScheduleSchema>,
+) -> ServerResult<Json<RoomSchedule>> {
+    let fire_at = DateTime::parse_from_rfc3339(&body.fire_at)
+        .map_err(|e| ServerError::InvalidScheduleTime(e.to_string()))?
+        .to_utc();
+
+    let action = match body.action {
+        ScheduledActionKind::Play => ScheduledAction::Play,
+        ScheduledActionKind::QueuePlaylist => ScheduledAction::QueuePlaylist(body.urls),
+    };
+
+    let schedule = context
+        .collab
+        .schedules
+        .schedule(room_id, session.user.id, action, fire_at)
+        .await?;
+
+    Ok(Json(schedule.to_serialized()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/rooms/{id}/schedules/{schedule_id}",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, description = "The action won't happen"),
+        (status = 403, description = "The user is not a member of the room"),
+        (status = 404, description = "The schedule does not exist or has already happened")
+    )
+)]
+async fn cancel_schedule(
+    session: Session,
+    context: ServerContext,
+    Path((room_id, schedule_id)): Path<(i32, i32)>,
+) -> ServerResult<()> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
+    let _ = room.member_by_user_id(session.user.id)?;
+
+    // Schedules of other rooms can't be cancelled through this one
+    if !context
+        .collab
+        .schedules
+        .list(room_id)
+        .iter()
+        .any(|s| s.id == schedule_id)
+    {
+        return Err(ServerError::NotFound {
+            resource: "schedule",
+            identifier: schedule_id.to_string(),
+        });
+    }
+
+    context.collab.schedules.cancel(schedule_id).await?;
+
+    Ok(())
+}
+
 #[utoipa::path(
     post,
     path = "/v1/rooms/{id}/invites",
@@ -189,12 +640,33 @@ async fn invite_by_token(context: ServerContext, Path(token): Path<String>) -> S
     security(
         ("BearerAuth" = [])
     ),
+    params(
+        ("expiresIn" = Option<u32>, Query, description = "How many seconds the invite works for. Forever if not set."),
+        ("maxUses" = Option<u32>, Query, description = "How many members can join with the invite, where 0 is any amount. Defaults to 1.")
+    ),
     responses(
         (status = 200, body = RoomInvite)
     )
 )]
-async fn create_invite(session: Session, context: ServerContext, Path(room_id): Path<i32>) -> ServerResult<Json<RoomInvite>> {
-    let invite = context.collab.rooms.create_invite(session.user.id, room_id).await?;
+async fn create_invite(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    params: Query<CreateInviteParams>,
+) -> ServerResult<Json<RoomInvite>> {
+    let expires_at = params
+        .expires_in
+        .map(|s| Utc::now() + TimeDelta::seconds(s.into()));
+    let max_uses = match params.max_uses.unwrap_or(1) {
+        0 => None,
+        max => Some(max.try_into().unwrap_or(i32::MAX)),
+    };
+
+    let invite = context
+        .collab
+        .rooms
+        .create_invite_with_limits(session.user.id, room_id, expires_at, max_uses)
+        .await?;
 
     Ok(Json(invite.to_serialized()))
 }
@@ -225,23 +697,185 @@ async fn join_with_invite(session: Session, context: ServerContext, ValidatedJso
         ("BearerAuth" = [])
     ),
     responses(
-        (status = 200, description = "Action was performed.")
+        (status = 200, description = "Action was performed."),
+        (status = 403, description = "The action requires an owner of the room, and the user isn't one"),
+        (status = 429, description = "The user moved their tracks to the front too recently")
     )
 )]
-async fn perform_room_action(_session: Session, context: ServerContext, Path(room_id): Path<i32>, Json(body): Json<RoomActionSchema>) -> ServerResult<()> {
-    let room = context.collab.rooms.room_by_id(room_id)?;
+async fn perform_room_action(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    Json(body): Json<RoomActionSchema>,
+) -> ServerResult<()> {
+    let room = context
+        .collab
+        .rooms
+        .room_by_id_for(room_id, session.user.id)?;
 
     match body {
-        RoomActionSchema::Play => { room.player()?.play() },
-        RoomActionSchema::Pause => { room.player()?.pause() },
-        RoomActionSchema::Next => { room.queue()?.next() },
-        RoomActionSchema::Previous => { room.queue()?.previous() },
-        RoomActionSchema::Seek { to } => { room.player()?.seek(to) }
+        RoomActionSchema::Play => room.player()?.play(),
+        RoomActionSchema::Pause => room.player()?.pause(),
+        RoomActionSchema::Next => room.queue()?.next(),
+        RoomActionSchema::Previous => room.queue()?.previous(),
+        RoomActionSchema::Seek { to } => room.player()?.seek(to),
+        RoomActionSchema::SeekFraction { to } => room.seek_fraction(session.user.id, to)?,
+        RoomActionSchema::Speed { to } => room.player()?.set_speed(to),
+        RoomActionSchema::MuteSubmitter { user_id } => {
+            room.set_submitter_muted(session.user.id, user_id, true)?
+        }
+        RoomActionSchema::UnmuteSubmitter { user_id } => {
+            room.set_submitter_muted(session.user.id, user_id, false)?
+        }
+        RoomActionSchema::SetTrackGain { track_id, gain } => {
+            room.set_track_gain(TrackId::from_value(track_id as u64), gain)?
+        }
+        RoomActionSchema::MoveMyTracksToFront => {
+            room.move_tracks_to_front(session.user.id)?;
+        }
+        RoomActionSchema::RequeueFromHistory { track_id } => {
+            room.requeue_from_history(TrackId::from_value(track_id as u64), &session.user)?;
+        }
     };
 
     Ok(())
 }
 
+#[utoipa::path(
+    get, 
+    path = "/v1/rooms/{id}/recording",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = Option<Recording>, description = "The recording of the room, or null if it's not being recorded"),
+        (status = 403, description = "The user is not a superuser")
+    )
+)]
+async fn recording(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<Json<Option<Recording>>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let room = context.collab.rooms.room_by_id(room_id)?;
+
+    Ok(Json(room.recording().map(|r| r.to_serialized())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/rooms/{id}/recording",
+    tag = "rooms",
+    request_body = NewRecordingSchema,
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = Recording, description = "The room is being recorded, without affecting listeners"),
+        (status = 403, description = "The user is not a superuser"),
+        (status = 409, description = "The room is already being recorded")
+    )
+)]
+async fn start_recording(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+    ValidatedJson(body): ValidatedJson<NewRecordingSchema>,
+) -> ServerResult<Json<Recording>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let encoder = match body.format {
+        Some(format) => EncoderKind::from_mime(&format)
+            .ok_or(ServerError::UnsupportedRecordingFormat(format))?,
+        None => EncoderKind::default(),
+    };
+
+    let room = context.collab.rooms.room_by_id(room_id)?;
+    let recording = room.start_recording(RecordingOptions {
+        encoder,
+        max_file_seconds: body.max_file_seconds,
+        max_file_bytes: body.max_file_bytes,
+    })?;
+
+    Ok(Json(recording.to_serialized()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/rooms/{id}/recording",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    responses(
+        (status = 200, body = Recording, description = "The recording was stopped, and its files are written"),
+        (status = 400, description = "The room is not being recorded"),
+        (status = 403, description = "The user is not a superuser")
+    )
+)]
+async fn stop_recording(
+    session: Session,
+    context: ServerContext,
+    Path(room_id): Path<i32>,
+) -> ServerResult<Json<Recording>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let room = context.collab.rooms.room_by_id(room_id)?;
+
+    // Stopping waits for the files to be written
+    let recording = spawn_blocking(move || room.stop_recording().map_err(ServerError::from))
+        .await
+        .expect("recording is stopped")?;
+
+    Ok(Json(recording.to_serialized()))
+}
+
+/// Gets a finished file of a recording of the room, supporting Range requests to resume downloads or seek.
+#[utoipa::path(
+    get,
+    path = "/v1/rooms/{id}/recordings/{name}",
+    tag = "rooms",
+    security(
+        ("BearerAuth" = [])
+    ),
+    params(
+        ("name" = String, Path, description = "The name of a file, as listed in the recording"),
+        ("Range" = Option<String>, Header, description = "A single range of bytes to get, like bytes=0-1023")
+    ),
+    responses(
+        (status = 200, content_type = "application/octet-stream", description = "The whole file"),
+        (status = 206, content_type = "application/octet-stream", description = "The requested range of the file"),
+        (status = 403, description = "The user is not a superuser"),
+        (status = 404, description = "The file does not exist, or is still being written"),
+        (status = 416, description = "The range is outside of the file")
+    )
+)]
+async fn recorded_file(
+    session: Session,
+    context: ServerContext,
+    headers: HeaderMap,
+    Path((room_id, name)): Path<(i32, String)>,
+) -> ServerResult<Response<Body>> {
+    if !session.user.superuser {
+        return Err(ServerError::SuperuserRequired);
+    }
+
+    let room = context.collab.rooms.room_by_id(room_id)?;
+    let (path, encoder) = room.recorded_file(&name)?;
+    let range = headers.get(RANGE).and_then(|v| v.to_str().ok());
+
+    serve_file(&path, encoder.content_type(), range).await
+}
+
 pub fn router() -> Router {
     Router::new()
         .route("/", get(list_rooms))
@@ -249,10 +883,381 @@ pub fn router() -> Router {
         .route("/invites/:id", get(invite_by_token))
         .route("/members", post(join_with_invite))
         .route("/:id", get(room))
+        .route("/:id/now-playing", get(now_playing))
         .route("/:id/keys", get(stream_keys))
         .route("/:id/keys", post(create_stream_key))
         .route("/:id/queue", get(queue))
         .route("/:id/queue", post(add_to_queue))
+        .route("/:id/queue/batch", post(add_batch_to_queue))
+        .route("/:id/queue/history", get(queue_history))
+        .route("/:id/queue/adds/:add_id", delete(cancel_add))
+        .route("/:id/queue/playlist", get(export_playlist))
+        .route("/:id/queue/playlist", post(import_playlist))
         .route("/:id/invites", post(create_invite))
+        .route("/:id/schedules", get(schedules))
+        .route("/:id/schedules", post(create_schedule))
+        .route("/:id/schedules/:schedule_id", delete(cancel_schedule))
+        .route("/:id/recording", get(recording))
+        .route("/:id/recording", post(start_recording))
+        .route("/:id/recording", delete(stop_recording))
+        .route("/:id/recordings/:name", get(recorded_file))
         .route("/:id/actions", post(perform_room_action))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{
+            header::{ACCEPT_RANGES, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, RANGE},
+            Method, Request, StatusCode,
+        },
+    };
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use turntable_collab::{
+        CollabConfig, Credentials, Input, NewPlainUser, NewRoom, RoomVisibility, Track,
+    };
+    use turntable_impls::EncoderKind;
+
+    use crate::{test_app, test_app_with_configs};
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_recording_requires_superuser(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let new_user = |username: &str| NewPlainUser {
+            username: username.to_string(),
+            password: "password".to_string(),
+            display_name: username.to_string(),
+        };
+
+        let admin = collab
+            .auth
+            .register_superuser(new_user("admin"))
+            .await
+            .unwrap();
+        collab.auth.register_basic(new_user("user")).await.unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: admin.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let login = |username: &str| {
+            collab.auth.login(Credentials {
+                username: username.to_string(),
+                password: "password".to_string(),
+            })
+        };
+
+        let user_session = login("user").await.unwrap();
+        let admin_session = login("admin").await.unwrap();
+
+        let request = |method: Method, token: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(format!("/v1/rooms/{}/recording", room.id()))
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(Method::POST, &user_session.token, "{}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(room.recording().is_none());
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                Method::POST,
+                &admin_session.token,
+                r#"{"format":"audio/mp3"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(request(Method::DELETE, &admin_session.token, ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "not recording");
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_recorded_file_ranges(pool: PgPool) {
+        let dir = std::env::temp_dir().join("turntable_recorded_file_ranges");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let collab_config = CollabConfig {
+            recordings_dir: dir.clone(),
+            ..Default::default()
+        };
+        let (app, context) = test_app_with_configs(pool, collab_config, Default::default()).await;
+        let collab = &context.collab;
+
+        let admin = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "admin".to_string(),
+                password: "password".to_string(),
+                display_name: "admin".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: admin.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let session = collab
+            .auth
+            .login(Credentials {
+                username: "admin".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // A finished file of a recording, with bytes that differ between nearby offsets
+        let name = format!("room-{}-20240101-000000-000.wav", room.id());
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.join(&name), &contents).unwrap();
+
+        let request = |name: &str, range: Option<String>| {
+            let mut request = Request::get(format!("/v1/rooms/{}/recordings/{}", room.id(), name))
+                .header(AUTHORIZATION, format!("Bearer {}", session.token));
+
+            if let Some(range) = range {
+                request = request.header(RANGE, range);
+            }
+
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request(&name, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(response.headers()[CONTENT_LENGTH], "10000");
+        assert_eq!(response.headers()["content-type"], "audio/wav");
+
+        let full = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(full, contents);
+
+        let half = contents.len() / 2;
+        let mut downloaded = vec![];
+
+        for range in [format!("bytes=0-{}", half - 1), format!("bytes={}-", half)] {
+            let response = app
+                .clone()
+                .oneshot(request(&name, Some(range)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(body.len(), half);
+
+            downloaded.extend_from_slice(&body);
+        }
+
+        assert_eq!(downloaded, contents, "the halves make up the file");
+
+        let response = app
+            .clone()
+            .oneshot(request(&name, Some("bytes=100-199".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 100-199/10000");
+
+        let response = app
+            .clone()
+            .oneshot(request(&name, Some("bytes=10000-".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10000");
+
+        // Files of other rooms, or outside of the directory, can't be requested
+        for name in ["room-0-20240101-000000-000.wav", "..%2Froom-1-000.wav"] {
+            let response = app.clone().oneshot(request(name, None)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_now_playing(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "Owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let new_room = |slug: &str| NewRoom {
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            visibility: RoomVisibility::Public,
+            user_id: user.id,
+            ..Default::default()
+        };
+
+        let room = collab.rooms.create_room(new_room("room")).await.unwrap();
+        collab
+            .rooms
+            .create_room(new_room("inactive"))
+            .await
+            .unwrap();
+
+        let session = collab
+            .auth
+            .login(Credentials {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let request = |slug: &str| {
+            Request::get(format!("/v1/rooms/{}/now-playing", slug))
+                .header(AUTHORIZATION, format!("Bearer {}", session.token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for slug in ["inactive", "unknown"] {
+            let response = app.clone().oneshot(request(slug)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        // A few seconds of audio, so it's still playing when requested
+        let config = &collab.config.pipeline;
+        let samples = 4 * config.sample_rate * config.channel_count;
+        let mut bytes = EncoderKind::Wave.header(config, Some(samples * 2));
+        bytes.extend((0..samples).flat_map(|i| ((i % 1000) as i16 + 1).to_le_bytes()));
+
+        let path = std::env::temp_dir().join("turntable_now_playing.wav");
+        std::fs::write(&path, bytes).unwrap();
+
+        let input = Input::query(&format!("file://{}", path.display()))
+            .await
+            .unwrap()
+            .remove(0);
+
+        let track = Track::from(input);
+        let track_id = track.id;
+
+        room.queue().unwrap().push(track, (&user).into()).unwrap();
+        let player = room.player().unwrap();
+
+        for _ in 0..5000 {
+            if player.current_time() > 0. {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let response = app.clone().oneshot(request("room")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["cache-control"], "private, max-age=1");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let now_playing: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(now_playing["roomId"], room.id());
+        assert_eq!(now_playing["item"]["track"]["id"], track_id.value());
+        assert_eq!(now_playing["item"]["submitter"]["displayName"], "Owner");
+        assert_eq!(now_playing["isPlaying"], true);
+        assert_eq!(now_playing["listenerCount"], 0);
+        assert!(now_playing["positionSeconds"].as_f64().unwrap() > 0.);
+        assert!((now_playing["durationSeconds"].as_f64().unwrap() - 4.).abs() < 0.1);
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_private_room_is_hidden_from_non_members(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let new_user = |username: &str| NewPlainUser {
+            username: username.to_string(),
+            password: "password".to_string(),
+            display_name: username.to_string(),
+        };
+
+        let owner = collab.auth.register_basic(new_user("owner")).await.unwrap();
+        collab.auth.register_basic(new_user("other")).await.unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Private,
+                user_id: owner.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let login = |username: &str| {
+            collab.auth.login(Credentials {
+                username: username.to_string(),
+                password: "password".to_string(),
+            })
+        };
+
+        let owner_session = login("owner").await.unwrap();
+        let other_session = login("other").await.unwrap();
+
+        let request = |token: &str| {
+            Request::get(format!("/v1/rooms/{}/queue", room.id()))
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(&other_session.token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(request(&owner_session.token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -6,6 +6,7 @@ use axum::{
 };
 
 use serde::{de::DeserializeOwned, Deserialize};
+use turntable_collab::{DisconnectPolicy, EmptyRoomPolicy, RoomVisibility};
 use utoipa::ToSchema;
 use validator::Validate;
 
@@ -33,12 +34,98 @@ pub struct RegisterSchema {
 #[derive(Debug, ToSchema, Validate, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct NewRoomSchema {
+    /// Derived from the title if not set
     #[validate(length(min = 3, max = 64))]
-    pub slug: String,
+    pub slug: Option<String>,
     #[validate(length(min = 3, max = 64))]
     pub title: String,
     #[validate(length(max = 2048))]
     pub description: Option<String>,
+    pub visibility: Option<RoomVisibilitySchema>,
+    /// How many items the queue can contain at most. Unlimited if not set.
+    #[validate(range(min = 1))]
+    pub max_queue_size: Option<i32>,
+    /// How many seconds a track can play for at most. Unlimited if not set.
+    #[validate(range(min = 1))]
+    pub max_track_duration: Option<i32>,
+    /// How many milliseconds of silence are played between tracks, like for talk or DJ sets. Defaults to none.
+    #[validate(range(min = 0, max = 10000))]
+    pub track_gap_in_ms: Option<i32>,
+    /// Sources that can be queued, by name (like `youtube`) or domain. Any source can be queued if not set.
+    pub allowed_sources: Option<Vec<String>>,
+    /// Sources that can't be queued, even if they're allowed
+    pub denied_sources: Option<Vec<String>>,
+    /// What happens to a member's queued items when they disconnect. Defaults to keeping them.
+    pub disconnect_policy: Option<DisconnectPolicySchema>,
+    /// Whether the room keeps playing when nobody is listening. Defaults to advancing the queue.
+    pub empty_room_policy: Option<EmptyRoomPolicySchema>,
+    /// How many milliseconds of latency streams have if they don't ask for any. Defaults to the server's default.
+    #[validate(range(min = 0))]
+    pub default_latency_in_ms: Option<i32>,
+    /// Whether silence at the start of tracks is skipped, up to a few seconds. Defaults to false.
+    pub trim_leading_silence: Option<bool>,
+    /// A URL or file path of a short jingle played between tracks, like a station ident. Defaults to none.
+    #[validate(length(min = 1))]
+    pub jingle: Option<String>,
+    /// Whether tracks flagged as explicit by their source, or matching the server's blocklist, are rejected when added. Defaults to false.
+    pub explicit_filter: Option<bool>,
+}
+
+#[derive(Debug, ToSchema, Validate, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PreferenceSchema {
+    /// Validated if the preference is known, like `encoder` which must be a supported content type
+    pub value: String,
+}
+
+#[derive(Debug, ToSchema, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomVisibilitySchema {
+    Public,
+    Unlisted,
+    Private,
+}
+
+impl From<RoomVisibilitySchema> for RoomVisibility {
+    fn from(value: RoomVisibilitySchema) -> Self {
+        match value {
+            RoomVisibilitySchema::Public => Self::Public,
+            RoomVisibilitySchema::Unlisted => Self::Unlisted,
+            RoomVisibilitySchema::Private => Self::Private,
+        }
+    }
+}
+
+#[derive(Debug, ToSchema, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DisconnectPolicySchema {
+    KeepQueued,
+    RemoveQueued,
+}
+
+impl From<DisconnectPolicySchema> for DisconnectPolicy {
+    fn from(value: DisconnectPolicySchema) -> Self {
+        match value {
+            DisconnectPolicySchema::KeepQueued => Self::KeepQueued,
+            DisconnectPolicySchema::RemoveQueued => Self::RemoveQueued,
+        }
+    }
+}
+
+#[derive(Debug, ToSchema, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmptyRoomPolicySchema {
+    AdvanceWhenEmpty,
+    PauseWhenEmpty,
+}
+
+impl From<EmptyRoomPolicySchema> for EmptyRoomPolicy {
+    fn from(value: EmptyRoomPolicySchema) -> Self {
+        match value {
+            EmptyRoomPolicySchema::AdvanceWhenEmpty => Self::AdvanceWhenEmpty,
+            EmptyRoomPolicySchema::PauseWhenEmpty => Self::PauseWhenEmpty,
+        }
+    }
 }
 
 #[derive(Debug, ToSchema, Validate, Deserialize)]
@@ -46,12 +133,20 @@ pub struct NewRoomSchema {
 pub struct NewStreamKeySchema {
     #[validate(length(min = 2, max = 24))]
     pub source: String,
+    /// If the holder of the key can add tracks to the queue. Defaults to false.
+    pub can_queue: Option<bool>,
 }
 
 #[derive(Debug, ToSchema, Validate, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct InputSchema {
     pub query: Vec<String>,
+    /// The time in seconds playback of the added item(s) should start at
+    #[validate(range(min = 0.))]
+    pub start: Option<f32>,
+    /// The time in seconds playback of the added item(s) should end at
+    #[validate(range(min = 0.))]
+    pub end: Option<f32>,
 }
 
 #[derive(Debug, ToSchema, Validate, Deserialize)]
@@ -60,6 +155,39 @@ pub struct JoinWithInviteSchema {
     pub token: String,
 }
 
+#[derive(Debug, ToSchema, Validate, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NewRecordingSchema {
+    /// The format to record in, as a mime type. Supports audio/wav and audio/pcm, where the sample format of audio/pcm can be picked like audio/pcm;format=s16le. Defaults to audio/wav.
+    pub format: Option<String>,
+    /// Starts a new file once the current one is this many seconds long
+    #[validate(range(min = 1.))]
+    pub max_file_seconds: Option<f32>,
+    /// Starts a new file once the audio in the current one is this many bytes
+    #[validate(range(min = 1024))]
+    pub max_file_bytes: Option<usize>,
+}
+
+#[derive(Debug, ToSchema, Validate, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NewScheduleSchema {
+    /// When the action happens, in RFC 3339. It happens right away if this has passed.
+    pub fire_at: String,
+    pub action: ScheduledActionKind,
+    /// The urls to queue, in order. Only used by queuePlaylist.
+    #[serde(default)]
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, ToSchema, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScheduledActionKind {
+    /// Starts playback
+    Play,
+    /// Adds the tracks of each url to the queue
+    QueuePlaylist,
+}
+
 #[derive(Debug, ToSchema, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "action", deny_unknown_fields)]
 pub enum RoomActionSchema {
@@ -67,7 +195,42 @@ pub enum RoomActionSchema {
     Pause,
     Next,
     Previous,
-    Seek { to: f32 },
+    Seek {
+        to: f32,
+    },
+    /// Seeks to a fraction of the current item's length, between 0.0 and 1.0.
+    /// Fails if the length is unknown, like for live streams.
+    SeekFraction {
+        to: f32,
+    },
+    /// Sets the playback speed between 0.5 and 2.0. Pitch is not preserved.
+    Speed {
+        to: f32,
+    },
+    /// Skips the tracks a user queued until they are unmuted, without removing them.
+    /// Only owners of the room can do this.
+    MuteSubmitter {
+        #[serde(rename = "userId")]
+        user_id: i32,
+    },
+    UnmuteSubmitter {
+        #[serde(rename = "userId")]
+        user_id: i32,
+    },
+    /// Adjusts the gain of a queued track in dB, between -30 and 12. A gain of null resets it.
+    SetTrackGain {
+        #[serde(rename = "trackId")]
+        track_id: i32,
+        gain: Option<f32>,
+    },
+    /// Plays the tracks you queued next, keeping their order. Tracks you queue afterwards are queued at the end like usual.
+    /// Members that aren't owners can only do this once every few minutes.
+    MoveMyTracksToFront,
+    /// Queues a track from the history again under your name, at the end of the queue.
+    RequeueFromHistory {
+        #[serde(rename = "trackId")]
+        track_id: i32,
+    },
 }
 
 pub struct ValidatedJson<T>(pub T);
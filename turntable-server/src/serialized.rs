@@ -5,12 +5,23 @@ use std::sync::Arc;
 
 use serde::Serialize;
 use turntable_collab::{
-    LinearQueueItem, Room as CollabRoom, RoomConnection as CollabRoomConnection, RoomInviteData,
-    RoomMemberData, SessionData, StreamKeyData, Track as CollabTrack, UserData,
+    DisconnectPolicy as CollabDisconnectPolicy, EmptyRoomPolicy as CollabEmptyRoomPolicy,
+    LinearQueueItem, PendingAdd as CollabPendingAdd, Recording as CollabRecording,
+    Room as CollabRoom, RoomConnection as CollabRoomConnection, RoomData, RoomError,
+    RoomInviteData, RoomMemberData, RoomScheduleData, RoomVisibility as CollabRoomVisibility,
+    SessionData, StreamKeyData, Submitter as CollabSubmitter, SubmitterStatsData,
+    Track as CollabTrack, TrackId, TrackStatsData, UserData,
 };
-use turntable_core::PlayerState as CorePlayerState;
+use turntable_core::{
+    ActivationError as CoreActivationError, Config, EncoderStatsIntrospection, PipelineHealth,
+    PlayerState as CorePlayerState, QueueItem as _, SinkBufferHealth as CoreSinkBufferHealth,
+    SinkUsage as CoreSinkUsage,
+};
+use turntable_impls::EncoderKind;
 use utoipa::ToSchema;
 
+use crate::artwork::ArtworkProxy;
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
@@ -33,9 +44,66 @@ pub struct Room {
     slug: String,
     title: String,
     description: Option<String>,
+    visibility: RoomVisibility,
+    max_queue_size: Option<i32>,
+    max_track_duration: Option<i32>,
+    /// How many milliseconds of silence are played between tracks
+    track_gap_in_ms: i32,
+    allowed_sources: Vec<String>,
+    denied_sources: Vec<String>,
+    disconnect_policy: DisconnectPolicy,
+    /// Whether the room keeps playing when nobody is listening
+    empty_room_policy: EmptyRoomPolicy,
+    /// How many milliseconds of latency streams have if they don't ask for any, if not the server's default
+    default_latency_in_ms: Option<i32>,
+    /// Whether silence at the start of tracks is skipped, up to a few seconds
+    trim_leading_silence: bool,
+    /// The URL or file path of the jingle played between tracks, if any
+    jingle: Option<String>,
+    /// Whether tracks flagged as explicit, or matching the server's blocklist, are rejected when added
+    explicit_filter: bool,
     members: Vec<RoomMember>,
     connections: Vec<RoomConnection>,
     player: Option<Player>,
+    /// Whether the player is not paused. It may still be buffering.
+    is_playing: bool,
+    /// The position in the current item, in seconds.
+    position_seconds: f32,
+    current_item: Option<QueueItem>,
+    /// How many clients are listening to the room, where a client that reconnected counts once.
+    listener_count: usize,
+}
+
+/// The details of a room needed to list it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSummary {
+    id: i32,
+    slug: String,
+    title: String,
+    visibility: RoomVisibility,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RoomVisibility {
+    Public,
+    Unlisted,
+    Private,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DisconnectPolicy {
+    KeepQueued,
+    RemoveQueued,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum EmptyRoomPolicy {
+    AdvanceWhenEmpty,
+    PauseWhenEmpty,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -60,6 +128,26 @@ pub struct RoomInvite {
     inviter: User,
     room_title: String,
     room_slug: String,
+    /// When the invite stops working, in RFC 3339. Never if not set.
+    expires_at: Option<String>,
+    /// How many members can join with the invite. Unlimited if not set.
+    max_uses: Option<i32>,
+    uses: i32,
+}
+
+/// An action that happens in a room at a given time
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSchedule {
+    id: i32,
+    /// The user that scheduled the action
+    user_id: i32,
+    /// Either play or queue_playlist
+    action: String,
+    /// The urls that are queued, if the action queues a playlist
+    urls: Vec<String>,
+    /// When the action happens, in RFC 3339
+    fire_at: String,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -70,6 +158,175 @@ pub struct StreamKey {
     source: String,
     room_id: i32,
     user_id: i32,
+    can_queue: bool,
+}
+
+/// An add to a queue whose entries are still resolving
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAdd {
+    /// Used to cancel the add
+    id: u64,
+    /// How many entries the add consists of
+    total: usize,
+}
+
+/// What one query of a batch added to the queue
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchEntry {
+    query: String,
+    /// The ids of the tracks the query added, in order
+    added: Vec<u64>,
+    /// Why the query added nothing, if it didn't
+    error: Option<String>,
+}
+
+impl BatchEntry {
+    pub fn new(query: String, result: Result<Vec<TrackId>, RoomError>) -> Self {
+        let (added, error) = match result {
+            Ok(ids) => (ids.into_iter().map(|id| id.value()).collect(), None),
+            Err(err) => (vec![], Some(err.to_string())),
+        };
+
+        Self {
+            query,
+            added,
+            error,
+        }
+    }
+}
+
+/// A recording of a room's audio
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    id: u64,
+    room_id: i32,
+    started_at: String,
+    content_type: String,
+    /// Whether the recording stopped, either because it was stopped or failed
+    stopped: bool,
+    files: Vec<RecordedFile>,
+    markers: Vec<TrackMarker>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedFile {
+    name: String,
+    /// Where the file starts in the recording
+    start_seconds: f32,
+    length_seconds: f32,
+}
+
+/// Marks where in a recording a track started playing
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMarker {
+    offset_seconds: f32,
+    title: String,
+    artist: Option<String>,
+    canonical: String,
+    submitter_id: Option<i32>,
+    submitter_name: String,
+}
+
+/// Describes the format of an audio stream
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamInfo {
+    content_type: String,
+    codec: String,
+    sample_rate: usize,
+    channel_count: usize,
+    bits_per_sample: usize,
+    /// In bits per second
+    bitrate: usize,
+    /// Whether the stream can be requested from a byte offset with a Range header
+    accepts_ranges: bool,
+    /// How many milliseconds behind live the stream starts if no latency is requested
+    latency_in_ms: u32,
+}
+
+/// Describes what is loaded of a current or upcoming item in a room
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkBufferHealth {
+    sink_id: u64,
+    /// Where playback is in the item, or starts from if it is upcoming
+    position_seconds: f32,
+    /// False if the item isn't loaded yet, or was being loaded into, in which case nothing else is known
+    available: bool,
+    /// How much is loaded without interruption from the position
+    loaded_ahead_seconds: f32,
+    /// The parts after the position that are not loaded
+    gaps: Vec<BufferGap>,
+    length_seconds: Option<f32>,
+    /// Why the item failed to load, if it did
+    error: Option<SinkActivationError>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkActivationError {
+    reason: String,
+    /// What kind of error it was, like `unsupported_format` or `io`
+    kind: String,
+    /// What was being loaded, like the URL of a video, if it is known
+    input: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferGap {
+    start_seconds: f32,
+    end_seconds: f32,
+}
+
+/// Describes how much an encoder has encoded for all listeners since startup, and what it cost
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EncoderStats {
+    name: String,
+    samples_encoded: u64,
+    /// How much audio has been encoded
+    audio_seconds_encoded: f32,
+    /// How many times samples were encoded
+    calls: u64,
+    /// How long encoding took in total
+    seconds_spent: f32,
+    /// How many seconds of audio are encoded per second spent, or null if nothing was encoded
+    realtime_factor: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    /// Whether every thread of the pipeline is making progress
+    healthy: bool,
+    stalled_threads: Vec<StalledThread>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StalledThread {
+    name: String,
+    /// How many seconds ago the thread last made progress
+    stalled_for_seconds: f32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkUsage {
+    /// How many sinks are activated or being activated
+    activated_sinks: usize,
+    /// How many sinks can be activated at once, or null if unlimited
+    max_activated_sinks: Option<usize>,
+    /// How many seconds of audio the sinks hold in total
+    seconds_in_memory: f32,
+    /// How many seconds of audio the sinks can hold, or null if unlimited
+    max_seconds_in_memory: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -84,15 +341,38 @@ pub struct Track {
 
     duration: f32,
     artwork: Option<String>,
+
+    start: Option<f32>,
+    end: Option<f32>,
+    /// The gain in dB applied when the track plays, if adjusted
+    gain: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct QueueItem {
-    user_id: i32,
+    /// The user that queued the item, if it wasn't added by turntable
+    user_id: Option<i32>,
+    submitter: Submitter,
     track: Track,
 }
 
+/// Who added an item to a queue
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Submitter {
+    User {
+        id: i32,
+        #[serde(rename = "displayName")]
+        display_name: String,
+    },
+    /// Added by turntable itself rather than a user
+    System {
+        #[serde(rename = "displayName")]
+        display_name: String,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Queue {
@@ -106,9 +386,27 @@ pub struct Player {
     state: PlayerState,
     total_time: f32,
     current_time: f32,
+    speed: f32,
     current_item: Option<QueueItem>,
 }
 
+/// What is playing in a room, for clients that poll instead of listening to events
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NowPlaying {
+    room_id: i32,
+    /// The item that is playing, or null if there is nothing to play
+    item: Option<QueueItem>,
+    state: PlayerState,
+    /// Whether the player is not paused. It may still be buffering.
+    is_playing: bool,
+    /// Where playback is in the current item, in seconds
+    position_seconds: f32,
+    /// The length of the current item in seconds, if known
+    duration_seconds: Option<f32>,
+    listener_count: usize,
+}
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum PlayerState {
@@ -117,6 +415,30 @@ pub enum PlayerState {
     Buffering,
 }
 
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackStats {
+    canonical: String,
+    title: String,
+    artist: String,
+    source: String,
+    plays: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmitterStats {
+    user: User,
+    plays: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    tracks: Vec<TrackStats>,
+    submitters: Vec<SubmitterStats>,
+}
+
 /// Helper trait to convert any type into a serialized version
 pub trait ToSerialized<T>
 where
@@ -164,33 +486,117 @@ impl ToSerialized<LoginResult> for SessionData {
     }
 }
 
-impl ToSerialized<Room> for Arc<CollabRoom> {
+impl ToSerialized<Room> for (&Arc<CollabRoom>, &ArtworkProxy) {
     fn to_serialized(&self) -> Room {
-        let data = self.data();
-
-        let track = self.current_item();
-        let player = self
-            .player()
-            .map(|p| Player {
-                current_time: p.current_time(),
-                total_time: p.current_total_time(),
-                current_item: track.map(|t| t.to_serialized()),
-                state: p.current_state().to_serialized(),
-            })
-            .ok();
+        let (room, artwork) = *self;
+        let data = room.data();
+
+        let current_item: Option<QueueItem> = (&room.current_item(), artwork).to_serialized();
+        let connections = room.current_connections();
+
+        // These are all atomic reads, so they never wait on the processing thread
+        let player_context = room.player().ok();
+        let player = player_context.as_ref().map(|p| Player {
+            current_time: p.current_time(),
+            total_time: p.current_total_time(),
+            speed: p.speed(),
+            current_item: current_item.clone(),
+            state: p.current_state().to_serialized(),
+        });
 
         Room {
             id: data.id,
             slug: data.slug,
             title: data.title,
             description: data.description,
+            visibility: data.visibility.to_serialized(),
+            max_queue_size: data.max_queue_size,
+            max_track_duration: data.max_track_duration,
+            track_gap_in_ms: data.track_gap_in_ms,
+            allowed_sources: data.allowed_sources,
+            denied_sources: data.denied_sources,
+            disconnect_policy: data.disconnect_policy.to_serialized(),
+            empty_room_policy: data.empty_room_policy.to_serialized(),
+            default_latency_in_ms: data.default_latency_in_ms,
+            trim_leading_silence: data.trim_leading_silence,
+            jingle: data.jingle,
+            explicit_filter: data.explicit_filter,
             members: data.members.to_serialized(),
-            connections: self.current_connections().to_serialized(),
+            listener_count: room.unique_listeners(),
+            connections: connections.to_serialized(),
+            is_playing: player_context.is_some_and(|p| p.is_playing()),
+            position_seconds: player.as_ref().map(|p| p.current_time).unwrap_or_default(),
+            current_item,
             player,
         }
     }
 }
 
+/// Rooms that aren't active have nothing playing, so this should only be used on active rooms
+impl ToSerialized<NowPlaying> for (&Arc<CollabRoom>, &ArtworkProxy) {
+    fn to_serialized(&self) -> NowPlaying {
+        let (room, artwork) = *self;
+        let item = room.current_item();
+        let player = room.player().ok();
+
+        NowPlaying {
+            room_id: room.id(),
+            duration_seconds: item
+                .as_ref()
+                .and_then(|i| i.track.length())
+                .or_else(|| player.as_ref().and_then(|p| p.current_duration())),
+            item: (&item, artwork).to_serialized(),
+            state: player
+                .as_ref()
+                .map(|p| p.current_state())
+                .unwrap_or_default()
+                .to_serialized(),
+            is_playing: player.as_ref().is_some_and(|p| p.is_playing()),
+            position_seconds: player.map(|p| p.current_time()).unwrap_or_default(),
+            listener_count: room.unique_listeners(),
+        }
+    }
+}
+
+impl ToSerialized<RoomSummary> for RoomData {
+    fn to_serialized(&self) -> RoomSummary {
+        RoomSummary {
+            id: self.id,
+            slug: self.slug.clone(),
+            title: self.title.clone(),
+            visibility: self.visibility.to_serialized(),
+        }
+    }
+}
+
+impl ToSerialized<RoomVisibility> for CollabRoomVisibility {
+    fn to_serialized(&self) -> RoomVisibility {
+        match self {
+            Self::Public => RoomVisibility::Public,
+            Self::Unlisted => RoomVisibility::Unlisted,
+            Self::Private => RoomVisibility::Private,
+        }
+    }
+}
+
+impl ToSerialized<DisconnectPolicy> for CollabDisconnectPolicy {
+    fn to_serialized(&self) -> DisconnectPolicy {
+        match self {
+            Self::KeepQueued => DisconnectPolicy::KeepQueued,
+            Self::RemoveQueued => DisconnectPolicy::RemoveQueued,
+        }
+    }
+}
+
+impl ToSerialized<EmptyRoomPolicy> for CollabEmptyRoomPolicy {
+    fn to_serialized(&self) -> EmptyRoomPolicy {
+        match self {
+            Self::AdvanceWhenEmpty => EmptyRoomPolicy::AdvanceWhenEmpty,
+            Self::PauseWhenEmpty => EmptyRoomPolicy::PauseWhenEmpty,
+        }
+    }
+}
+
 impl ToSerialized<RoomMember> for RoomMemberData {
     fn to_serialized(&self) -> RoomMember {
         RoomMember {
@@ -210,6 +616,18 @@ impl ToSerialized<RoomConnection> for CollabRoomConnection {
     }
 }
 
+impl ToSerialized<RoomSchedule> for RoomScheduleData {
+    fn to_serialized(&self) -> RoomSchedule {
+        RoomSchedule {
+            id: self.id,
+            user_id: self.user_id,
+            action: self.action.as_str().to_string(),
+            urls: self.action.urls().to_vec(),
+            fire_at: self.fire_at.to_rfc3339(),
+        }
+    }
+}
+
 impl ToSerialized<StreamKey> for StreamKeyData {
     fn to_serialized(&self) -> StreamKey {
         StreamKey {
@@ -218,6 +636,51 @@ impl ToSerialized<StreamKey> for StreamKeyData {
             source: self.source.clone(),
             room_id: self.room_id,
             user_id: self.user_id,
+            can_queue: self.can_queue,
+        }
+    }
+}
+
+impl ToSerialized<PendingAdd> for Arc<CollabPendingAdd> {
+    fn to_serialized(&self) -> PendingAdd {
+        PendingAdd {
+            id: self.id.value(),
+            total: self.total,
+        }
+    }
+}
+
+impl ToSerialized<Recording> for Arc<CollabRecording> {
+    fn to_serialized(&self) -> Recording {
+        let metadata = self.metadata();
+
+        Recording {
+            id: self.id.value(),
+            room_id: metadata.room_id,
+            started_at: metadata.started_at,
+            content_type: metadata.content_type,
+            stopped: self.is_stopped(),
+            files: metadata
+                .files
+                .into_iter()
+                .map(|f| RecordedFile {
+                    name: f.name,
+                    start_seconds: f.start_seconds,
+                    length_seconds: f.length_seconds,
+                })
+                .collect(),
+            markers: metadata
+                .markers
+                .into_iter()
+                .map(|m| TrackMarker {
+                    offset_seconds: m.offset_seconds,
+                    title: m.title,
+                    artist: m.artist,
+                    canonical: m.canonical,
+                    submitter_id: m.submitter_id,
+                    submitter_name: m.submitter_name,
+                })
+                .collect(),
         }
     }
 }
@@ -229,20 +692,129 @@ impl ToSerialized<RoomInvite> for RoomInviteData {
             inviter: self.inviter.to_serialized(),
             room_title: self.room.title.clone(),
             room_slug: self.room.slug.clone(),
+            expires_at: self.expires_at.map(|e| e.to_rfc3339()),
+            max_uses: self.max_uses,
+            uses: self.uses,
+        }
+    }
+}
+
+impl ToSerialized<StreamInfo> for (EncoderKind, Config, u32) {
+    fn to_serialized(&self) -> StreamInfo {
+        let (encoder, config, latency_in_ms) = self;
+
+        StreamInfo {
+            content_type: encoder.content_type().to_string(),
+            codec: encoder.codec().to_string(),
+            sample_rate: config.sample_rate,
+            channel_count: config.channel_count,
+            bits_per_sample: encoder.bits_per_sample(),
+            bitrate: encoder.bitrate(config),
+            // Streams are live, so there is nothing to resume from
+            accepts_ranges: false,
+            latency_in_ms: *latency_in_ms,
         }
     }
 }
 
-impl ToSerialized<Track> for CollabTrack {
+impl ToSerialized<SinkBufferHealth> for (&CoreSinkBufferHealth, &Config) {
+    fn to_serialized(&self) -> SinkBufferHealth {
+        let (health, config) = self;
+        let seconds = |samples: usize| config.samples_to_seconds(samples);
+
+        let buffer = health.buffer.as_ref();
+        let gaps = buffer.map(|b| b.gaps.as_slice()).unwrap_or_default();
+
+        SinkBufferHealth {
+            sink_id: health.sink_id,
+            position_seconds: seconds(health.offset),
+            available: buffer.is_some(),
+            loaded_ahead_seconds: seconds(buffer.map(|b| b.loaded_ahead).unwrap_or_default()),
+            gaps: gaps
+                .iter()
+                .map(|g| BufferGap {
+                    start_seconds: seconds(g.offset),
+                    end_seconds: seconds(g.offset + g.length),
+                })
+                .collect(),
+            length_seconds: buffer.and_then(|b| b.expected_length).map(seconds),
+            error: health.error.as_ref().map(|e| e.to_serialized()),
+        }
+    }
+}
+
+impl ToSerialized<SinkActivationError> for CoreActivationError {
+    fn to_serialized(&self) -> SinkActivationError {
+        SinkActivationError {
+            reason: self.reason.clone(),
+            kind: self.kind.as_str().to_string(),
+            input: self.input.clone(),
+        }
+    }
+}
+
+impl ToSerialized<EncoderStats> for (&EncoderStatsIntrospection, &Config) {
+    fn to_serialized(&self) -> EncoderStats {
+        let (stats, config) = self;
+
+        let audio_seconds_encoded = config.samples_to_seconds(stats.samples_encoded as usize);
+        let seconds_spent = stats.time_spent.as_secs_f32();
+
+        EncoderStats {
+            name: stats.name.clone(),
+            samples_encoded: stats.samples_encoded,
+            audio_seconds_encoded,
+            calls: stats.calls,
+            seconds_spent,
+            realtime_factor: (seconds_spent > 0.).then(|| audio_seconds_encoded / seconds_spent),
+        }
+    }
+}
+
+impl ToSerialized<Health> for PipelineHealth {
+    fn to_serialized(&self) -> Health {
+        Health {
+            healthy: self.is_healthy(),
+            stalled_threads: self
+                .stalled_threads
+                .iter()
+                .map(|t| StalledThread {
+                    name: t.name.clone(),
+                    stalled_for_seconds: t.since.as_secs_f32(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ToSerialized<SinkUsage> for (&CoreSinkUsage, &Config) {
+    fn to_serialized(&self) -> SinkUsage {
+        let (usage, config) = self;
+
+        SinkUsage {
+            activated_sinks: usage.activated_sinks,
+            max_activated_sinks: config.max_activated_sinks,
+            seconds_in_memory: config.samples_to_seconds(usage.samples_in_memory),
+            max_seconds_in_memory: config.max_sink_memory_in_seconds,
+        }
+    }
+}
+
+impl ToSerialized<Track> for (&CollabTrack, &ArtworkProxy) {
     fn to_serialized(&self) -> Track {
+        let (track, artwork) = *self;
+
         Track {
-            id: self.id.value() as i32,
-            title: self.metadata.title.clone(),
-            artwork: self.metadata.artwork.clone(),
-            canonical: self.metadata.canonical.clone(),
-            source: self.metadata.source.clone(),
-            duration: self.metadata.duration,
-            artist: self
+            id: track.id.value() as i32,
+            title: track.metadata.title.clone(),
+            artwork: artwork.url(&track.metadata.canonical, track.metadata.artwork.as_ref()),
+            canonical: track.metadata.canonical.clone(),
+            source: track.metadata.source.clone(),
+            duration: track.metadata.duration,
+            start: track.start,
+            end: track.end,
+            gain: track.gain,
+            artist: track
                 .metadata
                 .artist
                 .clone()
@@ -251,20 +823,53 @@ impl ToSerialized<Track> for CollabTrack {
     }
 }
 
-impl ToSerialized<QueueItem> for LinearQueueItem {
+impl ToSerialized<QueueItem> for (&LinearQueueItem, &ArtworkProxy) {
     fn to_serialized(&self) -> QueueItem {
+        let (item, artwork) = *self;
+
         QueueItem {
-            user_id: self.user_id,
-            track: self.track.to_serialized(),
+            user_id: item.submitter.user_id(),
+            submitter: item.submitter.to_serialized(),
+            track: (&item.track, artwork).to_serialized(),
         }
     }
 }
 
-impl ToSerialized<Queue> for (Vec<LinearQueueItem>, Vec<LinearQueueItem>) {
+impl ToSerialized<Option<QueueItem>> for (&Option<LinearQueueItem>, &ArtworkProxy) {
+    fn to_serialized(&self) -> Option<QueueItem> {
+        let (item, artwork) = *self;
+        item.as_ref().map(|i| (i, artwork).to_serialized())
+    }
+}
+
+impl ToSerialized<Vec<QueueItem>> for (&Vec<LinearQueueItem>, &ArtworkProxy) {
+    fn to_serialized(&self) -> Vec<QueueItem> {
+        let (items, artwork) = *self;
+        items.iter().map(|i| (i, artwork).to_serialized()).collect()
+    }
+}
+
+impl ToSerialized<Submitter> for CollabSubmitter {
+    fn to_serialized(&self) -> Submitter {
+        let display_name = self.display_name().to_string();
+
+        match self {
+            CollabSubmitter::User { id, .. } => Submitter::User {
+                id: *id,
+                display_name,
+            },
+            CollabSubmitter::System => Submitter::System { display_name },
+        }
+    }
+}
+
+impl ToSerialized<Queue> for (&(Vec<LinearQueueItem>, Vec<LinearQueueItem>), &ArtworkProxy) {
     fn to_serialized(&self) -> Queue {
+        let ((items, history), artwork) = *self;
+
         Queue {
-            items: self.0.to_serialized(),
-            history: self.1.to_serialized(),
+            items: (items, artwork).to_serialized(),
+            history: (history, artwork).to_serialized(),
         }
     }
 }
@@ -278,3 +883,208 @@ impl ToSerialized<PlayerState> for CorePlayerState {
         }
     }
 }
+
+impl ToSerialized<TrackStats> for TrackStatsData {
+    fn to_serialized(&self) -> TrackStats {
+        TrackStats {
+            canonical: self.canonical.clone(),
+            title: self.title.clone(),
+            source: self.source.clone(),
+            plays: self.plays,
+            artist: self
+                .artist
+                .clone()
+                .unwrap_or_else(|| "Unknown artist".to_string()),
+        }
+    }
+}
+
+impl ToSerialized<SubmitterStats> for SubmitterStatsData {
+    fn to_serialized(&self) -> SubmitterStats {
+        SubmitterStats {
+            user: self.user.to_serialized(),
+            plays: self.plays,
+        }
+    }
+}
+
+impl ToSerialized<Stats> for (Vec<TrackStatsData>, Vec<SubmitterStatsData>) {
+    fn to_serialized(&self) -> Stats {
+        Stats {
+            tracks: self.0.to_serialized(),
+            submitters: self.1.to_serialized(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use turntable_collab::{CollabConfig, NewPlainUser, NewRoom, RecordingOptions, RoomVisibility};
+    use turntable_core::{ActivationError, Config, IngestionError, SinkBufferHealth};
+    use turntable_impls::EncoderKind;
+
+    use super::{NowPlaying, Room, ToSerialized};
+    use crate::{test_app, test_app_with_configs};
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_room_playback_fields(pool: PgPool) {
+        let (_, context) = test_app(pool).await;
+        let rooms = &context.collab.rooms;
+
+        let user = context
+            .collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        room.activate();
+        let player = room.player().unwrap();
+
+        // Actions are handled on another thread, so wait until the room reflects them
+        let serialize_until = |is_playing: bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+
+            loop {
+                let serialized: Room = (&room, &*context.artwork).to_serialized();
+                let value = serde_json::to_value(serialized).unwrap();
+
+                if value["isPlaying"] == is_playing || Instant::now() > deadline {
+                    return value;
+                }
+
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        player.play();
+        let value = serialize_until(true);
+
+        assert_eq!(value["isPlaying"], true);
+        assert_eq!(value["listenerCount"], 0);
+        assert_eq!(value["currentItem"], Value::Null);
+        assert_eq!(value["positionSeconds"], value["player"]["currentTime"]);
+
+        player.pause();
+        let value = serialize_until(false);
+
+        assert_eq!(value["isPlaying"], false);
+        assert_eq!(value["listenerCount"], 0);
+        assert_eq!(value["currentItem"], value["player"]["currentItem"]);
+        assert_eq!(value["positionSeconds"], value["player"]["currentTime"]);
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_listener_count_is_unique(pool: PgPool) {
+        let dir = std::env::temp_dir().join("turntable_listener_count");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let collab_config = CollabConfig {
+            recordings_dir: dir,
+            ..Default::default()
+        };
+        let (_, context) = test_app_with_configs(pool, collab_config, Default::default()).await;
+        let rooms = &context.collab.rooms;
+
+        let user = context
+            .collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let connect = |token: &str| {
+            room.connect(
+                user.id,
+                "test".to_string(),
+                None,
+                EncoderKind::Wave,
+                Some(token.to_string()),
+            )
+            .unwrap()
+        };
+
+        // The first client reconnected, so it has two connections
+        let _connections = [connect("first"), connect("first"), connect("second")];
+        let _recording = room
+            .start_recording(RecordingOptions {
+                encoder: EncoderKind::Wave,
+                max_file_seconds: None,
+                max_file_bytes: None,
+            })
+            .unwrap();
+
+        let serialized: Room = (&room, &*context.artwork).to_serialized();
+        let value = serde_json::to_value(serialized).unwrap();
+
+        assert_eq!(value["connections"].as_array().unwrap().len(), 3);
+        assert_eq!(value["listenerCount"], 2);
+
+        let serialized: NowPlaying = (&room, &*context.artwork).to_serialized();
+        let value = serde_json::to_value(serialized).unwrap();
+
+        assert_eq!(value["listenerCount"], 2);
+
+        room.stop_recording().unwrap();
+    }
+
+    #[test]
+    fn test_buffer_health_includes_activation_errors() {
+        let error = IngestionError::Io("Too Many Requests".to_string());
+        let health = SinkBufferHealth {
+            sink_id: 1,
+            offset: 0,
+            buffer: None,
+            error: Some(ActivationError::new(
+                &error,
+                Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string()),
+            )),
+        };
+
+        let serialized: super::SinkBufferHealth = (&health, &Config::default()).to_serialized();
+        let value = serde_json::to_value(serialized).unwrap();
+
+        assert_eq!(value["available"], false);
+        assert_eq!(value["error"]["kind"], "io");
+        assert_eq!(value["error"]["reason"], "IO error: Too Many Requests");
+        assert_eq!(
+            value["error"]["input"],
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+    }
+}
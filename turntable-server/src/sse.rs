@@ -1,4 +1,6 @@
 use axum::{
+    extract::Query,
+    http::HeaderMap,
     response::{
         sse::{Event, KeepAlive},
         Sse,
@@ -8,28 +10,47 @@ use axum::{
 use futures_util::Stream;
 use log::info;
 use parking_lot::Mutex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::VecDeque,
     convert::Infallible,
     pin::Pin,
     sync::{Arc, Weak},
     task::{Context, Poll, Waker},
+    time::Duration,
 };
-use turntable_collab::CollabEvent;
-use turntable_core::Id;
+use turntable_collab::{CollabEvent, RoomError, RoomVisibility};
+use turntable_core::{Id, LevelsSubscription};
 use utoipa::ToSchema;
 
 use crate::{
+    artwork::ArtworkProxy,
     context::ServerContext,
-    serialized::{PlayerState, QueueItem, RoomMember, ToSerialized},
-    Router,
+    errors::ServerResult,
+    serialized::{PlayerState, QueueItem, RoomMember, RoomSummary, ToSerialized},
+    Router, ServerConfig,
 };
 
 type ConnectionId = Id<Connection>;
 
+#[derive(Debug, Deserialize)]
+struct EventStreamParams {
+    levels: Option<i32>,
+}
+
 #[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub enum ServerEvent {
+    /// A public room was created
+    RoomCreated { room: RoomSummary },
+    /// The details of a public room changed
+    RoomUpdated { room: RoomSummary },
+    /// A public room was deleted
+    RoomDeleted { room_id: i32 },
+    /// A public room was made unlisted or private, so it should be removed from room lists
+    RoomHidden { room_id: i32 },
+    /// A public room was activated after having been inactive
+    RoomResumed { room_id: i32 },
     /// A player's state has changed.
     PlayerStateUpdate {
         room_id: i32,
@@ -43,6 +64,14 @@ pub enum ServerEvent {
         /// The total position of the player, in seconds.
         total_position: f32,
     },
+    /// A player's playback speed has changed.
+    PlayerSpeedUpdate {
+        room_id: i32,
+        /// The playback speed, where 1.0 is normal speed.
+        speed: f32,
+    },
+    /// A player was rebuilt to recover it, so the current track started over.
+    PlayerReseated { room_id: i32 },
     /// A track as a queue item has been ingested
     TrackActivated {
         /// The id of the player the queue item's queue belongs to.
@@ -58,6 +87,25 @@ pub enum ServerEvent {
         track_id: i32,
         /// The error that happened while activating the queue item.
         error: String,
+        /// Whether activating the track again may succeed.
+        retryable: bool,
+    },
+    /// A source like YouTube is refusing to resolve tracks because too many requests were made.
+    /// This is sent at most once in a while for each source, not for every refused request.
+    SourceRateLimited {
+        /// The name of the source, like `youtube`
+        source: String,
+        /// How long the source asked to wait before trying again, in seconds, if it said
+        retry_after: Option<f32>,
+    },
+    /// A track stopped playing, either because it finished or because it was skipped
+    TrackEnded {
+        room_id: i32,
+        item: QueueItem,
+        /// How long the track was heard for, in seconds
+        time_played: f32,
+        /// If enough of the track was heard for it to count as a play
+        counted_as_play: bool,
     },
     /// The currently playing track of a room updated
     RoomQueueItemUpdate {
@@ -70,6 +118,26 @@ pub enum ServerEvent {
         history: Vec<QueueItem>,
         items: Vec<QueueItem>,
     },
+    /// An entry of an add to a room's queue resolved
+    QueueAddProgress {
+        room_id: i32,
+        add_id: u64,
+        user_id: i32,
+        /// How many entries have resolved so far, including failed ones
+        resolved: usize,
+        /// How many entries failed to resolve or be added
+        failed: usize,
+        /// How many entries the add consists of
+        total: usize,
+        /// Why the entry that just resolved could not be added, if it couldn't
+        error: Option<String>,
+    },
+    /// An add to a room's queue finished, either because all entries resolved or it was cancelled
+    QueueAddFinished {
+        room_id: i32,
+        add_id: u64,
+        cancelled: bool,
+    },
     /// User become a member of a room
     UserJoined {
         room_id: i32,
@@ -89,11 +157,52 @@ pub enum ServerEvent {
         user_id: i32,
         source: String,
     },
+    /// The levels of what a room played recently, from 0.0 to 1.0.
+    /// Only sent to clients that asked for the room's levels, and never replayed.
+    Levels {
+        room_id: i32,
+        peak_l: f32,
+        peak_r: f32,
+        rms_l: f32,
+        rms_r: f32,
+    },
+    /// The client missed too many events to replay them, and should fetch the full state again
+    ResyncRequired,
 }
 
-impl From<CollabEvent> for ServerEvent {
-    fn from(value: CollabEvent) -> Self {
-        match value {
+/// Only events that are relevant to clients can be converted.
+/// The others are returned as is.
+impl TryFrom<(CollabEvent, &ArtworkProxy)> for ServerEvent {
+    type Error = CollabEvent;
+
+    fn try_from((value, artwork): (CollabEvent, &ArtworkProxy)) -> Result<Self, Self::Error> {
+        let event = match value {
+            CollabEvent::RoomCreated { room } if room.visibility == RoomVisibility::Public => {
+                Self::RoomCreated {
+                    room: room.to_serialized(),
+                }
+            }
+            CollabEvent::RoomUpdated { room, .. } if room.visibility == RoomVisibility::Public => {
+                Self::RoomUpdated {
+                    room: room.to_serialized(),
+                }
+            }
+            // Clients that listed the room while it was public need to remove it
+            CollabEvent::RoomUpdated {
+                room,
+                previous_visibility: RoomVisibility::Public,
+            } => Self::RoomHidden { room_id: room.id },
+            CollabEvent::RoomDeleted { room } if room.visibility == RoomVisibility::Public => {
+                Self::RoomDeleted { room_id: room.id }
+            }
+            CollabEvent::RoomResumed { room } if room.visibility == RoomVisibility::Public => {
+                Self::RoomResumed { room_id: room.id }
+            }
+            // Clients aren't authenticated, so they are only told about public rooms
+            event @ (CollabEvent::RoomCreated { .. }
+            | CollabEvent::RoomUpdated { .. }
+            | CollabEvent::RoomDeleted { .. }
+            | CollabEvent::RoomResumed { .. }) => return Err(event),
             CollabEvent::PlayerStateUpdate { room_id, new_state } => Self::PlayerStateUpdate {
                 room_id,
                 new_state: new_state.to_serialized(),
@@ -107,9 +216,20 @@ impl From<CollabEvent> for ServerEvent {
                 position,
                 total_position,
             },
+            CollabEvent::PlayerSpeedUpdate { room_id, speed } => {
+                Self::PlayerSpeedUpdate { room_id, speed }
+            }
+            CollabEvent::PlayerReseated { room_id } => Self::PlayerReseated { room_id },
+            CollabEvent::SourceRateLimited {
+                source,
+                retry_after,
+            } => Self::SourceRateLimited {
+                source,
+                retry_after: retry_after.map(|d| d.as_secs_f32()),
+            },
             CollabEvent::RoomQueueItemUpdate { room_id, new_item } => Self::RoomQueueItemUpdate {
                 room_id,
-                new_item: new_item.to_serialized(),
+                new_item: (&new_item, artwork).to_serialized(),
             },
             CollabEvent::RoomQueueUpdate {
                 room_id,
@@ -117,8 +237,8 @@ impl From<CollabEvent> for ServerEvent {
                 items,
             } => Self::RoomQueueUpdate {
                 room_id,
-                history: history.to_serialized(),
-                items: items.to_serialized(),
+                history: (&history, artwork).to_serialized(),
+                items: (&items, artwork).to_serialized(),
             },
             CollabEvent::TrackActivated { room_id, track_id } => Self::TrackActivated {
                 room_id,
@@ -131,7 +251,45 @@ impl From<CollabEvent> for ServerEvent {
             } => Self::TrackActivationError {
                 room_id,
                 track_id: track_id.value() as i32,
+                retryable: error.is_retryable(),
+                error: error.to_string(),
+            },
+            CollabEvent::TrackEnded {
+                room_id,
+                item,
+                time_played,
+                counted_as_play,
+            } => Self::TrackEnded {
+                room_id,
+                item: (&item, artwork).to_serialized(),
+                time_played,
+                counted_as_play,
+            },
+            CollabEvent::QueueAddProgress {
+                room_id,
+                add_id,
+                user_id,
+                resolved,
+                failed,
+                total,
                 error,
+            } => Self::QueueAddProgress {
+                room_id,
+                add_id: add_id.value(),
+                user_id,
+                resolved,
+                failed,
+                total,
+                error,
+            },
+            CollabEvent::QueueAddFinished {
+                room_id,
+                add_id,
+                cancelled,
+            } => Self::QueueAddFinished {
+                room_id,
+                add_id: add_id.value(),
+                cancelled,
             },
             CollabEvent::UserConnected {
                 room_id,
@@ -159,7 +317,23 @@ impl From<CollabEvent> for ServerEvent {
                 new_member: new_member.to_serialized(),
             },
             CollabEvent::UserLeft { room_id, member_id } => Self::UserLeft { room_id, member_id },
-        }
+            CollabEvent::Levels {
+                room_id,
+                peak_l,
+                peak_r,
+                rms_l,
+                rms_r,
+            } => Self::Levels {
+                room_id,
+                peak_l,
+                peak_r,
+                rms_l,
+                rms_r,
+            },
+            event @ CollabEvent::SinksCleared { .. } => return Err(event),
+        };
+
+        Ok(event)
     }
 }
 
@@ -167,78 +341,236 @@ impl From<CollabEvent> for ServerEvent {
 pub struct ServerSentEvents {
     me: Weak<Self>,
     connections: Mutex<Vec<Connection>>,
+    history: Mutex<History>,
+    keep_alive_interval: Duration,
+    retry: Duration,
+    history_size: usize,
+    max_replay: usize,
+}
+
+/// An event and the id it was broadcast with
+#[derive(Debug, Clone)]
+struct Message {
+    id: u64,
+    event: ServerEvent,
+}
+
+/// The most recent events, replayed to clients that reconnect with the last id they received
+#[derive(Debug, Default)]
+struct History {
+    messages: VecDeque<Message>,
+    /// The id of the last broadcast event. Ids start at 1, so this is 0 until one is broadcast.
+    last_id: u64,
 }
 
 struct Connection {
     id: ConnectionId,
-    pending_messages: Arc<Mutex<Vec<ServerEvent>>>,
+    pending_messages: Arc<Mutex<VecDeque<Message>>>,
+    /// Only the latest levels are kept, so a slow client doesn't fall behind on them
+    pending_levels: Arc<Mutex<Option<ServerEvent>>>,
     waker: Arc<Mutex<Option<Waker>>>,
+    /// The room to send levels of
+    levels_room: Option<i32>,
 }
 
 struct ConnectionHandle {
     id: ConnectionId,
     /// A reference to [Connection]'s pending messages
-    pending_messages: Arc<Mutex<Vec<ServerEvent>>>,
+    pending_messages: Arc<Mutex<VecDeque<Message>>>,
+    /// A reference to [Connection]'s pending levels
+    pending_levels: Arc<Mutex<Option<ServerEvent>>>,
     /// A reference to [Connection]'s stored [Waker]
     waker: Arc<Mutex<Option<Waker>>>,
+    /// Keeps the room measuring its levels while connected
+    _levels_subscription: Option<LevelsSubscription>,
+    /// The reconnect delay to send before any events, if not sent yet
+    retry: Option<Duration>,
     /// Required to remove connection when dropped
     manager: Weak<ServerSentEvents>,
 }
 
+/// Converts a collab event to the event clients receive, if they may receive it.
+/// Clients aren't authenticated, so events happening in private rooms are left out.
+pub fn server_event(context: &ServerContext, event: CollabEvent) -> Option<ServerEvent> {
+    if let Some(room_id) = event.room_id() {
+        let room = context.collab.rooms.room_by_id(room_id).ok()?;
+
+        if !room.is_visible_to_anyone() {
+            return None;
+        }
+    }
+
+    (event, &*context.artwork).try_into().ok()
+}
+
 impl ServerSentEvents {
-    pub fn new() -> Arc<Self> {
+    pub fn new(config: &ServerConfig) -> Arc<Self> {
         Arc::new_cyclic(|me| Self {
             me: me.clone(),
             connections: Default::default(),
+            history: Default::default(),
+            keep_alive_interval: config.sse_keep_alive_interval,
+            retry: config.sse_retry,
+            history_size: config.sse_history_size,
+            max_replay: config.sse_max_replay,
         })
     }
 
     pub fn broadcast(&self, event: ServerEvent) {
+        if let ServerEvent::Levels { room_id, .. } = event {
+            return self.send_levels(room_id, event);
+        }
+
+        // Held while recording, so a connecting client can't miss the event between replay and subscribing
         let connections = self.connections.lock();
+        let mut history = self.history.lock();
+
+        history.last_id += 1;
+
+        let message = Message {
+            id: history.last_id,
+            event,
+        };
+
+        history.messages.push_back(message.clone());
+
+        while history.messages.len() > self.history_size {
+            history.messages.pop_front();
+        }
 
         for connection in connections.iter() {
-            connection.send(event.clone())
+            connection.send(message.clone())
         }
     }
 
-    fn connect(&self) -> ConnectionHandle {
-        let connection = Connection::new();
-        let handle = connection.handle(self.me.clone());
+    /// Sends levels to the connections that asked for them, replacing levels they haven't received yet.
+    fn send_levels(&self, room_id: i32, event: ServerEvent) {
+        let connections = self.connections.lock();
+
+        for connection in connections
+            .iter()
+            .filter(|c| c.levels_room == Some(room_id))
+        {
+            connection.send_levels(event.clone())
+        }
+    }
+
+    /// Creates a connection, replaying the events after `last_event_id` if given.
+    /// The connection receives the levels of the room the subscription is for, if given.
+    fn connect(
+        &self,
+        last_event_id: Option<u64>,
+        levels: Option<(i32, LevelsSubscription)>,
+    ) -> ConnectionHandle {
+        let (levels_room, levels_subscription) = levels.unzip();
+
+        let connection = Connection::new(levels_room);
+        let handle = connection.handle(self.me.clone(), self.retry, levels_subscription);
 
         info!("SSE connection #{} created", connection.id);
 
-        self.connections.lock().push(connection);
+        let mut connections = self.connections.lock();
+
+        if let Some(last_event_id) = last_event_id {
+            for message in self.replay(last_event_id) {
+                connection.send(message);
+            }
+        }
+
+        connections.push(connection);
         handle
     }
 
+    /// Returns the events a client missed since the given event.
+    /// If it missed more than can be replayed, a [ServerEvent::ResyncRequired] is returned instead.
+    fn replay(&self, last_event_id: u64) -> Vec<Message> {
+        let history = self.history.lock();
+        let missed = history.last_id.saturating_sub(last_event_id);
+
+        let oldest_id = history
+            .messages
+            .front()
+            .map(|m| m.id)
+            .unwrap_or(history.last_id + 1);
+
+        // Ids ahead of the server are from before it restarted, so the missed events are unknown
+        let is_unknown = last_event_id > history.last_id;
+        let is_evicted = missed > 0 && oldest_id > last_event_id + 1;
+
+        if is_unknown || is_evicted || missed > self.max_replay as u64 {
+            info!(
+                "SSE client missed {} events since #{}, requiring a resync",
+                missed, last_event_id
+            );
+
+            // The resync gets the latest id, so the client continues from here after refetching
+            return vec![Message {
+                id: history.last_id,
+                event: ServerEvent::ResyncRequired,
+            }];
+        }
+
+        history
+            .messages
+            .iter()
+            .filter(|m| m.id > last_event_id)
+            .cloned()
+            .collect()
+    }
+
     fn disconnect(&self, id: ConnectionId) {
         info!("SSE connection #{} dropped", id);
         self.connections.lock().retain(|c| c.id != id)
     }
+
+    /// Heartbeats are sent as comments, so clients never see them as events
+    fn keep_alive(&self) -> KeepAlive {
+        KeepAlive::new()
+            .interval(self.keep_alive_interval)
+            .text("keepalive")
+    }
 }
 
 impl Connection {
-    fn new() -> Self {
+    fn new(levels_room: Option<i32>) -> Self {
         Self {
             id: ConnectionId::new(),
             pending_messages: Default::default(),
+            pending_levels: Default::default(),
             waker: Default::default(),
+            levels_room,
         }
     }
 
-    fn send(&self, message: ServerEvent) {
-        self.pending_messages.lock().push(message);
+    fn send(&self, message: Message) {
+        self.pending_messages.lock().push_back(message);
+        self.wake();
+    }
 
+    fn send_levels(&self, event: ServerEvent) {
+        *self.pending_levels.lock() = Some(event);
+        self.wake();
+    }
+
+    fn wake(&self) {
         if let Some(waker) = self.waker.lock().take() {
             waker.wake()
         }
     }
 
-    fn handle(&self, manager: Weak<ServerSentEvents>) -> ConnectionHandle {
+    fn handle(
+        &self,
+        manager: Weak<ServerSentEvents>,
+        retry: Duration,
+        levels_subscription: Option<LevelsSubscription>,
+    ) -> ConnectionHandle {
         ConnectionHandle {
             id: self.id,
             pending_messages: self.pending_messages.clone(),
+            pending_levels: self.pending_levels.clone(),
             waker: self.waker.clone(),
+            _levels_subscription: levels_subscription,
+            retry: Some(retry),
             manager,
         }
     }
@@ -247,15 +579,32 @@ impl Connection {
 impl Stream for ConnectionHandle {
     type Item = Result<Event, Infallible>;
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // An event without data is not dispatched by clients, but the reconnect delay is applied
+        if let Some(retry) = self.retry.take() {
+            return Poll::Ready(Some(Ok(Event::default().retry(retry))));
+        }
+
         let mut pending_messages = self.pending_messages.lock();
 
-        let next_event = pending_messages
-            .pop()
-            .map(|m| serde_json::to_string(&m).expect("serializes properly"));
+        // Events are sent in the order they were broadcast, so replayed events are in order
+        let next_event = pending_messages.pop_front().map(|m| {
+            let data = serde_json::to_string(&m.event).expect("serializes properly");
+            Event::default().id(m.id.to_string()).data(data)
+        });
 
         if let Some(event) = next_event {
-            return Poll::Ready(Some(Ok(Event::default().data(event))));
+            return Poll::Ready(Some(Ok(event)));
+        }
+
+        // Levels have no id, since they can't be replayed
+        let next_levels = self.pending_levels.lock().take().map(|event| {
+            let data = serde_json::to_string(&event).expect("serializes properly");
+            Event::default().data(data)
+        });
+
+        if let Some(event) = next_levels {
+            return Poll::Ready(Some(Ok(event)));
         }
 
         *self.waker.lock() = Some(cx.waker().clone());
@@ -283,12 +632,295 @@ impl Drop for ConnectionHandle {
             description = "A stream of events from turntable",
             body = ServerEvent
         )
+    ),
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "The id of the last event received, to replay the events missed since"),
+        ("levels" = Option<i32>, Query, description = "The id of a room to receive the levels of, about 20 times per second. Private rooms can't be subscribed to")
     )
 )]
-async fn event_stream(context: ServerContext) -> Sse<ConnectionHandle> {
-    Sse::new(context.sse.connect()).keep_alive(KeepAlive::default())
+async fn event_stream(
+    context: ServerContext,
+    headers: HeaderMap,
+    params: Query<EventStreamParams>,
+) -> ServerResult<Sse<ConnectionHandle>> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+
+    let levels = match params.levels {
+        Some(room_id) => {
+            let room = context.collab.rooms.room_by_id(room_id)?;
+
+            if !room.is_visible_to_anyone() {
+                return Err(RoomError::RoomNotFound(room_id.to_string()).into());
+            }
+
+            Some((room_id, room.subscribe_levels()?))
+        }
+        None => None,
+    };
+
+    Ok(Sse::new(context.sse.connect(last_event_id, levels)).keep_alive(context.sse.keep_alive()))
 }
 
 pub fn router() -> Router {
     Router::new().route("/", get(event_stream))
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use axum::{
+        body::BodyDataStream,
+        response::{IntoResponse, Sse},
+    };
+    use futures_util::StreamExt;
+
+    use sqlx::PgPool;
+    use turntable_collab::{CollabEvent, NewPlainUser, NewRoom, RoomVisibility, UpdatedRoom};
+
+    use super::{server_event, ServerEvent, ServerSentEvents};
+    use crate::{test_app, ServerConfig};
+
+    async fn next_frame(body: &mut BodyDataStream) -> String {
+        let frame = body
+            .next()
+            .await
+            .expect("frame is received")
+            .expect("frame is ok");
+        String::from_utf8(frame.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retry_and_keep_alive() {
+        let sse = ServerSentEvents::new(&ServerConfig {
+            sse_keep_alive_interval: Duration::from_millis(50),
+            sse_retry: Duration::from_secs(5),
+            ..Default::default()
+        });
+
+        let response = Sse::new(sse.connect(None, None))
+            .keep_alive(sse.keep_alive())
+            .into_response();
+        let mut body = response.into_body().into_data_stream();
+
+        assert_eq!(next_frame(&mut body).await, "retry:5000\n\n");
+        assert_eq!(next_frame(&mut body).await, ": keepalive\n\n");
+
+        sse.broadcast(ServerEvent::UserLeft {
+            room_id: 1,
+            member_id: 2,
+        });
+
+        let frame = next_frame(&mut body).await;
+        assert!(
+            frame.starts_with("id: 1\ndata: "),
+            "real events are sent as data"
+        );
+        assert!(frame.contains("user-left"));
+    }
+
+    fn connect(sse: &ServerSentEvents, last_event_id: Option<u64>) -> BodyDataStream {
+        Sse::new(sse.connect(last_event_id, None))
+            .into_response()
+            .into_body()
+            .into_data_stream()
+    }
+
+    fn broadcast_many(sse: &ServerSentEvents, count: i32) {
+        for member_id in 1..=count {
+            sse.broadcast(ServerEvent::UserLeft {
+                room_id: 1,
+                member_id,
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let sse = ServerSentEvents::new(&ServerConfig {
+            sse_history_size: 100,
+            sse_max_replay: 5,
+            ..Default::default()
+        });
+
+        broadcast_many(&sse, 10);
+
+        let mut body = connect(&sse, Some(8));
+        next_frame(&mut body).await;
+
+        let frame = next_frame(&mut body).await;
+        assert!(frame.contains("id: 9") && frame.contains("\"member_id\":9"));
+
+        let frame = next_frame(&mut body).await;
+        assert!(frame.contains("id: 10") && frame.contains("\"member_id\":10"));
+    }
+
+    #[tokio::test]
+    async fn test_resync_when_far_behind() {
+        let sse = ServerSentEvents::new(&ServerConfig {
+            sse_history_size: 100,
+            sse_max_replay: 5,
+            ..Default::default()
+        });
+
+        broadcast_many(&sse, 50);
+
+        let mut body = connect(&sse, Some(2));
+        next_frame(&mut body).await;
+
+        let frame = next_frame(&mut body).await;
+        assert!(frame.contains("resync-required"));
+        assert!(
+            frame.contains("id: 50"),
+            "resync continues from the latest event"
+        );
+
+        // Nothing else is replayed
+        sse.broadcast(ServerEvent::UserLeft {
+            room_id: 1,
+            member_id: 51,
+        });
+
+        let frame = next_frame(&mut body).await;
+        assert!(frame.contains("id: 51"));
+
+        // Events that fell out of the history can't be replayed either
+        let sse = ServerSentEvents::new(&ServerConfig {
+            sse_history_size: 3,
+            sse_max_replay: 5,
+            ..Default::default()
+        });
+
+        broadcast_many(&sse, 5);
+
+        let mut body = connect(&sse, Some(1));
+        next_frame(&mut body).await;
+        assert!(next_frame(&mut body).await.contains("resync-required"));
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_hidden_rooms_are_removed(pool: PgPool) {
+        let (_, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let update = |visibility: RoomVisibility| UpdatedRoom {
+            id: room.id(),
+            visibility: Some(visibility),
+            ..Default::default()
+        };
+
+        // Returns the room events clients receive
+        let events = || {
+            let mut events = vec![];
+
+            while let Ok(event) = collab.try_wait_for_event() {
+                if let Ok(event) = (event, &*context.artwork).try_into() {
+                    match event {
+                        ServerEvent::RoomUpdated { .. } => events.push("updated"),
+                        ServerEvent::RoomHidden { .. } => events.push("hidden"),
+                        _ => {}
+                    }
+                }
+            }
+
+            events
+        };
+
+        events();
+
+        collab
+            .rooms
+            .update_room(update(RoomVisibility::Private))
+            .await
+            .unwrap();
+        assert_eq!(events(), ["hidden"]);
+
+        collab
+            .rooms
+            .update_room(update(RoomVisibility::Unlisted))
+            .await
+            .unwrap();
+        assert!(events().is_empty(), "non-public rooms are not told about");
+
+        collab
+            .rooms
+            .update_room(update(RoomVisibility::Public))
+            .await
+            .unwrap();
+        assert_eq!(events(), ["updated"]);
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_private_room_events_are_left_out(pool: PgPool) {
+        let (_, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let new_room = |slug: &str, visibility: RoomVisibility| NewRoom {
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            visibility,
+            user_id: user.id,
+            ..Default::default()
+        };
+
+        let public = collab
+            .rooms
+            .create_room(new_room("public", RoomVisibility::Public))
+            .await
+            .unwrap();
+        let unlisted = collab
+            .rooms
+            .create_room(new_room("unlisted", RoomVisibility::Unlisted))
+            .await
+            .unwrap();
+        let private = collab
+            .rooms
+            .create_room(new_room("private", RoomVisibility::Private))
+            .await
+            .unwrap();
+
+        let is_sent = |room_id: i32| {
+            server_event(&context, CollabEvent::PlayerReseated { room_id }).is_some()
+        };
+
+        assert!(is_sent(public.id()));
+        assert!(is_sent(unlisted.id()));
+        assert!(!is_sent(private.id()));
+        assert!(!is_sent(-1), "events of unknown rooms are not sent");
+    }
+}
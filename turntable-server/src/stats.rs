@@ -0,0 +1,112 @@
+use axum::{extract::Query, routing::get, Json};
+use serde::Deserialize;
+
+use crate::{
+    auth::Session,
+    context::ServerContext,
+    errors::ServerResult,
+    serialized::{Stats, ToSerialized},
+    Router,
+};
+
+#[derive(Debug, Deserialize)]
+struct StatsParams {
+    days: Option<u32>,
+    limit: Option<u32>,
+}
+
+/// The window used when no amount of days is given
+const DEFAULT_DAYS: u32 = 30;
+/// The maximum amount of days that can be requested, which is about a century
+const MAX_DAYS: u32 = 36500;
+/// The amount of entries returned when no limit is given
+const DEFAULT_LIMIT: u32 = 10;
+/// The maximum amount of entries that can be requested
+const MAX_LIMIT: u32 = 100;
+
+/// Gets the most played tracks and submitters within a time window.
+#[utoipa::path(
+    get,
+    path = "/v1/stats",
+    tag = "stats",
+    security(
+        ("BearerAuth" = [])
+    ),
+    params(
+        ("days" = Option<u32>, Query, description = "How many days back to include plays from. Defaults to 30, and is at most 36500."),
+        ("limit" = Option<u32>, Query, description = "How many tracks and submitters to return. Defaults to 10, and is at most 100.")
+    ),
+    responses(
+        (status = 200, body = Stats)
+    )
+)]
+async fn stats(
+    _session: Session,
+    context: ServerContext,
+    params: Query<StatsParams>,
+) -> ServerResult<Json<Stats>> {
+    let days = params.days.unwrap_or(DEFAULT_DAYS).min(MAX_DAYS);
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let tracks = context.collab.stats.top_tracks(days, limit).await?;
+    let submitters = context.collab.stats.top_submitters(days, limit).await?;
+
+    Ok(Json((tracks, submitters).to_serialized()))
+}
+
+pub fn router() -> Router {
+    Router::new().route("/", get(stats))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Request, StatusCode},
+    };
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use turntable_collab::{Credentials, NewPlainUser};
+
+    use crate::test_app;
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_stats_with_huge_window(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "admin".to_string(),
+                password: "password".to_string(),
+                display_name: "admin".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let session = collab
+            .auth
+            .login(Credentials {
+                username: "admin".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+
+        for days in [0, 100_000_000, u32::MAX] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/v1/stats?days={}", days))
+                        .header(AUTHORIZATION, format!("Bearer {}", session.token))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK, "{} days", days);
+        }
+    }
+}
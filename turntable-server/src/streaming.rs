@@ -1,11 +1,44 @@
-use axum::{body::Body, extract::{Path, Query}, response::Response, routing::get};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Query},
+    http::{header::ACCEPT, HeaderMap},
+    response::Response,
+    routing::{get, post},
+    Json,
+};
+use futures_util::{future::join_all, stream, StreamExt};
 use serde::Deserialize;
+use turntable_collab::Track as CollabTrack;
+use turntable_core::ConsumerStart;
+use turntable_impls::EncoderKind;
 
-use crate::{context::ServerContext, errors::ServerResult, Router};
+use crate::{
+    context::ServerContext,
+    errors::{ServerError, ServerResult},
+    schemas::{InputSchema, ValidatedJson},
+    serialized::{StreamInfo, ToSerialized},
+    Router,
+};
 
 #[derive(Debug, Deserialize)]
 struct StreamAudioParams {
-    latency: Option<u32>
+    latency: Option<u32>,
+    start: Option<StreamStart>,
+}
+
+/// What a stream does if the room has no audio yet when it's opened
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum StreamStart {
+    /// Respond right away, and send audio once there is some
+    #[default]
+    Wait,
+    /// Respond right away with a short bit of silence, so clients get a valid stream immediately
+    Silence,
+    /// Only respond once there is audio, failing if there is none in time
+    Audio,
 }
 
 /// Gets a live audio stream using a stream token.
@@ -15,36 +48,651 @@ struct StreamAudioParams {
     tag = "streaming",
     params(
         ("token" = String, Path, description = "Stream token of a room"),
-        ("latency" = Option<u32>, Query, description = "Controls the desired latency of the stream, where higher values means more latency. This is clamped to the pipeline's preload cache size.")
+        ("latency" = Option<u32>, Query, description = "Controls the desired latency of the stream, where higher values means more latency. Defaults to the room's default latency. This is clamped to the pipeline's preload cache size."),
+        ("start" = Option<String>, Query, description = "What happens if the room has no audio yet. wait responds right away and waits for audio, silence starts the stream with a short bit of silence, and audio only responds once there is audio. Defaults to wait."),
+        ("Accept" = Option<String>, Header, description = "The desired audio format. Supports audio/wav and audio/pcm, and falls back to audio/wav for anything else, including audio/ogg and audio/mpeg, which aren't supported. The sample format of audio/pcm is selected with a format parameter of f32le, s16le or s24le, like audio/pcm;format=s16le, and defaults to f32le.")
     ),
     responses(
         (
             status = 200,
             content_type = "application/octet-stream",
-            description = "A live audio stream"
-        )
+            description = "A live audio stream, encoded in the format negotiated by the Accept header"
+        ),
+        (status = 504, description = "The stream was requested to start with audio, but the room had none in time")
     )
 )]
 async fn stream_audio(
     context: ServerContext,
     params: Query<StreamAudioParams>,
+    headers: HeaderMap,
     Path(token): Path<String>,
 ) -> ServerResult<Response<Body>> {
-    let handle = context.collab.rooms.connect(token, params.latency).await?;
+    let accept = headers.get(ACCEPT).and_then(|v| v.to_str().ok());
+    let encoder = negotiate_encoder(accept);
+
+    let mut handle = context
+        .collab
+        .rooms
+        .connect(token, params.latency, encoder)
+        .await?;
     let content_type = handle.content_type();
-    let body = Body::from_stream(handle);
 
-    let response = Response::builder()
+    let body = match params.start.unwrap_or_default() {
+        StreamStart::Wait => Body::from_stream(handle),
+        StreamStart::Silence => {
+            handle.start_with(ConsumerStart::Silence(context.config.stream_preroll));
+            Body::from_stream(handle)
+        }
+        StreamStart::Audio => {
+            handle.start_with(ConsumerStart::Timeout(context.config.stream_start_timeout));
+
+            let first = handle.next().await.ok_or(ServerError::NoAudio)?;
+            Body::from_stream(stream::once(async { first }).chain(handle))
+        }
+    };
+
+    Ok(live_response(content_type, body))
+}
+
+/// Gets a short live audio stream of a public room, which closes after the configured preview duration.
+/// No stream key is needed, but only a limited amount of previews can be open per IP address,
+/// and only rooms that are already active can be previewed.
+///
+/// If the size of the encoded audio is known up front, the preview ends after exactly the preview duration of audio,
+/// and its length is sent instead of chunking the body.
+#[utoipa::path(
+    get, 
+    path = "/v1/streams/previews/{slug}",
+    tag = "streaming",
+    params(
+        ("slug" = String, Path, description = "Slug of a public room"),
+        ("Accept" = Option<String>, Header, description = "The desired audio format, negotiated the same way as when streaming")
+    ),
+    responses(
+        (
+            status = 200,
+            content_type = "application/octet-stream",
+            description = "A live audio stream that ends after the preview duration, with a Content-Length if its size is known"
+        ),
+        (status = 400, description = "The room is not active"),
+        (status = 404, description = "The room does not exist, or is not public"),
+        (status = 429, description = "Too many previews are open from this address")
+    )
+)]
+async fn stream_preview(
+    context: ServerContext,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Path(slug): Path<String>,
+) -> ServerResult<Response<Body>> {
+    let accept = headers.get(ACCEPT).and_then(|v| v.to_str().ok());
+    let encoder = negotiate_encoder(accept);
+
+    // Clients without a known address share a single limit
+    let ip = connect_info
+        .map(|c| c.0.ip())
+        .unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+    let permit = context.previews.acquire(ip)?;
+
+    let consumer = context.collab.rooms.preview(&slug, encoder)?;
+    let content_type = consumer.content_type();
+    let length = context.previews.length(&consumer);
+    let body = Body::from_stream(context.previews.stream(consumer, permit, length));
+
+    Ok(match length {
+        Some(length) => finite_response(content_type, length, body),
+        None => live_response(content_type, body),
+    })
+}
+
+/// Creates the response of a live audio stream, whose body yields a chunk every time the encoder has one.
+///
+/// Each chunk is sent as its own frame of the chunked body, which is written out as soon as it's yielded
+/// instead of waiting to fill a buffer. This keeps the latency of listeners down to the chunk cadence
+/// of the pipeline, about 100ms by default, plus the latency they requested.
+/// Proxies are told not to buffer or transform the stream either, since doing so would add seconds of latency.
+fn live_response(content_type: String, body: Body) -> Response<Body> {
+    Response::builder()
         .status(200)
         .header("Transfer-Encoding", "chunked")
         .header("Content-Type", content_type)
-        .header("Cache-Control", "no-store")
+        // Compressing audio saves little, and would buffer chunks until enough are compressed
+        .header("Cache-Control", "no-store, no-transform")
+        .header("X-Accel-Buffering", "no")
+        // The stream is live, so it has no known length to request ranges of
+        .header("Accept-Ranges", "none")
         .body(body)
-        .unwrap();
+        .unwrap()
+}
+
+/// Like [live_response], but for a stream that ends after a known amount of bytes.
+/// The length is sent instead of chunking the body, so clients can show how much is left.
+fn finite_response(content_type: String, length: usize, body: Body) -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header("Content-Length", length)
+        .header("Content-Type", content_type)
+        .header("Cache-Control", "no-store, no-transform")
+        .header("X-Accel-Buffering", "no")
+        // The audio is still produced live, so earlier parts can't be requested again
+        .header("Accept-Ranges", "none")
+        .body(body)
+        .unwrap()
+}
+
+/// Gets the format of the audio stream of a stream token, without opening the stream.
+#[utoipa::path(
+    get, 
+    path = "/v1/streams/{token}/info",
+    tag = "streaming",
+    params(
+        ("token" = String, Path, description = "Stream token of a room"),
+        ("Accept" = Option<String>, Header, description = "The desired audio format, negotiated the same way as when streaming")
+    ),
+    responses(
+        (status = 200, body = StreamInfo),
+        (status = 404, description = "The stream key does not exist")
+    )
+)]
+async fn stream_info(
+    context: ServerContext,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> ServerResult<Json<StreamInfo>> {
+    let accept = headers.get(ACCEPT).and_then(|v| v.to_str().ok());
+    let encoder = negotiate_encoder(accept);
+
+    // Ensures the token is valid
+    let room = context.collab.rooms.room_by_stream_token(&token).await?;
+    let config = context.collab.config.pipeline.clone();
+    let latency = room.stream_latency(None);
 
-    Ok(response)
+    Ok(Json((encoder, config, latency).to_serialized()))
+}
+
+/// Adds item(s) to the queue of a room using a stream token.
+/// The stream key must have been created with queue permissions.
+#[utoipa::path(
+    post,
+    path = "/v1/streams/{token}/queue",
+    tag = "streaming",
+    request_body = InputSchema,
+    params(
+        ("token" = String, Path, description = "Stream token of a room"),
+    ),
+    responses(
+        (status = 200, description = "Item(s) were added to the queue, leaving out the ones past the maximum queue size"),
+        (status = 400, description = "The queue is full"),
+        (status = 403, description = "The stream key does not allow adding to the queue, or an item comes from a source the room does not allow")
+    )
+)]
+async fn add_to_queue(
+    context: ServerContext,
+    Path(token): Path<String>,
+    ValidatedJson(body): ValidatedJson<InputSchema>,
+) -> ServerResult<()> {
+    // Only resolved once the stream key is known to allow queueing
+    let tracks = async {
+        let futs: Vec<_> = body
+            .query
+            .iter()
+            .map(|q| context.collab.inputs.query(q))
+            .collect();
+        let mut tracks: Vec<CollabTrack> = vec![];
+
+        for result in join_all(futs).await {
+            for input in result? {
+                tracks.push(CollabTrack::from(input).with_trim(body.start, body.end))
+            }
+        }
+
+        Ok(tracks)
+    };
+
+    context
+        .collab
+        .rooms
+        .add_to_queue_with_stream_key(token, tracks)
+        .await?;
+
+    Ok(())
+}
+
+/// Picks an encoder from the `Accept` header, preferring entries with a higher quality value.
+/// Falls back to the default encoder if the header is missing, a wildcard, or unrecognized.
+fn negotiate_encoder(accept: Option<&str>) -> EncoderKind {
+    let Some(accept) = accept else {
+        return EncoderKind::default();
+    };
+
+    let mut candidates: Vec<_> = accept
+        .split(',')
+        .filter_map(|entry| {
+            // The whole entry is passed on, so parameters like the PCM format are kept
+            let kind = EncoderKind::from_mime(entry)?;
+
+            let quality = entry
+                .split(';')
+                .skip(1)
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.);
+
+            (quality > 0.).then_some((quality, kind))
+        })
+        .collect();
+
+    // Stable sort, so entries with equal quality keep the order of the header
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    candidates
+        .first()
+        .map(|(_, kind)| *kind)
+        .unwrap_or_default()
 }
 
 pub fn router() -> Router {
-    Router::new().route("/:token", get(stream_audio))
+    Router::new()
+        .route("/:token", get(stream_audio))
+        .route("/previews/:slug", get(stream_preview))
+        .route("/:token/info", get(stream_info))
+        .route("/:token/queue", post(add_to_queue))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        body::{to_bytes, Body},
+        extract::ConnectInfo,
+        http::{
+            header::{ACCEPT, CONTENT_LENGTH, TRANSFER_ENCODING},
+            Request, StatusCode,
+        },
+    };
+    use futures_util::StreamExt;
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use turntable_collab::{NewPlainUser, NewRoom, RoomVisibility};
+    use turntable_impls::EncoderKind;
+
+    use super::negotiate_encoder;
+    use crate::{test_app, test_app_with_config, ServerConfig};
+
+    #[test]
+    fn test_negotiate_encoder() {
+        assert_eq!(negotiate_encoder(None), EncoderKind::Wave);
+        assert_eq!(negotiate_encoder(Some("*/*")), EncoderKind::Wave);
+        assert_eq!(negotiate_encoder(Some("audio/wav")), EncoderKind::Wave);
+        assert_eq!(negotiate_encoder(Some("audio/pcm")), EncoderKind::Pcm);
+        assert_eq!(negotiate_encoder(Some("audio/ogg")), EncoderKind::Wave);
+        assert_eq!(
+            negotiate_encoder(Some("audio/mpeg, audio/pcm")),
+            EncoderKind::Pcm
+        );
+        assert_eq!(
+            negotiate_encoder(Some("audio/wav;q=0.5, audio/pcm")),
+            EncoderKind::Pcm
+        );
+        assert_eq!(
+            negotiate_encoder(Some("audio/pcm;q=0, */*")),
+            EncoderKind::Wave
+        );
+        assert_eq!(
+            negotiate_encoder(Some("audio/pcm;format=s16le")),
+            EncoderKind::PcmS16
+        );
+        assert_eq!(
+            negotiate_encoder(Some("audio/pcm;format=s16le;q=0.5, audio/pcm;format=s24le")),
+            EncoderKind::PcmS24
+        );
+        assert_eq!(
+            negotiate_encoder(Some("audio/pcm;format=u8, audio/pcm")),
+            EncoderKind::Pcm
+        );
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_negotiated_content_type(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let key = collab
+            .rooms
+            .create_stream_key(room.id(), user.id, "test".to_string(), false)
+            .await
+            .unwrap();
+
+        for (accept, content_type) in [
+            ("audio/pcm", "audio/pcm"),
+            ("audio/pcm; format=s24le", "audio/pcm;format=s24le"),
+            ("audio/wav;q=0.5, audio/pcm", "audio/pcm"),
+            ("*/*", "audio/wav"),
+            // Unsupported formats fall back to wave
+            ("audio/ogg", "audio/wav"),
+            ("audio/mpeg", "audio/wav"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/v1/streams/{}?latency=0", key.token))
+                        .header(ACCEPT, accept)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()["content-type"],
+                content_type,
+                "negotiated from {}",
+                accept
+            );
+        }
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_stream_info(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "room".to_string(),
+                title: "Room".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let key = collab
+            .rooms
+            .create_stream_key(room.id(), user.id, "test".to_string(), false)
+            .await
+            .unwrap();
+
+        let request = |token: &str, accept: &str| {
+            Request::get(format!("/v1/streams/{}/info", token))
+                .header(ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(&key.token, "audio/pcm"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let info: Value = serde_json::from_slice(&body).unwrap();
+        let config = &collab.config.pipeline;
+
+        assert_eq!(info["sampleRate"], config.sample_rate);
+        assert_eq!(info["channelCount"], config.channel_count);
+        assert_eq!(info["contentType"], "audio/pcm");
+        assert_eq!(info["bitrate"], EncoderKind::Pcm.bitrate(config));
+        assert_eq!(info["acceptsRanges"], false);
+
+        let response = app
+            .clone()
+            .oneshot(request(&key.token, "audio/pcm;format=s16le"))
+            .await
+            .unwrap();
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let info: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(info["contentType"], "audio/pcm;format=s16le");
+        assert_eq!(info["codec"], "pcm_s16le");
+        assert_eq!(info["bitsPerSample"], 16);
+
+        let response = app.oneshot(request("unknown", "*/*")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_stream_chunks_arrive_at_cadence(pool: PgPool) {
+        let (app, context) = test_app(pool).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let room = collab
+            .rooms
+            .create_room(NewRoom {
+                slug: "live".to_string(),
+                title: "Live".to_string(),
+                visibility: RoomVisibility::Public,
+                user_id: user.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let key = collab
+            .rooms
+            .create_stream_key(room.id(), user.id, "test".to_string(), false)
+            .await
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::get(format!("/v1/streams/{}?latency=0", key.token))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Accel-Buffering"], "no");
+        assert!(
+            response.headers().get(CONTENT_LENGTH).is_none(),
+            "a live stream has no length"
+        );
+
+        let mut chunks = response.into_body().into_data_stream();
+        let mut arrivals = vec![];
+
+        while arrivals.len() < 10 {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), chunks.next())
+                .await
+                .expect("chunk arrives")
+                .expect("stream is open")
+                .unwrap();
+
+            arrivals.push((Instant::now(), chunk.len()));
+        }
+
+        let config = &collab.config.pipeline;
+        let cadence = Duration::from_secs_f32(config.buffer_size_in_seconds);
+        let chunk_size =
+            EncoderKind::Wave.bitrate(config) / 8 * cadence.as_millis() as usize / 1000;
+
+        // The first chunks can catch up on what was buffered before the stream opened
+        let (first, _) = arrivals[2];
+        let (last, _) = arrivals[9];
+
+        assert!(
+            last - first >= cadence * 7 / 2,
+            "chunks were buffered, 7 arrived in {:?}",
+            last - first
+        );
+        assert!(
+            arrivals[2..]
+                .iter()
+                .all(|(_, size)| *size <= chunk_size * 2),
+            "chunks are sent as they're encoded: {:?}",
+            arrivals.iter().map(|(_, size)| size).collect::<Vec<_>>()
+        );
+    }
+
+    #[sqlx::test(migrations = "../turntable-collab/migrations")]
+    async fn test_preview_closes_after_duration(pool: PgPool) {
+        let config = ServerConfig {
+            preview_duration: Duration::from_millis(500),
+            max_previews_per_ip: 1,
+            ..Default::default()
+        };
+
+        let (app, context) = test_app_with_config(pool, config).await;
+        let collab = &context.collab;
+
+        let user = collab
+            .auth
+            .register_superuser(NewPlainUser {
+                username: "owner".to_string(),
+                password: "password".to_string(),
+                display_name: "owner".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let mut rooms = vec![];
+
+        for (slug, visibility) in [
+            ("public", RoomVisibility::Public),
+            ("private", RoomVisibility::Private),
+        ] {
+            let room = collab
+                .rooms
+                .create_room(NewRoom {
+                    slug: slug.to_string(),
+                    title: slug.to_string(),
+                    visibility,
+                    user_id: user.id,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            rooms.push(room);
+        }
+
+        let request = |slug: &str, addr: &str| {
+            let addr: SocketAddr = addr.parse().unwrap();
+
+            Request::get(format!("/v1/streams/previews/{}", slug))
+                .extension(ConnectInfo(addr))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("private", "10.0.0.2:1000"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .clone()
+            .oneshot(request("public", "10.0.0.2:1000"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "previews don't activate rooms"
+        );
+
+        // Someone starts listening, so the room is active
+        let _connection = rooms[0]
+            .connect(user.id, "test".to_string(), None, EncoderKind::Wave, None)
+            .unwrap();
+
+        let preview = app
+            .clone()
+            .oneshot(request("public", "10.0.0.1:1000"))
+            .await
+            .unwrap();
+        assert_eq!(preview.status(), StatusCode::OK);
+        assert!(preview.headers().get(TRANSFER_ENCODING).is_none());
+
+        // A wave header, followed by half a second of 16-bit stereo audio
+        let length: usize = preview.headers()[CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(length, 44 + 44100 * 2 * 2 / 2);
+
+        let response = app
+            .clone()
+            .oneshot(request("public", "10.0.0.1:2000"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let body = tokio::time::timeout(
+            Duration::from_secs(5),
+            to_bytes(preview.into_body(), usize::MAX),
+        )
+        .await
+        .expect("preview closes")
+        .unwrap();
+
+        assert_eq!(body.len(), length, "the preview ends after its length");
+
+        let response = app
+            .oneshot(request("public", "10.0.0.1:1000"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "closed preview is released"
+        );
+    }
 }